@@ -1,3 +1,5 @@
 fn main() {
+    // Only the Tauri app needs its build step; the core builds without it.
+    #[cfg(feature = "desktop")]
     tauri_build::build()
 }
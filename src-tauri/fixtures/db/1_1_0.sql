@@ -0,0 +1,150 @@
+-- Database as left by the 1.1.0 release: schema version tracked only as
+-- app_meta.db_version, with a checked folder tree and random history in use.
+CREATE TABLE app_meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+CREATE TABLE folders (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    added_at TEXT NOT NULL,
+    current_index INTEGER NOT NULL DEFAULT -1,
+    current_random_index INTEGER NOT NULL DEFAULT -1
+);
+CREATE TABLE images (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL,
+    folder_id INTEGER,
+    FOREIGN KEY (folder_id) REFERENCES folders(id)
+);
+CREATE UNIQUE INDEX idx_images_folder_path ON images(folder_id, path);
+CREATE INDEX idx_images_folder_id ON images(folder_id);
+CREATE TABLE state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    current_index INTEGER NOT NULL DEFAULT -1,
+    current_random_index INTEGER NOT NULL DEFAULT -1,
+    current_folder_id INTEGER,
+    vertical_mirror INTEGER NOT NULL DEFAULT 0,
+    horizontal_mirror INTEGER NOT NULL DEFAULT 0,
+    greyscale INTEGER NOT NULL DEFAULT 0,
+    timer_flow_mode TEXT NOT NULL DEFAULT 'random',
+    show_folder_history_panel INTEGER NOT NULL DEFAULT 1,
+    show_top_controls INTEGER NOT NULL DEFAULT 1,
+    show_image_history_panel INTEGER NOT NULL DEFAULT 1,
+    show_bottom_controls INTEGER NOT NULL DEFAULT 1,
+    is_fullscreen_image INTEGER NOT NULL DEFAULT 0,
+    last_image_id INTEGER,
+    shortcut_hints_visible INTEGER NOT NULL DEFAULT 0,
+    shortcut_hint_side TEXT NOT NULL DEFAULT 'left'
+);
+CREATE TABLE random_history (
+    folder_id INTEGER NOT NULL,
+    order_index INTEGER NOT NULL,
+    image_id INTEGER NOT NULL,
+    PRIMARY KEY (folder_id, order_index),
+    FOREIGN KEY (folder_id) REFERENCES folders(id),
+    FOREIGN KEY (image_id) REFERENCES images(id)
+);
+CREATE TABLE current_lap (
+    folder_id INTEGER NOT NULL,
+    image_id INTEGER NOT NULL,
+    PRIMARY KEY (folder_id, image_id),
+    FOREIGN KEY (folder_id) REFERENCES folders(id),
+    FOREIGN KEY (image_id) REFERENCES images(id)
+);
+CREATE TABLE hidden_normal_images (
+    folder_id INTEGER NOT NULL,
+    image_id INTEGER NOT NULL,
+    PRIMARY KEY (folder_id, image_id),
+    FOREIGN KEY (folder_id) REFERENCES folders(id),
+    FOREIGN KEY (image_id) REFERENCES images(id)
+);
+CREATE TABLE hidden_random_images (
+    folder_id INTEGER NOT NULL,
+    image_id INTEGER NOT NULL,
+    PRIMARY KEY (folder_id, image_id),
+    FOREIGN KEY (folder_id) REFERENCES folders(id),
+    FOREIGN KEY (image_id) REFERENCES images(id)
+);
+CREATE INDEX idx_hidden_normal_folder ON hidden_normal_images(folder_id);
+CREATE INDEX idx_hidden_normal_image ON hidden_normal_images(image_id);
+CREATE INDEX idx_hidden_random_folder ON hidden_random_images(folder_id);
+CREATE INDEX idx_hidden_random_image ON hidden_random_images(image_id);
+CREATE TABLE folder_nodes (
+    path TEXT PRIMARY KEY,
+    parent_path TEXT,
+    root_folder_id INTEGER NOT NULL,
+    subtree_image_count INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (root_folder_id) REFERENCES folders(id)
+);
+CREATE INDEX idx_folder_nodes_parent_path ON folder_nodes(parent_path);
+CREATE INDEX idx_folder_nodes_root_folder_id ON folder_nodes(root_folder_id);
+CREATE TABLE checked_folders (
+    path TEXT PRIMARY KEY,
+    FOREIGN KEY (path) REFERENCES folder_nodes(path)
+);
+CREATE TABLE active_images (
+    image_id INTEGER PRIMARY KEY,
+    FOREIGN KEY (image_id) REFERENCES images(id)
+);
+CREATE TABLE active_image_refcounts (
+    image_id INTEGER PRIMARY KEY,
+    refcount INTEGER NOT NULL,
+    FOREIGN KEY (image_id) REFERENCES images(id)
+);
+CREATE TABLE folder_closure (
+    ancestor_path TEXT NOT NULL,
+    descendant_path TEXT NOT NULL,
+    PRIMARY KEY (ancestor_path, descendant_path),
+    FOREIGN KEY (ancestor_path) REFERENCES folder_nodes(path),
+    FOREIGN KEY (descendant_path) REFERENCES folder_nodes(path)
+);
+CREATE INDEX idx_folder_closure_descendant ON folder_closure(descendant_path);
+CREATE TABLE folder_images_direct (
+    folder_path TEXT NOT NULL,
+    image_id INTEGER NOT NULL,
+    PRIMARY KEY (folder_path, image_id),
+    FOREIGN KEY (folder_path) REFERENCES folder_nodes(path),
+    FOREIGN KEY (image_id) REFERENCES images(id)
+);
+CREATE INDEX idx_folder_images_direct_image ON folder_images_direct(image_id);
+CREATE TABLE random_history_global (
+    order_index INTEGER PRIMARY KEY,
+    image_id INTEGER NOT NULL,
+    FOREIGN KEY (image_id) REFERENCES images(id)
+);
+CREATE TABLE current_lap_global (
+    image_id INTEGER PRIMARY KEY,
+    FOREIGN KEY (image_id) REFERENCES images(id)
+);
+CREATE INDEX idx_images_path ON images(path);
+
+INSERT INTO app_meta (key, value) VALUES ('db_version', '1.1.0');
+INSERT INTO folders (id, path, added_at, current_index, current_random_index)
+VALUES (1, '/photos', '2025-03-14T08:30:00Z', 1, 0);
+INSERT INTO images (id, path, folder_id) VALUES
+    (1, '/photos/a.jpg', 1),
+    (2, '/photos/b.jpg', 1),
+    (3, '/photos/trip/c.png', 1);
+INSERT INTO state (id, current_folder_id, greyscale, timer_flow_mode, last_image_id)
+VALUES (1, 1, 1, 'normal', 2);
+INSERT INTO random_history (folder_id, order_index, image_id) VALUES (1, 0, 2), (1, 1, 3);
+INSERT INTO current_lap (folder_id, image_id) VALUES (1, 2), (1, 3);
+INSERT INTO hidden_normal_images (folder_id, image_id) VALUES (1, 1);
+INSERT INTO hidden_random_images (folder_id, image_id) VALUES (1, 1);
+INSERT INTO folder_nodes (path, parent_path, root_folder_id, subtree_image_count) VALUES
+    ('/photos', NULL, 1, 3),
+    ('/photos/trip', '/photos', 1, 1);
+INSERT INTO folder_closure (ancestor_path, descendant_path) VALUES
+    ('/photos', '/photos'),
+    ('/photos', '/photos/trip'),
+    ('/photos/trip', '/photos/trip');
+INSERT INTO folder_images_direct (folder_path, image_id) VALUES
+    ('/photos', 1),
+    ('/photos', 2),
+    ('/photos/trip', 3);
+INSERT INTO checked_folders (path) VALUES ('/photos');
+INSERT INTO active_image_refcounts (image_id, refcount) VALUES (1, 1), (2, 1), (3, 1);
+INSERT INTO active_images (image_id) VALUES (1), (2), (3);
+INSERT INTO random_history_global (order_index, image_id) VALUES (0, 2), (1, 3);
+INSERT INTO current_lap_global (image_id) VALUES (2), (3);
@@ -0,0 +1,66 @@
+-- Layout written by builds before 1.1.0: no app_meta, image paths unique
+-- across all folders, and history tables whose foreign keys still point at
+-- the `images_old` table left behind by an earlier rebuild.
+CREATE TABLE folders (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    added_at TEXT NOT NULL,
+    current_index INTEGER NOT NULL DEFAULT -1,
+    current_random_index INTEGER NOT NULL DEFAULT -1
+);
+CREATE TABLE images (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    folder_id INTEGER,
+    FOREIGN KEY (folder_id) REFERENCES folders(id)
+);
+CREATE TABLE state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    current_index INTEGER NOT NULL DEFAULT -1,
+    current_random_index INTEGER NOT NULL DEFAULT -1,
+    current_folder_id INTEGER,
+    vertical_mirror INTEGER NOT NULL DEFAULT 0,
+    horizontal_mirror INTEGER NOT NULL DEFAULT 0,
+    greyscale INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE random_history (
+    folder_id INTEGER NOT NULL,
+    order_index INTEGER NOT NULL,
+    image_id INTEGER NOT NULL,
+    PRIMARY KEY (folder_id, order_index),
+    FOREIGN KEY (folder_id) REFERENCES folders(id),
+    FOREIGN KEY (image_id) REFERENCES images_old(id)
+);
+CREATE TABLE current_lap (
+    folder_id INTEGER NOT NULL,
+    image_id INTEGER NOT NULL,
+    PRIMARY KEY (folder_id, image_id),
+    FOREIGN KEY (folder_id) REFERENCES folders(id),
+    FOREIGN KEY (image_id) REFERENCES images_old(id)
+);
+CREATE TABLE hidden_normal_images (
+    folder_id INTEGER NOT NULL,
+    image_id INTEGER NOT NULL,
+    PRIMARY KEY (folder_id, image_id),
+    FOREIGN KEY (folder_id) REFERENCES folders(id),
+    FOREIGN KEY (image_id) REFERENCES images_old(id)
+);
+CREATE TABLE hidden_random_images (
+    folder_id INTEGER NOT NULL,
+    image_id INTEGER NOT NULL,
+    PRIMARY KEY (folder_id, image_id),
+    FOREIGN KEY (folder_id) REFERENCES folders(id),
+    FOREIGN KEY (image_id) REFERENCES images_old(id)
+);
+
+INSERT INTO folders (id, path, added_at, current_index, current_random_index)
+VALUES (1, '/photos', '2024-01-05T10:00:00Z', 1, 0);
+INSERT INTO images (id, path, folder_id) VALUES
+    (1, '/photos/a.jpg', 1),
+    (2, '/photos/b.jpg', 1),
+    (3, '/photos/trip/c.png', 1);
+INSERT INTO state (id, current_folder_id, greyscale) VALUES (1, 1, 1);
+INSERT INTO random_history (folder_id, order_index, image_id) VALUES (1, 0, 2), (1, 1, 3);
+INSERT INTO current_lap (folder_id, image_id) VALUES (1, 2), (1, 3);
+INSERT INTO hidden_normal_images (folder_id, image_id) VALUES (1, 1);
+INSERT INTO hidden_random_images (folder_id, image_id) VALUES (1, 1);
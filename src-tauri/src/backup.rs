@@ -0,0 +1,300 @@
+use crate::error::LoaderError;
+use chrono::{NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use rusqlite::{Connection, DatabaseName, OpenFlags};
+use std::path::{Path, PathBuf};
+
+/// Snapshots kept per database; the oldest are removed once a new one lands.
+pub const MAX_BACKUPS: usize = 10;
+
+const BACKUP_DIR_NAME: &str = "backups";
+const BACKUP_EXTENSION: &str = "sqlite";
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%3f";
+
+/// Why a snapshot was taken; part of the file name so the list is readable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupReason {
+    Startup,
+    Migration,
+    FullWipe,
+    DeleteFolder,
+    PreRestore,
+    Import,
+    Repair,
+}
+
+impl BackupReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BackupReason::Startup => "startup",
+            BackupReason::Migration => "migration",
+            BackupReason::FullWipe => "full-wipe",
+            BackupReason::DeleteFolder => "delete-folder",
+            BackupReason::PreRestore => "pre-restore",
+            BackupReason::Import => "import",
+            BackupReason::Repair => "repair",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupEntry {
+    pub name: String,
+    pub reason: String,
+    /// RFC 3339, UTC, millisecond precision as encoded in the file name.
+    pub created_at: String,
+    pub size_bytes: u64,
+}
+
+/// Backups live next to the database so they follow it across profiles.
+pub fn backup_dir(db_path: &Path) -> PathBuf {
+    db_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(BACKUP_DIR_NAME)
+}
+
+fn db_stem(db_path: &Path) -> String {
+    db_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "db".to_string())
+}
+
+/// Copies the live database with SQLite's online backup API, so the snapshot
+/// is consistent even while other connections are writing, then prunes old
+/// snapshots. The copy is written under a temporary name and renamed so a
+/// half-written file never shows up in `list_backups`.
+pub fn create_backup(
+    conn: &Connection,
+    db_path: &Path,
+    reason: BackupReason,
+) -> Result<BackupEntry, LoaderError> {
+    let dir = backup_dir(db_path);
+    std::fs::create_dir_all(&dir)?;
+
+    let created = Utc::now();
+    let name = format!(
+        "{}-{}-{}.{}",
+        db_stem(db_path),
+        created.format(TIMESTAMP_FORMAT),
+        reason.as_str(),
+        BACKUP_EXTENSION
+    );
+    let partial = dir.join(format!("{}.partial", name));
+    let target = dir.join(&name);
+
+    if let Err(err) = conn.backup(DatabaseName::Main, &partial, None) {
+        let _ = std::fs::remove_file(&partial);
+        return Err(err.into());
+    }
+    std::fs::rename(&partial, &target)?;
+    eprintln!("[RUST] backup: wrote {}", target.display());
+
+    prune_backups(db_path)?;
+    let size_bytes = std::fs::metadata(&target)?.len();
+    Ok(BackupEntry {
+        name,
+        reason: reason.as_str().to_string(),
+        created_at: created.to_rfc3339_opts(SecondsFormat::Millis, true),
+        size_bytes,
+    })
+}
+
+fn parse_backup_name(stem_prefix: &str, name: &str) -> Option<(NaiveDateTime, String)> {
+    let rest = name
+        .strip_prefix(stem_prefix)?
+        .strip_suffix(&format!(".{}", BACKUP_EXTENSION))?;
+    let (timestamp, reason) = rest.split_once('-')?;
+    let created = NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?;
+    if reason.is_empty() {
+        return None;
+    }
+    Some((created, reason.to_string()))
+}
+
+/// Snapshots of this database, newest first.
+pub fn list_backups(db_path: &Path) -> Result<Vec<BackupEntry>, LoaderError> {
+    let dir = backup_dir(db_path);
+    let read_dir = match std::fs::read_dir(&dir) {
+        Ok(read_dir) => read_dir,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let stem_prefix = format!("{}-", db_stem(db_path));
+    let mut entries = Vec::new();
+    for dir_entry in read_dir {
+        let dir_entry = dir_entry?;
+        let name = dir_entry.file_name().to_string_lossy().into_owned();
+        let Some((created, reason)) = parse_backup_name(&stem_prefix, &name) else {
+            continue;
+        };
+        let metadata = dir_entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        entries.push((
+            created,
+            BackupEntry {
+                name,
+                reason,
+                created_at: Utc
+                    .from_utc_datetime(&created)
+                    .to_rfc3339_opts(SecondsFormat::Millis, true),
+                size_bytes: metadata.len(),
+            },
+        ));
+    }
+    entries.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.name.cmp(&a.1.name)));
+    Ok(entries.into_iter().map(|(_, entry)| entry).collect())
+}
+
+fn prune_backups(db_path: &Path) -> Result<(), LoaderError> {
+    let dir = backup_dir(db_path);
+    for stale in list_backups(db_path)?.into_iter().skip(MAX_BACKUPS) {
+        std::fs::remove_file(dir.join(&stale.name))?;
+    }
+    Ok(())
+}
+
+/// Maps a name from `list_backups` back to its file. Only listed names are
+/// accepted, so a caller cannot point the restore at an arbitrary path.
+pub fn resolve_backup(db_path: &Path, name: &str) -> Result<PathBuf, LoaderError> {
+    if list_backups(db_path)?
+        .iter()
+        .any(|entry| entry.name == name)
+    {
+        Ok(backup_dir(db_path).join(name))
+    } else {
+        Err(LoaderError::NotFound(format!("backup not found: {}", name)))
+    }
+}
+
+/// Rejects snapshots that are damaged or were written by a newer build.
+pub fn verify_backup(backup_path: &Path, max_schema_version: i64) -> Result<(), LoaderError> {
+    let conn = Connection::open_with_flags(backup_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let check: String = conn.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+    if check != "ok" {
+        return Err(LoaderError::Invalid(format!(
+            "backup is damaged: {}",
+            check
+        )));
+    }
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > max_schema_version {
+        return Err(LoaderError::Invalid(format!(
+            "backup was written by a newer version (schema {})",
+            version
+        )));
+    }
+    Ok(())
+}
+
+/// Replaces the contents of `conn` with the snapshot, page by page, through
+/// the same backup API. Other handles on the file see the restored data once
+/// this returns.
+pub fn restore_into(conn: &mut Connection, backup_path: &Path) -> Result<(), LoaderError> {
+    conn.restore(
+        DatabaseName::Main,
+        backup_path,
+        None::<fn(rusqlite::backup::Progress)>,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        backup_dir, create_backup, list_backups, resolve_backup, BackupReason, MAX_BACKUPS,
+    };
+    use crate::db::{Db, DB_FILE_NAME};
+    use crate::test_support::{open_temp_db, unique_temp_root};
+
+    fn folder_count(db: &Db) -> i64 {
+        db.conn()
+            .query_row("SELECT COUNT(*) FROM folders", [], |row| row.get(0))
+            .expect("folder count should work")
+    }
+
+    #[test]
+    fn backups_rotate_and_list_newest_first() {
+        let root = unique_temp_root("backup", "rotate");
+        let db_path = root.join(DB_FILE_NAME);
+        let db = open_temp_db(&root);
+
+        let mut written = Vec::new();
+        for _ in 0..MAX_BACKUPS + 2 {
+            written.push(
+                db.backup(BackupReason::Startup)
+                    .expect("backup should succeed"),
+            );
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        let listed = list_backups(&db_path).expect("backups should list");
+        assert_eq!(listed.len(), MAX_BACKUPS);
+        assert_eq!(listed[0], *written.last().unwrap());
+        assert!(!listed.iter().any(|entry| entry.name == written[0].name));
+        assert!(!backup_dir(&db_path).join(&written[0].name).exists());
+
+        // Files the rotation does not own are left alone.
+        std::fs::write(backup_dir(&db_path).join("notes.txt"), "keep").unwrap();
+        db.backup(BackupReason::FullWipe)
+            .expect("backup should succeed");
+        assert!(backup_dir(&db_path).join("notes.txt").exists());
+        assert_eq!(list_backups(&db_path).unwrap()[0].reason, "full-wipe");
+
+        drop(db);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn restore_brings_back_the_snapshot_and_rejects_unlisted_names() {
+        let root = unique_temp_root("backup", "restore");
+        let db_path = root.join(DB_FILE_NAME);
+        let db = open_temp_db(&root);
+        db.conn()
+            .execute(
+                "INSERT INTO folders (path, added_at) VALUES ('/photos', 'now')",
+                [],
+            )
+            .expect("folder insert should succeed");
+        let snapshot = db
+            .backup(BackupReason::DeleteFolder)
+            .expect("backup should succeed");
+        db.conn()
+            .execute("DELETE FROM folders", [])
+            .expect("folder delete should succeed");
+        assert_eq!(folder_count(&db), 0);
+
+        assert!(resolve_backup(&db_path, "../imgstate.sqlite").is_err());
+        assert!(resolve_backup(&db_path, "missing.sqlite").is_err());
+        let backup_path =
+            resolve_backup(&db_path, &snapshot.name).expect("listed backup should resolve");
+        db.restore_backup(&backup_path)
+            .expect("restore should succeed");
+        assert_eq!(folder_count(&db), 1);
+        drop(db);
+
+        let reopened = Db::open(db_path).expect("restored db should reopen");
+        assert_eq!(folder_count(&reopened), 1);
+        drop(reopened);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn create_backup_works_on_a_plain_connection() {
+        let root = unique_temp_root("backup", "plain");
+        let db_path = root.join("other.sqlite");
+        let conn = rusqlite::Connection::open(&db_path).expect("db should open");
+        conn.execute_batch("CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (7);")
+            .expect("table should be created");
+        let entry =
+            create_backup(&conn, &db_path, BackupReason::Migration).expect("backup should succeed");
+        assert!(entry.name.starts_with("other-"));
+        assert!(entry.name.ends_with("-migration.sqlite"));
+        assert!(entry.size_bytes > 0);
+
+        drop(conn);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+}
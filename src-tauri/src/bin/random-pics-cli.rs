@@ -0,0 +1,8 @@
+//! Command-line interface; see `app_lib::cli`.
+
+fn main() {
+    let mut out = std::io::stdout().lock();
+    let mut err = std::io::stderr().lock();
+    let code = app_lib::cli::run(std::env::args().skip(1), &mut out, &mut err);
+    std::process::exit(code);
+}
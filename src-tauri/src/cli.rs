@@ -0,0 +1,462 @@
+//! Headless front end over the same `Db` and `ImageLoader` the app uses, for
+//! scripting session prep and scheduled reindexing. The database is shared
+//! with a running app; WAL keeps the two from blocking each other for long.
+
+use crate::db::Db;
+use crate::error::{ImageMode, LoaderError};
+use crate::img_loader::ImageLoader;
+use crate::indexing::IndexingJob;
+use crate::library_io;
+use crate::profiles::Profiles;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Must match `identifier` in tauri.conf.json so the CLI finds the app's data.
+const APP_IDENTIFIER: &str = "com.randompics.app";
+
+const USAGE: &str = "\
+usage: random-pics-cli [--data-dir DIR] [--profile NAME | --db FILE] COMMAND
+
+commands:
+  folders                 list indexed root folders
+  index FOLDER [--rescan] add a folder (or rescan it) and make it current
+  reindex                 rescan every root folder; offline ones are skipped
+  random [-n COUNT]       pick COUNT random images from the checked folders
+  history [--normal]      print the random (or normal) history; '>' marks the current image
+  hide PATH [--normal]    hide an image from random (or normal) mode
+  tag PATH TAG...         add tags to an image
+  untag PATH TAG...       remove tags from an image
+  tags [PATH]             list an image's tags, or every tag with its image count
+  tagged TAG              list the images carrying a tag
+  export FILE             write the library to a JSON export file
+";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Folders,
+    Index { folder: String, rescan: bool },
+    Reindex,
+    Random { count: usize },
+    History { mode: ImageMode },
+    Hide { path: String, mode: ImageMode },
+    Tag { path: String, tags: Vec<String> },
+    Untag { path: String, tags: Vec<String> },
+    Tags { path: Option<String> },
+    Tagged { tag: String },
+    Export { file: PathBuf },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Options {
+    pub data_dir: Option<PathBuf>,
+    pub profile: Option<String>,
+    pub db: Option<PathBuf>,
+    pub command: Command,
+}
+
+fn take_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("{} needs a value", flag))
+}
+
+/// Parses everything after the program name.
+pub fn parse_args<I>(args: I) -> Result<Options, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+    let mut data_dir = None;
+    let mut profile = None;
+    let mut db = None;
+    let mut positional = Vec::new();
+    let mut rescan = false;
+    let mut normal = false;
+    let mut count = 1;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--data-dir" => data_dir = Some(PathBuf::from(take_value(&mut args, &arg)?)),
+            "--profile" => profile = Some(take_value(&mut args, &arg)?),
+            "--db" => db = Some(PathBuf::from(take_value(&mut args, &arg)?)),
+            "--rescan" => rescan = true,
+            "--normal" => normal = true,
+            "-n" | "--count" => {
+                let raw = take_value(&mut args, &arg)?;
+                count = raw
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|count| *count > 0)
+                    .ok_or_else(|| format!("invalid count: {}", raw))?;
+            }
+            flag if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("unknown option: {}", flag));
+            }
+            _ => positional.push(arg),
+        }
+    }
+    if profile.is_some() && db.is_some() {
+        return Err("--profile and --db cannot be combined".to_string());
+    }
+
+    let mode = if normal {
+        ImageMode::Normal
+    } else {
+        ImageMode::Random
+    };
+    let mut positional = positional.into_iter();
+    let name = positional.next().ok_or("missing command")?;
+    let mut operand = |what: &str| {
+        positional
+            .next()
+            .ok_or_else(|| format!("{} needs {}", name, what))
+    };
+    let command = match name.as_str() {
+        "folders" => Command::Folders,
+        "index" => Command::Index {
+            folder: operand("a folder")?,
+            rescan,
+        },
+        "reindex" => Command::Reindex,
+        "random" => Command::Random { count },
+        "history" => Command::History { mode },
+        "hide" => Command::Hide {
+            path: operand("an image path")?,
+            mode,
+        },
+        "tag" | "untag" => {
+            let path = operand("an image path")?;
+            let tags: Vec<String> = positional.by_ref().collect();
+            if tags.is_empty() {
+                return Err(format!("{} needs at least one tag", name));
+            }
+            if name == "tag" {
+                Command::Tag { path, tags }
+            } else {
+                Command::Untag { path, tags }
+            }
+        }
+        "tags" => Command::Tags {
+            path: positional.next(),
+        },
+        "tagged" => Command::Tagged {
+            tag: operand("a tag")?,
+        },
+        "export" => Command::Export {
+            file: PathBuf::from(operand("a file")?),
+        },
+        other => return Err(format!("unknown command: {}", other)),
+    };
+    if let Some(extra) = positional.next() {
+        return Err(format!("unexpected argument: {}", extra));
+    }
+
+    Ok(Options {
+        data_dir,
+        profile,
+        db,
+        command,
+    })
+}
+
+/// Where Tauri puts the app data on this platform, without needing an
+/// `AppHandle`.
+pub fn default_data_dir() -> Option<PathBuf> {
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+            })
+    };
+    base.map(|dir| dir.join(APP_IDENTIFIER))
+}
+
+fn resolve_db_path(options: &Options) -> Result<PathBuf, LoaderError> {
+    if let Some(db) = &options.db {
+        return Ok(db.clone());
+    }
+    let data_dir = match &options.data_dir {
+        Some(dir) => dir.clone(),
+        None => default_data_dir().ok_or_else(|| {
+            LoaderError::Invalid("cannot find the app data directory; pass --data-dir".to_string())
+        })?,
+    };
+    std::fs::create_dir_all(&data_dir)?;
+    let profiles = Profiles::new(data_dir);
+    let profile = match &options.profile {
+        Some(name) => profiles.find(name)?,
+        None => profiles.active()?,
+    };
+    Ok(profiles.db_path(&profile))
+}
+
+/// Per-file progress would drown the summary lines.
+fn report_progress(err: &mut dyn Write) -> impl FnMut(String) + '_ {
+    move |line: String| {
+        let per_file = line
+            .strip_prefix("index:")
+            .and_then(|rest| rest.split(' ').next())
+            .is_some_and(|counter| counter.contains('/'));
+        if !per_file {
+            let _ = writeln!(err, "{}", line);
+        }
+    }
+}
+
+fn execute(
+    loader: &ImageLoader,
+    command: Command,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<(), LoaderError> {
+    match command {
+        Command::Folders => {
+            for folder in loader.get_folder_history_with_status()?.history {
+                writeln!(
+                    out,
+                    "{}\t{}\t{}",
+                    folder.path,
+                    folder.image_count,
+                    if folder.online { "online" } else { "offline" }
+                )?;
+            }
+        }
+        Command::Index { folder, rescan } => {
+            let job = IndexingJob::detached();
+            let (_, path, _) = loader.set_current_folder_and_index_with_progress(
+                &folder,
+                &job,
+                report_progress(err),
+            )?;
+            if rescan {
+                loader.reindex_current_folder_with_progress(&job, report_progress(err))?;
+            }
+            writeln!(out, "{}", path)?;
+        }
+        Command::Reindex => {
+            let previous = loader.get_current_folder_id()?;
+            let mut failed = 0;
+            for (id, path, _, _) in loader.get_folder_history()? {
+                loader.set_current_folder_id(Some(id))?;
+                let job = IndexingJob::detached();
+                match loader.reindex_current_folder_with_progress(&job, report_progress(err)) {
+                    Ok(_) => writeln!(out, "{}", path)?,
+                    // Unplugged drives are expected; they keep their index.
+                    Err(LoaderError::FolderMissing { .. }) => {
+                        writeln!(err, "skipped {}: folder is offline", path)?;
+                    }
+                    Err(error) => {
+                        failed += 1;
+                        writeln!(err, "skipped {}: {}", path, error.user_message())?;
+                    }
+                }
+            }
+            loader.set_current_folder_id(previous)?;
+            if failed > 0 {
+                return Err(LoaderError::Invalid(format!(
+                    "{} folder(s) could not be reindexed",
+                    failed
+                )));
+            }
+        }
+        Command::Random { count } => {
+            for _ in 0..count {
+                writeln!(out, "{}", loader.pick_random_image_path()?)?;
+            }
+        }
+        Command::History { mode } => {
+            let (items, current) = match mode {
+                ImageMode::Normal => loader.get_normal_history()?,
+                ImageMode::Random => loader.get_random_history()?,
+            };
+            for (idx, item) in items.iter().enumerate() {
+                let marker = if idx as i64 == current { '>' } else { ' ' };
+                writeln!(out, "{} {}", marker, item.path)?;
+            }
+        }
+        Command::Hide { path, mode } => {
+            loader.hide_image_by_path(&path, mode)?;
+        }
+        Command::Tag { path, tags } => {
+            let tags = loader.tag_image_by_path(&path, &tags)?;
+            writeln!(out, "{}", tags.join("\t"))?;
+        }
+        Command::Untag { path, tags } => {
+            let tags = loader.untag_image_by_path(&path, &tags)?;
+            writeln!(out, "{}", tags.join("\t"))?;
+        }
+        Command::Tags { path: Some(path) } => {
+            for tag in loader.get_image_tags(&path)? {
+                writeln!(out, "{}", tag)?;
+            }
+        }
+        Command::Tags { path: None } => {
+            for (tag, count) in loader.get_tag_counts()? {
+                writeln!(out, "{}\t{}", tag, count)?;
+            }
+        }
+        Command::Tagged { tag } => {
+            for path in loader.get_tagged_image_paths(&tag)? {
+                writeln!(out, "{}", path)?;
+            }
+        }
+        Command::Export { file } => {
+            let document = loader.export_library()?;
+            library_io::write_document(&file, &document)?;
+            let images: usize = document.folders.iter().map(|f| f.images.len()).sum();
+            writeln!(
+                out,
+                "exported {} folder(s), {} image(s) to {}",
+                document.folders.len(),
+                images,
+                file.display()
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs one invocation and returns the process exit code: 0 on success, 1
+/// when the command failed, 2 on a usage error.
+pub fn run<I>(args: I, out: &mut dyn Write, err: &mut dyn Write) -> i32
+where
+    I: IntoIterator<Item = String>,
+{
+    let args: Vec<String> = args.into_iter().collect();
+    if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        let _ = write!(out, "{}", USAGE);
+        return if args.is_empty() { 2 } else { 0 };
+    }
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(message) => {
+            let _ = write!(err, "error: {}\n\n{}", message, USAGE);
+            return 2;
+        }
+    };
+
+    let result = resolve_db_path(&options)
+        .and_then(Db::open)
+        .map(ImageLoader::new)
+        .and_then(|loader| execute(&loader, options.command, out, err));
+    match result {
+        Ok(()) => 0,
+        Err(error) => {
+            let _ = writeln!(err, "error: {}", error.user_message());
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_args, run, Command, Options};
+    use crate::error::ImageMode;
+    use crate::test_support::unique_temp_root;
+
+    fn args(raw: &[&str]) -> Vec<String> {
+        raw.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn run_cli(raw: &[&str]) -> (i32, String, String) {
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let code = run(args(raw), &mut out, &mut err);
+        (
+            code,
+            String::from_utf8(out).expect("stdout should be utf-8"),
+            String::from_utf8(err).expect("stderr should be utf-8"),
+        )
+    }
+
+    #[test]
+    fn parse_args_reads_global_options_and_commands() {
+        assert_eq!(
+            parse_args(args(&["--profile", "figure", "random", "-n", "5"])),
+            Ok(Options {
+                data_dir: None,
+                profile: Some("figure".to_string()),
+                db: None,
+                command: Command::Random { count: 5 },
+            })
+        );
+        assert_eq!(
+            parse_args(args(&["hide", "/a/b.jpg", "--normal"])).map(|o| o.command),
+            Ok(Command::Hide {
+                path: "/a/b.jpg".to_string(),
+                mode: ImageMode::Normal,
+            })
+        );
+        assert!(parse_args(args(&["random", "-n", "0"])).is_err());
+        assert!(parse_args(args(&["index"])).is_err());
+        assert!(parse_args(args(&["folders", "extra"])).is_err());
+        assert!(parse_args(args(&["--db", "x", "--profile", "y", "folders"])).is_err());
+        assert_eq!(
+            parse_args(args(&["tag", "/a/b.jpg", "figure", "30s"])).map(|o| o.command),
+            Ok(Command::Tag {
+                path: "/a/b.jpg".to_string(),
+                tags: vec!["figure".to_string(), "30s".to_string()],
+            })
+        );
+        assert_eq!(
+            parse_args(args(&["tags"])).map(|o| o.command),
+            Ok(Command::Tags { path: None })
+        );
+        assert!(parse_args(args(&["tag", "/a/b.jpg"])).is_err());
+        assert!(parse_args(args(&["tagged"])).is_err());
+    }
+
+    #[test]
+    fn cli_indexes_picks_and_prints_history() {
+        let root = unique_temp_root("cli", "session");
+        let images = root.join("images");
+        for idx in 0..3u8 {
+            let path = images.join(format!("img_{idx}.jpg"));
+            std::fs::create_dir_all(&images).expect("image folder should be created");
+            std::fs::write(&path, [idx, idx, idx]).expect("test image should be written");
+        }
+        let data_dir = root.join("data");
+        let data_dir = data_dir.to_string_lossy().into_owned();
+        let images = images.to_string_lossy().into_owned();
+
+        let (code, out, _) = run_cli(&["--data-dir", &data_dir, "index", &images]);
+        assert_eq!(code, 0);
+        let root_path = out.trim().to_string();
+
+        let (code, out, _) = run_cli(&["--data-dir", &data_dir, "random", "-n", "2"]);
+        assert_eq!(code, 0);
+        let picks: Vec<&str> = out.lines().collect();
+        assert_eq!(picks.len(), 2);
+        assert!(picks.iter().all(|pick| pick.starts_with(&root_path)));
+
+        let (code, out, _) = run_cli(&["--data-dir", &data_dir, "history"]);
+        assert_eq!(code, 0);
+        let history: Vec<&str> = out.lines().collect();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1], format!("> {}", picks[1]));
+
+        let (code, out, _) = run_cli(&["--data-dir", &data_dir, "folders"]);
+        assert_eq!(code, 0);
+        assert_eq!(out, format!("{}\t3\tonline\n", root_path));
+
+        let (code, out, _) = run_cli(&["--data-dir", &data_dir, "tag", picks[0], "figure", "30s"]);
+        assert_eq!(code, 0);
+        assert_eq!(out, "30s\tfigure\n");
+        let (code, out, _) = run_cli(&["--data-dir", &data_dir, "tagged", "Figure"]);
+        assert_eq!(code, 0);
+        assert_eq!(out, format!("{}\n", picks[0]));
+        let (code, out, _) = run_cli(&["--data-dir", &data_dir, "tags"]);
+        assert_eq!(code, 0);
+        assert_eq!(out, "30s\t1\nfigure\t1\n");
+
+        let (code, _, err) = run_cli(&["--data-dir", &data_dir, "--profile", "nope", "folders"]);
+        assert_eq!(code, 1);
+        assert!(err.contains("profile not found"));
+
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+}
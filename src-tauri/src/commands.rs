@@ -1,166 +1,108 @@
+use crate::backup::BackupReason;
+use crate::db::Db;
+use crate::error::{ImageMode, LoaderError};
+use crate::img_loader::{ImageLoader, NormalSortOrder, SearchMode};
+use crate::indexing::{IndexingJob, IndexingStatus};
+use crate::instance::LaunchAction;
+use crate::library_io::{self, PathMapping};
+#[cfg(target_os = "linux")]
+use crate::mpris::MprisPlayer;
+use crate::profiles::{Profiles, DEFAULT_PROFILE};
+use crate::remote::{self, RemoteHost, RemoteHub, RemoteServer, TimerCommand};
+use crate::types::{
+    BackupInfo, FolderHistory, FolderInfo, FolderTreeNode, ImageHistory, ImageHistoryWindow,
+    ImageSearchResults, ImageState, IndexingSettings, IntegrityReport, LibraryTransferSummary,
+    ProfileInfo, RandomSelectionSettings, RemoteSettings, TimerStatus,
+};
 use rodio::{
     source::{SineWave, Source},
     OutputStreamBuilder, Sink,
 };
-use crate::img_loader::ImageLoader;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use std::cell::RefCell;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
-use tauri::{AppHandle, Emitter, State};
+use tauri::{AppHandle, Emitter, Manager, State};
 
 pub type ImageLoaderState = Arc<RwLock<Option<Arc<ImageLoader>>>>;
+pub type ProfilesState = Arc<Mutex<Profiles>>;
+pub type RemoteHubState = Arc<RemoteHub>;
+/// The remote-control server while it is enabled.
+pub type RemoteServerState = Arc<Mutex<Option<RemoteServer>>>;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct FolderInfo {
-    pub id: i64,
-    pub path: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ImageHistory {
-    pub history: Vec<ImageHistoryItem>,
-    #[serde(rename = "currentIndex")]
-    pub current_index: i64,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ImageHistoryItem {
-    #[serde(rename = "imageId")]
-    pub image_id: i64,
-    #[serde(rename = "orderIndex")]
-    pub order_index: i64,
-    pub path: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct FolderHistoryItem {
-    pub id: i64,
-    pub path: String,
-    #[serde(rename = "imageCount")]
-    pub image_count: i64,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct FolderHistory {
-    pub history: Vec<FolderHistoryItem>,
-    #[serde(rename = "currentIndex")]
-    pub current_index: i64,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct FolderTreeNode {
-    pub path: String,
-    #[serde(rename = "parentPath")]
-    pub parent_path: Option<String>,
-    #[serde(rename = "imageCount")]
-    pub image_count: i64,
-    pub checked: bool,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ImageState {
-    #[serde(rename = "verticalMirror")]
-    pub vertical_mirror: bool,
-    #[serde(rename = "horizontalMirror")]
-    pub horizontal_mirror: bool,
-    pub greyscale: bool,
-    #[serde(rename = "timerFlowMode")]
-    pub timer_flow_mode: String,
-    #[serde(rename = "showFolderHistoryPanel")]
-    pub show_folder_history_panel: bool,
-    #[serde(rename = "showTopControls")]
-    pub show_top_controls: bool,
-    #[serde(rename = "showImageHistoryPanel")]
-    pub show_image_history_panel: bool,
-    #[serde(rename = "showBottomControls")]
-    pub show_bottom_controls: bool,
-    #[serde(rename = "isFullscreenImage")]
-    pub is_fullscreen_image: bool,
-    #[serde(rename = "shortcutHintsVisible")]
-    pub shortcut_hints_visible: bool,
-    #[serde(rename = "shortcutHintSide")]
-    pub shortcut_hint_side: String,
-}
-
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ImageResponse {
     pub data: Vec<u8>,
     pub folder: Option<FolderInfo>,
     pub auto_switched_folder: bool,
 }
 
+/// The folder a pick settled on; `auto_switched_folder` is set when the
+/// picked root was offline and another folder was switched to.
+#[derive(Debug, Clone, Serialize)]
+pub struct PickedFolder {
+    #[serde(flatten)]
+    pub folder: FolderInfo,
+    pub auto_switched_folder: bool,
+}
+
+/// Error returned to the frontend. `code` is stable and machine-readable;
+/// `message` is human-readable and kept for display.
 #[derive(Debug, Serialize)]
 pub struct CommandError {
+    pub code: String,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
 }
 
-impl From<Box<dyn std::error::Error>> for CommandError {
-    fn from(err: Box<dyn std::error::Error>) -> Self {
-        let raw = err.to_string();
+impl From<LoaderError> for CommandError {
+    fn from(err: LoaderError) -> Self {
         Self {
-            message: sanitize_error_message(&raw),
+            code: err.code().to_string(),
+            message: err.user_message(),
+            details: err.details(),
         }
     }
 }
 
 impl From<rusqlite::Error> for CommandError {
     fn from(err: rusqlite::Error) -> Self {
-        let raw = err.to_string();
-        Self {
-            message: sanitize_error_message(&raw),
-        }
+        LoaderError::from(err).into()
     }
 }
 
 impl CommandError {
     fn internal() -> Self {
         Self {
+            code: "internal".to_string(),
             message: "internal error".to_string(),
+            details: None,
         }
     }
 
-    fn invalid(message: &str) -> Self {
+    fn not_initialized() -> Self {
         Self {
-            message: message.to_string(),
+            code: "not_initialized".to_string(),
+            message: "ImageLoader not initialized".to_string(),
+            details: None,
         }
     }
-}
 
-fn sanitize_error_message(raw: &str) -> String {
-    // Explicit error mapping for known issues
-    if raw.contains("FOREIGN KEY constraint failed") {
-        return "database constraint failed - try resetting history or reindexing".to_string();
-    }
-    if raw.contains("query returned no rows") {
-        return "no data found - folder or image may have been deleted".to_string();
-    }
-    if raw.contains("all images for this folder are hidden") {
-        return "all images are hidden for this folder and mode - reindex to clear hidden images"
-            .to_string();
-    }
-    if raw.contains("No folders selected. Check at least one folder.") {
-        return "No folders selected. Check at least one folder.".to_string();
+    fn invalid(message: &str) -> Self {
+        Self {
+            code: "invalid_argument".to_string(),
+            message: message.to_string(),
+            details: None,
+        }
     }
-    // Pass through all other errors as-is
-    raw.to_string()
 }
 
 #[tauri::command]
 pub async fn get_folder_tree(
     state: State<'_, ImageLoaderState>,
 ) -> Result<Vec<FolderTreeNode>, CommandError> {
-    let loader = get_loader(&state)?;
-    let nodes = loader.get_folder_tree()?;
-    Ok(nodes
-        .into_iter()
-        .map(|(path, parent_path, image_count, checked)| FolderTreeNode {
-            path,
-            parent_path,
-            image_count,
-            checked,
-        })
-        .collect())
+    with_loader(&state, move |loader| Ok(loader.get_folder_tree()?)).await
 }
 
 #[tauri::command]
@@ -169,9 +111,11 @@ pub async fn set_folder_checked(
     checked: bool,
     state: State<'_, ImageLoaderState>,
 ) -> Result<(), CommandError> {
-    let loader = get_loader(&state)?;
-    loader.set_folder_checked(&path, checked)?;
-    Ok(())
+    with_loader(&state, move |loader| {
+        loader.set_folder_checked(&path, checked)?;
+        Ok(())
+    })
+    .await
 }
 
 #[tauri::command]
@@ -179,9 +123,11 @@ pub async fn set_folder_exclusive(
     path: String,
     state: State<'_, ImageLoaderState>,
 ) -> Result<(), CommandError> {
-    let loader = get_loader(&state)?;
-    loader.set_folder_exclusive(&path)?;
-    Ok(())
+    with_loader(&state, move |loader| {
+        loader.set_folder_exclusive(&path)?;
+        Ok(())
+    })
+    .await
 }
 
 fn get_loader(state: &State<ImageLoaderState>) -> Result<Arc<ImageLoader>, CommandError> {
@@ -190,9 +136,49 @@ fn get_loader(state: &State<ImageLoaderState>) -> Result<Arc<ImageLoader>, Comma
         .map_err(|_| CommandError::internal())?
         .as_ref()
         .map(Arc::clone)
-        .ok_or_else(|| CommandError {
-            message: "ImageLoader not initialized".to_string(),
+        .ok_or_else(CommandError::not_initialized)
+}
+
+/// Runs loader work on the blocking thread pool. SQLite access, directory walks
+/// and image reads all block, so they must stay off the async executor.
+async fn with_loader<T, F>(state: &State<'_, ImageLoaderState>, f: F) -> Result<T, CommandError>
+where
+    T: Send + 'static,
+    F: FnOnce(&ImageLoader) -> Result<T, CommandError> + Send + 'static,
+{
+    let loader = get_loader(state)?;
+    tauri::async_runtime::spawn_blocking(move || f(&loader))
+        .await
+        .map_err(|_| CommandError::internal())?
+}
+
+/// Runs profile work on the blocking thread pool under the profiles lock, so
+/// changes to the profile directories never interleave.
+async fn with_profiles<T, F>(profiles: &State<'_, ProfilesState>, f: F) -> Result<T, CommandError>
+where
+    T: Send + 'static,
+    F: FnOnce(&Profiles) -> Result<T, CommandError> + Send + 'static,
+{
+    let profiles = Arc::clone(profiles);
+    tauri::async_runtime::spawn_blocking(move || {
+        let profiles = profiles.lock().map_err(|_| CommandError::internal())?;
+        f(&profiles)
+    })
+    .await
+    .map_err(|_| CommandError::internal())?
+}
+
+fn profile_infos(profiles: &Profiles) -> Result<Vec<ProfileInfo>, CommandError> {
+    let active = profiles.active()?;
+    Ok(profiles
+        .list()?
+        .into_iter()
+        .map(|name| ProfileInfo {
+            active: name == active,
+            is_default: name == DEFAULT_PROFILE,
+            name,
         })
+        .collect())
 }
 
 fn resolve_dual_i64_arg(
@@ -201,87 +187,281 @@ fn resolve_dual_i64_arg(
     snake_name: &str,
     camel_name: &str,
 ) -> Result<i64, CommandError> {
-    snake_case.or(camel_case).ok_or_else(|| CommandError {
-        message: format!("missing {camel_name}/{snake_name}"),
+    snake_case
+        .or(camel_case)
+        .ok_or_else(|| CommandError::invalid(&format!("missing {camel_name}/{snake_name}")))
+}
+
+/// Registers an indexing job whose typed progress is emitted as
+/// `indexing-progress`.
+fn start_indexing_job(loader: &ImageLoader, app: &AppHandle) -> Arc<IndexingJob> {
+    let app = app.clone();
+    loader.indexing_jobs().start(move |progress| {
+        let _ = app.emit("indexing-progress", progress.clone());
     })
 }
 
+/// Unregisters the job and emits its `indexing-summary`.
+fn finish_indexing_job<T>(
+    loader: &ImageLoader,
+    app: &AppHandle,
+    job: &IndexingJob,
+    result: &Result<T, LoaderError>,
+) {
+    loader.indexing_jobs().finish(job.id());
+    let summary = match result {
+        Ok(_) => job.summary(IndexingStatus::Completed, None),
+        Err(LoaderError::Cancelled) => job.summary(IndexingStatus::Cancelled, None),
+        Err(err) => job.summary(IndexingStatus::Failed, Some(err.user_message())),
+    };
+    let _ = app.emit("indexing-summary", summary);
+}
+
+/// Opens a loader whose shown images feed the remote-control hub.
+pub fn open_loader(db: Db, hub: &Arc<RemoteHub>) -> Arc<ImageLoader> {
+    let loader = ImageLoader::new(db);
+    let hub = Arc::clone(hub);
+    loader.set_image_listener(Some(Box::new(move |image_id, path| {
+        hub.image_shown(image_id, path)
+    })));
+    Arc::new(loader)
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct RemoteImageSelected {
+    mode: &'static str,
+    image: ImageResponse,
+}
+
+/// The active profile's loader, for code running outside a command.
+fn current_loader(app: &AppHandle) -> Option<Arc<ImageLoader>> {
+    let state = app.state::<ImageLoaderState>();
+    let slot = state.read().ok()?;
+    slot.as_ref().map(Arc::clone)
+}
+
+/// Tells the window to show an image the backend already moved to.
+fn emit_image_selected(
+    app: &AppHandle,
+    mode: ImageMode,
+    data: Vec<u8>,
+    auto_switched_folder: bool,
+) {
+    let folder = current_loader(app)
+        .and_then(|loader| loader.get_current_folder_id_and_path().ok().flatten())
+        .map(|(id, path)| FolderInfo { id, path });
+    let _ = app.emit(
+        "remote-image-selected",
+        RemoteImageSelected {
+            mode: mode.as_str(),
+            image: ImageResponse {
+                data,
+                folder,
+                auto_switched_folder,
+            },
+        },
+    );
+}
+
+/// Connects the remote-control server and the MPRIS player to the window:
+/// images they pick are emitted as `remote-image-selected` and timer actions
+/// as `remote-timer`.
+struct DesktopRemoteHost {
+    app: AppHandle,
+}
+
+impl RemoteHost for DesktopRemoteHost {
+    fn loader(&self) -> Option<Arc<ImageLoader>> {
+        current_loader(&self.app)
+    }
+
+    fn image_selected(&self, mode: ImageMode, data: Vec<u8>, auto_switched_folder: bool) {
+        emit_image_selected(&self.app, mode, data, auto_switched_folder);
+    }
+
+    fn timer_command(&self, command: TimerCommand) {
+        let _ = self.app.emit("remote-timer", command.as_str());
+    }
+
+    fn raise(&self) {
+        focus_main_window(&self.app);
+    }
+}
+
+fn focus_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct LaunchFolderFinished {
+    folder: Option<PickedFolder>,
+    error: Option<String>,
+}
+
+/// Carries out what a launch asked for on the command line. The backend acts
+/// and the window follows through the same events the remote-control server
+/// uses; opening a folder is bracketed by `launch-folder-started` and
+/// `launch-folder-finished`.
+pub fn handle_launch_actions(app: &AppHandle, actions: Vec<LaunchAction>) {
+    for action in actions {
+        if let Err(err) = handle_launch_action(app, action) {
+            eprintln!("[RUST] Launch action failed: {}", err.message);
+            let _ = app.emit("launch-error", err.message);
+        }
+    }
+}
+
+fn handle_launch_action(app: &AppHandle, action: LaunchAction) -> Result<(), CommandError> {
+    let loader = current_loader(app).ok_or_else(CommandError::not_initialized)?;
+    // Opening something is a request for the window; stepping is not.
+    if matches!(
+        action,
+        LaunchAction::OpenFolder(_) | LaunchAction::OpenImage(_)
+    ) {
+        focus_main_window(app);
+    }
+    match action {
+        LaunchAction::OpenFolder(path) => {
+            if loader.indexing_jobs().is_running() {
+                return Err(CommandError::invalid("indexing is already running"));
+            }
+            let _ = app.emit("launch-folder-started", path.clone());
+            let _ = app.emit("indexing-log", format!("folder:{}", path));
+            let job = start_indexing_job(&loader, app);
+            let result = loader.set_current_folder_and_index_with_progress(&path, &job, |line| {
+                let _ = app.emit("indexing-log", line);
+            });
+            finish_indexing_job(&loader, app, &job, &result);
+            let finished = match result {
+                Ok((id, path, auto_switched_folder)) => LaunchFolderFinished {
+                    folder: Some(PickedFolder {
+                        folder: FolderInfo { id, path },
+                        auto_switched_folder,
+                    }),
+                    error: None,
+                },
+                Err(err) => LaunchFolderFinished {
+                    folder: None,
+                    error: Some(err.user_message()),
+                },
+            };
+            let _ = app.emit("launch-folder-finished", finished);
+        }
+        LaunchAction::OpenImage(path) => {
+            let (data, auto_switched) = loader.open_image_by_path(&path, false)?;
+            emit_image_selected(app, ImageMode::Normal, data, auto_switched);
+        }
+        LaunchAction::Next | LaunchAction::Prev => {
+            if loader.indexing_jobs().is_running() {
+                return Err(CommandError::invalid("indexing is running"));
+            }
+            // Step in whichever mode the timer flows in.
+            let mode = loader.get_timer_flow_mode()?;
+            let next = action == LaunchAction::Next;
+            let (data, auto_switched) = match (mode, next) {
+                (ImageMode::Normal, true) => loader.get_next_image()?,
+                (ImageMode::Normal, false) => loader.get_prev_image()?,
+                (ImageMode::Random, true) => loader.get_next_random_image()?,
+                (ImageMode::Random, false) => loader.get_prev_random_image()?,
+            };
+            emit_image_selected(app, mode, data, auto_switched);
+        }
+        LaunchAction::Pause => {
+            let _ = app.emit("remote-timer", TimerCommand::Pause.as_str());
+        }
+        LaunchAction::Resume => {
+            let _ = app.emit("remote-timer", TimerCommand::Resume.as_str());
+        }
+    }
+    Ok(())
+}
+
+/// Stops the running server, if any, and starts a new one when enabled.
+pub fn apply_remote_settings(
+    app: &AppHandle,
+    settings: &RemoteSettings,
+) -> Result<(), CommandError> {
+    let servers = app.state::<RemoteServerState>();
+    let mut slot = servers.lock().map_err(|_| CommandError::internal())?;
+    // Free the port before binding it again.
+    *slot = None;
+    if settings.enabled {
+        let hub = Arc::clone(&app.state::<RemoteHubState>());
+        let host = Arc::new(DesktopRemoteHost { app: app.clone() });
+        *slot = Some(RemoteServer::start(settings, hub, host)?);
+    }
+    Ok(())
+}
+
+/// Puts the app on the session bus as an MPRIS player driving the window.
+#[cfg(target_os = "linux")]
+pub fn start_mpris_player(app: &AppHandle) -> Result<MprisPlayer, CommandError> {
+    let hub = Arc::clone(&app.state::<RemoteHubState>());
+    let host = Arc::new(DesktopRemoteHost { app: app.clone() });
+    Ok(MprisPlayer::start(hub, host)?)
+}
+
 #[tauri::command]
 pub async fn pick_folder(
     path: String,
     app: AppHandle,
     state: State<'_, ImageLoaderState>,
-) -> Result<FolderInfo, CommandError> {
-    let loader = get_loader(&state)?;
-    let _ = app.emit("indexing-log", format!("folder:{}", path));
-    let (id, folder_path) = loader
-        .set_current_folder_and_index_with_progress(&path, |line| {
+) -> Result<PickedFolder, CommandError> {
+    with_loader(&state, move |loader| {
+        if loader.indexing_jobs().is_running() {
+            return Err(CommandError::invalid("indexing is already running"));
+        }
+        let _ = app.emit("indexing-log", format!("folder:{}", path));
+        let job = start_indexing_job(loader, &app);
+        let result = loader.set_current_folder_and_index_with_progress(&path, &job, |line| {
             let _ = app.emit("indexing-log", line);
+        });
+        finish_indexing_job(loader, &app, &job, &result);
+        let (id, folder_path, auto_switched_folder) = result?;
+        Ok(PickedFolder {
+            folder: FolderInfo {
+                id,
+                path: folder_path,
+            },
+            auto_switched_folder,
         })
-        .await?;
-    Ok(FolderInfo {
-        id,
-        path: folder_path,
     })
+    .await
 }
 
 #[tauri::command]
 pub async fn next_folder(
     state: State<'_, ImageLoaderState>,
 ) -> Result<Option<FolderInfo>, CommandError> {
-    let loader = get_loader(&state)?;
-    match loader.get_next_folder()? {
+    with_loader(&state, move |loader| match loader.get_next_folder()? {
         Some((id, path)) => Ok(Some(FolderInfo { id, path })),
         None => Ok(None),
-    }
+    })
+    .await
 }
 
 #[tauri::command]
 pub async fn prev_folder(
     state: State<'_, ImageLoaderState>,
 ) -> Result<Option<FolderInfo>, CommandError> {
-    let loader = get_loader(&state)?;
-    match loader.get_prev_folder()? {
+    with_loader(&state, move |loader| match loader.get_prev_folder()? {
         Some((id, path)) => Ok(Some(FolderInfo { id, path })),
         None => Ok(None),
-    }
+    })
+    .await
 }
 
 #[tauri::command]
 pub async fn get_folder_history(
     state: State<'_, ImageLoaderState>,
 ) -> Result<FolderHistory, CommandError> {
-    let loader = get_loader(&state)?;
-    let history = loader.get_folder_history()?;
-
-    let current_index = if history.is_empty() {
-        -1
-    } else {
-        let current_id = loader.get_current_folder_id()?;
-
-        match current_id {
-            Some(id) => history
-                .iter()
-                .position(|(fid, _, _, _)| *fid == id)
-                .map(|index| index as i64)
-                .unwrap_or(-1),
-            None => -1,
-        }
-    };
-
-    let items: Vec<FolderHistoryItem> = history
-        .into_iter()
-        .map(|(id, path, _, image_count)| FolderHistoryItem {
-            id,
-            path,
-            image_count,
-        })
-        .collect();
-
-    Ok(FolderHistory {
-        history: items,
-        current_index,
+    with_loader(&state, move |loader| {
+        Ok(loader.get_folder_history_with_status()?)
     })
+    .await
 }
 
 #[tauri::command]
@@ -289,179 +469,232 @@ pub async fn reindex_current_folder(
     app: AppHandle,
     state: State<'_, ImageLoaderState>,
 ) -> Result<FolderInfo, CommandError> {
-    let loader = get_loader(&state)?;
-    let _ = app.emit("indexing-log", "reindex:start".to_string());
-    let (id, path) = loader
-        .reindex_current_folder_with_progress(|line| {
+    with_loader(&state, move |loader| {
+        if loader.indexing_jobs().is_running() {
+            return Err(CommandError::invalid("indexing is already running"));
+        }
+        let _ = app.emit("indexing-log", "reindex:start".to_string());
+        let job = start_indexing_job(loader, &app);
+        let result = loader.reindex_current_folder_with_progress(&job, |line| {
             let _ = app.emit("indexing-log", line);
-        })
-        .await?;
-    Ok(FolderInfo { id, path })
+        });
+        finish_indexing_job(loader, &app, &job, &result);
+        let (id, path) = result?;
+        Ok(FolderInfo { id, path })
+    })
+    .await
+}
+
+/// Cancels the given indexing job, or every running job when no id is passed.
+/// Returns how many jobs were signalled.
+#[tauri::command]
+pub async fn cancel_indexing(
+    job_id: Option<u64>,
+    #[allow(non_snake_case)] jobId: Option<u64>,
+    state: State<'_, ImageLoaderState>,
+) -> Result<usize, CommandError> {
+    with_loader(&state, move |loader| {
+        Ok(loader.indexing_jobs().cancel(job_id.or(jobId)))
+    })
+    .await
 }
 
 #[tauri::command]
 pub async fn get_current_image(
     state: State<'_, ImageLoaderState>,
 ) -> Result<ImageResponse, CommandError> {
-    let loader = get_loader(&state)?;
-    let (data, auto_switched) = loader
-        .get_current_image_or_first()
-        .await
-        .map_err(CommandError::from)?;
-    let folder = loader
-        .get_current_folder_id_and_path()
-        .ok()
-        .flatten()
-        .map(|(id, path)| FolderInfo { id, path });
-    Ok(ImageResponse {
-        data,
-        folder,
-        auto_switched_folder: auto_switched,
+    with_loader(&state, move |loader| {
+        let (data, auto_switched) = loader
+            .get_current_image_or_first()
+            .map_err(CommandError::from)?;
+        let folder = loader
+            .get_current_folder_id_and_path()
+            .ok()
+            .flatten()
+            .map(|(id, path)| FolderInfo { id, path });
+        Ok(ImageResponse {
+            data,
+            folder,
+            auto_switched_folder: auto_switched,
+        })
     })
+    .await
 }
 
 #[tauri::command]
 pub async fn get_current_random_image(
     state: State<'_, ImageLoaderState>,
 ) -> Result<ImageResponse, CommandError> {
-    let loader = get_loader(&state)?;
-    let (data, auto_switched) = loader
-        .get_current_random_image_or_last()
-        .await
-        .map_err(CommandError::from)?;
-    let folder = loader
-        .get_current_folder_id_and_path()
-        .ok()
-        .flatten()
-        .map(|(id, path)| FolderInfo { id, path });
-    Ok(ImageResponse {
-        data,
-        folder,
-        auto_switched_folder: auto_switched,
+    with_loader(&state, move |loader| {
+        let (data, auto_switched) = loader
+            .get_current_random_image_or_last()
+            .map_err(CommandError::from)?;
+        let folder = loader
+            .get_current_folder_id_and_path()
+            .ok()
+            .flatten()
+            .map(|(id, path)| FolderInfo { id, path });
+        Ok(ImageResponse {
+            data,
+            folder,
+            auto_switched_folder: auto_switched,
+        })
     })
+    .await
 }
 
 #[tauri::command]
 pub async fn get_next_image(
     state: State<'_, ImageLoaderState>,
 ) -> Result<ImageResponse, CommandError> {
-    let loader = get_loader(&state)?;
-    let (data, auto_switched) = loader.get_next_image().await.map_err(CommandError::from)?;
-    let folder = loader
-        .get_current_folder_id_and_path()
-        .ok()
-        .flatten()
-        .map(|(id, path)| FolderInfo { id, path });
-    Ok(ImageResponse {
-        data,
-        folder,
-        auto_switched_folder: auto_switched,
+    with_loader(&state, move |loader| {
+        let (data, auto_switched) = loader.get_next_image().map_err(CommandError::from)?;
+        let folder = loader
+            .get_current_folder_id_and_path()
+            .ok()
+            .flatten()
+            .map(|(id, path)| FolderInfo { id, path });
+        Ok(ImageResponse {
+            data,
+            folder,
+            auto_switched_folder: auto_switched,
+        })
     })
+    .await
 }
 
 #[tauri::command]
 pub async fn get_prev_image(
     state: State<'_, ImageLoaderState>,
 ) -> Result<ImageResponse, CommandError> {
-    let loader = get_loader(&state)?;
-    let (data, auto_switched) = loader.get_prev_image().await.map_err(CommandError::from)?;
-    let folder = loader
-        .get_current_folder_id_and_path()
-        .ok()
-        .flatten()
-        .map(|(id, path)| FolderInfo { id, path });
-    Ok(ImageResponse {
-        data,
-        folder,
-        auto_switched_folder: auto_switched,
+    with_loader(&state, move |loader| {
+        let (data, auto_switched) = loader.get_prev_image().map_err(CommandError::from)?;
+        let folder = loader
+            .get_current_folder_id_and_path()
+            .ok()
+            .flatten()
+            .map(|(id, path)| FolderInfo { id, path });
+        Ok(ImageResponse {
+            data,
+            folder,
+            auto_switched_folder: auto_switched,
+        })
     })
+    .await
 }
 
 #[tauri::command]
 pub async fn get_next_random_image(
     state: State<'_, ImageLoaderState>,
 ) -> Result<ImageResponse, CommandError> {
-    let loader = get_loader(&state)?;
-    let (data, auto_switched) = loader
-        .get_next_random_image()
-        .await
-        .map_err(CommandError::from)?;
-    let folder = loader
-        .get_current_folder_id_and_path()
-        .ok()
-        .flatten()
-        .map(|(id, path)| FolderInfo { id, path });
-    Ok(ImageResponse {
-        data,
-        folder,
-        auto_switched_folder: auto_switched,
+    with_loader(&state, move |loader| {
+        let (data, auto_switched) = loader.get_next_random_image().map_err(CommandError::from)?;
+        let folder = loader
+            .get_current_folder_id_and_path()
+            .ok()
+            .flatten()
+            .map(|(id, path)| FolderInfo { id, path });
+        Ok(ImageResponse {
+            data,
+            folder,
+            auto_switched_folder: auto_switched,
+        })
     })
+    .await
 }
 
 #[tauri::command]
 pub async fn get_prev_random_image(
     state: State<'_, ImageLoaderState>,
 ) -> Result<ImageResponse, CommandError> {
-    let loader = get_loader(&state)?;
-    let (data, auto_switched) = loader
-        .get_prev_random_image()
-        .await
-        .map_err(CommandError::from)?;
-    let folder = loader
-        .get_current_folder_id_and_path()
-        .ok()
-        .flatten()
-        .map(|(id, path)| FolderInfo { id, path });
-    Ok(ImageResponse {
-        data,
-        folder,
-        auto_switched_folder: auto_switched,
+    with_loader(&state, move |loader| {
+        let (data, auto_switched) = loader.get_prev_random_image().map_err(CommandError::from)?;
+        let folder = loader
+            .get_current_folder_id_and_path()
+            .ok()
+            .flatten()
+            .map(|(id, path)| FolderInfo { id, path });
+        Ok(ImageResponse {
+            data,
+            folder,
+            auto_switched_folder: auto_switched,
+        })
     })
+    .await
 }
 
 #[tauri::command]
 pub async fn get_force_random_image(
     state: State<'_, ImageLoaderState>,
 ) -> Result<ImageResponse, CommandError> {
-    let loader = get_loader(&state)?;
-    let (data, auto_switched) = loader
-        .get_force_random_image(true)
-        .await
-        .map_err(CommandError::from)?;
-    let folder = loader
-        .get_current_folder_id_and_path()
-        .ok()
-        .flatten()
-        .map(|(id, path)| FolderInfo { id, path });
-    Ok(ImageResponse {
-        data,
-        folder,
-        auto_switched_folder: auto_switched,
+    with_loader(&state, move |loader| {
+        let (data, auto_switched) = loader
+            .get_force_random_image(true)
+            .map_err(CommandError::from)?;
+        let folder = loader
+            .get_current_folder_id_and_path()
+            .ok()
+            .flatten()
+            .map(|(id, path)| FolderInfo { id, path });
+        Ok(ImageResponse {
+            data,
+            folder,
+            auto_switched_folder: auto_switched,
+        })
     })
+    .await
 }
 
 #[tauri::command]
 pub async fn get_normal_history(
     state: State<'_, ImageLoaderState>,
 ) -> Result<ImageHistory, CommandError> {
-    let loader = get_loader(&state)?;
-    let (history, current_index) = loader.get_normal_history()?;
-    Ok(ImageHistory {
-        history,
-        current_index,
+    with_loader(&state, move |loader| {
+        let (history, current_index) = loader.get_normal_history()?;
+        Ok(ImageHistory {
+            history,
+            current_index,
+        })
     })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_normal_history_window(
+    offset: Option<i64>,
+    limit: Option<i64>,
+    state: State<'_, ImageLoaderState>,
+) -> Result<ImageHistoryWindow, CommandError> {
+    with_loader(&state, move |loader| {
+        Ok(loader.get_normal_history_window(offset, limit.unwrap_or(200))?)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_random_history_window(
+    offset: Option<i64>,
+    limit: Option<i64>,
+    state: State<'_, ImageLoaderState>,
+) -> Result<ImageHistoryWindow, CommandError> {
+    with_loader(&state, move |loader| {
+        Ok(loader.get_random_history_window(offset, limit.unwrap_or(200))?)
+    })
+    .await
 }
 
 #[tauri::command]
 pub async fn get_random_history(
     state: State<'_, ImageLoaderState>,
 ) -> Result<ImageHistory, CommandError> {
-    let loader = get_loader(&state)?;
-    let (history, current_index) = loader.get_random_history()?;
-    Ok(ImageHistory {
-        history,
-        current_index,
+    with_loader(&state, move |loader| {
+        let (history, current_index) = loader.get_random_history()?;
+        Ok(ImageHistory {
+            history,
+            current_index,
+        })
     })
+    .await
 }
 
 #[tauri::command]
@@ -471,9 +704,11 @@ pub async fn hide_normal_history_image(
     state: State<'_, ImageLoaderState>,
 ) -> Result<(), CommandError> {
     let image_id = resolve_dual_i64_arg(image_id, imageId, "image_id", "imageId")?;
-    let loader = get_loader(&state)?;
-    loader.hide_normal_history_image(image_id)?;
-    Ok(())
+    with_loader(&state, move |loader| {
+        loader.hide_normal_history_image(image_id)?;
+        Ok(())
+    })
+    .await
 }
 
 #[tauri::command]
@@ -483,31 +718,39 @@ pub async fn hide_random_history_image(
     state: State<'_, ImageLoaderState>,
 ) -> Result<(), CommandError> {
     let image_id = resolve_dual_i64_arg(image_id, imageId, "image_id", "imageId")?;
-    let loader = get_loader(&state)?;
-    loader.hide_random_history_image(image_id)?;
-    Ok(())
+    with_loader(&state, move |loader| {
+        loader.hide_random_history_image(image_id)?;
+        Ok(())
+    })
+    .await
 }
 
 #[tauri::command]
 pub async fn reset_normal_history(state: State<'_, ImageLoaderState>) -> Result<(), CommandError> {
-    let loader = get_loader(&state)?;
-    loader.reset_normal_history()?;
-    Ok(())
+    with_loader(&state, move |loader| {
+        loader.reset_normal_history()?;
+        Ok(())
+    })
+    .await
 }
 
 #[tauri::command]
 pub async fn reset_random_history(state: State<'_, ImageLoaderState>) -> Result<(), CommandError> {
-    let loader = get_loader(&state)?;
-    loader.reset_random_history()?;
-    Ok(())
+    with_loader(&state, move |loader| {
+        loader.reset_random_history()?;
+        Ok(())
+    })
+    .await
 }
 
 #[tauri::command]
 pub async fn get_image_state(
     state: State<'_, ImageLoaderState>,
 ) -> Result<ImageState, CommandError> {
-    let loader = get_loader(&state)?;
-    loader.get_image_state().map_err(Into::into)
+    with_loader(&state, move |loader| {
+        loader.get_image_state().map_err(Into::into)
+    })
+    .await
 }
 
 #[tauri::command]
@@ -515,26 +758,302 @@ pub async fn set_image_state(
     state: ImageState,
     loader_state: State<'_, ImageLoaderState>,
 ) -> Result<(), CommandError> {
-    let loader = get_loader(&loader_state)?;
-    if state.timer_flow_mode != "normal" && state.timer_flow_mode != "random" {
-        return Err(CommandError::invalid("invalid timer flow mode"));
-    }
-    loader.set_image_state(&state)?;
-    Ok(())
+    with_loader(&loader_state, move |loader| {
+        if state.timer_flow_mode != "normal" && state.timer_flow_mode != "random" {
+            return Err(CommandError::invalid("invalid timer flow mode"));
+        }
+        loader.set_image_state(&state)?;
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_random_selection_settings(
+    state: State<'_, ImageLoaderState>,
+) -> Result<RandomSelectionSettings, CommandError> {
+    with_loader(&state, move |loader| {
+        loader.get_random_selection_settings().map_err(Into::into)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn set_random_selection_settings(
+    settings: RandomSelectionSettings,
+    state: State<'_, ImageLoaderState>,
+) -> Result<(), CommandError> {
+    with_loader(&state, move |loader| {
+        if settings.mode != "lap" && settings.mode != "spaced" {
+            return Err(CommandError::invalid("invalid random selection mode"));
+        }
+        if !(0.0..=1.0).contains(&settings.spaced_randomness) {
+            return Err(CommandError::invalid(
+                "spaced randomness must be between 0 and 1",
+            ));
+        }
+        loader.set_random_selection_settings(&settings)?;
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_indexing_settings(
+    state: State<'_, ImageLoaderState>,
+) -> Result<IndexingSettings, CommandError> {
+    with_loader(&state, move |loader| {
+        loader.get_indexing_settings().map_err(Into::into)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn set_indexing_settings(
+    settings: IndexingSettings,
+    state: State<'_, ImageLoaderState>,
+) -> Result<(), CommandError> {
+    with_loader(&state, move |loader| {
+        loader.set_indexing_settings(&settings)?;
+        Ok(())
+    })
+    .await
 }
 
 #[tauri::command]
 pub async fn full_wipe(state: State<'_, ImageLoaderState>) -> Result<(), CommandError> {
-    let loader = get_loader(&state)?;
-    loader.full_wipe()?;
+    with_loader(&state, move |loader| {
+        loader.backup_database(BackupReason::FullWipe)?;
+        loader.full_wipe()?;
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn export_library(
+    path: String,
+    state: State<'_, ImageLoaderState>,
+) -> Result<LibraryTransferSummary, CommandError> {
+    with_loader(&state, move |loader| {
+        let document = loader.export_library()?;
+        library_io::write_document(std::path::Path::new(&path), &document)?;
+        Ok(LibraryTransferSummary {
+            folders: document.folders.len() as i64,
+            images: document
+                .folders
+                .iter()
+                .map(|folder| folder.images.len() as i64)
+                .sum(),
+            skipped_references: 0,
+        })
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn import_library(
+    path: String,
+    mappings: Option<Vec<PathMapping>>,
+    state: State<'_, ImageLoaderState>,
+) -> Result<LibraryTransferSummary, CommandError> {
+    with_loader(&state, move |loader| {
+        let document = library_io::read_document(std::path::Path::new(&path))?;
+        Ok(loader.import_library(document, &mappings.unwrap_or_default())?)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn list_backups(
+    state: State<'_, ImageLoaderState>,
+) -> Result<Vec<BackupInfo>, CommandError> {
+    with_loader(&state, move |loader| {
+        Ok(loader
+            .list_backups()?
+            .into_iter()
+            .map(BackupInfo::from)
+            .collect())
+    })
+    .await
+}
+
+/// Restores a snapshot and swaps in a loader opened on the restored file.
+/// The state lock is held for writing throughout, so no new command can
+/// reach the old loader; commands already running finish on it first
+/// because the restore waits for the shared connection.
+#[tauri::command]
+pub async fn restore_backup(
+    name: String,
+    state: State<'_, ImageLoaderState>,
+    hub: State<'_, RemoteHubState>,
+) -> Result<(), CommandError> {
+    let state = Arc::clone(&state);
+    let hub = Arc::clone(&hub);
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut slot = state.write().map_err(|_| CommandError::internal())?;
+        let loader = slot
+            .as_ref()
+            .map(Arc::clone)
+            .ok_or_else(CommandError::not_initialized)?;
+        let db = loader
+            .restore_backup(&name)
+            .and_then(Db::open)
+            .map_err(CommandError::from)?;
+        *slot = Some(open_loader(db, &hub));
+        Ok(())
+    })
+    .await
+    .map_err(|_| CommandError::internal())?
+}
+
+#[tauri::command]
+pub async fn list_profiles(
+    profiles: State<'_, ProfilesState>,
+) -> Result<Vec<ProfileInfo>, CommandError> {
+    with_profiles(&profiles, profile_infos).await
+}
+
+#[tauri::command]
+pub async fn create_profile(
+    name: String,
+    profiles: State<'_, ProfilesState>,
+) -> Result<Vec<ProfileInfo>, CommandError> {
+    with_profiles(&profiles, move |profiles| {
+        profiles.create(&name)?;
+        profile_infos(profiles)
+    })
+    .await
+}
+
+/// Swaps in a loader on the profile's database. The database is opened
+/// before anything changes, so a failure leaves the current profile active.
+/// Indexing running on the old profile is cancelled.
+#[tauri::command]
+pub async fn switch_profile(
+    name: String,
+    state: State<'_, ImageLoaderState>,
+    profiles: State<'_, ProfilesState>,
+    hub: State<'_, RemoteHubState>,
+) -> Result<Vec<ProfileInfo>, CommandError> {
+    let state = Arc::clone(&state);
+    let hub = Arc::clone(&hub);
+    with_profiles(&profiles, move |profiles| {
+        let name = profiles.find(&name)?;
+        if name != profiles.active()? {
+            let db = Db::open(profiles.db_path(&name))?;
+            let mut slot = state.write().map_err(|_| CommandError::internal())?;
+            if let Some(old) = slot.as_ref() {
+                old.indexing_jobs().cancel(None);
+            }
+            profiles.set_active(&name)?;
+            *slot = Some(open_loader(db, &hub));
+        }
+        profile_infos(profiles)
+    })
+    .await
+}
+
+/// Renaming the active profile moves its open database, so the loader is
+/// dropped first and reopened on whichever path the profile ends up at.
+#[tauri::command]
+pub async fn rename_profile(
+    from: String,
+    to: String,
+    state: State<'_, ImageLoaderState>,
+    profiles: State<'_, ProfilesState>,
+    hub: State<'_, RemoteHubState>,
+) -> Result<Vec<ProfileInfo>, CommandError> {
+    let state = Arc::clone(&state);
+    let hub = Arc::clone(&hub);
+    with_profiles(&profiles, move |profiles| {
+        let from = profiles.find(&from)?;
+        if from != profiles.active()? {
+            profiles.rename(&from, &to)?;
+            return profile_infos(profiles);
+        }
+
+        let mut slot = state.write().map_err(|_| CommandError::internal())?;
+        if let Some(old) = slot.take() {
+            old.indexing_jobs().cancel(None);
+        }
+        let renamed = profiles.rename(&from, &to);
+        let current = renamed.as_ref().unwrap_or(&from);
+        let db = Db::open(profiles.db_path(current))?;
+        *slot = Some(open_loader(db, &hub));
+        renamed?;
+        profile_infos(profiles)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn delete_profile(
+    name: String,
+    profiles: State<'_, ProfilesState>,
+) -> Result<Vec<ProfileInfo>, CommandError> {
+    with_profiles(&profiles, move |profiles| {
+        profiles.delete(&name)?;
+        profile_infos(profiles)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_remote_settings(
+    profiles: State<'_, ProfilesState>,
+) -> Result<RemoteSettings, CommandError> {
+    with_profiles(&profiles, |profiles| {
+        Ok(remote::load_settings(profiles.data_dir())?)
+    })
+    .await
+}
+
+/// Stores the settings and restarts the server to match. Returns the stored
+/// settings, which carry the generated token when none was given.
+#[tauri::command]
+pub async fn set_remote_settings(
+    settings: RemoteSettings,
+    app: AppHandle,
+    profiles: State<'_, ProfilesState>,
+) -> Result<RemoteSettings, CommandError> {
+    with_profiles(&profiles, move |profiles| {
+        let settings = remote::save_settings(profiles.data_dir(), settings)?;
+        apply_remote_settings(&app, &settings)?;
+        Ok(settings)
+    })
+    .await
+}
+
+/// Called by the window whenever its timer changes, for the remote event
+/// stream.
+#[tauri::command]
+pub async fn report_timer_status(
+    status: TimerStatus,
+    hub: State<'_, RemoteHubState>,
+) -> Result<(), CommandError> {
+    hub.timer_changed(status);
     Ok(())
 }
 
+#[tauri::command]
+pub async fn check_integrity(
+    repair: Option<bool>,
+    state: State<'_, ImageLoaderState>,
+) -> Result<IntegrityReport, CommandError> {
+    with_loader(&state, move |loader| {
+        Ok(loader.check_integrity(repair.unwrap_or(false))?)
+    })
+    .await
+}
+
 #[tauri::command]
 pub async fn is_healthy(state: State<'_, ImageLoaderState>) -> Result<bool, CommandError> {
-    let loader = get_loader(&state)?;
-    let _ = loader.get_image_state()?;
-    Ok(true)
+    with_loader(&state, move |loader| {
+        let _ = loader.get_image_state()?;
+        Ok(true)
+    })
+    .await
 }
 
 #[tauri::command]
@@ -542,9 +1061,11 @@ pub async fn set_folder_by_index(
     index: i64,
     state: State<'_, ImageLoaderState>,
 ) -> Result<FolderInfo, CommandError> {
-    let loader = get_loader(&state)?;
-    let (id, path) = loader.set_folder_by_index(index)?;
-    Ok(FolderInfo { id, path })
+    with_loader(&state, move |loader| {
+        let (id, path) = loader.set_folder_by_index(index)?;
+        Ok(FolderInfo { id, path })
+    })
+    .await
 }
 
 #[tauri::command]
@@ -552,21 +1073,22 @@ pub async fn set_normal_image_by_index(
     index: i64,
     state: State<'_, ImageLoaderState>,
 ) -> Result<ImageResponse, CommandError> {
-    let loader = get_loader(&state)?;
-    let (data, auto_switched) = loader
-        .set_normal_image_by_index(index)
-        .await
-        .map_err(CommandError::from)?;
-    let folder = loader
-        .get_current_folder_id_and_path()
-        .ok()
-        .flatten()
-        .map(|(id, path)| FolderInfo { id, path });
-    Ok(ImageResponse {
-        data,
-        folder,
-        auto_switched_folder: auto_switched,
+    with_loader(&state, move |loader| {
+        let (data, auto_switched) = loader
+            .set_normal_image_by_index(index)
+            .map_err(CommandError::from)?;
+        let folder = loader
+            .get_current_folder_id_and_path()
+            .ok()
+            .flatten()
+            .map(|(id, path)| FolderInfo { id, path });
+        Ok(ImageResponse {
+            data,
+            folder,
+            auto_switched_folder: auto_switched,
+        })
     })
+    .await
 }
 
 #[tauri::command]
@@ -574,32 +1096,154 @@ pub async fn set_random_image_by_index(
     index: i64,
     state: State<'_, ImageLoaderState>,
 ) -> Result<ImageResponse, CommandError> {
-    let loader = get_loader(&state)?;
-    let (data, auto_switched) = loader
-        .set_random_image_by_index(index)
-        .await
-        .map_err(CommandError::from)?;
-    let folder = loader
-        .get_current_folder_id_and_path()
-        .ok()
-        .flatten()
-        .map(|(id, path)| FolderInfo { id, path });
-    Ok(ImageResponse {
-        data,
-        folder,
-        auto_switched_folder: auto_switched,
+    with_loader(&state, move |loader| {
+        let (data, auto_switched) = loader
+            .set_random_image_by_index(index)
+            .map_err(CommandError::from)?;
+        let folder = loader
+            .get_current_folder_id_and_path()
+            .ok()
+            .flatten()
+            .map(|(id, path)| FolderInfo { id, path });
+        Ok(ImageResponse {
+            data,
+            folder,
+            auto_switched_folder: auto_switched,
+        })
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn set_normal_image_by_id(
+    image_id: Option<i64>,
+    #[allow(non_snake_case)] imageId: Option<i64>,
+    state: State<'_, ImageLoaderState>,
+) -> Result<ImageResponse, CommandError> {
+    let image_id = resolve_dual_i64_arg(image_id, imageId, "image_id", "imageId")?;
+    with_loader(&state, move |loader| {
+        let (data, auto_switched) = loader
+            .set_normal_image_by_id(image_id)
+            .map_err(CommandError::from)?;
+        let folder = loader
+            .get_current_folder_id_and_path()
+            .ok()
+            .flatten()
+            .map(|(id, path)| FolderInfo { id, path });
+        Ok(ImageResponse {
+            data,
+            folder,
+            auto_switched_folder: auto_switched,
+        })
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn open_image_by_id(
+    image_id: Option<i64>,
+    #[allow(non_snake_case)] imageId: Option<i64>,
+    append_to_random_history: Option<bool>,
+    #[allow(non_snake_case)] appendToRandomHistory: Option<bool>,
+    state: State<'_, ImageLoaderState>,
+) -> Result<ImageResponse, CommandError> {
+    let image_id = resolve_dual_i64_arg(image_id, imageId, "image_id", "imageId")?;
+    let append = append_to_random_history
+        .or(appendToRandomHistory)
+        .unwrap_or(false);
+    with_loader(&state, move |loader| {
+        let (data, auto_switched) = loader
+            .open_image_by_id(image_id, append)
+            .map_err(CommandError::from)?;
+        let folder = loader
+            .get_current_folder_id_and_path()
+            .ok()
+            .flatten()
+            .map(|(id, path)| FolderInfo { id, path });
+        Ok(ImageResponse {
+            data,
+            folder,
+            auto_switched_folder: auto_switched,
+        })
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn open_image_by_path(
+    path: String,
+    append_to_random_history: Option<bool>,
+    #[allow(non_snake_case)] appendToRandomHistory: Option<bool>,
+    state: State<'_, ImageLoaderState>,
+) -> Result<ImageResponse, CommandError> {
+    if path.trim().is_empty() {
+        return Err(CommandError::invalid("path is required"));
+    }
+    let append = append_to_random_history
+        .or(appendToRandomHistory)
+        .unwrap_or(false);
+    with_loader(&state, move |loader| {
+        let (data, auto_switched) = loader
+            .open_image_by_path(&path, append)
+            .map_err(CommandError::from)?;
+        let folder = loader
+            .get_current_folder_id_and_path()
+            .ok()
+            .flatten()
+            .map(|(id, path)| FolderInfo { id, path });
+        Ok(ImageResponse {
+            data,
+            folder,
+            auto_switched_folder: auto_switched,
+        })
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn search_images(
+    query: String,
+    mode: Option<String>,
+    scope: Option<String>,
+    offset: Option<i64>,
+    limit: Option<i64>,
+    state: State<'_, ImageLoaderState>,
+) -> Result<ImageSearchResults, CommandError> {
+    let mode = match mode.as_deref() {
+        None => SearchMode::Substring,
+        Some(raw) => {
+            SearchMode::parse(raw).ok_or_else(|| CommandError::invalid("invalid search mode"))?
+        }
+    };
+    let checked_only = match scope.as_deref() {
+        None | Some("all") => false,
+        Some("checked") => true,
+        Some(_) => return Err(CommandError::invalid("invalid search scope")),
+    };
+    with_loader(&state, move |loader| {
+        let (items, total) = loader.search_images(
+            &query,
+            mode,
+            checked_only,
+            offset.unwrap_or(0),
+            limit.unwrap_or(50),
+        )?;
+        Ok(ImageSearchResults { items, total })
     })
+    .await
 }
 
 #[tauri::command]
 pub async fn get_current_folder(
     state: State<'_, ImageLoaderState>,
 ) -> Result<Option<FolderInfo>, CommandError> {
-    let loader = get_loader(&state)?;
-    match loader.get_current_folder_id_and_path()? {
-        Some((id, path)) => Ok(Some(FolderInfo { id, path })),
-        None => Ok(None),
-    }
+    with_loader(&state, move |loader| {
+        match loader.get_current_folder_id_and_path()? {
+            Some((id, path)) => Ok(Some(FolderInfo { id, path })),
+            None => Ok(None),
+        }
+    })
+    .await
 }
 
 #[tauri::command]
@@ -609,27 +1253,106 @@ pub async fn delete_folder(
     state: State<'_, ImageLoaderState>,
 ) -> Result<(), CommandError> {
     let folder_id = resolve_dual_i64_arg(folder_id, folderId, "folder_id", "folderId")?;
-    let loader = get_loader(&state)?;
-    loader.delete_folder_by_id(folder_id)?;
-    Ok(())
+    with_loader(&state, move |loader| {
+        loader.backup_database(BackupReason::DeleteFolder)?;
+        loader.delete_folder_by_id(folder_id)?;
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn relocate_folder(
+    old_root: Option<String>,
+    #[allow(non_snake_case)] oldRoot: Option<String>,
+    new_root: Option<String>,
+    #[allow(non_snake_case)] newRoot: Option<String>,
+    state: State<'_, ImageLoaderState>,
+) -> Result<FolderInfo, CommandError> {
+    let old_root = old_root
+        .or(oldRoot)
+        .filter(|path| !path.trim().is_empty())
+        .ok_or_else(|| CommandError::invalid("old_root is required"))?;
+    let new_root = new_root
+        .or(newRoot)
+        .filter(|path| !path.trim().is_empty())
+        .ok_or_else(|| CommandError::invalid("new_root is required"))?;
+    with_loader(&state, move |loader| {
+        let (id, path) = loader.relocate_folder(&old_root, &new_root)?;
+        Ok(FolderInfo { id, path })
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_folder_sort_order(
+    folder_id: Option<i64>,
+    #[allow(non_snake_case)] folderId: Option<i64>,
+    state: State<'_, ImageLoaderState>,
+) -> Result<String, CommandError> {
+    let folder_id = resolve_dual_i64_arg(folder_id, folderId, "folder_id", "folderId")?;
+    with_loader(&state, move |loader| {
+        let order = loader.get_folder_sort_order(folder_id)?;
+        Ok(order.as_str().to_string())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn set_folder_sort_order(
+    folder_id: Option<i64>,
+    #[allow(non_snake_case)] folderId: Option<i64>,
+    order: String,
+    state: State<'_, ImageLoaderState>,
+) -> Result<(), CommandError> {
+    let folder_id = resolve_dual_i64_arg(folder_id, folderId, "folder_id", "folderId")?;
+    let order = NormalSortOrder::parse(&order)
+        .ok_or_else(|| CommandError::invalid("invalid sort order"))?;
+    with_loader(&state, move |loader| {
+        loader.set_folder_sort_order(folder_id, order)?;
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_folder_follow_symlinks(
+    folder_id: Option<i64>,
+    #[allow(non_snake_case)] folderId: Option<i64>,
+    state: State<'_, ImageLoaderState>,
+) -> Result<bool, CommandError> {
+    let folder_id = resolve_dual_i64_arg(folder_id, folderId, "folder_id", "folderId")?;
+    with_loader(&state, move |loader| {
+        loader
+            .get_folder_follow_symlinks(folder_id)
+            .map_err(Into::into)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn set_folder_follow_symlinks(
+    folder_id: Option<i64>,
+    #[allow(non_snake_case)] folderId: Option<i64>,
+    follow: bool,
+    state: State<'_, ImageLoaderState>,
+) -> Result<(), CommandError> {
+    let folder_id = resolve_dual_i64_arg(folder_id, folderId, "folder_id", "folderId")?;
+    with_loader(&state, move |loader| {
+        loader.set_folder_follow_symlinks(folder_id, follow)?;
+        Ok(())
+    })
+    .await
 }
 
 #[tauri::command]
 pub async fn cleanup_stale_folders(
     state: State<'_, ImageLoaderState>,
 ) -> Result<Vec<String>, CommandError> {
-    let loader = get_loader(&state)?;
-    let history = loader.get_folder_history()?;
-    let mut removed_paths = Vec::new();
-
-    for (folder_id, path, _, _) in history {
-        if !std::path::Path::new(&path).exists() {
-            loader.delete_folder_by_id(folder_id)?;
-            removed_paths.push(path);
-        }
-    }
-
-    Ok(removed_paths)
+    with_loader(&state, move |loader| {
+        loader.cleanup_stale_folders().map_err(Into::into)
+    })
+    .await
 }
 
 thread_local! {
@@ -697,7 +1420,8 @@ pub async fn play_timer_tone(
 
 #[cfg(test)]
 mod tests {
-    use super::{resolve_dual_i64_arg, sanitize_error_message};
+    use super::{resolve_dual_i64_arg, CommandError};
+    use crate::error::{ImageMode, LoaderError};
 
     #[test]
     fn resolve_dual_i64_arg_accepts_camel_case() {
@@ -724,33 +1448,54 @@ mod tests {
     }
 
     #[test]
-    fn sanitize_error_message_maps_foreign_key_constraint() {
-        let msg = sanitize_error_message("FOREIGN KEY constraint failed");
+    fn command_error_maps_foreign_key_constraint() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "PRAGMA foreign_keys = ON;
+             CREATE TABLE parent (id INTEGER PRIMARY KEY);
+             CREATE TABLE child (parent_id INTEGER REFERENCES parent(id));",
+        )
+        .unwrap();
+        let raw = conn
+            .execute("INSERT INTO child (parent_id) VALUES (1)", [])
+            .unwrap_err();
+        let err = CommandError::from(raw);
+        assert_eq!(err.code, "db_constraint");
         assert_eq!(
-            msg,
+            err.message,
             "database constraint failed - try resetting history or reindexing"
         );
     }
 
     #[test]
-    fn sanitize_error_message_maps_no_rows_error() {
-        let msg = sanitize_error_message("query returned no rows");
-        assert_eq!(msg, "no data found - folder or image may have been deleted");
+    fn command_error_maps_no_rows_error() {
+        let err = CommandError::from(rusqlite::Error::QueryReturnedNoRows);
+        assert_eq!(err.code, "not_found");
+        assert_eq!(
+            err.message,
+            "no data found - folder or image may have been deleted"
+        );
     }
 
     #[test]
-    fn sanitize_error_message_maps_hidden_images_error() {
-        let msg = sanitize_error_message("all images for this folder are hidden in random mode");
+    fn command_error_reports_hidden_mode_in_details() {
+        let err = CommandError::from(LoaderError::AllHidden {
+            mode: ImageMode::Random,
+        });
+        assert_eq!(err.code, "all_hidden");
+        assert_eq!(err.details.unwrap()["mode"], "random");
         assert_eq!(
-            msg,
-            "all images are hidden for this folder and mode - reindex to clear hidden images"
+            err.message,
+            "all images for this folder are hidden in random mode - reindex to clear hidden images"
         );
     }
 
     #[test]
-    fn sanitize_error_message_passthrough_for_unknown_errors() {
+    fn command_error_passthrough_for_unknown_errors() {
         let raw = "custom backend failure details";
-        let msg = sanitize_error_message(raw);
-        assert_eq!(msg, raw);
+        let err = CommandError::from(LoaderError::Invalid(raw.to_string()));
+        assert_eq!(err.code, "invalid_argument");
+        assert_eq!(err.message, raw);
+        assert!(err.details.is_none());
     }
 }
@@ -1,660 +1,835 @@
-use rusqlite::{Connection, Result};
-use std::path::PathBuf;
-use std::sync::Arc;
-use tauri::AppHandle;
-
+use crate::backup::{self, BackupEntry, BackupReason};
+use crate::error::LoaderError;
+use rusqlite::{Connection, OpenFlags, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Read-only connections kept open next to the writer.
+const READ_POOL_SIZE: usize = 4;
+/// How long a connection waits on a lock held by another one before giving up
+/// with `SQLITE_BUSY`.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The database runs in WAL mode: one writer connection, which every change
+/// and every transaction goes through, and a small pool of read-only
+/// connections. Readers see the last committed state and are never blocked
+/// by the writer, so browsing keeps working while an indexing transaction is
+/// being written or committed.
 pub struct Db {
-    conn: Arc<std::sync::Mutex<Connection>>,
+    conn: Arc<Mutex<Connection>>,
+    readers: Vec<Mutex<Connection>>,
+    next_reader: AtomicUsize,
+    path: PathBuf,
+}
+
+/// One step of the schema history. Its `version` is written to
+/// `PRAGMA user_version` in the same transaction as its changes, so a step
+/// either lands completely or not at all and never runs twice.
+struct Migration {
+    version: i64,
+    name: &'static str,
+    apply: fn(&Connection) -> Result<()>,
 }
 
-const APP_DB_VERSION: &str = "1.1.0";
+/// Ordered schema history. Append new steps at the end and never change one
+/// that has shipped. Databases from before `user_version` was tracked start at
+/// 0 and replay every step, so each step must tolerate finding its changes
+/// already (partly) in place.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "schema_1_1_0",
+        apply: migrate_schema_1_1_0,
+    },
+    Migration {
+        version: 2,
+        name: "spaced_random_mode",
+        apply: migrate_spaced_random_mode,
+    },
+    Migration {
+        version: 3,
+        name: "normal_sort_orders",
+        apply: migrate_normal_sort_orders,
+    },
+    Migration {
+        version: 4,
+        name: "image_path_search",
+        apply: migrate_image_path_search,
+    },
+    Migration {
+        version: 5,
+        name: "ignore_rules",
+        apply: migrate_ignore_rules,
+    },
+    Migration {
+        version: 6,
+        name: "follow_symlinks",
+        apply: migrate_follow_symlinks,
+    },
+    Migration {
+        version: 7,
+        name: "folder_availability",
+        apply: migrate_folder_availability,
+    },
+    Migration {
+        version: 8,
+        name: "index_completion",
+        apply: migrate_index_completion,
+    },
+    Migration {
+        version: 9,
+        name: "image_tags",
+        apply: migrate_image_tags,
+    },
+];
+
+pub const SCHEMA_VERSION: i64 = 9;
 
 impl Db {
-    pub fn open(db_path: PathBuf) -> Result<Self> {
+    pub fn open(db_path: PathBuf) -> Result<Self, LoaderError> {
         eprintln!("[RUST] Db::open: opening database at {}", db_path.display());
-        let conn = Connection::open(db_path)?;
+        let mut conn = Connection::open(&db_path)?;
+        configure_connection(&conn)?;
+        run_migrations(&mut conn, &db_path)?;
+        // WAL has to be in place before the read-only connections open; they
+        // cannot switch the journal mode themselves.
+        let journal_mode: String =
+            conn.query_row("PRAGMA journal_mode = WAL", rusqlite::params![], |row| {
+                row.get(0)
+            })?;
+        if !journal_mode.eq_ignore_ascii_case("wal") {
+            eprintln!(
+                "[RUST] Db::open: WAL unavailable, journal mode is {}",
+                journal_mode
+            );
+        }
+        conn.execute_batch("PRAGMA synchronous = NORMAL")?;
+        conn.execute("INSERT OR IGNORE INTO state (id) VALUES (1)", rusqlite::params![])?;
+
+        let readers = (0..READ_POOL_SIZE)
+            .map(|_| open_reader(&db_path).map(Mutex::new))
+            .collect::<Result<Vec<_>>>()?;
         let db = Db {
-            conn: Arc::new(std::sync::Mutex::new(conn)),
+            conn: Arc::new(Mutex::new(conn)),
+            readers,
+            next_reader: AtomicUsize::new(0),
+            path: db_path,
         };
-        db.ensure_meta_table()?;
-        db.wipe_legacy_db_if_needed()?;
-        db.init_schema()?;
-        db.run_migrations()?;
-        db.set_db_version(APP_DB_VERSION)?;
         eprintln!("[RUST] Db::open: database opened successfully");
         Ok(db)
     }
 
-    fn ensure_meta_table(&self) -> Result<()> {
-        self.execute(
-            "CREATE TABLE IF NOT EXISTS app_meta (
-                key TEXT PRIMARY KEY,
-                value TEXT NOT NULL
-            )",
-            rusqlite::params![],
-        )?;
-        Ok(())
-    }
-
-    fn get_db_version(&self) -> Result<Option<String>> {
-        let conn = self.conn();
-        let mut stmt =
-            conn.prepare("SELECT value FROM app_meta WHERE key = 'db_version' LIMIT 1")?;
-        let mut rows = stmt.query(rusqlite::params![])?;
-        if let Some(row) = rows.next()? {
-            let value: String = row.get(0)?;
-            return Ok(Some(value));
-        }
-        Ok(None)
-    }
-
-    fn has_legacy_tables(&self) -> Result<bool> {
-        let count: i64 = self.query_row(
-            "SELECT COUNT(*)
-             FROM sqlite_master
-             WHERE type = 'table'
-               AND name IN (
-                 'folders',
-                 'images',
-                 'state',
-                 'random_history',
-                 'current_lap',
-                 'hidden_normal_images',
-                 'hidden_random_images',
-                 'folder_nodes',
-                 'checked_folders',
-                 'active_images'
-               )",
-            rusqlite::params![],
-            |row| row.get(0),
-        )?;
-        Ok(count > 0)
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
-    fn has_required_tables_for_current_version(&self) -> Result<bool> {
-        let count: i64 = self.query_row(
-            "SELECT COUNT(*)
-             FROM sqlite_master
-             WHERE type = 'table'
-               AND name IN (
-                 'app_meta',
-                 'folders',
-                 'images',
-                 'state',
-                 'random_history',
-                 'current_lap',
-                 'hidden_normal_images',
-                 'hidden_random_images',
-                 'folder_nodes',
-                 'checked_folders',
-                 'active_images',
-                 'active_image_refcounts',
-                 'folder_closure',
-                 'folder_images_direct',
-                 'random_history_global',
-                 'current_lap_global'
-               )",
-            rusqlite::params![],
-            |row| row.get(0),
-        )?;
-        Ok(count == 16)
-    }
-
-    fn wipe_legacy_db_if_needed(&self) -> Result<()> {
-        let version = self.get_db_version()?;
-        let schema_ok = self.has_required_tables_for_current_version()?;
-        if version.as_deref() == Some(APP_DB_VERSION) && schema_ok {
-            return Ok(());
-        }
-        if !self.has_legacy_tables()? {
-            return Ok(());
-        }
-
-        let mut conn = self.conn();
-        conn.execute("PRAGMA foreign_keys = OFF", rusqlite::params![])?;
-        let tx = conn.transaction()?;
-        tx.execute("DROP TABLE IF EXISTS random_history", rusqlite::params![])?;
-        tx.execute(
-            "DROP TABLE IF EXISTS random_history_global",
-            rusqlite::params![],
-        )?;
-        tx.execute("DROP TABLE IF EXISTS current_lap", rusqlite::params![])?;
-        tx.execute(
-            "DROP TABLE IF EXISTS current_lap_global",
-            rusqlite::params![],
-        )?;
-        tx.execute(
-            "DROP TABLE IF EXISTS hidden_normal_images",
-            rusqlite::params![],
-        )?;
-        tx.execute(
-            "DROP TABLE IF EXISTS hidden_random_images",
-            rusqlite::params![],
-        )?;
-        tx.execute("DROP TABLE IF EXISTS active_images", rusqlite::params![])?;
-        tx.execute("DROP TABLE IF EXISTS checked_folders", rusqlite::params![])?;
-        tx.execute("DROP TABLE IF EXISTS folder_nodes", rusqlite::params![])?;
-        tx.execute("DROP TABLE IF EXISTS images", rusqlite::params![])?;
-        tx.execute("DROP TABLE IF EXISTS folders", rusqlite::params![])?;
-        tx.execute("DROP TABLE IF EXISTS state", rusqlite::params![])?;
-        tx.commit()?;
-        conn.execute("PRAGMA foreign_keys = ON", rusqlite::params![])?;
-        Ok(())
+    pub fn backup(&self, reason: BackupReason) -> Result<BackupEntry, LoaderError> {
+        backup::create_backup(&self.conn(), &self.path, reason)
     }
 
-    fn set_db_version(&self, version: &str) -> Result<()> {
-        self.execute(
-            "INSERT INTO app_meta(key, value) VALUES('db_version', ?1)
-             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
-            rusqlite::params![version],
-        )?;
-        Ok(())
+    /// Overwrites this database with a snapshot from `list_backups`. Callers
+    /// must reopen the file afterwards so the restored schema gets migrated.
+    pub fn restore_backup(&self, backup_path: &Path) -> Result<(), LoaderError> {
+        backup::restore_into(&mut self.conn(), backup_path)
     }
 
-    fn execute<P: rusqlite::Params>(&self, sql: &str, params: P) -> Result<usize> {
-        self.conn.lock().unwrap().execute(sql, params)
+    /// The writer. Use it for every change and for reads that must see the
+    /// caller's own uncommitted transaction.
+    pub fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap()
     }
 
-    fn query_row<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<T>
+    pub fn with_conn<F, R>(&self, f: F) -> Result<R>
     where
-        P: rusqlite::Params,
-        F: FnOnce(&rusqlite::Row) -> Result<T, rusqlite::Error>,
+        F: FnOnce(&Connection) -> Result<R>,
     {
-        self.conn.lock().unwrap().query_row(sql, params, f)
+        let conn = self.conn.lock().unwrap();
+        f(&conn)
     }
 
-    pub fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
-        self.conn.lock().unwrap()
+    /// A read-only connection from the pool. It sees the last committed
+    /// state, so never use it for rows the caller is still writing in an open
+    /// transaction. Picks the first idle reader and only waits when all of
+    /// them are busy.
+    pub fn read(&self) -> MutexGuard<'_, Connection> {
+        let start = self.next_reader.fetch_add(1, Ordering::Relaxed) % self.readers.len();
+        for offset in 0..self.readers.len() {
+            let idx = (start + offset) % self.readers.len();
+            if let Ok(reader) = self.readers[idx].try_lock() {
+                return reader;
+            }
+        }
+        self.readers[start].lock().unwrap()
     }
 
-    pub fn with_conn<F, R>(&self, f: F) -> Result<R>
+    pub fn with_read<F, R>(&self, f: F) -> Result<R>
     where
         F: FnOnce(&Connection) -> Result<R>,
     {
-        let conn = self.conn.lock().unwrap();
-        f(&*conn)
+        let conn = self.read();
+        f(&conn)
     }
+}
 
-    fn init_schema(&self) -> Result<()> {
-        self.execute(
-            "CREATE TABLE IF NOT EXISTS folders (
-                id INTEGER PRIMARY KEY,
-                path TEXT NOT NULL UNIQUE,
-                added_at TEXT NOT NULL,
-                current_index INTEGER NOT NULL DEFAULT -1,
-                current_random_index INTEGER NOT NULL DEFAULT -1
-            )",
-            rusqlite::params![],
-        )?;
-
-        self.execute(
-            "CREATE TABLE IF NOT EXISTS images (
-                id INTEGER PRIMARY KEY,
-                path TEXT NOT NULL,
-                folder_id INTEGER,
-                FOREIGN KEY (folder_id) REFERENCES folders(id)
-            )",
-            rusqlite::params![],
-        )?;
-
-        self.execute(
-            "CREATE UNIQUE INDEX IF NOT EXISTS idx_images_folder_path ON images(folder_id, path)",
-            rusqlite::params![],
-        )?;
-        self.execute(
-            "CREATE INDEX IF NOT EXISTS idx_images_folder_id ON images(folder_id)",
-            rusqlite::params![],
-        )?;
-
-        self.execute(
-            "CREATE TABLE IF NOT EXISTS state (
-                id INTEGER PRIMARY KEY CHECK (id = 1),
-                current_index INTEGER NOT NULL DEFAULT -1,
-                current_random_index INTEGER NOT NULL DEFAULT -1,
-                current_folder_id INTEGER,
-                vertical_mirror INTEGER NOT NULL DEFAULT 0,
-                horizontal_mirror INTEGER NOT NULL DEFAULT 0,
-                greyscale INTEGER NOT NULL DEFAULT 0,
-                timer_flow_mode TEXT NOT NULL DEFAULT 'random',
-                show_folder_history_panel INTEGER NOT NULL DEFAULT 1,
-                show_top_controls INTEGER NOT NULL DEFAULT 1,
-                show_image_history_panel INTEGER NOT NULL DEFAULT 1,
-                show_bottom_controls INTEGER NOT NULL DEFAULT 1,
-                is_fullscreen_image INTEGER NOT NULL DEFAULT 0,
-                last_image_id INTEGER,
-                shortcut_hints_visible INTEGER NOT NULL DEFAULT 0,
-                shortcut_hint_side TEXT NOT NULL DEFAULT 'left'
-            )",
-            rusqlite::params![],
-        )?;
+/// Settings every connection needs. SQLite leaves foreign keys off unless
+/// each connection asks for them.
+fn configure_connection(conn: &Connection) -> Result<()> {
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.execute_batch("PRAGMA foreign_keys = ON")?;
+    crate::img_loader::register_sql_functions(conn)
+}
 
-        self.execute(
-            "CREATE TABLE IF NOT EXISTS random_history (
-                folder_id INTEGER NOT NULL,
-                order_index INTEGER NOT NULL,
-                image_id INTEGER NOT NULL,
-                PRIMARY KEY (folder_id, order_index),
-                FOREIGN KEY (folder_id) REFERENCES folders(id),
-                FOREIGN KEY (image_id) REFERENCES images(id)
-            )",
-            rusqlite::params![],
-        )?;
+fn open_reader(db_path: &Path) -> Result<Connection> {
+    let conn = Connection::open_with_flags(
+        db_path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX
+            | OpenFlags::SQLITE_OPEN_URI,
+    )?;
+    configure_connection(&conn)?;
+    Ok(conn)
+}
 
-        self.execute(
-            "CREATE TABLE IF NOT EXISTS current_lap (
-                folder_id INTEGER NOT NULL,
-                image_id INTEGER NOT NULL,
-                PRIMARY KEY (folder_id, image_id),
-                FOREIGN KEY (folder_id) REFERENCES folders(id),
-                FOREIGN KEY (image_id) REFERENCES images(id)
-            )",
-            rusqlite::params![],
-        )?;
+fn schema_version(conn: &Connection) -> Result<i64> {
+    conn.query_row("PRAGMA user_version", rusqlite::params![], |row| row.get(0))
+}
 
-        self.ensure_hidden_tables_and_indexes()?;
-        self.ensure_selection_tables_and_indexes()?;
+fn has_user_tables(conn: &Connection) -> Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'",
+        rusqlite::params![],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
 
-        self.execute(
-            "INSERT OR IGNORE INTO state (id) VALUES (1)",
-            rusqlite::params![],
-        )?;
+fn run_migrations(conn: &mut Connection, db_path: &Path) -> Result<(), LoaderError> {
+    let current = schema_version(conn)?;
+    if current > SCHEMA_VERSION {
+        // Written by a newer build; guessing at its layout could corrupt it.
+        return Err(LoaderError::Invalid(format!(
+            "database schema version {} is newer than this build supports ({})",
+            current, SCHEMA_VERSION
+        )));
+    }
+    if current == SCHEMA_VERSION {
+        return Ok(());
+    }
+    if has_user_tables(conn)? {
+        backup::create_backup(conn, db_path, BackupReason::Migration)?;
+    }
 
-        Ok(())
+    // Table rebuilds in the early steps need foreign keys off, and the pragma
+    // is ignored inside a transaction, so it is switched around the whole run.
+    let foreign_keys: bool =
+        conn.query_row("PRAGMA foreign_keys", rusqlite::params![], |row| row.get(0))?;
+    conn.execute_batch("PRAGMA foreign_keys = OFF")?;
+    let result = apply_migrations(conn, current);
+    if foreign_keys {
+        conn.execute_batch("PRAGMA foreign_keys = ON")?;
     }
+    Ok(result?)
+}
 
-    fn run_migrations(&self) -> Result<()> {
-        self.migrate_images_to_folder_scoped_paths()?;
-        self.repair_images_old_foreign_keys()?;
-        self.ensure_state_column("timer_flow_mode", "TEXT NOT NULL DEFAULT 'random'")?;
-        self.ensure_state_column("show_folder_history_panel", "INTEGER NOT NULL DEFAULT 1")?;
-        self.ensure_state_column("show_top_controls", "INTEGER NOT NULL DEFAULT 1")?;
-        self.ensure_state_column("show_image_history_panel", "INTEGER NOT NULL DEFAULT 1")?;
-        self.ensure_state_column("show_bottom_controls", "INTEGER NOT NULL DEFAULT 1")?;
-        self.ensure_state_column("is_fullscreen_image", "INTEGER NOT NULL DEFAULT 0")?;
-        self.ensure_state_column("last_image_id", "INTEGER")?;
-        self.ensure_state_column("shortcut_hints_visible", "INTEGER NOT NULL DEFAULT 0")?;
-        self.ensure_state_column("shortcut_hint_side", "TEXT NOT NULL DEFAULT 'left'")?;
-        self.ensure_hidden_tables_and_indexes()?;
-        self.ensure_selection_tables_and_indexes()?;
-        Ok(())
+fn apply_migrations(conn: &mut Connection, current: i64) -> Result<()> {
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        eprintln!(
+            "[RUST] Db::open: applying migration {} ({})",
+            migration.version, migration.name
+        );
+        let tx = conn.transaction()?;
+        (migration.apply)(&tx)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
     }
+    Ok(())
+}
 
-    fn migrate_images_to_folder_scoped_paths(&self) -> Result<()> {
-        let has_global_path_unique: i64 = self.query_row(
-            "SELECT COUNT(*) FROM pragma_index_list('images') WHERE origin = 'u'",
-            rusqlite::params![],
-            |row| row.get(0),
-        )?;
+/// The schema shipped in 1.1.0, plus the in-place repairs for the layouts that
+/// came before it. Earlier builds dropped those databases instead.
+fn migrate_schema_1_1_0(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS app_meta (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        rusqlite::params![],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS folders (
+            id INTEGER PRIMARY KEY,
+            path TEXT NOT NULL UNIQUE,
+            added_at TEXT NOT NULL,
+            current_index INTEGER NOT NULL DEFAULT -1,
+            current_random_index INTEGER NOT NULL DEFAULT -1
+        )",
+        rusqlite::params![],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS images (
+            id INTEGER PRIMARY KEY,
+            path TEXT NOT NULL,
+            folder_id INTEGER,
+            FOREIGN KEY (folder_id) REFERENCES folders(id)
+        )",
+        rusqlite::params![],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS state (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            current_index INTEGER NOT NULL DEFAULT -1,
+            current_random_index INTEGER NOT NULL DEFAULT -1,
+            current_folder_id INTEGER,
+            vertical_mirror INTEGER NOT NULL DEFAULT 0,
+            horizontal_mirror INTEGER NOT NULL DEFAULT 0,
+            greyscale INTEGER NOT NULL DEFAULT 0,
+            timer_flow_mode TEXT NOT NULL DEFAULT 'random',
+            show_folder_history_panel INTEGER NOT NULL DEFAULT 1,
+            show_top_controls INTEGER NOT NULL DEFAULT 1,
+            show_image_history_panel INTEGER NOT NULL DEFAULT 1,
+            show_bottom_controls INTEGER NOT NULL DEFAULT 1,
+            is_fullscreen_image INTEGER NOT NULL DEFAULT 0,
+            last_image_id INTEGER,
+            shortcut_hints_visible INTEGER NOT NULL DEFAULT 0,
+            shortcut_hint_side TEXT NOT NULL DEFAULT 'left'
+        )",
+        rusqlite::params![],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS random_history (
+            folder_id INTEGER NOT NULL,
+            order_index INTEGER NOT NULL,
+            image_id INTEGER NOT NULL,
+            PRIMARY KEY (folder_id, order_index),
+            FOREIGN KEY (folder_id) REFERENCES folders(id),
+            FOREIGN KEY (image_id) REFERENCES images(id)
+        )",
+        rusqlite::params![],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS current_lap (
+            folder_id INTEGER NOT NULL,
+            image_id INTEGER NOT NULL,
+            PRIMARY KEY (folder_id, image_id),
+            FOREIGN KEY (folder_id) REFERENCES folders(id),
+            FOREIGN KEY (image_id) REFERENCES images(id)
+        )",
+        rusqlite::params![],
+    )?;
+
+    ensure_hidden_tables_and_indexes(conn)?;
+
+    migrate_images_to_folder_scoped_paths(conn)?;
+    repair_images_old_foreign_keys(conn)?;
+    ensure_state_column(conn, "timer_flow_mode", "TEXT NOT NULL DEFAULT 'random'")?;
+    ensure_state_column(conn, "show_folder_history_panel", "INTEGER NOT NULL DEFAULT 1")?;
+    ensure_state_column(conn, "show_top_controls", "INTEGER NOT NULL DEFAULT 1")?;
+    ensure_state_column(conn, "show_image_history_panel", "INTEGER NOT NULL DEFAULT 1")?;
+    ensure_state_column(conn, "show_bottom_controls", "INTEGER NOT NULL DEFAULT 1")?;
+    ensure_state_column(conn, "is_fullscreen_image", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_state_column(conn, "last_image_id", "INTEGER")?;
+    ensure_state_column(conn, "shortcut_hints_visible", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_state_column(conn, "shortcut_hint_side", "TEXT NOT NULL DEFAULT 'left'")?;
+
+    ensure_selection_tables_and_indexes(conn)?;
+    Ok(())
+}
 
-        if has_global_path_unique == 0 {
-            self.execute(
-                "CREATE UNIQUE INDEX IF NOT EXISTS idx_images_folder_path ON images(folder_id, path)",
-                rusqlite::params![],
-            )?;
-            self.execute(
-                "CREATE INDEX IF NOT EXISTS idx_images_folder_id ON images(folder_id)",
-                rusqlite::params![],
-            )?;
-            return Ok(());
-        }
+fn migrate_spaced_random_mode(conn: &Connection) -> Result<()> {
+    ensure_state_column(conn, "random_selection_mode", "TEXT NOT NULL DEFAULT 'lap'")?;
+    ensure_state_column(conn, "spaced_randomness", "REAL NOT NULL DEFAULT 0.2")?;
+
+    // Keyed by path instead of image id so last-shown times survive reindexing
+    // and removing/re-adding a folder.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS image_views (
+            path TEXT PRIMARY KEY,
+            last_shown_at INTEGER NOT NULL,
+            show_count INTEGER NOT NULL DEFAULT 0
+        )",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_image_views_last_shown ON image_views(last_shown_at)",
+        rusqlite::params![],
+    )?;
+    Ok(())
+}
 
-        let mut conn = self.conn();
-        conn.execute("PRAGMA foreign_keys = OFF", rusqlite::params![])?;
-        let tx = conn.transaction()?;
-        tx.execute(
-            "DROP INDEX IF EXISTS idx_images_folder_path",
-            rusqlite::params![],
-        )?;
-        tx.execute(
-            "DROP INDEX IF EXISTS idx_images_folder_id",
-            rusqlite::params![],
-        )?;
-        tx.execute(
-            "CREATE TABLE images_new (
-                id INTEGER PRIMARY KEY,
-                path TEXT NOT NULL,
-                folder_id INTEGER,
-                FOREIGN KEY (folder_id) REFERENCES folders(id)
-            )",
-            rusqlite::params![],
-        )?;
-        tx.execute(
-            "INSERT INTO images_new (id, path, folder_id)
-             SELECT id, path, folder_id FROM images",
-            rusqlite::params![],
-        )?;
-        tx.execute("DROP TABLE images", rusqlite::params![])?;
-        tx.execute(
-            "ALTER TABLE images_new RENAME TO images",
-            rusqlite::params![],
-        )?;
-        tx.execute(
-            "CREATE UNIQUE INDEX idx_images_folder_path ON images(folder_id, path)",
-            rusqlite::params![],
-        )?;
-        tx.execute(
-            "CREATE INDEX idx_images_folder_id ON images(folder_id)",
+fn migrate_normal_sort_orders(conn: &Connection) -> Result<()> {
+    ensure_table_column(conn, "folders", "normal_sort_order", "TEXT NOT NULL DEFAULT 'path'")?;
+    ensure_table_column(conn, "folders", "normal_shuffle_seed", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_table_column(conn, "images", "mtime", "INTEGER")?;
+    ensure_table_column(conn, "images", "file_size", "INTEGER")?;
+    ensure_table_column(conn, "images", "width", "INTEGER")?;
+    ensure_table_column(conn, "images", "height", "INTEGER")?;
+    Ok(())
+}
+
+fn migrate_image_path_search(conn: &Connection) -> Result<()> {
+    // External-content trigram index over images.path for substring/glob search.
+    // Unversioned databases may have the index without its triggers if `images`
+    // was rebuilt in step 1, so a missing trigger also means a full rebuild.
+    let needs_rebuild = !sqlite_object_exists(conn, "table", "images_fts")?
+        || !sqlite_object_exists(conn, "trigger", "images_fts_ai")?;
+
+    conn.execute(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS images_fts USING fts5(
+            path,
+            content = 'images',
+            content_rowid = 'id',
+            tokenize = 'trigram'
+        )",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS images_fts_ai AFTER INSERT ON images BEGIN
+            INSERT INTO images_fts(rowid, path) VALUES (new.id, new.path);
+        END",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS images_fts_ad AFTER DELETE ON images BEGIN
+            INSERT INTO images_fts(images_fts, rowid, path) VALUES ('delete', old.id, old.path);
+        END",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE TRIGGER IF NOT EXISTS images_fts_au AFTER UPDATE OF path ON images BEGIN
+            INSERT INTO images_fts(images_fts, rowid, path) VALUES ('delete', old.id, old.path);
+            INSERT INTO images_fts(rowid, path) VALUES (new.id, new.path);
+        END",
+        rusqlite::params![],
+    )?;
+
+    if needs_rebuild {
+        conn.execute(
+            "INSERT INTO images_fts(images_fts) VALUES ('rebuild')",
             rusqlite::params![],
         )?;
-        tx.commit()?;
-        conn.execute("PRAGMA foreign_keys = ON", rusqlite::params![])?;
-        Ok(())
     }
+    Ok(())
+}
 
-    fn table_references_images_old(&self, table_name: &str) -> Result<bool> {
-        let count: i64 = self.query_row(
-            "SELECT COUNT(*) FROM pragma_foreign_key_list(?1) WHERE \"table\" = 'images_old'",
-            rusqlite::params![table_name],
-            |row| row.get(0),
-        )?;
-        Ok(count > 0)
-    }
+fn migrate_ignore_rules(conn: &Connection) -> Result<()> {
+    ensure_state_column(conn, "skip_hidden_dirs", "INTEGER NOT NULL DEFAULT 1")?;
+
+    // Global gitignore-style patterns applied to every scan, kept in the
+    // order the user entered them since later patterns override earlier ones.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS ignore_patterns (
+            position INTEGER PRIMARY KEY,
+            pattern TEXT NOT NULL
+        )",
+        rusqlite::params![],
+    )?;
+    Ok(())
+}
 
-    fn repair_images_old_foreign_keys(&self) -> Result<()> {
-        let targets = [
-            "random_history",
-            "current_lap",
-            "hidden_normal_images",
-            "hidden_random_images",
-        ];
+fn migrate_follow_symlinks(conn: &Connection) -> Result<()> {
+    ensure_table_column(conn, "folders", "follow_symlinks", "INTEGER NOT NULL DEFAULT 0")
+}
 
-        let mut needs_repair = false;
-        for table in targets {
-            if self.table_references_images_old(table)? {
-                needs_repair = true;
-                break;
-            }
-        }
+fn migrate_folder_availability(conn: &Connection) -> Result<()> {
+    ensure_table_column(conn, "folders", "offline", "INTEGER NOT NULL DEFAULT 0")?;
+    ensure_table_column(conn, "folders", "last_seen_at", "TEXT")?;
+    Ok(())
+}
 
-        if !needs_repair {
-            return Ok(());
-        }
+fn migrate_index_completion(conn: &Connection) -> Result<()> {
+    ensure_table_column(conn, "folders", "indexed_at", "TEXT")?;
+    // Roots indexed before completion was tracked have no way to tell a
+    // partial index apart, so any root with images counts as complete.
+    conn.execute(
+        "UPDATE folders SET indexed_at = added_at
+         WHERE indexed_at IS NULL
+           AND EXISTS (SELECT 1 FROM images WHERE images.folder_id = folders.id)",
+        rusqlite::params![],
+    )?;
+    Ok(())
+}
 
-        let mut conn = self.conn();
-        conn.execute("PRAGMA foreign_keys = OFF", rusqlite::params![])?;
-        let tx = conn.transaction()?;
+fn migrate_image_tags(conn: &Connection) -> Result<()> {
+    // Keyed by path like `image_views`, so tags survive reindexing.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS image_tags (
+            path TEXT NOT NULL,
+            tag TEXT NOT NULL COLLATE NOCASE,
+            PRIMARY KEY (path, tag)
+        )",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_image_tags_tag ON image_tags(tag)",
+        rusqlite::params![],
+    )?;
+    Ok(())
+}
 
-        tx.execute(
-            "ALTER TABLE random_history RENAME TO random_history_old",
-            rusqlite::params![],
-        )?;
-        tx.execute(
-            "CREATE TABLE random_history (
-                folder_id INTEGER NOT NULL,
-                order_index INTEGER NOT NULL,
-                image_id INTEGER NOT NULL,
-                PRIMARY KEY (folder_id, order_index),
-                FOREIGN KEY (folder_id) REFERENCES folders(id),
-                FOREIGN KEY (image_id) REFERENCES images(id)
-            )",
-            rusqlite::params![],
-        )?;
-        tx.execute(
-            "INSERT INTO random_history (folder_id, order_index, image_id)
-             SELECT folder_id, order_index, image_id FROM random_history_old",
-            rusqlite::params![],
-        )?;
-        tx.execute("DROP TABLE random_history_old", rusqlite::params![])?;
+fn migrate_images_to_folder_scoped_paths(conn: &Connection) -> Result<()> {
+    let has_global_path_unique: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_index_list('images') WHERE origin = 'u'",
+        rusqlite::params![],
+        |row| row.get(0),
+    )?;
 
-        tx.execute(
-            "ALTER TABLE current_lap RENAME TO current_lap_old",
-            rusqlite::params![],
-        )?;
-        tx.execute(
-            "CREATE TABLE current_lap (
-                folder_id INTEGER NOT NULL,
-                image_id INTEGER NOT NULL,
-                PRIMARY KEY (folder_id, image_id),
-                FOREIGN KEY (folder_id) REFERENCES folders(id),
-                FOREIGN KEY (image_id) REFERENCES images(id)
-            )",
+    if has_global_path_unique == 0 {
+        conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_images_folder_path ON images(folder_id, path)",
             rusqlite::params![],
         )?;
-        tx.execute(
-            "INSERT INTO current_lap (folder_id, image_id)
-             SELECT folder_id, image_id FROM current_lap_old",
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_images_folder_id ON images(folder_id)",
             rusqlite::params![],
         )?;
-        tx.execute("DROP TABLE current_lap_old", rusqlite::params![])?;
+        return Ok(());
+    }
 
-        tx.execute(
-            "ALTER TABLE hidden_normal_images RENAME TO hidden_normal_images_old",
-            rusqlite::params![],
-        )?;
-        tx.execute(
-            "CREATE TABLE hidden_normal_images (
-                folder_id INTEGER NOT NULL,
-                image_id INTEGER NOT NULL,
-                PRIMARY KEY (folder_id, image_id),
-                FOREIGN KEY (folder_id) REFERENCES folders(id),
-                FOREIGN KEY (image_id) REFERENCES images(id)
-            )",
-            rusqlite::params![],
-        )?;
-        tx.execute(
-            "INSERT INTO hidden_normal_images (folder_id, image_id)
-             SELECT folder_id, image_id FROM hidden_normal_images_old",
-            rusqlite::params![],
-        )?;
-        tx.execute("DROP TABLE hidden_normal_images_old", rusqlite::params![])?;
+    conn.execute(
+        "DROP INDEX IF EXISTS idx_images_folder_path",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "DROP INDEX IF EXISTS idx_images_folder_id",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE images_new (
+            id INTEGER PRIMARY KEY,
+            path TEXT NOT NULL,
+            folder_id INTEGER,
+            FOREIGN KEY (folder_id) REFERENCES folders(id)
+        )",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "INSERT INTO images_new (id, path, folder_id)
+         SELECT id, path, folder_id FROM images",
+        rusqlite::params![],
+    )?;
+    conn.execute("DROP TABLE images", rusqlite::params![])?;
+    conn.execute(
+        "ALTER TABLE images_new RENAME TO images",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE UNIQUE INDEX idx_images_folder_path ON images(folder_id, path)",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE INDEX idx_images_folder_id ON images(folder_id)",
+        rusqlite::params![],
+    )?;
+    Ok(())
+}
 
-        tx.execute(
-            "ALTER TABLE hidden_random_images RENAME TO hidden_random_images_old",
-            rusqlite::params![],
-        )?;
-        tx.execute(
-            "CREATE TABLE hidden_random_images (
-                folder_id INTEGER NOT NULL,
-                image_id INTEGER NOT NULL,
-                PRIMARY KEY (folder_id, image_id),
-                FOREIGN KEY (folder_id) REFERENCES folders(id),
-                FOREIGN KEY (image_id) REFERENCES images(id)
-            )",
-            rusqlite::params![],
-        )?;
-        tx.execute(
-            "INSERT INTO hidden_random_images (folder_id, image_id)
-             SELECT folder_id, image_id FROM hidden_random_images_old",
-            rusqlite::params![],
-        )?;
-        tx.execute("DROP TABLE hidden_random_images_old", rusqlite::params![])?;
+fn table_references_images_old(conn: &Connection, table_name: &str) -> Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_foreign_key_list(?1) WHERE \"table\" = 'images_old'",
+        rusqlite::params![table_name],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
 
-        tx.commit()?;
-        conn.execute("PRAGMA foreign_keys = ON", rusqlite::params![])?;
-        Ok(())
+fn repair_images_old_foreign_keys(conn: &Connection) -> Result<()> {
+    let targets = [
+        "random_history",
+        "current_lap",
+        "hidden_normal_images",
+        "hidden_random_images",
+    ];
+
+    let mut needs_repair = false;
+    for table in targets {
+        if table_references_images_old(conn, table)? {
+            needs_repair = true;
+            break;
+        }
     }
 
-    fn ensure_hidden_tables_and_indexes(&self) -> Result<()> {
-        self.execute(
-            "CREATE TABLE IF NOT EXISTS hidden_normal_images (
-                folder_id INTEGER NOT NULL,
-                image_id INTEGER NOT NULL,
-                PRIMARY KEY (folder_id, image_id),
-                FOREIGN KEY (folder_id) REFERENCES folders(id),
-                FOREIGN KEY (image_id) REFERENCES images(id)
-            )",
-            rusqlite::params![],
-        )?;
-        self.execute(
-            "CREATE TABLE IF NOT EXISTS hidden_random_images (
-                folder_id INTEGER NOT NULL,
-                image_id INTEGER NOT NULL,
-                PRIMARY KEY (folder_id, image_id),
-                FOREIGN KEY (folder_id) REFERENCES folders(id),
-                FOREIGN KEY (image_id) REFERENCES images(id)
-            )",
-            rusqlite::params![],
-        )?;
-        self.execute(
-            "CREATE INDEX IF NOT EXISTS idx_hidden_normal_folder ON hidden_normal_images(folder_id)",
-            rusqlite::params![],
-        )?;
-        self.execute(
-            "CREATE INDEX IF NOT EXISTS idx_hidden_normal_image ON hidden_normal_images(image_id)",
-            rusqlite::params![],
-        )?;
-        self.execute(
-            "CREATE INDEX IF NOT EXISTS idx_hidden_random_folder ON hidden_random_images(folder_id)",
-            rusqlite::params![],
-        )?;
-        self.execute(
-            "CREATE INDEX IF NOT EXISTS idx_hidden_random_image ON hidden_random_images(image_id)",
-            rusqlite::params![],
-        )?;
-        Ok(())
+    if !needs_repair {
+        return Ok(());
     }
 
-    fn ensure_selection_tables_and_indexes(&self) -> Result<()> {
-        self.execute(
-            "CREATE TABLE IF NOT EXISTS folder_nodes (
-                path TEXT PRIMARY KEY,
-                parent_path TEXT,
-                root_folder_id INTEGER NOT NULL,
-                subtree_image_count INTEGER NOT NULL DEFAULT 0,
-                FOREIGN KEY (root_folder_id) REFERENCES folders(id)
-            )",
-            rusqlite::params![],
-        )?;
-        self.execute(
-            "CREATE INDEX IF NOT EXISTS idx_folder_nodes_parent_path ON folder_nodes(parent_path)",
-            rusqlite::params![],
-        )?;
-        self.execute(
-            "CREATE INDEX IF NOT EXISTS idx_folder_nodes_root_folder_id ON folder_nodes(root_folder_id)",
-            rusqlite::params![],
-        )?;
+    conn.execute(
+        "ALTER TABLE random_history RENAME TO random_history_old",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE random_history (
+            folder_id INTEGER NOT NULL,
+            order_index INTEGER NOT NULL,
+            image_id INTEGER NOT NULL,
+            PRIMARY KEY (folder_id, order_index),
+            FOREIGN KEY (folder_id) REFERENCES folders(id),
+            FOREIGN KEY (image_id) REFERENCES images(id)
+        )",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "INSERT INTO random_history (folder_id, order_index, image_id)
+         SELECT folder_id, order_index, image_id FROM random_history_old",
+        rusqlite::params![],
+    )?;
+    conn.execute("DROP TABLE random_history_old", rusqlite::params![])?;
+
+    conn.execute(
+        "ALTER TABLE current_lap RENAME TO current_lap_old",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE current_lap (
+            folder_id INTEGER NOT NULL,
+            image_id INTEGER NOT NULL,
+            PRIMARY KEY (folder_id, image_id),
+            FOREIGN KEY (folder_id) REFERENCES folders(id),
+            FOREIGN KEY (image_id) REFERENCES images(id)
+        )",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "INSERT INTO current_lap (folder_id, image_id)
+         SELECT folder_id, image_id FROM current_lap_old",
+        rusqlite::params![],
+    )?;
+    conn.execute("DROP TABLE current_lap_old", rusqlite::params![])?;
+
+    conn.execute(
+        "ALTER TABLE hidden_normal_images RENAME TO hidden_normal_images_old",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE hidden_normal_images (
+            folder_id INTEGER NOT NULL,
+            image_id INTEGER NOT NULL,
+            PRIMARY KEY (folder_id, image_id),
+            FOREIGN KEY (folder_id) REFERENCES folders(id),
+            FOREIGN KEY (image_id) REFERENCES images(id)
+        )",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "INSERT INTO hidden_normal_images (folder_id, image_id)
+         SELECT folder_id, image_id FROM hidden_normal_images_old",
+        rusqlite::params![],
+    )?;
+    conn.execute("DROP TABLE hidden_normal_images_old", rusqlite::params![])?;
+
+    conn.execute(
+        "ALTER TABLE hidden_random_images RENAME TO hidden_random_images_old",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE hidden_random_images (
+            folder_id INTEGER NOT NULL,
+            image_id INTEGER NOT NULL,
+            PRIMARY KEY (folder_id, image_id),
+            FOREIGN KEY (folder_id) REFERENCES folders(id),
+            FOREIGN KEY (image_id) REFERENCES images(id)
+        )",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "INSERT INTO hidden_random_images (folder_id, image_id)
+         SELECT folder_id, image_id FROM hidden_random_images_old",
+        rusqlite::params![],
+    )?;
+    conn.execute("DROP TABLE hidden_random_images_old", rusqlite::params![])?;
+    Ok(())
+}
 
-        self.execute(
-            "CREATE TABLE IF NOT EXISTS checked_folders (
-                path TEXT PRIMARY KEY,
-                FOREIGN KEY (path) REFERENCES folder_nodes(path)
-            )",
-            rusqlite::params![],
-        )?;
+fn ensure_hidden_tables_and_indexes(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS hidden_normal_images (
+            folder_id INTEGER NOT NULL,
+            image_id INTEGER NOT NULL,
+            PRIMARY KEY (folder_id, image_id),
+            FOREIGN KEY (folder_id) REFERENCES folders(id),
+            FOREIGN KEY (image_id) REFERENCES images(id)
+        )",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS hidden_random_images (
+            folder_id INTEGER NOT NULL,
+            image_id INTEGER NOT NULL,
+            PRIMARY KEY (folder_id, image_id),
+            FOREIGN KEY (folder_id) REFERENCES folders(id),
+            FOREIGN KEY (image_id) REFERENCES images(id)
+        )",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_hidden_normal_folder ON hidden_normal_images(folder_id)",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_hidden_normal_image ON hidden_normal_images(image_id)",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_hidden_random_folder ON hidden_random_images(folder_id)",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_hidden_random_image ON hidden_random_images(image_id)",
+        rusqlite::params![],
+    )?;
+    Ok(())
+}
 
-        self.execute(
-            "CREATE TABLE IF NOT EXISTS active_images (
-                image_id INTEGER PRIMARY KEY,
-                FOREIGN KEY (image_id) REFERENCES images(id)
-            )",
-            rusqlite::params![],
-        )?;
-        self.execute(
-            "CREATE TABLE IF NOT EXISTS active_image_refcounts (
-                image_id INTEGER PRIMARY KEY,
-                refcount INTEGER NOT NULL,
-                FOREIGN KEY (image_id) REFERENCES images(id)
-            )",
-            rusqlite::params![],
-        )?;
-        self.execute(
-            "CREATE TABLE IF NOT EXISTS folder_closure (
-                ancestor_path TEXT NOT NULL,
-                descendant_path TEXT NOT NULL,
-                PRIMARY KEY (ancestor_path, descendant_path),
-                FOREIGN KEY (ancestor_path) REFERENCES folder_nodes(path),
-                FOREIGN KEY (descendant_path) REFERENCES folder_nodes(path)
-            )",
-            rusqlite::params![],
-        )?;
-        self.execute(
-            "CREATE INDEX IF NOT EXISTS idx_folder_closure_descendant ON folder_closure(descendant_path)",
-            rusqlite::params![],
-        )?;
-        self.execute(
-            "CREATE TABLE IF NOT EXISTS folder_images_direct (
-                folder_path TEXT NOT NULL,
-                image_id INTEGER NOT NULL,
-                PRIMARY KEY (folder_path, image_id),
-                FOREIGN KEY (folder_path) REFERENCES folder_nodes(path),
-                FOREIGN KEY (image_id) REFERENCES images(id)
-            )",
-            rusqlite::params![],
-        )?;
-        self.execute(
-            "CREATE INDEX IF NOT EXISTS idx_folder_images_direct_image ON folder_images_direct(image_id)",
-            rusqlite::params![],
-        )?;
+fn ensure_selection_tables_and_indexes(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS folder_nodes (
+            path TEXT PRIMARY KEY,
+            parent_path TEXT,
+            root_folder_id INTEGER NOT NULL,
+            subtree_image_count INTEGER NOT NULL DEFAULT 0,
+            FOREIGN KEY (root_folder_id) REFERENCES folders(id)
+        )",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_folder_nodes_parent_path ON folder_nodes(parent_path)",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_folder_nodes_root_folder_id ON folder_nodes(root_folder_id)",
+        rusqlite::params![],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS checked_folders (
+            path TEXT PRIMARY KEY,
+            FOREIGN KEY (path) REFERENCES folder_nodes(path)
+        )",
+        rusqlite::params![],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS active_images (
+            image_id INTEGER PRIMARY KEY,
+            FOREIGN KEY (image_id) REFERENCES images(id)
+        )",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS active_image_refcounts (
+            image_id INTEGER PRIMARY KEY,
+            refcount INTEGER NOT NULL,
+            FOREIGN KEY (image_id) REFERENCES images(id)
+        )",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS folder_closure (
+            ancestor_path TEXT NOT NULL,
+            descendant_path TEXT NOT NULL,
+            PRIMARY KEY (ancestor_path, descendant_path),
+            FOREIGN KEY (ancestor_path) REFERENCES folder_nodes(path),
+            FOREIGN KEY (descendant_path) REFERENCES folder_nodes(path)
+        )",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_folder_closure_descendant ON folder_closure(descendant_path)",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS folder_images_direct (
+            folder_path TEXT NOT NULL,
+            image_id INTEGER NOT NULL,
+            PRIMARY KEY (folder_path, image_id),
+            FOREIGN KEY (folder_path) REFERENCES folder_nodes(path),
+            FOREIGN KEY (image_id) REFERENCES images(id)
+        )",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_folder_images_direct_image ON folder_images_direct(image_id)",
+        rusqlite::params![],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS random_history_global (
+            order_index INTEGER PRIMARY KEY,
+            image_id INTEGER NOT NULL,
+            FOREIGN KEY (image_id) REFERENCES images(id)
+        )",
+        rusqlite::params![],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS current_lap_global (
+            image_id INTEGER PRIMARY KEY,
+            FOREIGN KEY (image_id) REFERENCES images(id)
+        )",
+        rusqlite::params![],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_images_path ON images(path)",
+        rusqlite::params![],
+    )?;
+    Ok(())
+}
 
-        self.execute(
-            "CREATE TABLE IF NOT EXISTS random_history_global (
-                order_index INTEGER PRIMARY KEY,
-                image_id INTEGER NOT NULL,
-                FOREIGN KEY (image_id) REFERENCES images(id)
-            )",
-            rusqlite::params![],
-        )?;
+fn sqlite_object_exists(conn: &Connection, object_type: &str, name: &str) -> Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = ?1 AND name = ?2",
+        rusqlite::params![object_type, name],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
 
-        self.execute(
-            "CREATE TABLE IF NOT EXISTS current_lap_global (
-                image_id INTEGER PRIMARY KEY,
-                FOREIGN KEY (image_id) REFERENCES images(id)
-            )",
-            rusqlite::params![],
-        )?;
+fn ensure_state_column(conn: &Connection, column_name: &str, column_def: &str) -> Result<()> {
+    ensure_table_column(conn, "state", column_name, column_def)
+}
 
-        self.execute(
-            "CREATE INDEX IF NOT EXISTS idx_images_path ON images(path)",
+fn ensure_table_column(
+    conn: &Connection,
+    table_name: &str,
+    column_name: &str,
+    column_def: &str,
+) -> Result<()> {
+    let column_count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+        rusqlite::params![table_name, column_name],
+        |row: &rusqlite::Row| row.get(0),
+    )?;
+
+    if column_count == 0 {
+        let full_def = format!("{} {}", column_name, column_def);
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {}", table_name, full_def),
             rusqlite::params![],
         )?;
-        Ok(())
     }
 
-    fn ensure_state_column(&self, column_name: &str, column_def: &str) -> Result<()> {
-        let column_exists: Result<i64> = self.query_row(
-            "SELECT COUNT(*) FROM pragma_table_info('state') WHERE name = ?1",
-            rusqlite::params![column_name],
-            |row: &rusqlite::Row| row.get(0),
-        );
-
-        if let Ok(0) = column_exists {
-            let full_def = format!("{} {}", column_name, column_def);
-            self.execute(
-                &format!("ALTER TABLE state ADD COLUMN {}", full_def),
-                rusqlite::params![],
-            )?;
-        }
-
-        Ok(())
-    }
+    Ok(())
 }
 
-use tauri::Manager;
-
-pub fn get_db_path(app: &AppHandle) -> Result<PathBuf, Box<dyn std::error::Error>> {
-    let app_data_dir = app.path().app_data_dir()?;
-    std::fs::create_dir_all(&app_data_dir)?;
-    Ok(app_data_dir.join("imgstate.sqlite"))
-}
+pub const DB_FILE_NAME: &str = "imgstate.sqlite";
 
 #[cfg(test)]
 mod tests {
-    use super::Db;
+    use super::{Db, MIGRATIONS, SCHEMA_VERSION};
+    use crate::test_support::unique_temp_path;
     use rusqlite::{params, Connection};
-    use std::path::PathBuf;
-    use std::sync::atomic::{AtomicU64, Ordering};
-    use std::time::{SystemTime, UNIX_EPOCH};
-
-    static TEST_COUNTER: AtomicU64 = AtomicU64::new(0);
-
-    fn unique_temp_db_path(name: &str) -> PathBuf {
-        let counter = TEST_COUNTER.fetch_add(1, Ordering::Relaxed);
-        let nanos = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_nanos();
-        std::env::temp_dir().join(format!(
-            "random_pics_{}_{}_{}_{}.sqlite",
-            name,
-            std::process::id(),
-            nanos,
-            counter
-        ))
-    }
+    use std::path::{Path, PathBuf};
 
     #[test]
     fn open_initializes_schema_and_state_row() {
-        let db_path = unique_temp_db_path("schema_init");
+        let db_path = unique_temp_path("db", "schema_init").with_extension("sqlite");
         let db = Db::open(db_path.clone()).expect("db open should succeed");
 
         let conn = db.conn();
@@ -703,12 +878,12 @@ mod tests {
         assert_eq!(state_row, 1);
 
         drop(conn);
-        std::fs::remove_file(db_path).expect("temp db should be removable");
+        remove_db_and_backups(&db_path);
     }
 
     #[test]
     fn open_adds_missing_state_columns_via_migration() {
-        let db_path = unique_temp_db_path("state_migration");
+        let db_path = unique_temp_path("db", "state_migration").with_extension("sqlite");
 
         let legacy = Connection::open(&db_path).expect("legacy db should open");
         legacy
@@ -764,12 +939,12 @@ mod tests {
         assert_eq!(shortcut_side_col, 1);
 
         drop(conn);
-        std::fs::remove_file(db_path).expect("temp db should be removable");
+        remove_db_and_backups(&db_path);
     }
 
     #[test]
     fn open_migrates_global_unique_image_path_to_folder_scoped_index() {
-        let db_path = unique_temp_db_path("images_unique_migration");
+        let db_path = unique_temp_path("db", "images_unique_migration").with_extension("sqlite");
 
         let legacy = Connection::open(&db_path).expect("legacy db should open");
         legacy
@@ -862,6 +1037,175 @@ mod tests {
         assert_eq!(shared_count, 2);
 
         drop(conn);
+        remove_db_and_backups(&db_path);
+    }
+
+    fn remove_db_and_backups(db_path: &Path) {
+        for entry in crate::backup::list_backups(db_path).expect("backups should list") {
+            std::fs::remove_file(crate::backup::backup_dir(db_path).join(entry.name))
+                .expect("backup should be removable");
+        }
         std::fs::remove_file(db_path).expect("temp db should be removable");
+        // Left behind only if a connection was not closed cleanly.
+        for suffix in ["-wal", "-shm"] {
+            let mut sidecar = db_path.as_os_str().to_owned();
+            sidecar.push(suffix);
+            let _ = std::fs::remove_file(PathBuf::from(sidecar));
+        }
+    }
+
+    fn open_fixture(name: &str, sql: &str) -> (Db, PathBuf) {
+        let db_path = unique_temp_path("db", name).with_extension("sqlite");
+        let fixture = Connection::open(&db_path).expect("fixture db should open");
+        // Old layouts may carry foreign keys that no longer resolve.
+        fixture
+            .execute_batch("PRAGMA foreign_keys = OFF")
+            .expect("foreign keys should be switchable");
+        fixture.execute_batch(sql).expect("fixture should load");
+        drop(fixture);
+        let db = Db::open(db_path.clone()).expect("db open should migrate fixture");
+        (db, db_path)
+    }
+
+    #[test]
+    fn migrations_are_numbered_consecutively() {
+        for (idx, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, idx as i64 + 1, "{}", migration.name);
+        }
+        assert_eq!(MIGRATIONS.last().map(|m| m.version), Some(SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn open_upgrades_every_released_schema_without_losing_data() {
+        let fixtures = [
+            ("fixture_pre_1_1_0", include_str!("../fixtures/db/pre_1_1_0.sql")),
+            ("fixture_1_1_0", include_str!("../fixtures/db/1_1_0.sql")),
+        ];
+        for (name, sql) in fixtures {
+            let (db, db_path) = open_fixture(name, sql);
+            let conn = db.conn();
+            let count = |sql: &str| -> i64 {
+                conn.query_row(sql, [], |row| row.get(0))
+                    .unwrap_or_else(|err| panic!("{name}: {sql}: {err}"))
+            };
+
+            assert_eq!(count("PRAGMA user_version"), SCHEMA_VERSION, "{name}");
+            assert_eq!(count("SELECT COUNT(*) FROM folders"), 1, "{name}");
+            assert_eq!(count("SELECT COUNT(*) FROM images"), 3, "{name}");
+            assert_eq!(count("SELECT COUNT(*) FROM random_history"), 2, "{name}");
+            assert_eq!(count("SELECT COUNT(*) FROM current_lap"), 2, "{name}");
+            assert_eq!(count("SELECT COUNT(*) FROM hidden_normal_images"), 1, "{name}");
+            assert_eq!(count("SELECT COUNT(*) FROM hidden_random_images"), 1, "{name}");
+            assert_eq!(count("SELECT greyscale FROM state WHERE id = 1"), 1, "{name}");
+            assert_eq!(count("SELECT current_folder_id FROM state WHERE id = 1"), 1, "{name}");
+            assert_eq!(
+                count("SELECT COUNT(*) FROM pragma_index_list('images') WHERE origin = 'u'"),
+                0,
+                "{name}"
+            );
+            for table in ["random_history", "current_lap", "hidden_normal_images"] {
+                assert_eq!(
+                    count(&format!(
+                        "SELECT COUNT(*) FROM pragma_foreign_key_list('{table}') WHERE \"table\" = 'images_old'"
+                    )),
+                    0,
+                    "{name}: {table}"
+                );
+            }
+            assert_eq!(
+                count("SELECT COUNT(*) FROM pragma_table_info('folders') WHERE name IN ('normal_sort_order', 'follow_symlinks', 'offline', 'last_seen_at')"),
+                4,
+                "{name}"
+            );
+            assert_eq!(
+                count("SELECT COUNT(*) FROM folders WHERE indexed_at IS NOT NULL"),
+                1,
+                "{name}"
+            );
+            assert_eq!(count("SELECT COUNT(*) FROM image_tags"), 0, "{name}");
+            assert_eq!(
+                count("SELECT COUNT(*) FROM images_fts WHERE images_fts MATCH '\"trip\"'"),
+                1,
+                "{name}"
+            );
+
+            drop(conn);
+            drop(db);
+            let backups = crate::backup::list_backups(&db_path).expect("backups should list");
+            assert_eq!(backups.len(), 1, "{name}");
+            assert_eq!(backups[0].reason, "migration", "{name}");
+            let snapshot = Connection::open(crate::backup::backup_dir(&db_path).join(&backups[0].name))
+                .expect("snapshot should open");
+            let snapshot_version: i64 = snapshot
+                .query_row("PRAGMA user_version", [], |row| row.get(0))
+                .expect("snapshot version should read");
+            assert_eq!(snapshot_version, 0, "{name}");
+            drop(snapshot);
+
+            let reopened = Db::open(db_path.clone()).expect("migrated db should reopen");
+            drop(reopened);
+            assert_eq!(
+                crate::backup::list_backups(&db_path).expect("backups should list").len(),
+                1,
+                "{name}: an up-to-date database is not backed up again"
+            );
+            remove_db_and_backups(&db_path);
+        }
+    }
+
+    #[test]
+    fn open_refuses_database_from_newer_schema() {
+        let db_path = unique_temp_path("db", "newer_schema").with_extension("sqlite");
+        let newer = Connection::open(&db_path).expect("newer db should open");
+        newer
+            .pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .expect("user_version should be settable");
+        drop(newer);
+
+        assert!(Db::open(db_path.clone()).is_err());
+
+        remove_db_and_backups(&db_path);
+    }
+
+    #[test]
+    fn readers_see_committed_state_while_the_writer_holds_a_transaction() {
+        let db_path = unique_temp_path("db", "wal_readers").with_extension("sqlite");
+        let db = Db::open(db_path.clone()).expect("db open should succeed");
+
+        let journal_mode: String = db
+            .read()
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .expect("journal mode should be readable");
+        assert_eq!(journal_mode.to_lowercase(), "wal");
+        for foreign_keys in [
+            db.conn().query_row("PRAGMA foreign_keys", [], |row| row.get::<_, i64>(0)),
+            db.read().query_row("PRAGMA foreign_keys", [], |row| row.get::<_, i64>(0)),
+        ] {
+            assert_eq!(foreign_keys.expect("pragma should be readable"), 1);
+        }
+        assert!(db
+            .read()
+            .execute("INSERT INTO folders (path, added_at) VALUES ('/x', 'now')", [])
+            .is_err());
+
+        let folder_count = |db: &Db| -> i64 {
+            db.read()
+                .query_row("SELECT COUNT(*) FROM folders", [], |row| row.get(0))
+                .expect("reader query should not block")
+        };
+        let mut writer = db.conn();
+        let tx = writer.transaction().expect("transaction should start");
+        tx.execute(
+            "INSERT INTO folders (path, added_at) VALUES (?1, 'now')",
+            params!["/photos"],
+        )
+        .expect("folder insert should succeed");
+        assert_eq!(folder_count(&db), 0);
+        tx.commit().expect("commit should succeed");
+        drop(writer);
+        assert_eq!(folder_count(&db), 1);
+
+        drop(db);
+        remove_db_and_backups(&db_path);
     }
 }
@@ -0,0 +1,175 @@
+use crate::backup::BackupReason;
+use crate::cli;
+use crate::commands::{self, ImageLoaderState, ProfilesState, RemoteHubState, RemoteServerState};
+use crate::db::Db;
+use crate::instance::{self, Launch};
+use crate::profiles::Profiles;
+use crate::remote;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_global_shortcut::{Code, GlobalShortcutExt, Shortcut, ShortcutState};
+
+/// Where the databases live; see `profiles` for the layout inside it.
+fn get_data_dir(app: &AppHandle) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let app_data_dir = app.path().app_data_dir()?;
+    std::fs::create_dir_all(&app_data_dir)?;
+    Ok(app_data_dir)
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    let actions = instance::parse_launch_args(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("[RUST] Ignoring the command line: {}", err);
+        Vec::new()
+    });
+    // One window per data directory; later launches hand over their
+    // arguments and exit.
+    let primary = match cli::default_data_dir().map(|dir| {
+        std::fs::create_dir_all(&dir)?;
+        instance::claim(&dir, &actions)
+    }) {
+        Some(Ok(Launch::Forwarded)) => return,
+        Some(Ok(Launch::Primary(primary, forwarded))) => Some((primary, forwarded)),
+        Some(Err(err)) => {
+            eprintln!("[RUST] Single-instance check failed: {}", err);
+            None
+        }
+        None => None,
+    };
+    let f11 = Shortcut::new(None, Code::F11);
+    let f11_for_handler = f11.clone();
+
+    tauri::Builder::default()
+        .manage(ImageLoaderState::new(std::sync::RwLock::new(None)))
+        .manage(RemoteHubState::default())
+        .manage(RemoteServerState::default())
+        .plugin(
+            tauri_plugin_global_shortcut::Builder::new()
+                .with_handler(move |app, shortcut, event| {
+                    if shortcut == &f11_for_handler && event.state() == ShortcutState::Pressed {
+                        if let Some(win) = app.get_webview_window("main") {
+                            if let Ok(is_fullscreen) = win.is_fullscreen() {
+                                let _ = win.set_fullscreen(!is_fullscreen);
+                            }
+                        }
+                    }
+                })
+                .build(),
+        )
+        .plugin(tauri_plugin_window_state::Builder::default().build())
+        .setup(move |app| {
+            let profiles = Profiles::new(get_data_dir(app.handle())?);
+            let profile = profiles.active()?;
+            eprintln!("[RUST] Using profile {}", profile);
+            let db = Db::open(profiles.db_path(&profile))?;
+            let remote_settings = remote::load_settings(profiles.data_dir())?;
+            app.manage(ProfilesState::new(Mutex::new(profiles)));
+            let loader = commands::open_loader(db, &app.state::<RemoteHubState>());
+            if let Err(err) = loader.backup_database(BackupReason::Startup) {
+                eprintln!("[RUST] Startup backup failed: {}", err);
+            }
+            *app.state::<ImageLoaderState>()
+                .write()
+                .map_err(|_| std::io::Error::other("image loader state lock poisoned"))? =
+                Some(loader);
+
+            if cfg!(debug_assertions) {
+                app.handle().plugin(
+                    tauri_plugin_log::Builder::default()
+                        .level(log::LevelFilter::Info)
+                        .build(),
+                )?;
+            }
+            app.handle().plugin(tauri_plugin_dialog::init())?;
+            if let Err(err) = commands::apply_remote_settings(app.handle(), &remote_settings) {
+                eprintln!("[RUST] Remote control unavailable: {}", err.message);
+            }
+            #[cfg(target_os = "linux")]
+            match commands::start_mpris_player(app.handle()) {
+                Ok(player) => {
+                    app.manage(player);
+                }
+                Err(err) => eprintln!("[RUST] MPRIS player unavailable: {}", err.message),
+            }
+            if let Err(err) = app.global_shortcut().register(f11.clone()) {
+                eprintln!("[RUST] Global F11 shortcut unavailable: {}", err);
+            }
+
+            let app_handle = app.handle().clone();
+            std::thread::spawn(move || {
+                commands::handle_launch_actions(&app_handle, actions);
+                if let Some((_primary, forwarded)) = primary {
+                    for actions in forwarded {
+                        commands::handle_launch_actions(&app_handle, actions);
+                    }
+                }
+            });
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            commands::pick_folder,
+            commands::next_folder,
+            commands::prev_folder,
+            commands::get_folder_history,
+            commands::reindex_current_folder,
+            commands::cancel_indexing,
+            commands::get_current_image,
+            commands::get_current_random_image,
+            commands::get_next_image,
+            commands::get_prev_image,
+            commands::get_next_random_image,
+            commands::get_prev_random_image,
+            commands::get_force_random_image,
+            commands::get_normal_history,
+            commands::get_random_history,
+            commands::get_normal_history_window,
+            commands::get_random_history_window,
+            commands::hide_normal_history_image,
+            commands::hide_random_history_image,
+            commands::reset_normal_history,
+            commands::reset_random_history,
+            commands::get_image_state,
+            commands::set_image_state,
+            commands::get_random_selection_settings,
+            commands::set_random_selection_settings,
+            commands::get_indexing_settings,
+            commands::set_indexing_settings,
+            commands::full_wipe,
+            commands::export_library,
+            commands::import_library,
+            commands::list_backups,
+            commands::check_integrity,
+            commands::restore_backup,
+            commands::list_profiles,
+            commands::create_profile,
+            commands::switch_profile,
+            commands::rename_profile,
+            commands::delete_profile,
+            commands::get_remote_settings,
+            commands::set_remote_settings,
+            commands::report_timer_status,
+            commands::is_healthy,
+            commands::set_folder_by_index,
+            commands::set_normal_image_by_index,
+            commands::set_random_image_by_index,
+            commands::set_normal_image_by_id,
+            commands::open_image_by_id,
+            commands::open_image_by_path,
+            commands::search_images,
+            commands::get_current_folder,
+            commands::delete_folder,
+            commands::cleanup_stale_folders,
+            commands::relocate_folder,
+            commands::get_folder_sort_order,
+            commands::set_folder_sort_order,
+            commands::get_folder_follow_symlinks,
+            commands::set_folder_follow_symlinks,
+            commands::get_folder_tree,
+            commands::set_folder_checked,
+            commands::set_folder_exclusive,
+            commands::play_timer_tone,
+        ])
+        .run(tauri::generate_context!())
+        .unwrap_or_else(|err| eprintln!("error while running tauri application: {}", err));
+}
@@ -0,0 +1,153 @@
+use serde_json::json;
+use std::fmt;
+
+/// Which history/hide list an error refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageMode {
+    Normal,
+    Random,
+}
+
+impl ImageMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ImageMode::Normal => "normal",
+            ImageMode::Random => "random",
+        }
+    }
+}
+
+impl fmt::Display for ImageMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoaderError {
+    #[error("No folders selected. Check at least one folder.")]
+    NoFoldersSelected,
+    #[error("no folder selected - pick a folder first")]
+    NoCurrentFolder,
+    #[error("no folders available - pick a folder first")]
+    NoFoldersAvailable,
+    #[error("all folders in history no longer exist - pick a folder first")]
+    AllFoldersMissing,
+    #[error("folder no longer exists: {path}")]
+    FolderMissing { path: String },
+    #[error("folder is offline: {path} - reconnect the drive or relocate the folder")]
+    FolderOffline { path: String },
+    #[error("folder path is unreadable: {path}")]
+    FolderUnreadable { path: String },
+    #[error("invalid path: {path}")]
+    InvalidPath { path: String },
+    #[error("path is not a directory: {path}")]
+    NotADirectory { path: String },
+    #[error("image path is not a file: {path}")]
+    NotAFile { path: String },
+    #[error("unsupported image type: {path}")]
+    UnsupportedType { path: String },
+    #[error("no images found in folder")]
+    NoImages,
+    #[error(
+        "all images for this folder are hidden in {mode} mode - reindex to clear hidden images"
+    )]
+    AllHidden { mode: ImageMode },
+    #[error("image file not found: {path} - reindex please")]
+    ImageMissing { path: String },
+    #[error("failed to read image: {path}: {reason} - reindex please")]
+    ImageUnreadable { path: String, reason: String },
+    #[error("image is hidden in {mode} mode: {path}")]
+    ImageHidden { path: String, mode: ImageMode },
+    #[error("image is outside the checked folders: {path}")]
+    ImageNotChecked { path: String },
+    #[error("indexing cancelled")]
+    Cancelled,
+    #[error("{0} - reindex please")]
+    ReindexRequired(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Db(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl LoaderError {
+    /// Stable, machine-readable identifier sent to the frontend.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LoaderError::NoFoldersSelected => "no_folders_selected",
+            LoaderError::NoCurrentFolder => "no_current_folder",
+            LoaderError::NoFoldersAvailable => "no_folders_available",
+            LoaderError::AllFoldersMissing => "all_folders_missing",
+            LoaderError::FolderMissing { .. } => "folder_missing",
+            LoaderError::FolderOffline { .. } => "folder_offline",
+            LoaderError::FolderUnreadable { .. } => "folder_unreadable",
+            LoaderError::InvalidPath { .. } => "invalid_path",
+            LoaderError::NotADirectory { .. } => "not_a_directory",
+            LoaderError::NotAFile { .. } => "not_a_file",
+            LoaderError::UnsupportedType { .. } => "unsupported_type",
+            LoaderError::NoImages => "no_images",
+            LoaderError::AllHidden { .. } => "all_hidden",
+            LoaderError::ImageMissing { .. } => "image_missing",
+            LoaderError::ImageUnreadable { .. } => "image_unreadable",
+            LoaderError::ImageHidden { .. } => "image_hidden",
+            LoaderError::ImageNotChecked { .. } => "image_not_checked",
+            LoaderError::Cancelled => "cancelled",
+            LoaderError::ReindexRequired(_) => "reindex_required",
+            LoaderError::NotFound(_) => "not_found",
+            LoaderError::Invalid(_) => "invalid_argument",
+            LoaderError::Db(rusqlite::Error::QueryReturnedNoRows) => "not_found",
+            LoaderError::Db(err) if is_constraint_violation(err) => "db_constraint",
+            LoaderError::Db(_) => "db",
+            LoaderError::Io(_) => "io",
+        }
+    }
+
+    /// Structured fields the frontend can act on without parsing the message.
+    pub fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            LoaderError::AllHidden { mode } => Some(json!({ "mode": mode.as_str() })),
+            LoaderError::FolderMissing { path }
+            | LoaderError::FolderOffline { path }
+            | LoaderError::FolderUnreadable { path }
+            | LoaderError::InvalidPath { path }
+            | LoaderError::NotADirectory { path }
+            | LoaderError::NotAFile { path }
+            | LoaderError::UnsupportedType { path }
+            | LoaderError::ImageMissing { path }
+            | LoaderError::ImageNotChecked { path } => Some(json!({ "path": path })),
+            LoaderError::ImageHidden { path, mode } => {
+                Some(json!({ "path": path, "mode": mode.as_str() }))
+            }
+            LoaderError::ImageUnreadable { path, reason } => {
+                Some(json!({ "path": path, "reason": reason }))
+            }
+            _ => None,
+        }
+    }
+
+    /// Message shown to the user; database errors get a friendlier wording.
+    pub fn user_message(&self) -> String {
+        match self {
+            LoaderError::Db(rusqlite::Error::QueryReturnedNoRows) => {
+                "no data found - folder or image may have been deleted".to_string()
+            }
+            LoaderError::Db(err) if is_constraint_violation(err) => {
+                "database constraint failed - try resetting history or reindexing".to_string()
+            }
+            other => other.to_string(),
+        }
+    }
+}
+
+fn is_constraint_violation(err: &rusqlite::Error) -> bool {
+    matches!(
+        err,
+        rusqlite::Error::SqliteFailure(inner, _)
+            if inner.code == rusqlite::ErrorCode::ConstraintViolation
+    )
+}
@@ -1,57 +1,313 @@
-use crate::db::Db;
+use crate::backup::{self, BackupEntry, BackupReason};
+use crate::types::{IntegrityIssue, IntegrityReport};
+use crate::db::{Db, SCHEMA_VERSION};
+use crate::error::{ImageMode, LoaderError};
+use crate::library_io::{
+    LibraryDocument, LibraryFolder, LibraryHistoryEntry, LibraryImage, LibraryImageTag,
+    LibraryImageView, PathMapping, LIBRARY_FORMAT, LIBRARY_VERSION,
+};
+use crate::integrity::{self, Repair, INVARIANT_CHECKS};
+use crate::indexing::{
+    dedup_by_canonical_path, validate_ignore_patterns, IndexingJob, IndexingJobs, IndexingPhase,
+    ScanRules, ScanWalkDir,
+};
+use rusqlite::functions::FunctionFlags;
 use rusqlite::{params, OptionalExtension};
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
-use std::path::Path;
-use walkdir::WalkDir;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Rows per transaction when inserting scan results.
+const INDEX_INSERT_CHUNK_SIZE: usize = 2000;
+
+const MAX_TAG_LEN: usize = 64;
+
+/// Called with the id and path of every image the loader hands out.
+pub type ImageListener = Box<dyn Fn(i64, &str) + Send + Sync>;
 
 pub struct ImageLoader {
     db: Db,
+    indexing_jobs: IndexingJobs,
+    image_listener: RwLock<Option<ImageListener>>,
 }
 
-const NO_FOLDERS_SELECTED_ERROR: &str = "No folders selected. Check at least one folder.";
-
-#[derive(Clone, Copy)]
-enum HiddenImageTable {
-    Normal,
-    Random,
+/// Per-root ordering of the normal (sequential) traversal list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NormalSortOrder {
+    Path,
+    Natural,
+    Mtime,
+    Size,
+    Dimensions,
+    Shuffle,
 }
 
-impl HiddenImageTable {
-    fn select_sql(self) -> &'static str {
+impl NormalSortOrder {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "path" => Some(NormalSortOrder::Path),
+            "natural" => Some(NormalSortOrder::Natural),
+            "mtime" => Some(NormalSortOrder::Mtime),
+            "size" => Some(NormalSortOrder::Size),
+            "dimensions" => Some(NormalSortOrder::Dimensions),
+            "shuffle" => Some(NormalSortOrder::Shuffle),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
         match self {
-            HiddenImageTable::Normal => {
-                "SELECT image_id FROM hidden_normal_images WHERE folder_id = ?1"
+            NormalSortOrder::Path => "path",
+            NormalSortOrder::Natural => "natural",
+            NormalSortOrder::Mtime => "mtime",
+            NormalSortOrder::Size => "size",
+            NormalSortOrder::Dimensions => "dimensions",
+            NormalSortOrder::Shuffle => "shuffle",
+        }
+    }
+
+    fn needs_file_metadata(self) -> bool {
+        matches!(self, NormalSortOrder::Mtime | NormalSortOrder::Size)
+    }
+
+    fn needs_dimensions(self) -> bool {
+        matches!(self, NormalSortOrder::Dimensions)
+    }
+}
+
+/// How `search_images` interprets its query.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchMode {
+    Substring,
+    Glob,
+    Fuzzy,
+}
+
+impl SearchMode {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "substring" => Some(SearchMode::Substring),
+            "glob" => Some(SearchMode::Glob),
+            "fuzzy" => Some(SearchMode::Fuzzy),
+            _ => None,
+        }
+    }
+}
+
+/// Scores `target` as a case-insensitive subsequence match of `query`; higher is
+/// better. Consecutive characters and matches at word starts score extra.
+fn fuzzy_subsequence_score(query: &str, target: &str) -> Option<i64> {
+    let query = query.to_lowercase().chars().collect::<Vec<_>>();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let mut score = 0;
+    let mut query_idx = 0;
+    let mut prev_matched = false;
+    let mut prev_char: Option<char> = None;
+    for c in target.to_lowercase().chars() {
+        if query_idx < query.len() && c == query[query_idx] {
+            score += 1;
+            if prev_matched {
+                score += 5;
+            }
+            if prev_char.map_or(true, |p| matches!(p, '/' | '\\' | '_' | '-' | ' ' | '.')) {
+                score += 3;
+            }
+            query_idx += 1;
+            prev_matched = true;
+        } else {
+            prev_matched = false;
+        }
+        prev_char = Some(c);
+    }
+
+    if query_idx == query.len() {
+        Some(score)
+    } else {
+        None
+    }
+}
+
+/// Fuzzy score of an image path, preferring hits inside the file name over
+/// hits spread across directories.
+fn fuzzy_path_score(query: &str, path: &str) -> Option<i64> {
+    let file_name = Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("");
+    fuzzy_subsequence_score(query, file_name)
+        .map(|score| score + 100)
+        .or_else(|| fuzzy_subsequence_score(query, path))
+}
+
+/// Visible images in the checked scope; images under several checked folders
+/// appear once. Ordered by `NORMAL_ORDER_SQL`.
+const VISIBLE_NORMAL_ENTRIES_SQL: &str = "
+    FROM folder_images_direct fi
+    JOIN images i ON i.id = fi.image_id
+    LEFT JOIN folders r ON r.id = i.folder_id
+    WHERE EXISTS (
+        SELECT 1 FROM folder_closure c
+        JOIN checked_folders cf ON cf.path = c.ancestor_path
+        WHERE c.descendant_path = fi.folder_path
+    )
+    AND NOT EXISTS (SELECT 1 FROM hidden_normal_images h WHERE h.image_id = i.id)
+    GROUP BY i.id";
+
+/// Normal traversal order: grouped by root (in root path order), each root
+/// sorted by its configured order. `register_sql_functions` installs the
+/// `natural_path` collation and the `shuffle_key` function it uses.
+const NORMAL_ORDER_SQL: &str = "
+    r.path COLLATE NOCASE, i.folder_id,
+        CASE r.normal_sort_order
+            WHEN 'mtime' THEN COALESCE(i.mtime, 0)
+            WHEN 'size' THEN COALESCE(i.file_size, 0)
+            WHEN 'dimensions' THEN COALESCE(i.width, 0) * COALESCE(i.height, 0)
+            WHEN 'shuffle' THEN shuffle_key(r.normal_shuffle_seed, i.path)
+        END,
+        CASE WHEN r.normal_sort_order IN ('natural', 'mtime', 'size', 'dimensions')
+            THEN i.path END COLLATE natural_path,
+        CASE WHEN r.normal_sort_order IN ('natural', 'mtime', 'size', 'dimensions', 'shuffle')
+            THEN NULL ELSE fi.folder_path END COLLATE NOCASE,
+        CASE WHEN r.normal_sort_order IN ('natural', 'mtime', 'size', 'dimensions', 'shuffle')
+            THEN NULL ELSE i.path END COLLATE NOCASE,
+        i.id";
+
+/// Installs the collation and functions the loader's queries use on a
+/// connection, so sorting and scoring happen before rows leave SQLite.
+pub(crate) fn register_sql_functions(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+    conn.create_collation("natural_path", natural_cmp)?;
+    conn.create_scalar_function("shuffle_key", 2, flags, |ctx| {
+        let seed: i64 = ctx.get(0)?;
+        let path = ctx.get_raw(1).as_str().unwrap_or_default();
+        // Flipping the sign bit keeps the u64 order in SQLite's i64.
+        Ok((shuffle_key(seed, path) ^ (1 << 63)) as i64)
+    })?;
+    conn.create_scalar_function("fuzzy_path_score", 2, flags, |ctx| {
+        let query = ctx.get_raw(0).as_str().unwrap_or_default();
+        let path = ctx.get_raw(1).as_str().unwrap_or_default();
+        Ok(fuzzy_path_score(query, path))
+    })
+}
+
+/// Case-insensitive comparison that orders digit runs numerically, so `img2`
+/// sorts before `img10`.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let mut digits_a = String::new();
+                while let Some(c) = a_chars.peek().copied().filter(|c| c.is_ascii_digit()) {
+                    digits_a.push(c);
+                    a_chars.next();
+                }
+                let mut digits_b = String::new();
+                while let Some(c) = b_chars.peek().copied().filter(|c| c.is_ascii_digit()) {
+                    digits_b.push(c);
+                    b_chars.next();
+                }
+
+                let trimmed_a = digits_a.trim_start_matches('0');
+                let trimmed_b = digits_b.trim_start_matches('0');
+                let ord = trimmed_a
+                    .len()
+                    .cmp(&trimmed_b.len())
+                    .then_with(|| trimmed_a.cmp(trimmed_b))
+                    .then_with(|| digits_a.len().cmp(&digits_b.len()));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
             }
-            HiddenImageTable::Random => {
-                "SELECT image_id FROM hidden_random_images WHERE folder_id = ?1"
+            (Some(ca), Some(cb)) => {
+                let ord = ca.to_lowercase().cmp(cb.to_lowercase());
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a_chars.next();
+                b_chars.next();
             }
         }
     }
 }
 
-unsafe impl Send for ImageLoader {}
-unsafe impl Sync for ImageLoader {}
+/// Start of a history window: the requested offset, or one that centers the
+/// window on `current` (-1 when there is no pointer), clamped to the list.
+fn history_window_offset(total: i64, current: i64, offset: Option<i64>, limit: i64) -> i64 {
+    let last_start = (total - limit).max(0);
+    let start = offset.unwrap_or(if current >= 0 { current - limit / 2 } else { 0 });
+    start.clamp(0, last_start)
+}
+
+/// Trims a tag and rejects empty, overlong or control-character tags.
+fn normalize_tag(raw: &str) -> Result<String, LoaderError> {
+    let tag = raw.trim();
+    if tag.is_empty() {
+        return Err(LoaderError::Invalid("tag must not be empty".to_string()));
+    }
+    if tag.chars().count() > MAX_TAG_LEN {
+        return Err(LoaderError::Invalid(format!(
+            "tag must be at most {} characters",
+            MAX_TAG_LEN
+        )));
+    }
+    if tag.chars().any(char::is_control) {
+        return Err(LoaderError::Invalid(
+            "tag must not contain control characters".to_string(),
+        ));
+    }
+    Ok(tag.to_string())
+}
+
+/// Deterministic per-seed sort key for the stable shuffle order.
+fn shuffle_key(seed: i64, path: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325 ^ (seed as u64);
+    for byte in path.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash ^= hash >> 30;
+    hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash ^= hash >> 27;
+    hash = hash.wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
 
 impl ImageLoader {
-    fn canonicalize_folder_path(path: &str) -> Result<String, Box<dyn std::error::Error>> {
+    fn canonicalize_folder_path(path: &str) -> Result<String, LoaderError> {
         let trimmed = path.trim();
+        let invalid = || LoaderError::InvalidPath {
+            path: trimmed.to_string(),
+        };
         if trimmed.is_empty() {
-            return Err("invalid folder path".into());
+            return Err(invalid());
         }
 
-        let canonical =
-            std::fs::canonicalize(Path::new(trimmed)).map_err(|_| "invalid folder path")?;
+        let canonical = std::fs::canonicalize(Path::new(trimmed)).map_err(|_| invalid())?;
 
         if !canonical.is_dir() {
-            return Err("folder path is not a directory".into());
+            return Err(LoaderError::NotADirectory {
+                path: trimmed.to_string(),
+            });
         }
 
-        std::fs::read_dir(&canonical).map_err(|_| "folder path is unreadable")?;
+        std::fs::read_dir(&canonical).map_err(|_| LoaderError::FolderUnreadable {
+            path: trimmed.to_string(),
+        })?;
 
         canonical
             .to_str()
             .map(|s| s.to_string())
-            .ok_or_else(|| "invalid folder path".into())
+            .ok_or_else(invalid)
     }
 
     fn is_supported_image_ext(ext: &str) -> bool {
@@ -63,16 +319,37 @@ impl ImageLoader {
     }
 
     pub fn new(db: Db) -> Self {
-        let loader = Self { db };
+        let loader = Self {
+            db,
+            indexing_jobs: IndexingJobs::default(),
+            image_listener: RwLock::new(None),
+        };
         if let Err(err) = loader.bootstrap_checked_scope() {
             eprintln!("[RUST] bootstrap_checked_scope failed: {}", err);
         }
         loader
     }
 
-    fn bootstrap_checked_scope(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn bootstrap_checked_scope(&self) -> Result<(), LoaderError> {
         self.rebuild_missing_folder_nodes()?;
         self.rebuild_selection_indexes_if_missing()?;
+        // Called before most reads; only take the writer when there is
+        // something to prune.
+        let needs_pruning: bool = self.db.read().query_row(
+            "SELECT EXISTS (SELECT 1 FROM checked_folders
+                            WHERE path NOT IN (SELECT path FROM folder_nodes))
+                 OR EXISTS (SELECT 1
+                            FROM checked_folders child
+                            JOIN checked_folders parent ON parent.path <> child.path
+                            JOIN folder_closure c
+                              ON c.ancestor_path = parent.path
+                             AND c.descendant_path = child.path)",
+            [],
+            |row| row.get(0),
+        )?;
+        if !needs_pruning {
+            return Ok(());
+        }
         self.db.conn().execute(
             "DELETE FROM checked_folders WHERE path NOT IN (SELECT path FROM folder_nodes)",
             [],
@@ -92,40 +369,14 @@ impl ImageLoader {
         Ok(())
     }
 
-    fn ensure_active_scope_initialized(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let checked_count: i64 = self
-            .db
-            .conn()
-            .query_row("SELECT COUNT(*) FROM checked_folders", [], |row| row.get(0))?;
-
-        if checked_count == 0 {
-            self.db.conn().execute("DELETE FROM active_image_refcounts", [])?;
-            self.db.conn().execute("DELETE FROM active_images", [])?;
-            return Ok(());
-        }
-
-        let refcount_count: i64 = self
-            .db
-            .conn()
-            .query_row("SELECT COUNT(*) FROM active_image_refcounts", [], |row| {
-                row.get(0)
-            })?;
-
-        if refcount_count == 0 {
-            self.rebuild_active_images()?;
-        }
-
-        Ok(())
-    }
-
-    fn rebuild_selection_indexes_if_missing(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn rebuild_selection_indexes_if_missing(&self) -> Result<(), LoaderError> {
         let closure_count: i64 = self
             .db
-            .conn()
+            .read()
             .query_row("SELECT COUNT(*) FROM folder_closure", [], |row| row.get(0))?;
         let direct_count: i64 = self
             .db
-            .conn()
+            .read()
             .query_row("SELECT COUNT(*) FROM folder_images_direct", [], |row| row.get(0))?;
 
         if closure_count > 0 && direct_count > 0 {
@@ -147,10 +398,10 @@ impl ImageLoader {
         Ok(())
     }
 
-    fn rebuild_missing_folder_nodes(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn rebuild_missing_folder_nodes(&self) -> Result<(), LoaderError> {
         let node_count: i64 = self
             .db
-            .conn()
+            .read()
             .query_row("SELECT COUNT(*) FROM folder_nodes", [], |row| row.get(0))?;
         if node_count > 0 {
             return Ok(());
@@ -170,15 +421,26 @@ impl ImageLoader {
         Ok(())
     }
 
-    fn rebuild_folder_nodes_for_root(&self, folder_id: i64) -> Result<(), Box<dyn std::error::Error>> {
-        let root_path: String = self.db.conn().query_row(
+    fn rebuild_folder_nodes_for_root(&self, folder_id: i64) -> Result<(), LoaderError> {
+        let mut conn = self.db.conn();
+        let tx = conn.transaction()?;
+        Self::rebuild_folder_nodes_for_root_in(&tx, folder_id)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn rebuild_folder_nodes_for_root_in(
+        tx: &rusqlite::Transaction<'_>,
+        folder_id: i64,
+    ) -> Result<(), LoaderError> {
+        let root_path: String = tx.query_row(
             "SELECT path FROM folders WHERE id = ?1",
             params![folder_id],
             |row| row.get(0),
         )?;
 
-        let image_rows = self.db.with_conn(|conn| {
-            let mut stmt = conn.prepare("SELECT id, path FROM images WHERE folder_id = ?1")?;
+        let image_rows = {
+            let mut stmt = tx.prepare("SELECT id, path FROM images WHERE folder_id = ?1")?;
             let rows = stmt
                 .query_map(params![folder_id], |row| {
                     let id: i64 = row.get(0)?;
@@ -186,8 +448,8 @@ impl ImageLoader {
                     Ok((id, path))
                 })?
                 .collect::<Result<Vec<(i64, String)>, _>>()?;
-            Ok(rows)
-        })?;
+            rows
+        };
 
         let root = Path::new(&root_path);
         let mut subtree_counts: HashMap<String, i64> = HashMap::new();
@@ -222,8 +484,6 @@ impl ImageLoader {
             depth_a.cmp(&depth_b).then_with(|| a.cmp(b))
         });
 
-        let mut conn = self.db.conn();
-        let tx = conn.transaction()?;
         tx.execute(
             "DELETE FROM folder_closure
              WHERE ancestor_path IN (SELECT path FROM folder_nodes WHERE root_folder_id = ?1)
@@ -235,6 +495,23 @@ impl ImageLoader {
              WHERE folder_path IN (SELECT path FROM folder_nodes WHERE root_folder_id = ?1)",
             params![folder_id],
         )?;
+        // checked_folders references folder_nodes, so park this root's checked paths
+        // while the nodes are replaced and restore the ones that still exist.
+        let checked_paths = {
+            let mut stmt = tx.prepare(
+                "SELECT cf.path
+                 FROM checked_folders cf
+                 JOIN folder_nodes n ON n.path = cf.path
+                 WHERE n.root_folder_id = ?1",
+            )?;
+            let rows = stmt.query_map(params![folder_id], |row| row.get::<_, String>(0))?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+        tx.execute(
+            "DELETE FROM checked_folders
+             WHERE path IN (SELECT path FROM folder_nodes WHERE root_folder_id = ?1)",
+            params![folder_id],
+        )?;
         tx.execute(
             "DELETE FROM folder_nodes WHERE root_folder_id = ?1",
             params![folder_id],
@@ -271,6 +548,12 @@ impl ImageLoader {
         }
 
         let known_paths: HashSet<String> = parent_lookup.keys().cloned().collect();
+        for path in checked_paths.iter().filter(|p| known_paths.contains(*p)) {
+            tx.execute(
+                "INSERT OR IGNORE INTO checked_folders(path) VALUES (?1)",
+                params![path],
+            )?;
+        }
         for (image_id, image_path) in &image_rows {
             if let Some(parent) = Path::new(image_path).parent().and_then(|p| p.to_str()) {
                 if known_paths.contains(parent) {
@@ -282,11 +565,10 @@ impl ImageLoader {
             }
         }
 
-        tx.commit()?;
         Ok(())
     }
 
-    fn ensure_default_checked_folder(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn ensure_default_checked_folder(&self) -> Result<(), LoaderError> {
         let checked_count: i64 = self
             .db
             .conn()
@@ -328,15 +610,23 @@ impl ImageLoader {
         Ok(())
     }
 
-    fn rebuild_active_images(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn rebuild_active_images(&self) -> Result<(), LoaderError> {
         let mut conn = self.db.conn();
         let tx = conn.transaction()?;
+        Self::rebuild_active_images_in(&tx)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn rebuild_active_images_in(tx: &rusqlite::Transaction<'_>) -> Result<(), LoaderError> {
         tx.execute("DELETE FROM active_image_refcounts", [])?;
         tx.execute("DELETE FROM active_images", [])?;
         tx.execute(
             "INSERT INTO active_image_refcounts (image_id, refcount)
              SELECT fi.image_id, COUNT(*)
              FROM checked_folders cf
+             JOIN folder_nodes n ON n.path = cf.path
+             JOIN folders f ON f.id = n.root_folder_id AND f.offline = 0
              JOIN folder_closure c ON c.ancestor_path = cf.path
              JOIN folder_images_direct fi ON fi.folder_path = c.descendant_path
              GROUP BY fi.image_id",
@@ -347,65 +637,10 @@ impl ImageLoader {
              SELECT image_id FROM active_image_refcounts WHERE refcount > 0",
             [],
         )?;
-        tx.commit()?;
-        Ok(())
-    }
-
-    fn add_subtree_to_active_set(&self, folder_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-        self.db.conn().execute(
-            "INSERT INTO active_image_refcounts (image_id, refcount)
-             SELECT fi.image_id, COUNT(*)
-             FROM folder_closure c
-             JOIN folder_images_direct fi ON fi.folder_path = c.descendant_path
-             WHERE c.ancestor_path = ?1
-             GROUP BY fi.image_id
-             ON CONFLICT(image_id) DO UPDATE SET refcount = refcount + excluded.refcount",
-            params![folder_path],
-        )?;
-        self.db.conn().execute(
-            "INSERT OR IGNORE INTO active_images(image_id)
-             SELECT fi.image_id
-             FROM folder_closure c
-             JOIN folder_images_direct fi ON fi.folder_path = c.descendant_path
-             WHERE c.ancestor_path = ?1",
-            params![folder_path],
-        )?;
-        Ok(())
-    }
-
-    fn remove_subtree_from_active_set(
-        &self,
-        folder_path: &str,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        self.db.conn().execute(
-            "WITH removed AS (
-                SELECT fi.image_id, COUNT(*) AS removed_count
-                FROM folder_closure c
-                JOIN folder_images_direct fi ON fi.folder_path = c.descendant_path
-                WHERE c.ancestor_path = ?1
-                GROUP BY fi.image_id
-             )
-             UPDATE active_image_refcounts
-             SET refcount = refcount - (
-                 SELECT removed_count
-                 FROM removed
-                 WHERE removed.image_id = active_image_refcounts.image_id
-             )
-             WHERE image_id IN (SELECT image_id FROM removed)",
-            params![folder_path],
-        )?;
-        self.db
-            .conn()
-            .execute("DELETE FROM active_image_refcounts WHERE refcount <= 0", [])?;
-        self.db.conn().execute(
-            "DELETE FROM active_images
-             WHERE image_id NOT IN (SELECT image_id FROM active_image_refcounts)",
-            [],
-        )?;
         Ok(())
     }
 
-    fn has_checked_folders(&self) -> Result<bool, Box<dyn std::error::Error>> {
+    fn has_checked_folders(&self) -> Result<bool, LoaderError> {
         let count: i64 = self
             .db
             .conn()
@@ -413,16 +648,16 @@ impl ImageLoader {
         Ok(count > 0)
     }
 
-    fn require_checked_folders(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn require_checked_folders(&self) -> Result<(), LoaderError> {
         if !self.has_checked_folders()? {
-            return Err(NO_FOLDERS_SELECTED_ERROR.into());
+            return Err(LoaderError::NoFoldersSelected);
         }
         Ok(())
     }
 
     pub fn get_current_folder_id_and_path(
         &self,
-    ) -> Result<Option<(i64, String)>, Box<dyn std::error::Error>> {
+    ) -> Result<Option<(i64, String)>, LoaderError> {
         eprintln!("[RUST] get_current_folder_id_and_path: querying state table");
         let current = self.db.conn().query_row(
             "SELECT current_folder_id FROM state WHERE id = 1",
@@ -457,104 +692,16 @@ impl ImageLoader {
         }
     }
 
-    fn get_image_ids(&self, folder_id: i64) -> Result<Vec<i64>, Box<dyn std::error::Error>> {
-        self.db
-            .with_conn(|conn| {
-                let mut stmt =
-                    conn.prepare("SELECT id FROM images WHERE folder_id = ?1 ORDER BY id")?;
-                let ids = stmt
-                    .query_map(params![folder_id], |row| row.get(0))?
-                    .collect::<Result<Vec<i64>, _>>()?;
-                Ok::<Vec<i64>, rusqlite::Error>(ids)
-            })
-            .map_err(|e| e.into())
-    }
-
-    fn get_active_image_ids(&self) -> Result<Vec<i64>, Box<dyn std::error::Error>> {
-        self.db
-            .with_conn(|conn| {
-                let mut stmt = conn.prepare("SELECT image_id FROM active_images ORDER BY image_id")?;
-                let ids = stmt
-                    .query_map([], |row| row.get(0))?
-                    .collect::<Result<Vec<i64>, _>>()?;
-                Ok::<Vec<i64>, rusqlite::Error>(ids)
-            })
-            .map_err(|e| e.into())
-    }
-
-    fn get_active_normal_entries(&self) -> Result<Vec<(i64, i64, String)>, Box<dyn std::error::Error>> {
-        self.db
-            .with_conn(|conn| {
-                let mut stmt = conn.prepare(
-                    "SELECT a.image_id, i.path
-                     FROM active_images a
-                     JOIN images i ON i.id = a.image_id
-                     ORDER BY a.image_id",
-                )?;
-                let rows = stmt
-                    .query_map([], |row| {
-                        let image_id: i64 = row.get(0)?;
-                        let path: String = row.get(1)?;
-                        Ok((image_id, path))
-                    })?
-                    .collect::<Result<Vec<_>, _>>()?;
-                Ok(rows
-                    .into_iter()
-                    .enumerate()
-                    .map(|(idx, (image_id, path))| (idx as i64, image_id, path))
-                    .collect::<Vec<_>>())
-            })
-            .map_err(|e| e.into())
-    }
-
-    fn get_visible_active_normal_entries(
-        &self,
-    ) -> Result<Vec<(i64, i64, String)>, Box<dyn std::error::Error>> {
-        let hidden_ids = self.db.with_conn(|conn| {
-            let mut stmt = conn.prepare(
-                "SELECT DISTINCT h.image_id
-                 FROM hidden_normal_images h
-                 JOIN active_images a ON a.image_id = h.image_id",
-            )?;
-            let ids = stmt
-                .query_map([], |row| row.get(0))?
-                .collect::<Result<Vec<i64>, _>>()?;
-            Ok(ids)
-        })?;
-        let hidden: HashSet<i64> = hidden_ids.into_iter().collect();
-        let entries = self.get_active_normal_entries()?;
-        Ok(entries
-            .into_iter()
-            .filter(|(_, image_id, _)| !hidden.contains(image_id))
-            .collect())
-    }
-
-    fn get_visible_active_random_image_ids(&self) -> Result<Vec<i64>, Box<dyn std::error::Error>> {
-        let mut ids = self.get_active_image_ids()?;
-        let hidden = self.db.with_conn(|conn| {
-            let mut stmt = conn.prepare(
-                "SELECT DISTINCT h.image_id
-                 FROM hidden_random_images h
-                 JOIN active_images a ON a.image_id = h.image_id",
-            )?;
-            let rows = stmt
-                .query_map([], |row| row.get(0))?
-                .collect::<Result<Vec<i64>, _>>()?;
-            Ok(rows)
-        })?;
-        let hidden_set: HashSet<i64> = hidden.into_iter().collect();
-        ids.retain(|id| !hidden_set.contains(id));
-        Ok(ids)
-    }
-
     fn get_checked_folder_available_counts(
         &self,
-    ) -> Result<Vec<(String, i64)>, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<(String, i64)>, LoaderError> {
         self.db
             .with_conn(|conn| {
                 let mut stmt = conn.prepare(
                     "SELECT cf.path, COUNT(DISTINCT fi.image_id) AS available_count
                      FROM checked_folders cf
+                     JOIN folder_nodes n ON n.path = cf.path
+                     JOIN folders f ON f.id = n.root_folder_id AND f.offline = 0
                      JOIN folder_closure c ON c.ancestor_path = cf.path
                      JOIN folder_images_direct fi ON fi.folder_path = c.descendant_path
                      LEFT JOIN hidden_random_images h ON h.image_id = fi.image_id
@@ -578,7 +725,7 @@ impl ImageLoader {
     fn get_available_random_image_ids_for_checked_folder(
         &self,
         checked_folder_path: &str,
-    ) -> Result<Vec<i64>, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<i64>, LoaderError> {
         self.db
             .with_conn(|conn| {
                 let mut stmt = conn.prepare(
@@ -599,170 +746,50 @@ impl ImageLoader {
             .map_err(|e| e.into())
     }
 
-    fn get_hidden_image_ids(
-        &self,
-        table: HiddenImageTable,
-        folder_id: i64,
-    ) -> Result<HashSet<i64>, Box<dyn std::error::Error>> {
-        let sql = table.select_sql();
-        let ids = self.db.with_conn(|conn| {
-            let mut stmt = conn.prepare(sql)?;
-            let rows = stmt
-                .query_map(params![folder_id], |row| row.get(0))?
-                .collect::<Result<Vec<i64>, _>>()?;
-            Ok(rows)
-        })?;
-        Ok(ids.into_iter().collect())
+    fn count_images(&self, folder_id: i64) -> Result<i64, LoaderError> {
+        let count: i64 = self.db.read().query_row(
+            "SELECT COUNT(*) FROM images WHERE folder_id = ?1",
+            params![folder_id],
+            |row| row.get(0),
+        )?;
+        Ok(count)
     }
 
-    fn get_visible_random_image_ids(
-        &self,
-        folder_id: i64,
-    ) -> Result<Vec<i64>, Box<dyn std::error::Error>> {
-        let mut image_ids = self.get_image_ids(folder_id)?;
-        let hidden_random_ids = self.get_hidden_image_ids(HiddenImageTable::Random, folder_id)?;
-        image_ids.retain(|id| !hidden_random_ids.contains(id));
-        Ok(image_ids)
+    /// Whether the last index of this root ran to completion.
+    fn is_folder_indexed(&self, folder_id: i64) -> Result<bool, LoaderError> {
+        let indexed_at: Option<String> = self.db.conn().query_row(
+            "SELECT indexed_at FROM folders WHERE id = ?1",
+            params![folder_id],
+            |row| row.get(0),
+        )?;
+        Ok(indexed_at.is_some())
     }
 
-    fn get_normal_entries(
-        &self,
+    fn mark_folder_indexed_in(
+        tx: &rusqlite::Transaction<'_>,
         folder_id: i64,
-    ) -> Result<Vec<(i64, i64, String)>, Box<dyn std::error::Error>> {
-        self.db
-            .with_conn(|conn| {
-                let mut stmt =
-                    conn.prepare("SELECT id, path FROM images WHERE folder_id = ?1 ORDER BY id")?;
-                let rows = stmt
-                    .query_map(params![folder_id], |row| {
-                        let image_id: i64 = row.get(0)?;
-                        let path: String = row.get(1)?;
-                        Ok((image_id, path))
-                    })?
-                    .collect::<Result<Vec<_>, _>>()?;
-                Ok(rows
-                    .into_iter()
-                    .enumerate()
-                    .map(|(idx, (image_id, path))| (idx as i64, image_id, path))
-                    .collect::<Vec<_>>())
-            })
-            .map_err(|e| e.into())
+    ) -> Result<(), LoaderError> {
+        tx.execute(
+            "UPDATE folders SET indexed_at = ?1 WHERE id = ?2",
+            params![chrono::Utc::now().to_rfc3339(), folder_id],
+        )?;
+        Ok(())
     }
 
-    fn get_visible_normal_entries(
-        &self,
-        folder_id: i64,
-    ) -> Result<Vec<(i64, i64, String)>, Box<dyn std::error::Error>> {
-        let hidden = self.get_hidden_image_ids(HiddenImageTable::Normal, folder_id)?;
-        let entries = self.get_normal_entries(folder_id)?;
-        Ok(entries
-            .into_iter()
-            .filter(|(_, image_id, _)| !hidden.contains(image_id))
-            .collect())
+    fn get_image_path(&self, image_id: i64) -> Result<String, LoaderError> {
+        let path: String = self.db.read().query_row(
+            "SELECT path FROM images WHERE id = ?1",
+            params![image_id],
+            |row| row.get(0),
+        )?;
+        Ok(path)
     }
 
-    fn get_random_entries(
-        &self,
-        folder_id: i64,
-    ) -> Result<Vec<(i64, i64, String)>, Box<dyn std::error::Error>> {
-        self.db
-            .with_conn(|conn| {
-                let mut stmt = conn.prepare(
-                    "SELECT rh.order_index, rh.image_id, i.path FROM random_history rh JOIN images i ON i.id = rh.image_id WHERE rh.folder_id = ?1 ORDER BY rh.order_index",
-                )?;
-                let rows = stmt
-                    .query_map(params![folder_id], |row| {
-                        let order_index: i64 = row.get(0)?;
-                        let image_id: i64 = row.get(1)?;
-                        let path: String = row.get(2)?;
-                        Ok((order_index, image_id, path))
-                    })?
-                    .collect::<Result<Vec<_>, _>>()?;
-                Ok(rows)
-            })
-            .map_err(|e| e.into())
-    }
-
-    fn get_visible_random_entries(
-        &self,
-        folder_id: i64,
-    ) -> Result<Vec<(i64, i64, String)>, Box<dyn std::error::Error>> {
-        let hidden = self.get_hidden_image_ids(HiddenImageTable::Random, folder_id)?;
-        let entries = self.get_random_entries(folder_id)?;
-        Ok(entries
-            .into_iter()
-            .filter(|(_, image_id, _)| !hidden.contains(image_id))
-            .collect())
-    }
-
-    fn count_images(&self, folder_id: i64) -> Result<i64, Box<dyn std::error::Error>> {
-        let count: i64 = self.db.conn().query_row(
-            "SELECT COUNT(*) FROM images WHERE folder_id = ?1",
-            params![folder_id],
-            |row| row.get(0),
-        )?;
-        Ok(count)
-    }
-
-    fn get_current_folder_index(&self, folder_id: i64) -> Result<i64, Box<dyn std::error::Error>> {
-        let index: i64 = self.db.conn().query_row(
-            "SELECT current_index FROM folders WHERE id = ?1",
-            params![folder_id],
-            |row| row.get(0),
-        )?;
-        Ok(index)
-    }
-
-    fn set_current_folder_index(
-        &self,
-        folder_id: i64,
-        index: i64,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        self.db.conn().execute(
-            "UPDATE folders SET current_index = ?1 WHERE id = ?2",
-            params![index, folder_id],
-        )?;
-        Ok(())
-    }
-
-    fn get_current_folder_random_index(
-        &self,
-        folder_id: i64,
-    ) -> Result<i64, Box<dyn std::error::Error>> {
-        let index: i64 = self.db.conn().query_row(
-            "SELECT current_random_index FROM folders WHERE id = ?1",
-            params![folder_id],
-            |row| row.get(0),
-        )?;
-        Ok(index)
-    }
-
-    fn set_current_folder_random_index(
-        &self,
-        folder_id: i64,
-        index: i64,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        self.db.conn().execute(
-            "UPDATE folders SET current_random_index = ?1 WHERE id = ?2",
-            params![index, folder_id],
-        )?;
-        Ok(())
-    }
-
-    fn get_image_path(&self, image_id: i64) -> Result<String, Box<dyn std::error::Error>> {
-        let path: String = self.db.conn().query_row(
-            "SELECT path FROM images WHERE id = ?1",
-            params![image_id],
-            |row| row.get(0),
-        )?;
-        Ok(path)
-    }
-
-    fn get_image_folder_id(
+    fn get_image_folder_id(
         &self,
         image_id: i64,
-    ) -> Result<Option<i64>, Box<dyn std::error::Error>> {
-        let folder_id: Option<i64> = self.db.conn().query_row(
+    ) -> Result<Option<i64>, LoaderError> {
+        let folder_id: Option<i64> = self.db.read().query_row(
             "SELECT folder_id FROM images WHERE id = ?1",
             params![image_id],
             |row| row.get(0),
@@ -770,7 +797,7 @@ impl ImageLoader {
         Ok(folder_id)
     }
 
-    fn set_last_image_id(&self, image_id: Option<i64>) -> Result<(), Box<dyn std::error::Error>> {
+    fn set_last_image_id(&self, image_id: Option<i64>) -> Result<(), LoaderError> {
         self.db.conn().execute(
             "UPDATE state SET last_image_id = ?1 WHERE id = 1",
             params![image_id],
@@ -778,8 +805,8 @@ impl ImageLoader {
         Ok(())
     }
 
-    fn get_last_image_id(&self) -> Result<Option<i64>, Box<dyn std::error::Error>> {
-        let last_image_id: Option<i64> = self.db.conn().query_row(
+    fn get_last_image_id(&self) -> Result<Option<i64>, LoaderError> {
+        let last_image_id: Option<i64> = self.db.read().query_row(
             "SELECT last_image_id FROM state WHERE id = 1",
             [],
             |row| row.get(0),
@@ -787,8 +814,8 @@ impl ImageLoader {
         Ok(last_image_id)
     }
 
-    pub fn get_current_folder_id(&self) -> Result<Option<i64>, Box<dyn std::error::Error>> {
-        let current_id: Option<i64> = self.db.conn().query_row(
+    pub fn get_current_folder_id(&self) -> Result<Option<i64>, LoaderError> {
+        let current_id: Option<i64> = self.db.read().query_row(
             "SELECT current_folder_id FROM state WHERE id = 1",
             [],
             |row| row.get(0),
@@ -796,54 +823,7 @@ impl ImageLoader {
         Ok(current_id)
     }
 
-    fn random_history_count(&self, folder_id: i64) -> Result<i64, Box<dyn std::error::Error>> {
-        let count: i64 = self.db.conn().query_row(
-            "SELECT COUNT(*) FROM random_history WHERE folder_id = ?1",
-            params![folder_id],
-            |row| row.get(0),
-        )?;
-        Ok(count)
-    }
-
-    fn random_history_max_index(&self, folder_id: i64) -> Result<i64, Box<dyn std::error::Error>> {
-        let max_index: Option<i64> = self.db.conn().query_row(
-            "SELECT COALESCE(MAX(order_index), -1) FROM random_history WHERE folder_id = ?1",
-            params![folder_id],
-            |row| row.get(0),
-        )?;
-        Ok(max_index.unwrap_or(-1))
-    }
-
-    fn random_history_at(
-        &self,
-        folder_id: i64,
-        index: i64,
-    ) -> Result<Option<i64>, Box<dyn std::error::Error>> {
-        if index < 0 {
-            return Ok(None);
-        }
-        let image_id: Option<i64> = self.db.conn().query_row(
-            "SELECT image_id FROM random_history WHERE folder_id = ?1 AND order_index = ?2",
-            params![folder_id, index],
-            |row| row.get(0),
-        )?;
-        Ok(image_id)
-    }
-
-    fn random_history_insert(
-        &self,
-        folder_id: i64,
-        order_index: i64,
-        image_id: i64,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        self.db.conn().execute(
-            "INSERT INTO random_history (folder_id, order_index, image_id) VALUES (?1, ?2, ?3)",
-            params![folder_id, order_index, image_id],
-        )?;
-        Ok(())
-    }
-
-    fn random_history_global_count(&self) -> Result<i64, Box<dyn std::error::Error>> {
+    fn random_history_global_count(&self) -> Result<i64, LoaderError> {
         let count: i64 = self
             .db
             .conn()
@@ -851,7 +831,7 @@ impl ImageLoader {
         Ok(count)
     }
 
-    fn random_history_global_max_index(&self) -> Result<i64, Box<dyn std::error::Error>> {
+    fn random_history_global_max_index(&self) -> Result<i64, LoaderError> {
         let max_index: Option<i64> = self.db.conn().query_row(
             "SELECT COALESCE(MAX(order_index), -1) FROM random_history_global",
             [],
@@ -860,7 +840,7 @@ impl ImageLoader {
         Ok(max_index.unwrap_or(-1))
     }
 
-    fn append_random_history_global(&self, image_id: i64) -> Result<i64, Box<dyn std::error::Error>> {
+    fn append_random_history_global(&self, image_id: i64) -> Result<i64, LoaderError> {
         let next = self.random_history_global_max_index()? + 1;
         self.db.conn().execute(
             "INSERT INTO random_history_global (order_index, image_id) VALUES (?1, ?2)",
@@ -869,7 +849,7 @@ impl ImageLoader {
         Ok(next)
     }
 
-    fn prepend_random_history_global(&self, image_id: i64) -> Result<i64, Box<dyn std::error::Error>> {
+    fn prepend_random_history_global(&self, image_id: i64) -> Result<i64, LoaderError> {
         let mut conn = self.db.conn();
         let tx = conn.transaction()?;
         tx.execute(
@@ -888,17 +868,17 @@ impl ImageLoader {
         Ok(0)
     }
 
-    fn get_state_random_index(&self) -> Result<i64, Box<dyn std::error::Error>> {
+    fn get_state_random_index(&self) -> Result<i64, LoaderError> {
         let idx: i64 = self
             .db
-            .conn()
+            .read()
             .query_row("SELECT current_random_index FROM state WHERE id = 1", [], |row| {
                 row.get(0)
             })?;
         Ok(idx)
     }
 
-    fn set_state_random_index(&self, idx: i64) -> Result<(), Box<dyn std::error::Error>> {
+    fn set_state_random_index(&self, idx: i64) -> Result<(), LoaderError> {
         self.db.conn().execute(
             "UPDATE state SET current_random_index = ?1 WHERE id = 1",
             params![idx],
@@ -906,17 +886,17 @@ impl ImageLoader {
         Ok(())
     }
 
-    fn get_state_normal_index(&self) -> Result<i64, Box<dyn std::error::Error>> {
+    fn get_state_normal_index(&self) -> Result<i64, LoaderError> {
         let idx: i64 = self
             .db
-            .conn()
+            .read()
             .query_row("SELECT current_index FROM state WHERE id = 1", [], |row| {
                 row.get(0)
             })?;
         Ok(idx)
     }
 
-    fn set_state_normal_index(&self, idx: i64) -> Result<(), Box<dyn std::error::Error>> {
+    fn set_state_normal_index(&self, idx: i64) -> Result<(), LoaderError> {
         self.db.conn().execute(
             "UPDATE state SET current_index = ?1 WHERE id = 1",
             params![idx],
@@ -924,7 +904,7 @@ impl ImageLoader {
         Ok(())
     }
 
-    fn clear_random_history_global(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn clear_random_history_global(&self) -> Result<(), LoaderError> {
         self.db.conn().execute("DELETE FROM random_history_global", [])?;
         self.set_state_random_index(-1)?;
         Ok(())
@@ -932,44 +912,233 @@ impl ImageLoader {
 
     fn get_visible_checked_normal_entries(
         &self,
-    ) -> Result<Vec<(i64, i64, String, i64)>, Box<dyn std::error::Error>> {
-        let rows: Vec<(String, i64, String, i64)> = self.db.with_conn(|conn| {
-            let mut stmt = conn.prepare(
-                "SELECT fi.folder_path, i.id, i.path, COALESCE(i.folder_id, -1)
-                 FROM checked_folders cf
-                 JOIN folder_closure c ON c.ancestor_path = cf.path
-                 JOIN folder_images_direct fi ON fi.folder_path = c.descendant_path
-                 JOIN images i ON i.id = fi.image_id
-                 LEFT JOIN hidden_normal_images h ON h.image_id = i.id
-                 WHERE h.image_id IS NULL
-                 ORDER BY fi.folder_path COLLATE NOCASE, i.path COLLATE NOCASE, i.id",
-            )?;
+    ) -> Result<Vec<(i64, i64, String, i64)>, LoaderError> {
+        let rows = self.db.with_read(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT i.id, i.path, COALESCE(i.folder_id, -1) {} ORDER BY {}",
+                VISIBLE_NORMAL_ENTRIES_SQL, NORMAL_ORDER_SQL
+            ))?;
             let rows = stmt
                 .query_map([], |row| {
-                    let folder_path: String = row.get(0)?;
-                    let image_id: i64 = row.get(1)?;
-                    let image_path: String = row.get(2)?;
-                    let folder_id: i64 = row.get(3)?;
-                    Ok((folder_path, image_id, image_path, folder_id))
+                    let image_id: i64 = row.get(0)?;
+                    let path: String = row.get(1)?;
+                    let folder_id: i64 = row.get(2)?;
+                    Ok((image_id, path, folder_id))
                 })?
                 .collect::<Result<Vec<_>, _>>()?;
             Ok(rows)
         })?;
 
-        let mut dedup = HashSet::new();
-        let mut ordered = Vec::new();
-        for (_folder_path, image_id, image_path, folder_id) in rows {
-            if dedup.insert(image_id) {
-                ordered.push((ordered.len() as i64, image_id, image_path, folder_id));
+        Ok(rows
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (image_id, path, folder_id))| (idx as i64, image_id, path, folder_id))
+            .collect())
+    }
+
+    /// Normal-list positions of `image_ids`; ids outside the list are left out.
+    fn get_normal_positions(&self, image_ids: &[i64]) -> Result<HashMap<i64, i64>, LoaderError> {
+        if image_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let ids = image_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        let positions = self.db.with_read(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT image_id, position FROM (
+                     SELECT i.id AS image_id,
+                            ROW_NUMBER() OVER (ORDER BY {}) - 1 AS position
+                     {}
+                 )
+                 WHERE image_id IN ({})",
+                NORMAL_ORDER_SQL,
+                VISIBLE_NORMAL_ENTRIES_SQL,
+                ids.join(",")
+            ))?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))?
+                .collect::<Result<HashMap<_, _>, _>>()?;
+            Ok(rows)
+        })?;
+        Ok(positions)
+    }
+
+    /// Fills in the file metadata a sort order depends on for images that were
+    /// indexed without it. Unreadable files get zeroes so they are not retried.
+    fn ensure_sort_metadata(
+        &self,
+        folder_id: i64,
+        order: NormalSortOrder,
+    ) -> Result<(), LoaderError> {
+        if order.needs_file_metadata() {
+            let missing = self.db.with_read(|conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT id, path FROM images
+                     WHERE folder_id = ?1 AND (mtime IS NULL OR file_size IS NULL)",
+                )?;
+                let rows = stmt
+                    .query_map(params![folder_id], |row| {
+                        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(rows)
+            })?;
+
+            if !missing.is_empty() {
+                let metadata = missing
+                    .into_iter()
+                    .map(|(id, path)| {
+                        let (mtime, size) = std::fs::metadata(&path)
+                            .map(|meta| {
+                                let mtime = meta
+                                    .modified()
+                                    .ok()
+                                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                                    .map(|d| d.as_secs() as i64)
+                                    .unwrap_or(0);
+                                (mtime, meta.len() as i64)
+                            })
+                            .unwrap_or((0, 0));
+                        (id, mtime, size)
+                    })
+                    .collect::<Vec<_>>();
+
+                let mut conn = self.db.conn();
+                let tx = conn.transaction()?;
+                {
+                    let mut stmt =
+                        tx.prepare("UPDATE images SET mtime = ?1, file_size = ?2 WHERE id = ?3")?;
+                    for (id, mtime, size) in metadata {
+                        stmt.execute(params![mtime, size, id])?;
+                    }
+                }
+                tx.commit()?;
+            }
+        }
+
+        if order.needs_dimensions() {
+            let missing = self.db.with_read(|conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT id, path FROM images
+                     WHERE folder_id = ?1 AND (width IS NULL OR height IS NULL)",
+                )?;
+                let rows = stmt
+                    .query_map(params![folder_id], |row| {
+                        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(rows)
+            })?;
+
+            if !missing.is_empty() {
+                let dimensions = missing
+                    .into_iter()
+                    .map(|(id, path)| {
+                        let (width, height) = image::image_dimensions(&path).unwrap_or((0, 0));
+                        (id, width as i64, height as i64)
+                    })
+                    .collect::<Vec<_>>();
+
+                let mut conn = self.db.conn();
+                let tx = conn.transaction()?;
+                {
+                    let mut stmt =
+                        tx.prepare("UPDATE images SET width = ?1, height = ?2 WHERE id = ?3")?;
+                    for (id, width, height) in dimensions {
+                        stmt.execute(params![width, height, id])?;
+                    }
+                }
+                tx.commit()?;
             }
         }
 
-        Ok(ordered)
+        Ok(())
+    }
+
+    pub fn get_folder_sort_order(
+        &self,
+        folder_id: i64,
+    ) -> Result<NormalSortOrder, LoaderError> {
+        let order: String = self.db.conn().query_row(
+            "SELECT normal_sort_order FROM folders WHERE id = ?1",
+            params![folder_id],
+            |row| row.get(0),
+        )?;
+        Ok(NormalSortOrder::parse(&order).unwrap_or(NormalSortOrder::Path))
+    }
+
+    pub fn get_folder_follow_symlinks(&self, folder_id: i64) -> Result<bool, LoaderError> {
+        let follow: i64 = self.db.conn().query_row(
+            "SELECT follow_symlinks FROM folders WHERE id = ?1",
+            params![folder_id],
+            |row| row.get(0),
+        )?;
+        Ok(follow != 0)
+    }
+
+    /// Opts a root folder in or out of following symlinked directories.
+    /// Takes effect on the next scan of that root.
+    pub fn set_folder_follow_symlinks(
+        &self,
+        folder_id: i64,
+        follow: bool,
+    ) -> Result<(), LoaderError> {
+        let updated = self.db.conn().execute(
+            "UPDATE folders SET follow_symlinks = ?1 WHERE id = ?2",
+            params![follow as i64, folder_id],
+        )?;
+        if updated == 0 {
+            return Err(LoaderError::NotFound("folder not found".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Changes a root folder's normal order and keeps the normal pointer on the
+    /// image it showed before. Picking `Shuffle` again reshuffles.
+    pub fn set_folder_sort_order(
+        &self,
+        folder_id: i64,
+        order: NormalSortOrder,
+    ) -> Result<(), LoaderError> {
+        self.bootstrap_checked_scope()?;
+        let pointer_raw = self.get_state_normal_index()?;
+        let current_image_id = self
+            .get_visible_checked_normal_entries()?
+            .into_iter()
+            .find(|(order_index, _, _, _)| *order_index == pointer_raw)
+            .map(|(_, image_id, _, _)| image_id);
+
+        let updated = if order == NormalSortOrder::Shuffle {
+            self.db.conn().execute(
+                "UPDATE folders SET normal_sort_order = ?1, normal_shuffle_seed = ?2 WHERE id = ?3",
+                params![order.as_str(), rand::random::<i64>(), folder_id],
+            )?
+        } else {
+            self.db.conn().execute(
+                "UPDATE folders SET normal_sort_order = ?1 WHERE id = ?2",
+                params![order.as_str(), folder_id],
+            )?
+        };
+        if updated == 0 {
+            return Err(LoaderError::NotFound("folder not found".to_string()));
+        }
+
+        self.ensure_sort_metadata(folder_id, order)?;
+
+        if let Some(image_id) = current_image_id {
+            if let Some((order_index, _, _, _)) = self
+                .get_visible_checked_normal_entries()?
+                .into_iter()
+                .find(|(_, id, _, _)| *id == image_id)
+            {
+                self.set_state_normal_index(order_index)?;
+            }
+        }
+        Ok(())
     }
 
-    fn get_random_entries_global(&self) -> Result<Vec<(i64, i64, String)>, Box<dyn std::error::Error>> {
+    fn get_random_entries_global(&self) -> Result<Vec<(i64, i64, String)>, LoaderError> {
         self.db
-            .with_conn(|conn| {
+            .with_read(|conn| {
                 let mut stmt = conn.prepare(
                     "SELECT rh.order_index, rh.image_id, i.path
                      FROM random_history_global rh
@@ -991,8 +1160,8 @@ impl ImageLoader {
 
     fn get_visible_random_entries_global(
         &self,
-    ) -> Result<Vec<(i64, i64, String)>, Box<dyn std::error::Error>> {
-        let hidden_ids = self.db.with_conn(|conn| {
+    ) -> Result<Vec<(i64, i64, String)>, LoaderError> {
+        let hidden_ids = self.db.with_read(|conn| {
             let mut stmt = conn.prepare("SELECT DISTINCT image_id FROM hidden_random_images")?;
             let ids = stmt
                 .query_map([], |row| row.get(0))?
@@ -1007,28 +1176,7 @@ impl ImageLoader {
             .collect())
     }
 
-    fn lap_global_count(&self) -> Result<i64, Box<dyn std::error::Error>> {
-        let count: i64 = self
-            .db
-            .conn()
-            .query_row("SELECT COUNT(*) FROM current_lap_global", [], |row| row.get(0))?;
-        Ok(count)
-    }
-
-    fn lap_global_has(&self, image_id: i64) -> Result<bool, Box<dyn std::error::Error>> {
-        let exists: Option<i64> = self
-            .db
-            .conn()
-            .query_row(
-                "SELECT 1 FROM current_lap_global WHERE image_id = ?1 LIMIT 1",
-                params![image_id],
-                |row| row.get(0),
-            )
-            .optional()?;
-        Ok(exists.is_some())
-    }
-
-    fn lap_global_insert(&self, image_id: i64) -> Result<(), Box<dyn std::error::Error>> {
+    fn lap_global_insert(&self, image_id: i64) -> Result<(), LoaderError> {
         self.db.conn().execute(
             "INSERT OR IGNORE INTO current_lap_global (image_id) VALUES (?1)",
             params![image_id],
@@ -1036,188 +1184,81 @@ impl ImageLoader {
         Ok(())
     }
 
-    fn lap_global_clear(&self) -> Result<(), Box<dyn std::error::Error>> {
+    fn lap_global_clear(&self) -> Result<(), LoaderError> {
         self.db.conn().execute("DELETE FROM current_lap_global", [])?;
         Ok(())
     }
 
-    fn ensure_lap_global_capacity(&self, total_images: i64) -> Result<(), Box<dyn std::error::Error>> {
-        let count = self.lap_global_count()?;
-        if count >= total_images {
-            self.lap_global_clear()?;
-        }
-        Ok(())
-    }
-
-    fn random_history_shift_up_safe(
-        &self,
-        folder_id: i64,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut conn = self.db.conn();
-        let tx = conn.transaction()?;
-        tx.execute(
-            "UPDATE random_history SET order_index = order_index + 1000000000 WHERE folder_id = ?1",
-            params![folder_id],
-        )?;
-        tx.execute(
-            "UPDATE random_history SET order_index = order_index - 999999999 WHERE folder_id = ?1",
-            params![folder_id],
+    fn insert_folder(&self, path: &str, added_at: &str) -> Result<i64, LoaderError> {
+        self.db.conn().execute(
+            "INSERT OR IGNORE INTO folders (path, added_at) VALUES (?1, ?2)",
+            params![path, added_at],
         )?;
-        tx.commit()?;
-        Ok(())
-    }
 
-    fn append_random_history(
-        &self,
-        folder_id: i64,
-        image_id: i64,
-    ) -> Result<i64, Box<dyn std::error::Error>> {
-        let max_index = self.random_history_max_index(folder_id)?;
-        let next_index = max_index + 1;
-        self.random_history_insert(folder_id, next_index, image_id)?;
-        Ok(next_index)
+        let id: i64 = self.db.conn().query_row(
+            "SELECT id FROM folders WHERE path = ?1",
+            params![path],
+            |row| row.get(0),
+        )?;
+        Ok(id)
     }
 
-    fn prepend_random_history(
+    pub fn set_current_folder_id(
         &self,
-        folder_id: i64,
-        image_id: i64,
-    ) -> Result<i64, Box<dyn std::error::Error>> {
-        self.random_history_shift_up_safe(folder_id)?;
-        self.random_history_insert(folder_id, 0, image_id)?;
-        Ok(0)
-    }
-
-    fn clear_random_history(&self, folder_id: i64) -> Result<(), Box<dyn std::error::Error>> {
-        self.db.conn().execute(
-            "DELETE FROM random_history WHERE folder_id = ?1",
+        folder_id: Option<i64>,
+    ) -> Result<(), LoaderError> {
+        eprintln!("[RUST] set_current_folder_id: setting to {:?}", folder_id);
+        let rows = self.db.conn().execute(
+            "UPDATE state SET current_folder_id = ?1 WHERE id = 1",
             params![folder_id],
         )?;
-        self.set_current_folder_random_index(folder_id, -1)?;
+        eprintln!("[RUST] set_current_folder_id: updated {} rows", rows);
         Ok(())
     }
 
-    fn lap_count(&self, folder_id: i64) -> Result<i64, Box<dyn std::error::Error>> {
-        let count: i64 = self.db.conn().query_row(
-            "SELECT COUNT(*) FROM current_lap WHERE folder_id = ?1",
-            params![folder_id],
-            |row| row.get(0),
-        )?;
-        Ok(count)
+    pub fn get_folder_tree(&self) -> Result<Vec<crate::types::FolderTreeNode>, LoaderError> {
+        self.bootstrap_checked_scope()?;
+        self.db
+            .with_read(|conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT fn.path, fn.parent_path, fn.subtree_image_count,
+                            CASE WHEN cf.path IS NULL THEN 0 ELSE 1 END AS checked
+                     FROM folder_nodes fn
+                     LEFT JOIN checked_folders cf ON cf.path = fn.path
+                     ORDER BY fn.path",
+                )?;
+                let rows = stmt
+                    .query_map([], |row| {
+                        Ok(crate::types::FolderTreeNode {
+                            path: row.get(0)?,
+                            parent_path: row.get(1)?,
+                            image_count: row.get(2)?,
+                            checked: row.get::<_, i64>(3)? != 0,
+                        })
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(rows)
+            })
+            .map_err(|e| e.into())
     }
 
-    fn lap_has(&self, folder_id: i64, image_id: i64) -> Result<bool, Box<dyn std::error::Error>> {
+    pub fn set_folder_checked(
+        &self,
+        folder_path: &str,
+        checked: bool,
+    ) -> Result<(), LoaderError> {
+        self.bootstrap_checked_scope()?;
         let exists: Option<i64> = self
             .db
             .conn()
             .query_row(
-                "SELECT 1 FROM current_lap WHERE folder_id = ?1 AND image_id = ?2 LIMIT 1",
-                params![folder_id, image_id],
-                |row| row.get(0),
-            )
-            .optional()?;
-        Ok(exists.is_some())
-    }
-
-    fn lap_insert(&self, folder_id: i64, image_id: i64) -> Result<(), Box<dyn std::error::Error>> {
-        self.db.conn().execute(
-            "INSERT OR IGNORE INTO current_lap (folder_id, image_id) VALUES (?1, ?2)",
-            params![folder_id, image_id],
-        )?;
-        Ok(())
-    }
-
-    fn lap_clear(&self, folder_id: i64) -> Result<(), Box<dyn std::error::Error>> {
-        self.db.conn().execute(
-            "DELETE FROM current_lap WHERE folder_id = ?1",
-            params![folder_id],
-        )?;
-        Ok(())
-    }
-
-    fn ensure_lap_capacity(
-        &self,
-        folder_id: i64,
-        total_images: i64,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let count = self.lap_count(folder_id)?;
-        if count >= total_images {
-            self.lap_clear(folder_id)?;
-        }
-        Ok(())
-    }
-
-    fn insert_folder(&self, path: &str, added_at: &str) -> Result<i64, Box<dyn std::error::Error>> {
-        self.db.conn().execute(
-            "INSERT OR IGNORE INTO folders (path, added_at) VALUES (?1, ?2)",
-            params![path, added_at],
-        )?;
-
-        let id: i64 = self.db.conn().query_row(
-            "SELECT id FROM folders WHERE path = ?1",
-            params![path],
-            |row| row.get(0),
-        )?;
-        Ok(id)
-    }
-
-    pub fn set_current_folder_id(
-        &self,
-        folder_id: Option<i64>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        eprintln!("[RUST] set_current_folder_id: setting to {:?}", folder_id);
-        let rows = self.db.conn().execute(
-            "UPDATE state SET current_folder_id = ?1 WHERE id = 1",
-            params![folder_id],
-        )?;
-        eprintln!("[RUST] set_current_folder_id: updated {} rows", rows);
-        Ok(())
-    }
-
-    pub fn get_folder_tree(
-        &self,
-    ) -> Result<Vec<(String, Option<String>, i64, bool)>, Box<dyn std::error::Error>> {
-        self.bootstrap_checked_scope()?;
-        self.db
-            .with_conn(|conn| {
-                let mut stmt = conn.prepare(
-                    "SELECT fn.path, fn.parent_path, fn.subtree_image_count,
-                            CASE WHEN cf.path IS NULL THEN 0 ELSE 1 END AS checked
-                     FROM folder_nodes fn
-                     LEFT JOIN checked_folders cf ON cf.path = fn.path
-                     ORDER BY fn.path",
-                )?;
-                let rows = stmt
-                    .query_map([], |row| {
-                        let path: String = row.get(0)?;
-                        let parent_path: Option<String> = row.get(1)?;
-                        let subtree_image_count: i64 = row.get(2)?;
-                        let checked_raw: i64 = row.get(3)?;
-                        Ok((path, parent_path, subtree_image_count, checked_raw != 0))
-                    })?
-                    .collect::<Result<Vec<_>, _>>()?;
-                Ok(rows)
-            })
-            .map_err(|e| e.into())
-    }
-
-    pub fn set_folder_checked(
-        &self,
-        folder_path: &str,
-        checked: bool,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        self.bootstrap_checked_scope()?;
-        let exists: Option<i64> = self
-            .db
-            .conn()
-            .query_row(
-                "SELECT 1 FROM folder_nodes WHERE path = ?1 LIMIT 1",
-                params![folder_path],
+                "SELECT 1 FROM folder_nodes WHERE path = ?1 LIMIT 1",
+                params![folder_path],
                 |row| row.get(0),
             )
             .optional()?;
         if exists.is_none() {
-            return Err("folder not found in indexed tree".into());
+            return Err(LoaderError::NotFound("folder not found in indexed tree".to_string()));
         }
 
         {
@@ -1239,7 +1280,7 @@ impl ImageLoader {
         Ok(())
     }
 
-    pub fn set_folder_exclusive(&self, folder_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn set_folder_exclusive(&self, folder_path: &str) -> Result<(), LoaderError> {
         self.bootstrap_checked_scope()?;
         let exists: Option<i64> = self
             .db
@@ -1251,7 +1292,7 @@ impl ImageLoader {
             )
             .optional()?;
         if exists.is_none() {
-            return Err("folder not found in indexed tree".into());
+            return Err(LoaderError::NotFound("folder not found in indexed tree".to_string()));
         }
 
         {
@@ -1267,7 +1308,7 @@ impl ImageLoader {
         Ok(())
     }
 
-    fn insert_image(&self, path: &str, folder_id: i64) -> Result<(), Box<dyn std::error::Error>> {
+    fn insert_image(&self, path: &str, folder_id: i64) -> Result<(), LoaderError> {
         self.db.conn().execute(
             "INSERT OR IGNORE INTO images (path, folder_id) VALUES (?1, ?2)",
             params![path, folder_id],
@@ -1275,11 +1316,12 @@ impl ImageLoader {
         Ok(())
     }
 
-    fn delete_images_by_folder_id(&self, folder_id: i64) -> Result<(), Box<dyn std::error::Error>> {
-        {
-            let mut conn = self.db.conn();
-            let tx = conn.transaction()?;
-
+    /// Removes a root's images and everything that references them, inside the
+    /// caller's transaction.
+    fn delete_images_by_folder_id_in(
+        tx: &rusqlite::Transaction<'_>,
+        folder_id: i64,
+    ) -> Result<(), LoaderError> {
         // Must delete from dependent tables first due to FOREIGN KEY constraints
         tx.execute(
             "DELETE FROM hidden_normal_images WHERE folder_id = ?1",
@@ -1326,23 +1368,10 @@ impl ImageLoader {
             "DELETE FROM images WHERE folder_id = ?1",
             params![folder_id],
         )?;
-
-        tx.commit()?;
-        }
         Ok(())
     }
 
-    pub fn delete_folder_by_id(&self, folder_id: i64) -> Result<(), Box<dyn std::error::Error>> {
-        let folder_path: Option<String> = self
-            .db
-            .conn()
-            .query_row(
-                "SELECT path FROM folders WHERE id = ?1",
-                params![folder_id],
-                |row| row.get(0),
-            )
-            .optional()?;
-
+    pub fn delete_folder_by_id(&self, folder_id: i64) -> Result<(), LoaderError> {
         let current_folder_id: Option<i64> = self.db.conn().query_row(
             "SELECT current_folder_id FROM state WHERE id = 1",
             [],
@@ -1368,65 +1397,15 @@ impl ImageLoader {
         let mut conn = self.db.conn();
         let tx = conn.transaction()?;
 
-        // Delete related records first
-        tx.execute(
-            "DELETE FROM hidden_normal_images WHERE folder_id = ?1",
-            params![folder_id],
-        )?;
-        tx.execute(
-            "DELETE FROM hidden_random_images WHERE folder_id = ?1",
-            params![folder_id],
-        )?;
-        tx.execute(
-            "DELETE FROM random_history WHERE folder_id = ?1",
-            params![folder_id],
-        )?;
-        tx.execute(
-            "DELETE FROM current_lap WHERE folder_id = ?1",
-            params![folder_id],
-        )?;
-        tx.execute(
-            "DELETE FROM random_history_global
-             WHERE image_id IN (SELECT id FROM images WHERE folder_id = ?1)",
-            params![folder_id],
-        )?;
-        tx.execute(
-            "DELETE FROM current_lap_global
-             WHERE image_id IN (SELECT id FROM images WHERE folder_id = ?1)",
-            params![folder_id],
-        )?;
-        tx.execute(
-            "DELETE FROM active_images
-             WHERE image_id IN (SELECT id FROM images WHERE folder_id = ?1)",
-            params![folder_id],
-        )?;
-        tx.execute(
-            "DELETE FROM active_image_refcounts
-             WHERE image_id IN (SELECT id FROM images WHERE folder_id = ?1)",
-            params![folder_id],
-        )?;
-        tx.execute(
-            "DELETE FROM folder_images_direct
-             WHERE image_id IN (SELECT id FROM images WHERE folder_id = ?1)",
-            params![folder_id],
-        )?;
+        Self::delete_images_by_folder_id_in(&tx, folder_id)?;
+
+        // Only this root's own nodes: an overlapping root's checked folders stay.
         tx.execute(
-            "DELETE FROM images WHERE folder_id = ?1",
+            "DELETE FROM checked_folders
+             WHERE path IN (SELECT path FROM folder_nodes WHERE root_folder_id = ?1)",
             params![folder_id],
         )?;
 
-        if let Some(path) = &folder_path {
-            tx.execute(
-                "DELETE FROM checked_folders
-                 WHERE path IN (
-                   SELECT descendant_path
-                   FROM folder_closure
-                   WHERE ancestor_path = ?1
-                 )",
-                params![path],
-            )?;
-        }
-
         tx.execute(
             "DELETE FROM folder_closure
              WHERE ancestor_path IN (SELECT path FROM folder_nodes WHERE root_folder_id = ?1)
@@ -1457,7 +1436,7 @@ impl ImageLoader {
     pub fn set_current_folder_by_path(
         &self,
         path: &str,
-    ) -> Result<(i64, String), Box<dyn std::error::Error>> {
+    ) -> Result<(i64, String), LoaderError> {
         let canonical_path = Self::canonicalize_folder_path(path)?;
         let added_at = chrono::Utc::now().to_rfc3339();
         let id = self.insert_folder(&canonical_path, &added_at)?;
@@ -1465,1266 +1444,4609 @@ impl ImageLoader {
         Ok((id, canonical_path))
     }
 
-    /// Returns (folder_id, auto_switched)
-    pub async fn ensure_images_indexed_with_progress<F>(
-        &self,
-        mut on_progress: F,
-        force_rescan: bool,
-    ) -> Result<(i64, bool), Box<dyn std::error::Error>>
-    where
-        F: FnMut(String),
-    {
-        // First, check if current folder is valid and switch if needed
-        let (folder_id, auto_switched) = self.ensure_valid_current_folder(&mut on_progress)?;
-
-        // Now index the valid folder
-        self.index_folder_with_progress(folder_id, &mut on_progress, force_rescan)
-            .await?;
-        Ok((folder_id, auto_switched))
+    /// Records whether a root is reachable. Online roots also get a fresh
+    /// `last_seen_at`. Returns true when the online/offline state flipped.
+    fn set_folder_availability(&self, folder_id: i64, online: bool) -> Result<bool, LoaderError> {
+        let changed = self.db.conn().execute(
+            "UPDATE folders SET offline = ?1 WHERE id = ?2 AND offline != ?1",
+            params![!online as i64, folder_id],
+        )? > 0;
+        if online {
+            self.db.conn().execute(
+                "UPDATE folders SET last_seen_at = ?1 WHERE id = ?2",
+                params![chrono::Utc::now().to_rfc3339(), folder_id],
+            )?;
+        }
+        Ok(changed)
     }
 
-    /// Ensures we have a valid current folder, switching to another if needed.
-    /// Returns (folder_id, auto_switched) where auto_switched is true if a
-    /// deleted folder was detected and we fell back to another one.
-    fn ensure_valid_current_folder<F>(
+    /// Checks that a root still exists on disk and records the result.
+    /// Missing roots are kept, with their history, hidden lists and laps, so
+    /// they can come back or be relocated; while offline their images are left
+    /// out of the active set.
+    fn refresh_folder_availability(
         &self,
-        on_progress: &mut F,
-    ) -> Result<(i64, bool), Box<dyn std::error::Error>>
-    where
-        F: FnMut(String),
-    {
-        // Check current folder
-        if let Some((folder_id, folder_path)) = self.get_current_folder_id_and_path()? {
-            if std::path::Path::new(&folder_path).exists() {
-                return Ok((folder_id, false));
-            }
-            // Current folder deleted, remove it
-            on_progress(format!("folder deleted: {}", folder_path));
-            self.delete_folder_by_id(folder_id)?;
+        folder_id: i64,
+        folder_path: &str,
+    ) -> Result<bool, LoaderError> {
+        let online = Path::new(folder_path).is_dir();
+        if self.set_folder_availability(folder_id, online)? {
+            self.rebuild_active_images()?;
         }
+        Ok(online)
+    }
 
-        // Try to find another valid folder from history
-        let history = self.get_folder_history()?;
-
-        if history.is_empty() {
-            return Err("no folders available - pick a folder first".into());
+    /// Re-checks every root, e.g. after a drive was plugged in or removed.
+    pub fn refresh_all_folder_availability(&self) -> Result<(), LoaderError> {
+        let roots = self.db.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT id, path FROM folders")?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        })?;
+        let mut changed = false;
+        for (folder_id, path) in roots {
+            changed |= self.set_folder_availability(folder_id, Path::new(&path).is_dir())?;
+        }
+        if changed {
+            self.rebuild_active_images()?;
         }
+        Ok(())
+    }
 
-        // Try each folder in history
-        for (folder_id, folder_path, _, _) in history {
-            if std::path::Path::new(&folder_path).exists() {
-                on_progress(format!("switched to folder: {}", folder_path));
-                self.set_current_folder_id(Some(folder_id))?;
-                return Ok((folder_id, true));
-            } else {
-                on_progress(format!("cleaning up deleted folder: {}", folder_path));
+    /// Removes offline roots that were deleted from disk. A root whose parent
+    /// directory is gone as well looks like an unplugged drive or unmounted
+    /// share, so it is kept. Returns the removed paths.
+    pub fn cleanup_stale_folders(&self) -> Result<Vec<String>, LoaderError> {
+        self.refresh_all_folder_availability()?;
+        let availability = self.get_folder_availability()?;
+        let mut removed_paths = Vec::new();
+        for (folder_id, path, _, _) in self.get_folder_history()? {
+            let online = availability.get(&folder_id).map_or(true, |(online, _)| *online);
+            let parent_present = Path::new(&path).parent().is_some_and(|parent| parent.is_dir());
+            if !online && parent_present {
                 self.delete_folder_by_id(folder_id)?;
+                removed_paths.push(path);
             }
         }
-
-        Err("all folders in history have been deleted - pick a folder first".into())
+        Ok(removed_paths)
     }
 
-    /// Indexes a specific folder (internal method)
-    async fn index_folder_with_progress<F>(
+    /// Availability per root: (online, last_seen_at).
+    pub fn get_folder_availability(
         &self,
-        folder_id: i64,
-        on_progress: &mut F,
-        force_rescan: bool,
-    ) -> Result<i64, Box<dyn std::error::Error>>
-    where
-        F: FnMut(String),
-    {
-        let folder_path: String = self.db.conn().query_row(
-            "SELECT path FROM folders WHERE id = ?1",
-            params![folder_id],
-            |row| row.get(0),
-        )?;
+    ) -> Result<HashMap<i64, (bool, Option<String>)>, LoaderError> {
+        self.db
+            .with_read(|conn| {
+                let mut stmt = conn.prepare("SELECT id, offline, last_seen_at FROM folders")?;
+                let rows = stmt
+                    .query_map([], |row| {
+                        let id: i64 = row.get(0)?;
+                        let offline: i64 = row.get(1)?;
+                        let last_seen_at: Option<String> = row.get(2)?;
+                        Ok((id, (offline == 0, last_seen_at)))
+                    })?
+                    .collect::<Result<HashMap<_, _>, _>>()?;
+                Ok(rows)
+            })
+            .map_err(|e| e.into())
+    }
 
-        on_progress(format!("scan:start {}", folder_path));
+    /// Handles an image whose file is gone. If its root went offline the image
+    /// is kept and the root is taken out of the active set; otherwise the image
+    /// is removed. Returns the offline root's path in the first case.
+    fn forget_missing_image(&self, image_id: i64) -> Result<Option<String>, LoaderError> {
+        let root: Option<(i64, String)> = self
+            .db
+            .conn()
+            .query_row(
+                "SELECT f.id, f.path FROM images i JOIN folders f ON f.id = i.folder_id
+                 WHERE i.id = ?1",
+                params![image_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        if let Some((folder_id, path)) = root {
+            if !self.refresh_folder_availability(folder_id, &path)? {
+                return Ok(Some(path));
+            }
+        }
+        self.delete_image_by_id(image_id)?;
+        Ok(None)
+    }
 
-        let count = self.count_images(folder_id)?;
-        if count > 0 && !force_rescan {
-            on_progress(format!("scan:skip already indexed count={}", count));
-            return Ok(folder_id);
+    /// Moves a root to a new location, e.g. after a drive letter or mount
+    /// point changed. Every stored path below the old root is rewritten in one
+    /// transaction, so image ids and everything keyed by them are kept.
+    pub fn relocate_folder(
+        &self,
+        old_root: &str,
+        new_root: &str,
+    ) -> Result<(i64, String), LoaderError> {
+        let old_root = old_root.trim().trim_end_matches(['/', '\\']);
+        let folder_id: i64 = self
+            .db
+            .conn()
+            .query_row(
+                "SELECT id FROM folders WHERE path = ?1",
+                params![old_root],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| LoaderError::NotFound(format!("folder not found: {}", old_root)))?;
+        let new_root = Self::canonicalize_folder_path(new_root)?;
+        if new_root == old_root {
+            self.refresh_folder_availability(folder_id, &new_root)?;
+            return Ok((folder_id, new_root));
         }
 
-        let mut paths: Vec<String> = Vec::new();
-        paths.reserve(1024);
+        let other_roots = self.db.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT path FROM folders WHERE id != ?1")?;
+            let rows = stmt
+                .query_map(params![folder_id], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        })?;
+        if let Some(other) = other_roots.iter().find(|other| {
+            Path::new(&new_root).starts_with(other) || Path::new(other).starts_with(&new_root)
+        }) {
+            return Err(LoaderError::Invalid(format!(
+                "new location overlaps another folder: {}",
+                other
+            )));
+        }
 
-        for entry in WalkDir::new(&folder_path)
-            .follow_links(false)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.path().is_file())
+        // Only accept a location that actually holds this folder's images.
+        let sample = self.db.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT path FROM images WHERE folder_id = ?1 LIMIT 50")?;
+            let rows = stmt
+                .query_map(params![folder_id], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        })?;
+        let relocated = |path: &str| format!("{}{}", new_root, &path[old_root.len()..]);
+        if !sample.is_empty()
+            && !sample
+                .iter()
+                .filter(|path| path.starts_with(old_root))
+                .any(|path| Path::new(&relocated(path)).is_file())
         {
-            let path = entry.path();
-            if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                if Self::is_supported_image_ext(ext) {
-                    if let Some(path_str) = path.to_str() {
-                        paths.push(path_str.to_string());
-                        if paths.len() % 200 == 0 {
-                            on_progress(format!("scan:found {}", paths.len()));
-                        }
-                    }
-                }
-            }
+            return Err(LoaderError::Invalid(
+                "new location does not contain this folder's images".to_string(),
+            ));
         }
 
-        on_progress(format!("scan:done total={}", paths.len()));
+        // substr() counts characters, not bytes.
+        let old_len = old_root.chars().count() as i64;
+        let old_prefix = format!("{}{}", old_root, std::path::MAIN_SEPARATOR);
+        let rewrite = |column: &str| {
+            format!(
+                "{column} = CASE WHEN {column} = ?1 OR substr({column}, 1, ?3 + 1) = ?4
+                     THEN ?2 || substr({column}, ?3 + 1) ELSE {column} END"
+            )
+        };
+        let under_old = |column: &str| format!("({column} = ?1 OR substr({column}, 1, ?3 + 1) = ?4)");
 
-        if !paths.is_empty() {
-            let mut conn = self.db.conn();
-            let tx = conn.transaction()?;
-            {
-                let total = paths.len();
-                let mut stmt =
-                    tx.prepare("INSERT OR IGNORE INTO images (path, folder_id) VALUES (?1, ?2)")?;
-                for (i, path) in paths.iter().enumerate() {
-                    stmt.execute(params![path, folder_id])?;
-                    let done = i + 1;
-                    if done <= 10 || done == total || done % 100 == 0 {
-                        on_progress(format!("index:{}/{} {}", done, total, path));
-                    }
-                }
-            }
-            tx.commit()?;
-            on_progress("index:done".to_string());
-        }
-
-        let after_count = self.count_images(folder_id)?;
-        if after_count == 0 {
-            on_progress("index:error no images found".to_string());
-            return Err("no images found in folder".into());
-        }
-
-        self.rebuild_folder_nodes_for_root(folder_id)?;
-        self.ensure_default_checked_folder()?;
-        self.rebuild_active_images()?;
-
-        on_progress(format!("index:ready count={}", after_count));
-        Ok(folder_id)
-    }
-
-    /// Returns (folder_id, auto_switched)
-    pub async fn ensure_images_indexed(&self) -> Result<(i64, bool), Box<dyn std::error::Error>> {
-        self.ensure_images_indexed_with_progress(|_| {}, false)
-            .await
-    }
-
-    pub async fn set_current_folder_and_index(
-        &self,
-        path: &str,
-    ) -> Result<(i64, String), Box<dyn std::error::Error>> {
-        let (_id, canonical_path) = self.set_current_folder_by_path(path)?;
-        let (folder_id, _) = self.ensure_images_indexed().await?;
-        Ok((folder_id, canonical_path))
-    }
-
-    pub async fn set_current_folder_and_index_with_progress<F>(
-        &self,
-        path: &str,
-        on_progress: F,
-    ) -> Result<(i64, String), Box<dyn std::error::Error>>
-    where
-        F: FnMut(String),
-    {
-        let (_id, canonical_path) = self.set_current_folder_by_path(path)?;
-        let (folder_id, _) = self
-            .ensure_images_indexed_with_progress(on_progress, false)
-            .await?;
-        Ok((folder_id, canonical_path))
-    }
-
-    pub async fn load_by_image_id(
-        &self,
-        image_id: i64,
-    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let path = self.get_image_path(image_id)?;
-        self.set_last_image_id(Some(image_id))?;
-
-        match std::fs::read(&path) {
-            Ok(data) => Ok(data),
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                // Image file no longer exists - remove from database and return specific error
-                self.delete_image_by_id(image_id)?;
-                Err(format!("image file not found: {} - reindex please", path).into())
-            }
-            Err(e) => Err(format!("failed to read image: {} - reindex please", e).into()),
-        }
-    }
-
-    fn delete_image_by_id(&self, image_id: i64) -> Result<(), Box<dyn std::error::Error>> {
         let mut conn = self.db.conn();
         let tx = conn.transaction()?;
-
+        // Node paths are referenced by several tables; check them at commit.
+        tx.execute_batch("PRAGMA defer_foreign_keys = ON")?;
+        let args = params![old_root, new_root, old_len, old_prefix];
         tx.execute(
-            "DELETE FROM hidden_normal_images WHERE image_id = ?1",
-            params![image_id],
+            &format!("UPDATE images SET {} WHERE folder_id = ?5", rewrite("path")),
+            params![old_root, new_root, old_len, old_prefix, folder_id],
         )?;
         tx.execute(
-            "DELETE FROM hidden_random_images WHERE image_id = ?1",
-            params![image_id],
+            &format!(
+                "UPDATE folder_nodes SET {}, {} WHERE root_folder_id = ?5",
+                rewrite("path"),
+                rewrite("parent_path")
+            ),
+            params![old_root, new_root, old_len, old_prefix, folder_id],
         )?;
-
-        // Delete references in random_history first (foreign key constraint)
         tx.execute(
-            "DELETE FROM random_history WHERE image_id = ?1",
-            params![image_id],
+            &format!(
+                "UPDATE folder_closure SET {}, {} WHERE {} OR {}",
+                rewrite("ancestor_path"),
+                rewrite("descendant_path"),
+                under_old("ancestor_path"),
+                under_old("descendant_path")
+            ),
+            args,
         )?;
         tx.execute(
-            "DELETE FROM random_history_global WHERE image_id = ?1",
-            params![image_id],
+            &format!(
+                "UPDATE checked_folders SET {} WHERE {}",
+                rewrite("path"),
+                under_old("path")
+            ),
+            args,
         )?;
         tx.execute(
-            "DELETE FROM current_lap_global WHERE image_id = ?1",
-            params![image_id],
+            &format!(
+                "UPDATE folder_images_direct SET {} WHERE {}",
+                rewrite("folder_path"),
+                under_old("folder_path")
+            ),
+            args,
         )?;
-        tx.execute("DELETE FROM active_images WHERE image_id = ?1", params![image_id])?;
         tx.execute(
-            "DELETE FROM active_image_refcounts WHERE image_id = ?1",
-            params![image_id],
+            &format!(
+                "UPDATE OR REPLACE image_views SET {} WHERE {}",
+                rewrite("path"),
+                under_old("path")
+            ),
+            args,
         )?;
         tx.execute(
-            "DELETE FROM folder_images_direct WHERE image_id = ?1",
-            params![image_id],
+            &format!(
+                "UPDATE OR REPLACE image_tags SET {} WHERE {}",
+                rewrite("path"),
+                under_old("path")
+            ),
+            args,
+        )?;
+        tx.execute(
+            "UPDATE folders SET path = ?1, offline = 0, last_seen_at = ?2 WHERE id = ?3",
+            params![new_root, chrono::Utc::now().to_rfc3339(), folder_id],
         )?;
-
-        // Then delete the image
-        tx.execute("DELETE FROM images WHERE id = ?1", params![image_id])?;
-
         tx.commit()?;
-        Ok(())
+        drop(conn);
+        self.rebuild_active_images()?;
+        Ok((folder_id, new_root))
     }
 
-    /// Returns (image_data, auto_switched_folder)
-    pub async fn get_next_image(&self) -> Result<(Vec<u8>, bool), Box<dyn std::error::Error>> {
-        let (_folder_id, auto_switched) = self.ensure_images_indexed().await?;
-        self.bootstrap_checked_scope()?;
-        self.require_checked_folders()?;
-        let visible = self.get_visible_checked_normal_entries()?;
-        if visible.is_empty() {
-            return Err("all images for this folder are hidden in normal mode - reindex to clear hidden images".into());
+    /// The root whose tree contains `path` (the root itself included). When
+    /// older databases still hold nested roots, the innermost one wins.
+    fn find_owning_root(&self, path: &Path) -> Result<Option<(i64, String)>, LoaderError> {
+        let roots = self.db.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT id, path FROM folders")?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        })?;
+        Ok(roots
+            .into_iter()
+            .filter(|(_, root)| path.starts_with(root))
+            .max_by_key(|(_, root)| root.len()))
+    }
+
+    /// Merges every root nested below `folder_id`'s path into it, in one
+    /// transaction. The outer root has already been scanned, so its rows for
+    /// the nested paths are dropped in favour of the nested roots' own rows;
+    /// image ids, hidden images, laps and history therefore survive, and the
+    /// nested roots' nodes keep their checked state under the new owner.
+    fn absorb_nested_roots(&self, folder_id: i64) -> Result<(), LoaderError> {
+        let root_path: String = self.db.conn().query_row(
+            "SELECT path FROM folders WHERE id = ?1",
+            params![folder_id],
+            |row| row.get(0),
+        )?;
+        let nested = self.db.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT id, path FROM folders WHERE id != ?1 ORDER BY path")?;
+            let rows = stmt
+                .query_map(params![folder_id], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        })?;
+        let nested: Vec<i64> = nested
+            .into_iter()
+            .filter(|(_, path)| Path::new(path).starts_with(&root_path))
+            .map(|(id, _)| id)
+            .collect();
+        if nested.is_empty() {
+            return Ok(());
         }
 
-        let current_raw_index = self.get_state_normal_index()?;
-        let next_pos = match visible
-            .iter()
-            .position(|(order_index, _, _, _)| *order_index == current_raw_index)
         {
-            Some(pos) => (pos + 1) % visible.len(),
-            None => 0,
-        };
+            let mut conn = self.db.conn();
+            let tx = conn.transaction()?;
+            tx.execute(
+                "CREATE TEMP TABLE IF NOT EXISTS absorbed_duplicates (image_id INTEGER PRIMARY KEY)",
+                [],
+            )?;
+            for child_id in &nested {
+                tx.execute("DELETE FROM absorbed_duplicates", [])?;
+                tx.execute(
+                    "INSERT INTO absorbed_duplicates (image_id)
+                     SELECT outer_image.id
+                     FROM images outer_image
+                     JOIN images inner_image ON inner_image.path = outer_image.path
+                     WHERE outer_image.folder_id = ?1 AND inner_image.folder_id = ?2",
+                    params![folder_id, child_id],
+                )?;
+                for table in [
+                    "hidden_normal_images",
+                    "hidden_random_images",
+                    "random_history",
+                    "current_lap",
+                    "random_history_global",
+                    "current_lap_global",
+                    "active_images",
+                    "active_image_refcounts",
+                    "folder_images_direct",
+                ] {
+                    tx.execute(
+                        &format!(
+                            "DELETE FROM {} WHERE image_id IN (SELECT image_id FROM absorbed_duplicates)",
+                            table
+                        ),
+                        [],
+                    )?;
+                }
+                tx.execute(
+                    "DELETE FROM images WHERE id IN (SELECT image_id FROM absorbed_duplicates)",
+                    [],
+                )?;
 
-        let (order_index, image_id, _, selected_folder_id) = visible[next_pos].clone();
-        self.set_state_normal_index(order_index)?;
-        if selected_folder_id > 0 {
-            self.set_current_folder_id(Some(selected_folder_id))?;
+                let history_offset: i64 = tx.query_row(
+                    "SELECT COALESCE(MAX(order_index), -1) + 1 FROM random_history WHERE folder_id = ?1",
+                    params![folder_id],
+                    |row| row.get(0),
+                )?;
+                tx.execute(
+                    "UPDATE random_history SET folder_id = ?1, order_index = order_index + ?2
+                     WHERE folder_id = ?3",
+                    params![folder_id, history_offset, child_id],
+                )?;
+                for table in [
+                    "images",
+                    "hidden_normal_images",
+                    "hidden_random_images",
+                    "current_lap",
+                ] {
+                    tx.execute(
+                        &format!("UPDATE {} SET folder_id = ?1 WHERE folder_id = ?2", table),
+                        params![folder_id, child_id],
+                    )?;
+                }
+                tx.execute(
+                    "UPDATE folder_nodes SET root_folder_id = ?1 WHERE root_folder_id = ?2",
+                    params![folder_id, child_id],
+                )?;
+                tx.execute(
+                    "UPDATE state SET current_folder_id = ?1 WHERE id = 1 AND current_folder_id = ?2",
+                    params![folder_id, child_id],
+                )?;
+                tx.execute("DELETE FROM folders WHERE id = ?1", params![child_id])?;
+            }
+            tx.execute("DROP TABLE IF EXISTS absorbed_duplicates", [])?;
+            tx.commit()?;
         }
-        let data = self.load_by_image_id(image_id).await?;
-        Ok((data, auto_switched))
+
+        self.rebuild_folder_nodes_for_root(folder_id)?;
+        self.rebuild_active_images()?;
+        Ok(())
     }
 
-    /// Returns (image_data, auto_switched_folder)
-    pub async fn get_prev_image(&self) -> Result<(Vec<u8>, bool), Box<dyn std::error::Error>> {
-        let (_folder_id, auto_switched) = self.ensure_images_indexed().await?;
-        self.bootstrap_checked_scope()?;
-        self.require_checked_folders()?;
-        let visible = self.get_visible_checked_normal_entries()?;
-        if visible.is_empty() {
-            return Err("all images for this folder are hidden in normal mode - reindex to clear hidden images".into());
-        }
+    pub fn indexing_jobs(&self) -> &IndexingJobs {
+        &self.indexing_jobs
+    }
 
-        let current_raw_index = self.get_state_normal_index()?;
-        let prev_pos = match visible
-            .iter()
-            .position(|(order_index, _, _, _)| *order_index == current_raw_index)
-        {
-            Some(pos) if pos == 0 => visible.len() - 1,
-            Some(pos) => pos - 1,
-            None => visible.len() - 1,
-        };
+    /// Returns (folder_id, auto_switched)
+    pub fn ensure_images_indexed_with_progress<F>(
+        &self,
+        job: &IndexingJob,
+        mut on_progress: F,
+        force_rescan: bool,
+    ) -> Result<(i64, bool), LoaderError>
+    where
+        F: FnMut(String),
+    {
+        // Pick up drives that were plugged in or removed since the last call
+        self.refresh_all_folder_availability()?;
+        // First, check if current folder is valid and switch if needed
+        let (folder_id, auto_switched) = self.ensure_valid_current_folder(&mut on_progress)?;
 
-        let (order_index, image_id, _, selected_folder_id) = visible[prev_pos].clone();
-        self.set_state_normal_index(order_index)?;
-        if selected_folder_id > 0 {
-            self.set_current_folder_id(Some(selected_folder_id))?;
-        }
-        let data = self.load_by_image_id(image_id).await?;
-        Ok((data, auto_switched))
+        // Now index the valid folder
+        self.index_folder_with_progress(folder_id, job, &mut on_progress, force_rescan)?;
+        Ok((folder_id, auto_switched))
     }
 
-    pub async fn get_current_image_or_first(
+    /// Ensures we have a valid current folder, switching to another if needed.
+    /// Returns (folder_id, auto_switched) where auto_switched is true if a
+    /// deleted folder was detected and we fell back to another one.
+    fn ensure_valid_current_folder<F>(
         &self,
-    ) -> Result<(Vec<u8>, bool), Box<dyn std::error::Error>> {
-        let (_folder_id, auto_switched) = self.ensure_images_indexed().await?;
-        self.bootstrap_checked_scope()?;
-        self.require_checked_folders()?;
-        let visible = self.get_visible_checked_normal_entries()?;
-        if visible.is_empty() {
-            return Err("all images for this folder are hidden in normal mode - reindex to clear hidden images".into());
+        on_progress: &mut F,
+    ) -> Result<(i64, bool), LoaderError>
+    where
+        F: FnMut(String),
+    {
+        // Check current folder
+        if let Some((folder_id, folder_path)) = self.get_current_folder_id_and_path()? {
+            if self.refresh_folder_availability(folder_id, &folder_path)? {
+                return Ok((folder_id, false));
+            }
+            // Current folder is gone, keep it for relocation but move on
+            on_progress(format!("folder offline: {}", folder_path));
         }
 
-        let current_raw_index = self.get_state_normal_index()?;
-        let selected = visible
-            .iter()
-            .find(|(order_index, _, _, _)| *order_index == current_raw_index)
-            .cloned()
-            .unwrap_or_else(|| visible[0].clone());
-
-        self.set_state_normal_index(selected.0)?;
-        if selected.3 > 0 {
-            self.set_current_folder_id(Some(selected.3))?;
-        }
-        let data = self.load_by_image_id(selected.1).await?;
-        Ok((data, auto_switched))
-    }
+        // Try to find another valid folder from history
+        let history = self.get_folder_history()?;
 
-    pub async fn get_current_random_image_or_last(
-        &self,
-    ) -> Result<(Vec<u8>, bool), Box<dyn std::error::Error>> {
-        let (_folder_id, auto_switched) = self.ensure_images_indexed().await?;
-        if self.random_history_global_count()? == 0 {
-            return self.get_force_random_image(true).await;
+        if history.is_empty() {
+            return Err(LoaderError::NoFoldersAvailable);
         }
 
-        let visible = self.get_visible_random_entries_global()?;
-        if visible.is_empty() {
-            return self.get_force_random_image(true).await;
+        // Try each folder in history
+        for (folder_id, folder_path, _, _) in history {
+            if self.refresh_folder_availability(folder_id, &folder_path)? {
+                on_progress(format!("switched to folder: {}", folder_path));
+                self.set_current_folder_id(Some(folder_id))?;
+                return Ok((folder_id, true));
+            } else {
+                on_progress(format!("folder offline: {}", folder_path));
+            }
         }
 
-        let current_order_index = self.get_state_random_index()?;
-        let selected = visible
-            .iter()
-            .find(|(order_index, _, _)| *order_index == current_order_index)
-            .cloned()
-            .unwrap_or_else(|| visible[visible.len() - 1].clone());
+        Err(LoaderError::AllFoldersMissing)
+    }
 
-        self.set_state_random_index(selected.0)?;
-        let data = self.load_by_image_id(selected.1).await?;
-        Ok((data, auto_switched))
+    /// Walks `root` in parallel and collects supported image files. File types
+    /// come from the directory entries, so only symlinks cost an extra stat.
+    fn scan_image_paths<F>(
+        root: &str,
+        rules: &Arc<ScanRules>,
+        job: &IndexingJob,
+        on_progress: &mut F,
+    ) -> Result<Vec<String>, LoaderError>
+    where
+        F: FnMut(String),
+    {
+        let walk_rules = Arc::clone(rules);
+        let mut paths: Vec<String> = Vec::with_capacity(1024);
+        for entry in ScanWalkDir::new(root)
+            .skip_hidden(false)
+            .follow_links(false)
+            .process_read_dir(move |_depth, dir, stack, children| {
+                walk_rules.filter_children(dir, stack, children)
+            })
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            if job.is_cancelled() {
+                on_progress("scan:cancelled".to_string());
+                return Err(LoaderError::Cancelled);
+            }
+            let file_type = entry.file_type();
+            if file_type.is_dir() {
+                continue;
+            }
+            let path = entry.path();
+            let supported = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(Self::is_supported_image_ext)
+                .unwrap_or(false);
+            if !supported {
+                continue;
+            }
+            let is_file = file_type.is_file() || (file_type.is_symlink() && path.is_file());
+            if !is_file {
+                continue;
+            }
+            if let Some(path_str) = path.to_str() {
+                paths.push(path_str.to_string());
+                if paths.len() % 200 == 0 {
+                    on_progress(format!("scan:found {}", paths.len()));
+                    job.set_found(paths.len() as u64);
+                    job.set_ignored(rules.ignored_count());
+                    job.report(IndexingPhase::Scan, Some(path_str));
+                }
+            }
+        }
+        Ok(paths)
     }
 
-    pub async fn get_force_random_image(
+    /// Scans and indexes one root. Rows are inserted in chunked transactions so
+    /// the connection is released between chunks. Cancellation is honoured while
+    /// scanning and between chunks; a cancelled fresh index removes its partial
+    /// rows, and a rescan only replaces the old rows once it has every new path
+    /// (see `rescan_folder`). `folders.indexed_at` is set in the last
+    /// transaction, so rows left by an index that never finished are replaced
+    /// like a rescan instead of being taken as the whole folder.
+    fn index_folder_with_progress<F>(
         &self,
-        force_pointer_to_last: bool,
-    ) -> Result<(Vec<u8>, bool), Box<dyn std::error::Error>> {
-        let (_folder_id, auto_switched) = self.ensure_images_indexed().await?;
-        self.bootstrap_checked_scope()?;
-        self.require_checked_folders()?;
-
-        let mut skipped_count = 0;
-        let mut reset_lap_once = false;
-
-        let image_id = loop {
-            let folder_counts = self.get_checked_folder_available_counts()?;
-            if folder_counts.is_empty() {
-                if !reset_lap_once {
-                    self.lap_global_clear()?;
-                    reset_lap_once = true;
-                    continue;
-                }
+        folder_id: i64,
+        job: &IndexingJob,
+        on_progress: &mut F,
+        force_rescan: bool,
+    ) -> Result<i64, LoaderError>
+    where
+        F: FnMut(String),
+    {
+        let (folder_path, follow_symlinks): (String, bool) = self.db.conn().query_row(
+            "SELECT path, follow_symlinks FROM folders WHERE id = ?1",
+            params![folder_id],
+            |row| Ok((row.get(0)?, row.get::<_, i64>(1)? != 0)),
+        )?;
 
-                if skipped_count > 0 {
-                    return Err(format!(
-                        "skipped {} deleted image(s), no valid images found - reindex please",
-                        skipped_count
-                    )
-                    .into());
-                }
+        on_progress(format!("scan:start {}", folder_path));
+        job.report(IndexingPhase::Scan, Some(&folder_path));
 
-                return Err(
-                    "all images for this folder are hidden in random mode - reindex to clear hidden images"
-                        .into(),
-                );
-            }
+        let count = self.count_images(folder_id)?;
+        if count > 0 && self.is_folder_indexed(folder_id)? && !force_rescan {
+            on_progress(format!("scan:skip already indexed count={}", count));
+            return Ok(folder_id);
+        }
+        let rescan = force_rescan || count > 0;
+        if rescan && !force_rescan {
+            on_progress(format!("scan:resume incomplete index count={}", count));
+        }
 
-            let total: i64 = folder_counts.iter().map(|(_, count)| *count).sum();
-            if total <= 0 {
-                return Err("no images available".into());
+        let (ignore_patterns, skip_hidden_dirs) = self.load_ignore_settings()?;
+        let rules = Arc::new(ScanRules::new(
+            Path::new(&folder_path),
+            &ignore_patterns,
+            skip_hidden_dirs,
+            follow_symlinks,
+        )?);
+        let mut paths = Self::scan_image_paths(&folder_path, &rules, job, on_progress)?;
+        if rules.follow_symlinks() {
+            let (deduped, duplicates) = dedup_by_canonical_path(paths);
+            paths = deduped;
+            on_progress(format!(
+                "scan:symlinks duplicates={} loops={}",
+                duplicates,
+                rules.symlink_loop_count()
+            ));
+        }
+
+        on_progress(format!(
+            "scan:done total={} ignored={}",
+            paths.len(),
+            rules.ignored_count()
+        ));
+        job.set_found(paths.len() as u64);
+        job.set_ignored(rules.ignored_count());
+        job.set_total(paths.len() as u64);
+        job.report(IndexingPhase::Scan, None);
+
+        if rescan {
+            if paths.is_empty() {
+                on_progress("index:error no images found".to_string());
+                return Err(LoaderError::NoImages);
             }
+            self.rescan_folder(folder_id, &paths, job, on_progress)?;
+        } else if !paths.is_empty() {
+            let total = paths.len();
+            let chunk_count = total.div_ceil(INDEX_INSERT_CHUNK_SIZE);
+            let mut done = 0;
+            for (chunk_idx, chunk) in paths.chunks(INDEX_INSERT_CHUNK_SIZE).enumerate() {
+                if job.is_cancelled() {
+                    let mut conn = self.db.conn();
+                    let tx = conn.transaction()?;
+                    Self::delete_images_by_folder_id_in(&tx, folder_id)?;
+                    tx.commit()?;
+                    on_progress("index:cancelled".to_string());
+                    return Err(LoaderError::Cancelled);
+                }
 
-            let mut draw = rand::random::<u64>() % (total as u64);
-            let mut selected_folder = folder_counts[0].0.clone();
-            for (path, count) in &folder_counts {
-                let weight = *count as u64;
-                if draw < weight {
-                    selected_folder = path.clone();
-                    break;
+                let mut conn = self.db.conn();
+                let tx = conn.transaction()?;
+                {
+                    let mut stmt = tx
+                        .prepare("INSERT OR IGNORE INTO images (path, folder_id) VALUES (?1, ?2)")?;
+                    for path in chunk {
+                        stmt.execute(params![path, folder_id])?;
+                        done += 1;
+                        if done <= 10 || done == total || done % 100 == 0 {
+                            on_progress(format!("index:{}/{} {}", done, total, path));
+                            job.set_inserted(done as u64);
+                            job.report(IndexingPhase::Insert, Some(path));
+                        }
+                    }
                 }
-                draw -= weight;
+                if chunk_idx + 1 == chunk_count {
+                    Self::mark_folder_indexed_in(&tx, folder_id)?;
+                }
+                tx.commit()?;
             }
+            on_progress("index:done".to_string());
+        }
 
-            let image_ids = self.get_available_random_image_ids_for_checked_folder(&selected_folder)?;
-            if image_ids.is_empty() {
-                continue;
-            }
-            let pick_idx = (rand::random::<u64>() % (image_ids.len() as u64)) as usize;
-            let candidate = image_ids[pick_idx];
+        job.report(IndexingPhase::Finalize, None);
+        let after_count = self.count_images(folder_id)?;
+        if after_count == 0 {
+            on_progress("index:error no images found".to_string());
+            return Err(LoaderError::NoImages);
+        }
 
-            let path = match self.get_image_path(candidate) {
-                Ok(p) => p,
-                Err(_) => continue,
-            };
+        // Sorting must not stat or decode files while navigating.
+        let order = self.get_folder_sort_order(folder_id)?;
+        self.ensure_sort_metadata(folder_id, order)?;
 
-            if !std::path::Path::new(&path).exists() {
-                self.delete_image_by_id(candidate)?;
-                skipped_count += 1;
-                continue;
+        if rescan {
+            // The swap already rebuilt the nodes and active images.
+            if !self.has_checked_folders()? {
+                self.ensure_default_checked_folder()?;
+                self.rebuild_active_images()?;
             }
+        } else {
+            self.rebuild_folder_nodes_for_root(folder_id)?;
+            self.ensure_default_checked_folder()?;
+            self.rebuild_active_images()?;
+        }
 
-            self.lap_global_insert(candidate)?;
-            break candidate;
-        };
+        on_progress(format!("index:ready count={}", after_count));
+        Ok(folder_id)
+    }
 
-        let next_index = if force_pointer_to_last {
-            self.append_random_history_global(image_id)?
-        } else {
-            self.prepend_random_history_global(image_id)?
+    /// Replaces a root's images with a fresh scan. The paths are staged in a
+    /// temp table in chunks, and the old rows are only swapped out, with the
+    /// folder nodes and active images rebuilt, in one final transaction; a
+    /// cancelled or failed rescan leaves the previous index and its history
+    /// untouched.
+    fn rescan_folder<F>(
+        &self,
+        folder_id: i64,
+        paths: &[String],
+        job: &IndexingJob,
+        on_progress: &mut F,
+    ) -> Result<(), LoaderError>
+    where
+        F: FnMut(String),
+    {
+        self.db.conn().execute_batch(
+            "CREATE TEMP TABLE IF NOT EXISTS rescan_paths (path TEXT PRIMARY KEY);
+             DELETE FROM temp.rescan_paths;",
+        )?;
+        let clear_staged = || {
+            self.db
+                .conn()
+                .execute("DELETE FROM temp.rescan_paths", [])
+                .map(|_| ())
         };
 
-        self.set_state_random_index(next_index)?;
-
-        match self.load_by_image_id(image_id).await {
-            Ok(data) => {
-                if skipped_count > 0 {
-                    return Err(format!(
-                        "skipped {} deleted image(s) - reindex please",
-                        skipped_count
-                    )
-                    .into());
-                }
-                Ok((data, auto_switched))
+        let total = paths.len();
+        let mut done = 0;
+        for chunk in paths.chunks(INDEX_INSERT_CHUNK_SIZE) {
+            if job.is_cancelled() {
+                clear_staged()?;
+                on_progress("index:cancelled".to_string());
+                return Err(LoaderError::Cancelled);
             }
-            Err(e) => {
-                // If we still failed to load, delete and report
-                let error_str = e.to_string();
-                if error_str.contains("not found") || error_str.contains("reindex") {
-                    self.delete_image_by_id(image_id)?;
-                    if skipped_count > 0 {
-                        return Err(format!(
-                            "skipped {} deleted image(s) - reindex please",
-                            skipped_count + 1
-                        )
-                        .into());
+
+            let mut conn = self.db.conn();
+            let tx = conn.transaction()?;
+            {
+                let mut stmt =
+                    tx.prepare("INSERT OR IGNORE INTO temp.rescan_paths (path) VALUES (?1)")?;
+                for path in chunk {
+                    stmt.execute(params![path])?;
+                    done += 1;
+                    if done <= 10 || done == total || done % 100 == 0 {
+                        on_progress(format!("index:{}/{} {}", done, total, path));
+                        job.set_inserted(done as u64);
+                        job.report(IndexingPhase::Insert, Some(path));
                     }
-                    return Err("deleted image found - reindex please".into());
                 }
-                Err(e)
             }
+            tx.commit()?;
         }
-    }
-
-    pub async fn get_next_random_image(
-        &self,
-    ) -> Result<(Vec<u8>, bool), Box<dyn std::error::Error>> {
-        let (_folder_id, auto_switched) = self.ensure_images_indexed().await?;
-        if self.random_history_global_count()? == 0 {
-            return self.get_force_random_image(true).await;
-        }
-
-        let visible = self.get_visible_random_entries_global()?;
-        if visible.is_empty() {
-            return self.get_force_random_image(true).await;
+        if job.is_cancelled() {
+            clear_staged()?;
+            on_progress("index:cancelled".to_string());
+            return Err(LoaderError::Cancelled);
         }
 
-        let current_order_index = self.get_state_random_index()?;
-        if let Some((order_index, image_id, _)) = visible
-            .iter()
-            .find(|(order_index, _, _)| *order_index > current_order_index)
-            .cloned()
-        {
-            self.set_state_random_index(order_index)?;
-            let data = self.load_by_image_id(image_id).await?;
-            return Ok((data, auto_switched));
+        let swap = || -> Result<(), LoaderError> {
+            let mut conn = self.db.conn();
+            let tx = conn.transaction()?;
+            Self::delete_images_by_folder_id_in(&tx, folder_id)?;
+            tx.execute(
+                "UPDATE folders SET current_index = -1, current_random_index = -1 WHERE id = ?1",
+                params![folder_id],
+            )?;
+            tx.execute(
+                "INSERT OR IGNORE INTO images (path, folder_id)
+                 SELECT path, ?1 FROM temp.rescan_paths",
+                params![folder_id],
+            )?;
+            tx.execute("DELETE FROM temp.rescan_paths", [])?;
+            Self::mark_folder_indexed_in(&tx, folder_id)?;
+            Self::rebuild_folder_nodes_for_root_in(&tx, folder_id)?;
+            Self::rebuild_active_images_in(&tx)?;
+            tx.commit()?;
+            Ok(())
+        };
+        if let Err(err) = swap() {
+            clear_staged()?;
+            return Err(err);
         }
+        on_progress("index:done".to_string());
+        Ok(())
+    }
 
-        self.get_force_random_image(true).await
+    /// Returns (folder_id, auto_switched)
+    pub fn ensure_images_indexed(&self) -> Result<(i64, bool), LoaderError> {
+        self.ensure_images_indexed_with_progress(&IndexingJob::detached(), |_| {}, false)
     }
 
-    pub async fn get_prev_random_image(
+    /// Returns (folder_id, folder_path, auto_switched)
+    pub fn set_current_folder_and_index(
         &self,
-    ) -> Result<(Vec<u8>, bool), Box<dyn std::error::Error>> {
-        let (_folder_id, auto_switched) = self.ensure_images_indexed().await?;
-        if self.random_history_global_count()? == 0 {
-            return self.get_force_random_image(false).await;
-        }
+        path: &str,
+    ) -> Result<(i64, String, bool), LoaderError> {
+        self.set_current_folder_and_index_with_progress(path, &IndexingJob::detached(), |_| {})
+    }
 
-        let visible = self.get_visible_random_entries_global()?;
-        if visible.is_empty() {
-            return self.get_force_random_image(false).await;
+    /// Like `set_current_folder_and_index`, but reports progress and can be
+    /// cancelled. A cancelled pick of a new folder removes it again and restores
+    /// the previously current folder.
+    ///
+    /// Roots never nest: picking a subfolder of an existing root switches to
+    /// that root and checks the subfolder, and picking a parent of existing
+    /// roots absorbs them once the parent is indexed. The returned folder is
+    /// the one indexing settled on, which differs from the pick when it was
+    /// offline and another folder was switched to.
+    pub fn set_current_folder_and_index_with_progress<F>(
+        &self,
+        path: &str,
+        job: &IndexingJob,
+        on_progress: F,
+    ) -> Result<(i64, String, bool), LoaderError>
+    where
+        F: FnMut(String),
+    {
+        let previous_folder_id = self.get_current_folder_id()?;
+        let canonical = Self::canonicalize_folder_path(path)?;
+        let enclosing_root = match Path::new(&canonical).parent() {
+            Some(parent) => self.find_owning_root(parent)?,
+            None => None,
+        };
+        let settled_path = |folder_id: i64| -> Result<String, LoaderError> {
+            Ok(self.db.conn().query_row(
+                "SELECT path FROM folders WHERE id = ?1",
+                params![folder_id],
+                |row| row.get(0),
+            )?)
+        };
+        if let Some((root_id, _)) = enclosing_root {
+            self.set_current_folder_id(Some(root_id))?;
+            let (folder_id, auto_switched) =
+                match self.ensure_images_indexed_with_progress(job, on_progress, false) {
+                    Ok(settled) => settled,
+                    Err(err) => {
+                        if matches!(err, LoaderError::Cancelled) {
+                            self.set_current_folder_id(previous_folder_id)?;
+                        }
+                        return Err(err);
+                    }
+                };
+            self.absorb_nested_roots(folder_id)?;
+            // A subfolder without images has no node to check, and one under
+            // a root that was switched away from is not in the settled tree.
+            if !auto_switched {
+                match self.set_folder_checked(&canonical, true) {
+                    Ok(()) | Err(LoaderError::NotFound(_)) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            return Ok((folder_id, settled_path(folder_id)?, auto_switched));
         }
 
-        let current_order_index = self.get_state_random_index()?;
-        if let Some((order_index, image_id, _)) = visible
-            .iter()
-            .rev()
-            .find(|(order_index, _, _)| *order_index < current_order_index)
-            .cloned()
+        let (id, _) = self.set_current_folder_by_path(&canonical)?;
+        match self
+            .ensure_images_indexed_with_progress(job, on_progress, false)
         {
-            self.set_state_random_index(order_index)?;
-            let data = self.load_by_image_id(image_id).await?;
-            return Ok((data, auto_switched));
+            Ok((folder_id, auto_switched)) => {
+                self.absorb_nested_roots(folder_id)?;
+                Ok((folder_id, settled_path(folder_id)?, auto_switched))
+            }
+            Err(LoaderError::Cancelled) => {
+                if self.count_images(id)? == 0 {
+                    self.delete_folder_by_id(id)?;
+                    if let Some(previous) = previous_folder_id.filter(|prev| *prev != id) {
+                        self.set_current_folder_id(Some(previous))?;
+                    }
+                }
+                Err(LoaderError::Cancelled)
+            }
+            Err(err) => Err(err),
         }
-
-        self.get_force_random_image(false).await
     }
 
-    fn delete_image_from_random_history(
-        &self,
-        folder_id: i64,
-        image_id: i64,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        self.db.conn().execute(
-            "DELETE FROM random_history WHERE folder_id = ?1 AND image_id = ?2",
-            params![folder_id, image_id],
-        )?;
-        Ok(())
+    /// Replaces the listener told about every image shown, in either mode
+    /// and through any caller.
+    pub fn set_image_listener(&self, listener: Option<ImageListener>) {
+        if let Ok(mut slot) = self.image_listener.write() {
+            *slot = listener;
+        }
     }
 
-    pub fn get_normal_history(
+    /// The image shown last, in either mode.
+    pub fn get_current_image_info(
         &self,
-    ) -> Result<(Vec<crate::commands::ImageHistoryItem>, i64), Box<dyn std::error::Error>> {
-        self.bootstrap_checked_scope()?;
-        let visible = self.get_visible_checked_normal_entries()?;
-        let pointer_raw = self.get_state_normal_index()?;
-        let pointer = visible
-            .iter()
-            .position(|(order_index, _, _, _)| *order_index == pointer_raw)
-            .map(|idx| idx as i64)
-            .unwrap_or(-1);
-        let items = visible
-            .into_iter()
-            .map(
-                |(order_index, image_id, path, _)| crate::commands::ImageHistoryItem {
-                    image_id,
-                    order_index,
-                    path,
-                },
-            )
-            .collect::<Vec<_>>();
-        Ok((items, pointer))
+    ) -> Result<Option<crate::types::ImageInfo>, LoaderError> {
+        let Some(image_id) = self.get_last_image_id()? else {
+            return Ok(None);
+        };
+        match self.get_image_path(image_id) {
+            Ok(path) => Ok(Some(crate::types::ImageInfo { image_id, path })),
+            Err(LoaderError::Db(rusqlite::Error::QueryReturnedNoRows)) => Ok(None),
+            Err(err) => Err(err),
+        }
     }
 
-    pub fn get_random_history(
+    pub fn load_by_image_id(
         &self,
-    ) -> Result<(Vec<crate::commands::ImageHistoryItem>, i64), Box<dyn std::error::Error>> {
-        let visible = self.get_visible_random_entries_global()?;
-        let pointer_raw = self.get_state_random_index()?;
-        let pointer = visible
-            .iter()
-            .position(|(order_index, _, _)| *order_index == pointer_raw)
-            .map(|idx| idx as i64)
-            .unwrap_or(-1);
-        let items = visible
-            .into_iter()
-            .map(
-                |(order_index, image_id, path)| crate::commands::ImageHistoryItem {
-                    image_id,
-                    order_index,
-                    path,
-                },
-            )
-            .collect::<Vec<_>>();
-        Ok((items, pointer))
-    }
+        image_id: i64,
+    ) -> Result<Vec<u8>, LoaderError> {
+        let path = self.get_image_path(image_id)?;
+        self.set_last_image_id(Some(image_id))?;
 
-    pub fn reset_normal_history(&self) -> Result<(), Box<dyn std::error::Error>> {
-        self.set_state_normal_index(-1)?;
-        Ok(())
+        match std::fs::read(&path) {
+            Ok(data) => {
+                self.record_image_shown(&path)?;
+                if let Ok(listener) = self.image_listener.read() {
+                    if let Some(listener) = listener.as_ref() {
+                        listener(image_id, &path);
+                    }
+                }
+                Ok(data)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // Image file no longer exists - remove it unless its whole root went offline
+                match self.forget_missing_image(image_id)? {
+                    Some(root) => Err(LoaderError::FolderOffline { path: root }),
+                    None => Err(LoaderError::ImageMissing { path }),
+                }
+            }
+            Err(e) => Err(LoaderError::ImageUnreadable {
+                path,
+                reason: e.to_string(),
+            }),
+        }
     }
 
-    pub fn reset_random_history(&self) -> Result<(), Box<dyn std::error::Error>> {
-        self.clear_random_history_global()?;
-        self.lap_global_clear()?;
-        Ok(())
-    }
+    fn delete_image_by_id(&self, image_id: i64) -> Result<(), LoaderError> {
+        let mut conn = self.db.conn();
+        let tx = conn.transaction()?;
 
-    fn clear_hidden_images_for_folder(
-        &self,
-        folder_id: i64,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        self.db.conn().execute(
-            "DELETE FROM hidden_normal_images WHERE folder_id = ?1",
-            params![folder_id],
+        tx.execute(
+            "DELETE FROM hidden_normal_images WHERE image_id = ?1",
+            params![image_id],
         )?;
-        self.db.conn().execute(
-            "DELETE FROM hidden_random_images WHERE folder_id = ?1",
-            params![folder_id],
+        tx.execute(
+            "DELETE FROM hidden_random_images WHERE image_id = ?1",
+            params![image_id],
         )?;
-        Ok(())
-    }
 
-    pub fn hide_normal_history_image(
-        &self,
-        image_id: i64,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let folder_id = self
-            .get_image_folder_id(image_id)?
-            .ok_or("no folder selected - pick a folder first")?;
-
-        self.db.conn().execute(
-            "INSERT OR IGNORE INTO hidden_normal_images (folder_id, image_id) VALUES (?1, ?2)",
-            params![folder_id, image_id],
+        // Delete references in random_history first (foreign key constraint)
+        tx.execute(
+            "DELETE FROM random_history WHERE image_id = ?1",
+            params![image_id],
+        )?;
+        tx.execute(
+            "DELETE FROM random_history_global WHERE image_id = ?1",
+            params![image_id],
+        )?;
+        tx.execute(
+            "DELETE FROM current_lap_global WHERE image_id = ?1",
+            params![image_id],
+        )?;
+        tx.execute("DELETE FROM active_images WHERE image_id = ?1", params![image_id])?;
+        tx.execute(
+            "DELETE FROM active_image_refcounts WHERE image_id = ?1",
+            params![image_id],
         )?;
+        tx.execute(
+            "DELETE FROM folder_images_direct WHERE image_id = ?1",
+            params![image_id],
+        )?;
+
+        // Then delete the image
+        tx.execute("DELETE FROM images WHERE id = ?1", params![image_id])?;
+
+        tx.commit()?;
+        Ok(())
+    }
 
+    /// Returns (image_data, auto_switched_folder)
+    pub fn get_next_image(&self) -> Result<(Vec<u8>, bool), LoaderError> {
+        let (_folder_id, auto_switched) = self.ensure_images_indexed()?;
         self.bootstrap_checked_scope()?;
+        self.require_checked_folders()?;
         let visible = self.get_visible_checked_normal_entries()?;
         if visible.is_empty() {
-            return Err("all images for this folder are hidden in normal mode - reindex to clear hidden images".into());
+            return Err(LoaderError::AllHidden {
+                mode: ImageMode::Normal,
+            });
         }
 
         let current_raw_index = self.get_state_normal_index()?;
-        let current_still_visible = visible
+        let next_pos = match visible
             .iter()
-            .any(|(order_index, _, _, _)| *order_index == current_raw_index);
-        if current_raw_index >= 0 && !current_still_visible {
-            let next_order_index = visible
-                .iter()
-                .rev()
-                .find(|(order_index, _, _, _)| *order_index < current_raw_index)
-                .map(|(order_index, _, _, _)| *order_index)
-                .unwrap_or_else(|| visible[visible.len() - 1].0);
-            self.set_state_normal_index(next_order_index)?;
+            .position(|(order_index, _, _, _)| *order_index == current_raw_index)
+        {
+            Some(pos) => (pos + 1) % visible.len(),
+            None => 0,
+        };
+
+        let (order_index, image_id, _, selected_folder_id) = visible[next_pos].clone();
+        self.set_state_normal_index(order_index)?;
+        if selected_folder_id > 0 {
+            self.set_current_folder_id(Some(selected_folder_id))?;
         }
+        let data = self.load_by_image_id(image_id)?;
+        Ok((data, auto_switched))
+    }
 
-        Ok(())
+    /// Returns (image_data, auto_switched_folder)
+    pub fn get_prev_image(&self) -> Result<(Vec<u8>, bool), LoaderError> {
+        let (_folder_id, auto_switched) = self.ensure_images_indexed()?;
+        self.bootstrap_checked_scope()?;
+        self.require_checked_folders()?;
+        let visible = self.get_visible_checked_normal_entries()?;
+        if visible.is_empty() {
+            return Err(LoaderError::AllHidden {
+                mode: ImageMode::Normal,
+            });
+        }
+
+        let current_raw_index = self.get_state_normal_index()?;
+        let prev_pos = match visible
+            .iter()
+            .position(|(order_index, _, _, _)| *order_index == current_raw_index)
+        {
+            Some(0) => visible.len() - 1,
+            Some(pos) => pos - 1,
+            None => visible.len() - 1,
+        };
+
+        let (order_index, image_id, _, selected_folder_id) = visible[prev_pos].clone();
+        self.set_state_normal_index(order_index)?;
+        if selected_folder_id > 0 {
+            self.set_current_folder_id(Some(selected_folder_id))?;
+        }
+        let data = self.load_by_image_id(image_id)?;
+        Ok((data, auto_switched))
     }
 
-    pub fn hide_random_history_image(
+    pub fn get_current_image_or_first(
         &self,
-        image_id: i64,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let (folder_id, _) = self
-            .get_current_folder_id_and_path()?
-            .ok_or("no folder selected - pick a folder first")?;
+    ) -> Result<(Vec<u8>, bool), LoaderError> {
+        let (_folder_id, auto_switched) = self.ensure_images_indexed()?;
+        self.bootstrap_checked_scope()?;
+        self.require_checked_folders()?;
+        let visible = self.get_visible_checked_normal_entries()?;
+        if visible.is_empty() {
+            return Err(LoaderError::AllHidden {
+                mode: ImageMode::Normal,
+            });
+        }
 
-        self.db.conn().execute(
-            "INSERT OR IGNORE INTO hidden_random_images (folder_id, image_id) VALUES (?1, ?2)",
-            params![folder_id, image_id],
-        )?;
+        let current_raw_index = self.get_state_normal_index()?;
+        let selected = visible
+            .iter()
+            .find(|(order_index, _, _, _)| *order_index == current_raw_index)
+            .cloned()
+            .unwrap_or_else(|| visible[0].clone());
 
-        let visible_history = self.get_visible_random_entries_global()?;
-        let has_available_checked_scope = !self.get_checked_folder_available_counts()?.is_empty();
-        if visible_history.is_empty() && !has_available_checked_scope {
-            return Err("all images for this folder are hidden in random mode - reindex to clear hidden images".into());
+        self.set_state_normal_index(selected.0)?;
+        if selected.3 > 0 {
+            self.set_current_folder_id(Some(selected.3))?;
+        }
+        let data = self.load_by_image_id(selected.1)?;
+        Ok((data, auto_switched))
+    }
+
+    pub fn get_current_random_image_or_last(
+        &self,
+    ) -> Result<(Vec<u8>, bool), LoaderError> {
+        let (_folder_id, auto_switched) = self.ensure_images_indexed()?;
+        if self.random_history_global_count()? == 0 {
+            return self.get_force_random_image(true);
+        }
+
+        let visible = self.get_visible_random_entries_global()?;
+        if visible.is_empty() {
+            return self.get_force_random_image(true);
         }
 
         let current_order_index = self.get_state_random_index()?;
-        let current_still_visible = visible_history
+        let selected = visible
             .iter()
-            .any(|(order_index, _, _)| *order_index == current_order_index);
-        if current_order_index >= 0 && !current_still_visible {
-            let next_order_index = visible_history
-                .iter()
-                .rev()
-                .find(|(order_index, _, _)| *order_index < current_order_index)
-                .map(|(order_index, _, _)| *order_index)
-                .unwrap_or_else(|| visible_history[visible_history.len() - 1].0);
-            self.set_state_random_index(next_order_index)?;
-        }
+            .find(|(order_index, _, _)| *order_index == current_order_index)
+            .cloned()
+            .unwrap_or_else(|| visible[visible.len() - 1].clone());
 
-        Ok(())
+        self.set_state_random_index(selected.0)?;
+        let data = self.load_by_image_id(selected.1)?;
+        Ok((data, auto_switched))
     }
 
-    pub fn get_folder_history(
+    fn is_spaced_random_mode(&self) -> Result<bool, LoaderError> {
+        let mode: String = self.db.conn().query_row(
+            "SELECT random_selection_mode FROM state WHERE id = 1",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(mode == "spaced")
+    }
+
+    fn get_spaced_randomness(&self) -> Result<f64, LoaderError> {
+        let randomness: f64 = self.db.conn().query_row(
+            "SELECT spaced_randomness FROM state WHERE id = 1",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(randomness.clamp(0.0, 1.0))
+    }
+
+    /// Picks from the least-recently-shown slice of the visible random
+    /// candidates in the checked scope, leaving out `excluded`. `randomness` is
+    /// the fraction of the library eligible for the draw: 0 always takes the
+    /// oldest image (ties broken by `draw`), 1 is a uniform pick over
+    /// everything. Only the picked row leaves SQLite.
+    fn pick_spaced_candidate(
         &self,
-    ) -> Result<Vec<(i64, String, String, i64)>, Box<dyn std::error::Error>> {
+        excluded: &[i64],
+        randomness: f64,
+        draw: u64,
+    ) -> Result<Option<i64>, LoaderError> {
+        let exclusion = if excluded.is_empty() {
+            String::new()
+        } else {
+            let ids: Vec<String> = excluded.iter().map(|id| id.to_string()).collect();
+            format!(" AND fi.image_id NOT IN ({})", ids.join(","))
+        };
+        let candidates = format!(
+            "WITH candidates AS (
+                 SELECT fi.image_id AS image_id, MAX(v.last_shown_at) AS last_shown_at
+                 FROM checked_folders cf
+                 JOIN folder_nodes n ON n.path = cf.path
+                 JOIN folders f ON f.id = n.root_folder_id AND f.offline = 0
+                 JOIN folder_closure c ON c.ancestor_path = cf.path
+                 JOIN folder_images_direct fi ON fi.folder_path = c.descendant_path
+                 JOIN images i ON i.id = fi.image_id
+                 LEFT JOIN hidden_random_images h ON h.image_id = fi.image_id
+                 LEFT JOIN image_views v ON v.path = i.path
+                 WHERE h.image_id IS NULL{}
+                 GROUP BY fi.image_id
+             )",
+            exclusion
+        );
+        // Never-shown first, then oldest-shown first.
+        let order = "ORDER BY last_shown_at IS NOT NULL, last_shown_at, image_id";
+
         self.db
             .with_conn(|conn| {
-                let history: Vec<(i64, String, String, i64)> = conn
-                    .prepare(
-                        "SELECT f.id, f.path, f.added_at, COUNT(i.id) AS image_count FROM folders f LEFT JOIN images i ON i.folder_id = f.id GROUP BY f.id ORDER BY f.added_at DESC",
-                    )?
-                    .query_map([], |row| {
-                        let id: i64 = row.get(0)?;
-                        let path: String = row.get(1)?;
-                        let added_at: String = row.get(2)?;
-                        let image_count: i64 = row.get(3)?;
-                        Ok((id, path, added_at, image_count))
-                    })?
-                    .collect::<Result<Vec<_>, _>>()?;
+                let total: i64 = conn.query_row(
+                    &format!("{} SELECT COUNT(*) FROM candidates", candidates),
+                    [],
+                    |row| row.get(0),
+                )?;
+                if total == 0 {
+                    return Ok(None);
+                }
 
-                Ok::<Vec<(i64, String, String, i64)>, rusqlite::Error>(history)
+                let wanted = ((total as f64) * randomness.clamp(0.0, 1.0)).ceil() as i64;
+                let wanted = wanted.clamp(1, total);
+                // Images shown at the same moment as the last one in the slice
+                // (or never shown, like it) are just as eligible.
+                let boundary: Option<i64> = conn.query_row(
+                    &format!(
+                        "{} SELECT last_shown_at FROM candidates {} LIMIT 1 OFFSET ?1",
+                        candidates, order
+                    ),
+                    params![wanted - 1],
+                    |row| row.get(0),
+                )?;
+                let pool_size: i64 = conn.query_row(
+                    &format!(
+                        "{} SELECT COUNT(*) FROM candidates
+                         WHERE last_shown_at IS NULL OR last_shown_at <= ?1",
+                        candidates
+                    ),
+                    params![boundary],
+                    |row| row.get(0),
+                )?;
+
+                let pick_offset = (draw % pool_size.max(1) as u64) as i64;
+                conn.query_row(
+                    &format!(
+                        "{} SELECT image_id FROM candidates {} LIMIT 1 OFFSET ?1",
+                        candidates, order
+                    ),
+                    params![pick_offset],
+                    |row| row.get(0),
+                )
+                .optional()
             })
             .map_err(|e| e.into())
     }
 
-    pub fn get_next_folder(&self) -> Result<Option<(i64, String)>, Box<dyn std::error::Error>> {
-        let history = self.get_folder_history()?;
-        if history.is_empty() {
-            return Ok(None);
-        }
-
-        let current_id = self.db.conn().query_row(
-            "SELECT current_folder_id FROM state WHERE id = 1",
-            [],
-            |row| {
-                let id: Option<i64> = row.get(0)?;
-                Ok(id)
-            },
-        )?;
+    fn pick_spaced_random_image_id(
+        &self,
+        skipped_count: &mut usize,
+    ) -> Result<i64, LoaderError> {
+        let randomness = self.get_spaced_randomness()?;
+        // Images whose file is gone but could not be forgotten, e.g. because
+        // their root went offline.
+        let mut excluded = Vec::new();
+
+        loop {
+            let Some(candidate) =
+                self.pick_spaced_candidate(&excluded, randomness, rand::random::<u64>())?
+            else {
+                if *skipped_count > 0 {
+                    return Err(LoaderError::ReindexRequired(format!(
+                        "skipped {} deleted image(s), no valid images found",
+                        *skipped_count
+                    )));
+                }
+                return Err(LoaderError::AllHidden {
+                    mode: ImageMode::Random,
+                });
+            };
 
-        let start_idx = match current_id {
-            Some(id) => {
-                let idx = history.iter().position(|(fid, _, _, _)| *fid == id);
-                match idx {
-                    Some(i) if i <= 0 => history.len() - 1,
-                    Some(i) => i - 1,
-                    None => history.len() - 1,
+            let path = self.get_image_path(candidate)?;
+            if !std::path::Path::new(&path).exists() {
+                if self.forget_missing_image(candidate)?.is_none() {
+                    *skipped_count += 1;
+                } else {
+                    excluded.push(candidate);
                 }
+                continue;
             }
-            None => history.len() - 1,
-        };
-
-        // Try folders starting from start_idx, skip deleted ones
-        for i in 0..history.len() {
-            let try_idx = (start_idx + i) % history.len();
-            let (folder_id, folder_path, _, _) = &history[try_idx];
 
-            if std::path::Path::new(folder_path).exists() {
-                self.set_current_folder_id(Some(*folder_id))?;
-                return Ok(Some((*folder_id, folder_path.clone())));
-            } else {
-                // Folder no longer exists - delete it from history
-                self.delete_folder_by_id(*folder_id)?;
-            }
+            return Ok(candidate);
         }
-
-        Err("all folders in history no longer exist - reindex please".into())
     }
 
-    pub fn get_prev_folder(&self) -> Result<Option<(i64, String)>, Box<dyn std::error::Error>> {
-        let history = self.get_folder_history()?;
-        if history.is_empty() {
-            return Ok(None);
-        }
+    fn record_image_shown(&self, path: &str) -> Result<(), LoaderError> {
+        // Keep timestamps strictly increasing so ordering stays stable even when the
+        // clock goes backwards or two images are shown within the same millisecond.
+        let now = chrono::Utc::now().timestamp_millis();
+        self.db.conn().execute(
+            "INSERT INTO image_views (path, last_shown_at, show_count)
+             VALUES (?1, MAX(?2, COALESCE((SELECT MAX(last_shown_at) FROM image_views), 0) + 1), 1)
+             ON CONFLICT(path) DO UPDATE
+             SET last_shown_at = excluded.last_shown_at,
+                 show_count = show_count + 1",
+            params![path, now],
+        )?;
+        Ok(())
+    }
 
-        let current_id = self.db.conn().query_row(
-            "SELECT current_folder_id FROM state WHERE id = 1",
+    pub fn get_random_selection_settings(
+        &self,
+    ) -> Result<crate::types::RandomSelectionSettings, LoaderError> {
+        let (mode, spaced_randomness) = self.db.conn().query_row(
+            "SELECT random_selection_mode, spaced_randomness FROM state WHERE id = 1",
             [],
             |row| {
-                let id: Option<i64> = row.get(0)?;
-                Ok(id)
+                let mode: String = row.get(0)?;
+                let spaced_randomness: f64 = row.get(1)?;
+                Ok((mode, spaced_randomness))
             },
         )?;
 
-        let start_idx = match current_id {
-            Some(id) => {
-                let idx = history.iter().position(|(fid, _, _, _)| *fid == id);
-                match idx {
-                    Some(i) if i >= history.len() - 1 => 0usize,
-                    Some(i) => i + 1,
-                    None => 0,
-                }
-            }
-            None => 0,
-        };
-
-        // Try folders starting from start_idx, skip deleted ones
-        for i in 0..history.len() {
-            let try_idx = (start_idx + i) % history.len();
-            let (folder_id, folder_path, _, _) = &history[try_idx];
-
-            if std::path::Path::new(folder_path).exists() {
-                self.set_current_folder_id(Some(*folder_id))?;
-                return Ok(Some((*folder_id, folder_path.clone())));
+        Ok(crate::types::RandomSelectionSettings {
+            mode: if mode == "spaced" {
+                "spaced".to_string()
             } else {
-                // Folder no longer exists - delete it from history
-                self.delete_folder_by_id(*folder_id)?;
-            }
-        }
+                "lap".to_string()
+            },
+            spaced_randomness: spaced_randomness.clamp(0.0, 1.0),
+        })
+    }
 
-        Err("all folders in history no longer exist - reindex please".into())
+    pub fn set_random_selection_settings(
+        &self,
+        settings: &crate::types::RandomSelectionSettings,
+    ) -> Result<(), LoaderError> {
+        self.db.conn().execute(
+            "UPDATE state SET random_selection_mode = ?1, spaced_randomness = ?2 WHERE id = 1",
+            params![&settings.mode, settings.spaced_randomness.clamp(0.0, 1.0)],
+        )?;
+        Ok(())
     }
 
-    pub async fn reindex_current_folder(
+    pub fn get_indexing_settings(
         &self,
-    ) -> Result<(i64, String), Box<dyn std::error::Error>> {
-        let (folder_id, folder_path) = match self.get_current_folder_id_and_path()? {
-            Some((id, path)) => (id, path),
-            None => return Err("no folder selected - pick a folder first".into()),
-        };
-
-        // Validate folder still exists on disk before reindexing
-        if !std::path::Path::new(&folder_path).exists() {
-            // Delete the stale folder entry and its related data
-            self.delete_folder_by_id(folder_id)?;
-            return Err(format!(
-                "folder no longer exists: {} - deleted from history",
-                folder_path
-            )
-            .into());
-        }
-
-        self.delete_images_by_folder_id(folder_id)?;
-        self.clear_hidden_images_for_folder(folder_id)?;
-        self.set_current_folder_index(folder_id, -1)?;
-        self.set_current_folder_random_index(folder_id, -1)?;
-        self.ensure_images_indexed_with_progress(|_| {}, true)
-            .await?;
-        Ok((folder_id, folder_path))
+    ) -> Result<crate::types::IndexingSettings, LoaderError> {
+        let (ignore_patterns, skip_hidden_dirs) = self.load_ignore_settings()?;
+        Ok(crate::types::IndexingSettings {
+            ignore_patterns,
+            skip_hidden_dirs,
+        })
     }
 
-    pub async fn reindex_current_folder_with_progress<F>(
+    /// Replaces the global ignore list. Takes effect on the next scan; folders
+    /// that are already indexed keep their images until they are reindexed.
+    pub fn set_indexing_settings(
         &self,
-        on_progress: F,
-    ) -> Result<(i64, String), Box<dyn std::error::Error>>
-    where
-        F: FnMut(String),
-    {
-        let (folder_id, folder_path) = match self.get_current_folder_id_and_path()? {
-            Some((id, path)) => (id, path),
-            None => return Err("no folder selected - pick a folder first".into()),
-        };
-
-        // Validate folder still exists on disk before reindexing
-        if !std::path::Path::new(&folder_path).exists() {
-            // Delete the stale folder entry and its related data
-            self.delete_folder_by_id(folder_id)?;
-            return Err(format!(
-                "folder no longer exists: {} - deleted from history",
-                folder_path
-            )
-            .into());
-        }
-
-        self.delete_images_by_folder_id(folder_id)?;
-        self.clear_hidden_images_for_folder(folder_id)?;
-        self.set_current_folder_index(folder_id, -1)?;
-        self.set_current_folder_random_index(folder_id, -1)?;
-        self.ensure_images_indexed_with_progress(on_progress, true)
-            .await?;
-        Ok((folder_id, folder_path))
-    }
+        settings: &crate::types::IndexingSettings,
+    ) -> Result<(), LoaderError> {
+        let patterns: Vec<String> = settings
+            .ignore_patterns
+            .iter()
+            .filter(|p| !p.trim().is_empty())
+            .cloned()
+            .collect();
+        validate_ignore_patterns(&patterns)?;
 
-    pub fn full_wipe(&self) -> Result<(), Box<dyn std::error::Error>> {
         let mut conn = self.db.conn();
         let tx = conn.transaction()?;
-
-        tx.execute("DELETE FROM random_history", [])?;
-        tx.execute("DELETE FROM random_history_global", [])?;
-        tx.execute("DELETE FROM current_lap", [])?;
-        tx.execute("DELETE FROM current_lap_global", [])?;
-        tx.execute("DELETE FROM active_images", [])?;
-        tx.execute("DELETE FROM active_image_refcounts", [])?;
-        tx.execute("DELETE FROM hidden_normal_images", [])?;
-        tx.execute("DELETE FROM hidden_random_images", [])?;
-        tx.execute("DELETE FROM folder_images_direct", [])?;
-        tx.execute("DELETE FROM folder_closure", [])?;
-        tx.execute("DELETE FROM images", [])?;
-        tx.execute("DELETE FROM checked_folders", [])?;
-        tx.execute("DELETE FROM folder_nodes", [])?;
-        tx.execute("DELETE FROM folders", [])?;
+        tx.execute("DELETE FROM ignore_patterns", [])?;
+        {
+            let mut stmt =
+                tx.prepare("INSERT INTO ignore_patterns (position, pattern) VALUES (?1, ?2)")?;
+            for (position, pattern) in patterns.iter().enumerate() {
+                stmt.execute(params![position as i64, pattern])?;
+            }
+        }
         tx.execute(
-            "UPDATE state
-             SET current_folder_id = NULL,
-                 current_index = -1,
-                 current_random_index = -1,
-                 last_image_id = NULL
-             WHERE id = 1",
-            [],
+            "UPDATE state SET skip_hidden_dirs = ?1 WHERE id = 1",
+            params![settings.skip_hidden_dirs as i64],
         )?;
-
         tx.commit()?;
         Ok(())
     }
 
-    pub fn get_image_state(
-        &self,
-    ) -> Result<crate::commands::ImageState, Box<dyn std::error::Error>> {
-        let row = self.db.conn().query_row(
-            "SELECT vertical_mirror, horizontal_mirror, greyscale, timer_flow_mode, show_folder_history_panel, show_top_controls, show_image_history_panel, show_bottom_controls, is_fullscreen_image, shortcut_hints_visible, shortcut_hint_side FROM state WHERE id = 1",
-            [],
-            |row| {
-                let vertical_mirror: i64 = row.get(0)?;
-                let horizontal_mirror: i64 = row.get(1)?;
-                let greyscale: i64 = row.get(2)?;
-                let timer_flow_mode: String = row.get(3)?;
-                let show_folder_history_panel: i64 = row.get(4)?;
-                let show_top_controls: i64 = row.get(5)?;
-                let show_image_history_panel: i64 = row.get(6)?;
-                let show_bottom_controls: i64 = row.get(7)?;
-                let is_fullscreen_image: i64 = row.get(8)?;
-                let shortcut_hints_visible: i64 = row.get(9)?;
-                let shortcut_hint_side: String = row.get(10)?;
-                Ok((vertical_mirror, horizontal_mirror, greyscale, timer_flow_mode, show_folder_history_panel, show_top_controls, show_image_history_panel, show_bottom_controls, is_fullscreen_image, shortcut_hints_visible, shortcut_hint_side))
-            },
-        )?;
-
-        Ok(crate::commands::ImageState {
-            vertical_mirror: row.0 != 0,
-            horizontal_mirror: row.1 != 0,
-            greyscale: row.2 != 0,
-            timer_flow_mode: if row.3 == "normal" {
-                "normal".to_string()
-            } else {
-                "random".to_string()
-            },
-            show_folder_history_panel: row.4 != 0,
-            show_top_controls: row.5 != 0,
-            show_image_history_panel: row.6 != 0,
-            show_bottom_controls: row.7 != 0,
-            is_fullscreen_image: row.8 != 0,
-            shortcut_hints_visible: row.9 != 0,
-            shortcut_hint_side: if row.10 == "right" {
-                "right".to_string()
-            } else {
-                "left".to_string()
-            },
-        })
+    fn load_ignore_settings(&self) -> Result<(Vec<String>, bool), LoaderError> {
+        let conn = self.db.conn();
+        let patterns = conn
+            .prepare("SELECT pattern FROM ignore_patterns ORDER BY position")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let skip_hidden_dirs: i64 =
+            conn.query_row("SELECT skip_hidden_dirs FROM state WHERE id = 1", [], |row| {
+                row.get(0)
+            })?;
+        Ok((patterns, skip_hidden_dirs != 0))
     }
 
-    pub fn set_image_state(
+    fn pick_lap_random_image_id(
         &self,
-        state: &crate::commands::ImageState,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        self.db.conn().execute(
-            "UPDATE state SET vertical_mirror = ?1, horizontal_mirror = ?2, greyscale = ?3, timer_flow_mode = ?4, show_folder_history_panel = ?5, show_top_controls = ?6, show_image_history_panel = ?7, show_bottom_controls = ?8, is_fullscreen_image = ?9, shortcut_hints_visible = ?10, shortcut_hint_side = ?11 WHERE id = 1",
-            params![
-                state.vertical_mirror as i64,
-                state.horizontal_mirror as i64,
-                state.greyscale as i64,
-                &state.timer_flow_mode,
-                state.show_folder_history_panel as i64,
-                state.show_top_controls as i64,
-                state.show_image_history_panel as i64,
-                state.show_bottom_controls as i64,
-                state.is_fullscreen_image as i64,
-                state.shortcut_hints_visible as i64,
-                &state.shortcut_hint_side,
-            ],
-        )?;
-        Ok(())
+        skipped_count: &mut usize,
+    ) -> Result<i64, LoaderError> {
+        let mut reset_lap_once = false;
+
+        loop {
+            let folder_counts = self.get_checked_folder_available_counts()?;
+            if folder_counts.is_empty() {
+                if !reset_lap_once {
+                    self.lap_global_clear()?;
+                    reset_lap_once = true;
+                    continue;
+                }
+
+                if *skipped_count > 0 {
+                    return Err(LoaderError::ReindexRequired(format!(
+                        "skipped {} deleted image(s), no valid images found",
+                        *skipped_count
+                    )));
+                }
+
+                return Err(LoaderError::AllHidden {
+                    mode: ImageMode::Random,
+                });
+            }
+
+            let total: i64 = folder_counts.iter().map(|(_, count)| *count).sum();
+            if total <= 0 {
+                return Err(LoaderError::NoImages);
+            }
+
+            let mut draw = rand::random::<u64>() % (total as u64);
+            let mut selected_folder = folder_counts[0].0.clone();
+            for (path, count) in &folder_counts {
+                let weight = *count as u64;
+                if draw < weight {
+                    selected_folder = path.clone();
+                    break;
+                }
+                draw -= weight;
+            }
+
+            let image_ids = self.get_available_random_image_ids_for_checked_folder(&selected_folder)?;
+            if image_ids.is_empty() {
+                continue;
+            }
+            let pick_idx = (rand::random::<u64>() % (image_ids.len() as u64)) as usize;
+            let candidate = image_ids[pick_idx];
+
+            let path = match self.get_image_path(candidate) {
+                Ok(p) => p,
+                Err(_) => continue,
+            };
+
+            if !std::path::Path::new(&path).exists() {
+                if self.forget_missing_image(candidate)?.is_none() {
+                    *skipped_count += 1;
+                }
+                continue;
+            }
+
+            self.lap_global_insert(candidate)?;
+            return Ok(candidate);
+        }
     }
 
-    pub fn set_folder_by_index(
+    pub fn get_force_random_image(
         &self,
-        index: i64,
-    ) -> Result<(i64, String), Box<dyn std::error::Error>> {
-        let history = self.get_folder_history()?;
-        if history.is_empty() {
-            return Err("no folders available".into());
-        }
+        force_pointer_to_last: bool,
+    ) -> Result<(Vec<u8>, bool), LoaderError> {
+        let (_folder_id, auto_switched) = self.ensure_images_indexed()?;
+        self.bootstrap_checked_scope()?;
+        self.require_checked_folders()?;
 
-        let idx = if index < 0 {
-            0
-        } else if index >= history.len() as i64 {
-            history.len() as i64 - 1
+        let mut skipped_count = 0;
+        let image_id = if self.is_spaced_random_mode()? {
+            self.pick_spaced_random_image_id(&mut skipped_count)?
         } else {
-            index
+            self.pick_lap_random_image_id(&mut skipped_count)?
         };
 
-        let (folder_id, path, _, _) = &history[idx as usize];
+        let next_index = if force_pointer_to_last {
+            self.append_random_history_global(image_id)?
+        } else {
+            self.prepend_random_history_global(image_id)?
+        };
+
+        self.set_state_random_index(next_index)?;
 
-        // Check if folder still exists
-        if !std::path::Path::new(path).exists() {
-            // Folder no longer exists - delete it from history
-            self.delete_folder_by_id(*folder_id)?;
-            return Err(format!("folder no longer exists: {} - reindex please", path).into());
+        match self.load_by_image_id(image_id) {
+            Ok(data) => {
+                if skipped_count > 0 {
+                    return Err(LoaderError::ReindexRequired(format!(
+                        "skipped {} deleted image(s)",
+                        skipped_count
+                    )));
+                }
+                Ok((data, auto_switched))
+            }
+            Err(e) => {
+                // If we still failed to load, delete and report
+                if matches!(
+                    e,
+                    LoaderError::ImageMissing { .. } | LoaderError::ImageUnreadable { .. }
+                ) {
+                    self.delete_image_by_id(image_id)?;
+                    if skipped_count > 0 {
+                        return Err(LoaderError::ReindexRequired(format!(
+                            "skipped {} deleted image(s)",
+                            skipped_count + 1
+                        )));
+                    }
+                    return Err(LoaderError::ReindexRequired(
+                        "deleted image found".to_string(),
+                    ));
+                }
+                Err(e)
+            }
         }
+    }
 
-        self.set_current_folder_id(Some(*folder_id))?;
-        Ok((*folder_id, path.clone()))
+    /// `get_force_random_image` for callers that want the path rather than
+    /// the bytes, such as the command-line interface.
+    pub fn pick_random_image_path(&self) -> Result<String, LoaderError> {
+        self.get_force_random_image(true)?;
+        let image_id = self.get_last_image_id()?.ok_or(LoaderError::NoImages)?;
+        self.get_image_path(image_id)
     }
 
-    pub async fn set_normal_image_by_index(
+    pub fn get_next_random_image(
         &self,
-        index: i64,
-    ) -> Result<(Vec<u8>, bool), Box<dyn std::error::Error>> {
-        let (_folder_id, auto_switched) = self.ensure_images_indexed().await?;
-        self.bootstrap_checked_scope()?;
-        self.require_checked_folders()?;
-        let visible = self.get_visible_checked_normal_entries()?;
+    ) -> Result<(Vec<u8>, bool), LoaderError> {
+        let (_folder_id, auto_switched) = self.ensure_images_indexed()?;
+        if self.random_history_global_count()? == 0 {
+            return self.get_force_random_image(true);
+        }
 
+        let visible = self.get_visible_random_entries_global()?;
         if visible.is_empty() {
-            return Err("all images for this folder are hidden in normal mode - reindex to clear hidden images".into());
+            return self.get_force_random_image(true);
         }
 
-        let idx = if index < 0 {
-            0
-        } else if index >= visible.len() as i64 {
-            visible.len() as i64 - 1
-        } else {
-            index
-        };
-
-        let (order_index, image_id, _, selected_folder_id) = visible[idx as usize].clone();
-        self.set_state_normal_index(order_index)?;
-        if selected_folder_id > 0 {
-            self.set_current_folder_id(Some(selected_folder_id))?;
+        let current_order_index = self.get_state_random_index()?;
+        if let Some((order_index, image_id, _)) = visible
+            .iter()
+            .find(|(order_index, _, _)| *order_index > current_order_index)
+            .cloned()
+        {
+            self.set_state_random_index(order_index)?;
+            let data = self.load_by_image_id(image_id)?;
+            return Ok((data, auto_switched));
         }
-        let data = self.load_by_image_id(image_id).await?;
-        Ok((data, auto_switched))
+
+        self.get_force_random_image(true)
     }
 
-    pub async fn set_random_image_by_index(
+    pub fn get_prev_random_image(
         &self,
-        index: i64,
-    ) -> Result<(Vec<u8>, bool), Box<dyn std::error::Error>> {
-        let (_folder_id, auto_switched) = self.ensure_images_indexed().await?;
-        let visible = self.get_visible_random_entries_global()?;
+    ) -> Result<(Vec<u8>, bool), LoaderError> {
+        let (_folder_id, auto_switched) = self.ensure_images_indexed()?;
+        if self.random_history_global_count()? == 0 {
+            return self.get_force_random_image(false);
+        }
 
+        let visible = self.get_visible_random_entries_global()?;
         if visible.is_empty() {
-            return self.get_force_random_image(true).await;
+            return self.get_force_random_image(false);
         }
 
-        let idx = if index < 0 {
-            0
-        } else if index >= visible.len() as i64 {
-            visible.len() as i64 - 1
-        } else {
-            index
-        };
+        let current_order_index = self.get_state_random_index()?;
+        if let Some((order_index, image_id, _)) = visible
+            .iter()
+            .rev()
+            .find(|(order_index, _, _)| *order_index < current_order_index)
+            .cloned()
+        {
+            self.set_state_random_index(order_index)?;
+            let data = self.load_by_image_id(image_id)?;
+            return Ok((data, auto_switched));
+        }
 
-        let (order_index, image_id, _) = visible[idx as usize].clone();
-        self.set_state_random_index(order_index)?;
-        let data = self.load_by_image_id(image_id).await?;
-        Ok((data, auto_switched))
+        self.get_force_random_image(false)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::ImageLoader;
-    use crate::db::Db;
-    use rusqlite::params;
+    pub fn get_normal_history(
+        &self,
+    ) -> Result<(Vec<crate::types::ImageHistoryItem>, i64), LoaderError> {
+        self.bootstrap_checked_scope()?;
+        let visible = self.get_visible_checked_normal_entries()?;
+        let pointer_raw = self.get_state_normal_index()?;
+        let pointer = visible
+            .iter()
+            .position(|(order_index, _, _, _)| *order_index == pointer_raw)
+            .map(|idx| idx as i64)
+            .unwrap_or(-1);
+        let items = visible
+            .into_iter()
+            .map(
+                |(order_index, image_id, path, _)| crate::types::ImageHistoryItem {
+                    image_id,
+                    order_index,
+                    path,
+                },
+            )
+            .collect::<Vec<_>>();
+        Ok((items, pointer))
+    }
+
+    pub fn get_random_history(
+        &self,
+    ) -> Result<(Vec<crate::types::ImageHistoryItem>, i64), LoaderError> {
+        let visible = self.get_visible_random_entries_global()?;
+        let pointer_raw = self.get_state_random_index()?;
+        let pointer = visible
+            .iter()
+            .position(|(order_index, _, _)| *order_index == pointer_raw)
+            .map(|idx| idx as i64)
+            .unwrap_or(-1);
+        let items = visible
+            .into_iter()
+            .map(
+                |(order_index, image_id, path)| crate::types::ImageHistoryItem {
+                    image_id,
+                    order_index,
+                    path,
+                },
+            )
+            .collect::<Vec<_>>();
+        Ok((items, pointer))
+    }
+
+    /// Windowed variant of `get_normal_history`, paginated in SQL.
+    pub fn get_normal_history_window(
+        &self,
+        offset: Option<i64>,
+        limit: i64,
+    ) -> Result<crate::types::ImageHistoryWindow, LoaderError> {
+        let limit = limit.clamp(1, 1000);
+        self.bootstrap_checked_scope()?;
+        let total: i64 = self.db.with_read(|conn| {
+            conn.query_row(
+                &format!("SELECT COUNT(*) FROM (SELECT i.id {VISIBLE_NORMAL_ENTRIES_SQL})"),
+                [],
+                |row| row.get(0),
+            )
+        })?;
+        let pointer_raw = self.get_state_normal_index()?;
+        // order_index is the position in the visible list.
+        let current_index = if (0..total).contains(&pointer_raw) {
+            pointer_raw
+        } else {
+            -1
+        };
+        let offset = history_window_offset(total, current_index, offset, limit);
+        let items = self.db.with_read(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT i.id, i.path {VISIBLE_NORMAL_ENTRIES_SQL}
+                 ORDER BY {NORMAL_ORDER_SQL}
+                 LIMIT ?1 OFFSET ?2"
+            ))?;
+            let rows = stmt
+                .query_map(params![limit, offset], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        })?;
+        let items = items
+            .into_iter()
+            .zip(offset..)
+            .map(|((image_id, path), order_index)| crate::types::ImageHistoryItem {
+                image_id,
+                order_index,
+                path,
+            })
+            .collect::<Vec<_>>();
+        Ok(crate::types::ImageHistoryWindow {
+            items,
+            current_index,
+            total,
+            offset,
+        })
+    }
+
+    /// Windowed variant of `get_random_history`, paginated in SQL.
+    pub fn get_random_history_window(
+        &self,
+        offset: Option<i64>,
+        limit: i64,
+    ) -> Result<crate::types::ImageHistoryWindow, LoaderError> {
+        let limit = limit.clamp(1, 1000);
+        let pointer_raw = self.get_state_random_index()?;
+        let (total, current_index) = self.db.with_read(|conn| {
+            let total: i64 = conn.query_row(
+                "SELECT COUNT(*)
+                 FROM random_history_global rh
+                 JOIN images i ON i.id = rh.image_id
+                 WHERE rh.image_id NOT IN (SELECT image_id FROM hidden_random_images)",
+                [],
+                |row| row.get(0),
+            )?;
+            let pointer_visible: Option<i64> = conn
+                .query_row(
+                    "SELECT 1
+                     FROM random_history_global rh
+                     JOIN images i ON i.id = rh.image_id
+                     WHERE rh.order_index = ?1
+                       AND rh.image_id NOT IN (SELECT image_id FROM hidden_random_images)",
+                    params![pointer_raw],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let current_index = match pointer_visible {
+                Some(_) => conn.query_row(
+                    "SELECT COUNT(*)
+                     FROM random_history_global rh
+                     JOIN images i ON i.id = rh.image_id
+                     WHERE rh.order_index < ?1
+                       AND rh.image_id NOT IN (SELECT image_id FROM hidden_random_images)",
+                    params![pointer_raw],
+                    |row| row.get(0),
+                )?,
+                None => -1,
+            };
+            Ok((total, current_index))
+        })?;
+
+        let offset = history_window_offset(total, current_index, offset, limit);
+        let items = self.db.with_read(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT rh.order_index, rh.image_id, i.path
+                 FROM random_history_global rh
+                 JOIN images i ON i.id = rh.image_id
+                 WHERE rh.image_id NOT IN (SELECT image_id FROM hidden_random_images)
+                 ORDER BY rh.order_index
+                 LIMIT ?1 OFFSET ?2",
+            )?;
+            let rows = stmt
+                .query_map(params![limit, offset], |row| {
+                    Ok(crate::types::ImageHistoryItem {
+                        order_index: row.get(0)?,
+                        image_id: row.get(1)?,
+                        path: row.get(2)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        })?;
+        Ok(crate::types::ImageHistoryWindow {
+            items,
+            current_index,
+            total,
+            offset,
+        })
+    }
+
+    pub fn reset_normal_history(&self) -> Result<(), LoaderError> {
+        self.set_state_normal_index(-1)?;
+        Ok(())
+    }
+
+    pub fn reset_random_history(&self) -> Result<(), LoaderError> {
+        self.clear_random_history_global()?;
+        self.lap_global_clear()?;
+        Ok(())
+    }
+
+    pub fn hide_normal_history_image(
+        &self,
+        image_id: i64,
+    ) -> Result<(), LoaderError> {
+        let folder_id = self
+            .get_image_folder_id(image_id)?
+            .ok_or(LoaderError::NoCurrentFolder)?;
+
+        self.db.conn().execute(
+            "INSERT OR IGNORE INTO hidden_normal_images (folder_id, image_id) VALUES (?1, ?2)",
+            params![folder_id, image_id],
+        )?;
+
+        self.bootstrap_checked_scope()?;
+        let visible = self.get_visible_checked_normal_entries()?;
+        if visible.is_empty() {
+            return Err(LoaderError::AllHidden {
+                mode: ImageMode::Normal,
+            });
+        }
+
+        let current_raw_index = self.get_state_normal_index()?;
+        let current_still_visible = visible
+            .iter()
+            .any(|(order_index, _, _, _)| *order_index == current_raw_index);
+        if current_raw_index >= 0 && !current_still_visible {
+            let next_order_index = visible
+                .iter()
+                .rev()
+                .find(|(order_index, _, _, _)| *order_index < current_raw_index)
+                .map(|(order_index, _, _, _)| *order_index)
+                .unwrap_or_else(|| visible[visible.len() - 1].0);
+            self.set_state_normal_index(next_order_index)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn hide_random_history_image(
+        &self,
+        image_id: i64,
+    ) -> Result<(), LoaderError> {
+        let (folder_id, _) = self
+            .get_current_folder_id_and_path()?
+            .ok_or(LoaderError::NoCurrentFolder)?;
+
+        self.db.conn().execute(
+            "INSERT OR IGNORE INTO hidden_random_images (folder_id, image_id) VALUES (?1, ?2)",
+            params![folder_id, image_id],
+        )?;
+
+        let visible_history = self.get_visible_random_entries_global()?;
+        let has_available_checked_scope = !self.get_checked_folder_available_counts()?.is_empty();
+        if visible_history.is_empty() && !has_available_checked_scope {
+            return Err(LoaderError::AllHidden {
+                mode: ImageMode::Random,
+            });
+        }
+
+        let current_order_index = self.get_state_random_index()?;
+        let current_still_visible = visible_history
+            .iter()
+            .any(|(order_index, _, _)| *order_index == current_order_index);
+        if current_order_index >= 0 && !current_still_visible {
+            let next_order_index = visible_history
+                .iter()
+                .rev()
+                .find(|(order_index, _, _)| *order_index < current_order_index)
+                .map(|(order_index, _, _)| *order_index)
+                .unwrap_or_else(|| visible_history[visible_history.len() - 1].0);
+            self.set_state_random_index(next_order_index)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn hide_image_by_path(&self, path: &str, mode: ImageMode) -> Result<(), LoaderError> {
+        let image_id = self.resolve_image_id_for_path(path)?;
+        match mode {
+            ImageMode::Normal => self.hide_normal_history_image(image_id),
+            ImageMode::Random => self.hide_random_history_image(image_id),
+        }
+    }
+
+    /// Adds `tags` to an image and returns all of its tags. Tags are stored
+    /// by image path, compared case-insensitively, and the first spelling
+    /// used is kept.
+    pub fn tag_image_by_path(
+        &self,
+        path: &str,
+        tags: &[String],
+    ) -> Result<Vec<String>, LoaderError> {
+        let tags = tags
+            .iter()
+            .map(|tag| normalize_tag(tag))
+            .collect::<Result<Vec<_>, _>>()?;
+        let path = self.get_image_path(self.resolve_image_id_for_path(path)?)?;
+        {
+            let mut conn = self.db.conn();
+            let tx = conn.transaction()?;
+            for tag in &tags {
+                tx.execute(
+                    "INSERT OR IGNORE INTO image_tags (path, tag) VALUES (?1, ?2)",
+                    params![path, tag],
+                )?;
+            }
+            tx.commit()?;
+        }
+        self.tags_for_path(&path)
+    }
+
+    /// Removes `tags` from an image and returns the tags it still has.
+    pub fn untag_image_by_path(
+        &self,
+        path: &str,
+        tags: &[String],
+    ) -> Result<Vec<String>, LoaderError> {
+        let tags = tags
+            .iter()
+            .map(|tag| normalize_tag(tag))
+            .collect::<Result<Vec<_>, _>>()?;
+        let path = self.get_image_path(self.resolve_image_id_for_path(path)?)?;
+        {
+            let mut conn = self.db.conn();
+            let tx = conn.transaction()?;
+            for tag in &tags {
+                tx.execute(
+                    "DELETE FROM image_tags WHERE path = ?1 AND tag = ?2",
+                    params![path, tag],
+                )?;
+            }
+            tx.commit()?;
+        }
+        self.tags_for_path(&path)
+    }
+
+    pub fn get_image_tags(&self, path: &str) -> Result<Vec<String>, LoaderError> {
+        let path = self.get_image_path(self.resolve_image_id_for_path(path)?)?;
+        self.tags_for_path(&path)
+    }
+
+    /// Every tag in use with the number of images carrying it.
+    pub fn get_tag_counts(&self) -> Result<Vec<(String, i64)>, LoaderError> {
+        self.db
+            .with_read(|conn| {
+                conn.prepare(
+                    "SELECT MIN(tag), COUNT(*) FROM image_tags
+                     GROUP BY tag ORDER BY tag",
+                )?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<Vec<_>, _>>()
+            })
+            .map_err(|e| e.into())
+    }
+
+    /// Paths of the images carrying `tag`, whether or not their root is
+    /// currently indexed or online.
+    pub fn get_tagged_image_paths(&self, tag: &str) -> Result<Vec<String>, LoaderError> {
+        let tag = normalize_tag(tag)?;
+        self.db
+            .with_read(|conn| {
+                conn.prepare("SELECT path FROM image_tags WHERE tag = ?1 ORDER BY path")?
+                    .query_map(params![tag], |row| row.get(0))?
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .map_err(|e| e.into())
+    }
+
+    fn tags_for_path(&self, path: &str) -> Result<Vec<String>, LoaderError> {
+        self.db
+            .with_conn(|conn| {
+                conn.prepare("SELECT tag FROM image_tags WHERE path = ?1 ORDER BY tag")?
+                    .query_map(params![path], |row| row.get(0))?
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .map_err(|e| e.into())
+    }
+
+    pub fn get_folder_history(
+        &self,
+    ) -> Result<Vec<(i64, String, String, i64)>, LoaderError> {
+        self.db
+            .with_read(|conn| {
+                let history: Vec<(i64, String, String, i64)> = conn
+                    .prepare(
+                        "SELECT f.id, f.path, f.added_at, COUNT(i.id) AS image_count FROM folders f LEFT JOIN images i ON i.folder_id = f.id GROUP BY f.id ORDER BY f.added_at DESC",
+                    )?
+                    .query_map([], |row| {
+                        let id: i64 = row.get(0)?;
+                        let path: String = row.get(1)?;
+                        let added_at: String = row.get(2)?;
+                        let image_count: i64 = row.get(3)?;
+                        Ok((id, path, added_at, image_count))
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok::<Vec<(i64, String, String, i64)>, rusqlite::Error>(history)
+            })
+            .map_err(|e| e.into())
+    }
+
+    /// The folder history as shown in the folder panel: availability is
+    /// refreshed first and the current folder's position is resolved.
+    pub fn get_folder_history_with_status(
+        &self,
+    ) -> Result<crate::types::FolderHistory, LoaderError> {
+        self.refresh_all_folder_availability()?;
+        let history = self.get_folder_history()?;
+        let mut availability = self.get_folder_availability()?;
+
+        let current_index = if history.is_empty() {
+            -1
+        } else {
+            match self.get_current_folder_id()? {
+                Some(id) => history
+                    .iter()
+                    .position(|(fid, _, _, _)| *fid == id)
+                    .map(|index| index as i64)
+                    .unwrap_or(-1),
+                None => -1,
+            }
+        };
+
+        let items = history
+            .into_iter()
+            .map(|(id, path, _, image_count)| {
+                let (online, last_seen_at) = availability.remove(&id).unwrap_or((true, None));
+                crate::types::FolderHistoryItem {
+                    id,
+                    path,
+                    image_count,
+                    online,
+                    last_seen_at,
+                }
+            })
+            .collect();
+
+        Ok(crate::types::FolderHistory {
+            history: items,
+            current_index,
+        })
+    }
+
+    pub fn get_next_folder(&self) -> Result<Option<(i64, String)>, LoaderError> {
+        let history = self.get_folder_history()?;
+        if history.is_empty() {
+            return Ok(None);
+        }
+
+        let current_id = self.db.conn().query_row(
+            "SELECT current_folder_id FROM state WHERE id = 1",
+            [],
+            |row| {
+                let id: Option<i64> = row.get(0)?;
+                Ok(id)
+            },
+        )?;
+
+        let start_idx = match current_id {
+            Some(id) => {
+                let idx = history.iter().position(|(fid, _, _, _)| *fid == id);
+                match idx {
+                    Some(0) => history.len() - 1,
+                    Some(i) => i - 1,
+                    None => history.len() - 1,
+                }
+            }
+            None => history.len() - 1,
+        };
+
+        // Try folders starting from start_idx, skip offline ones
+        for i in 0..history.len() {
+            let try_idx = (start_idx + i) % history.len();
+            let (folder_id, folder_path, _, _) = &history[try_idx];
+
+            if self.refresh_folder_availability(*folder_id, folder_path)? {
+                self.set_current_folder_id(Some(*folder_id))?;
+                return Ok(Some((*folder_id, folder_path.clone())));
+            }
+        }
+
+        Err(LoaderError::AllFoldersMissing)
+    }
+
+    pub fn get_prev_folder(&self) -> Result<Option<(i64, String)>, LoaderError> {
+        let history = self.get_folder_history()?;
+        if history.is_empty() {
+            return Ok(None);
+        }
+
+        let current_id = self.db.conn().query_row(
+            "SELECT current_folder_id FROM state WHERE id = 1",
+            [],
+            |row| {
+                let id: Option<i64> = row.get(0)?;
+                Ok(id)
+            },
+        )?;
+
+        let start_idx = match current_id {
+            Some(id) => {
+                let idx = history.iter().position(|(fid, _, _, _)| *fid == id);
+                match idx {
+                    Some(i) if i >= history.len() - 1 => 0usize,
+                    Some(i) => i + 1,
+                    None => 0,
+                }
+            }
+            None => 0,
+        };
+
+        // Try folders starting from start_idx, skip offline ones
+        for i in 0..history.len() {
+            let try_idx = (start_idx + i) % history.len();
+            let (folder_id, folder_path, _, _) = &history[try_idx];
+
+            if self.refresh_folder_availability(*folder_id, folder_path)? {
+                self.set_current_folder_id(Some(*folder_id))?;
+                return Ok(Some((*folder_id, folder_path.clone())));
+            }
+        }
+
+        Err(LoaderError::AllFoldersMissing)
+    }
+
+    pub fn reindex_current_folder(
+        &self,
+    ) -> Result<(i64, String), LoaderError> {
+        self.reindex_current_folder_with_progress(&IndexingJob::detached(), |_| {})
+    }
+
+    /// Rescans the current folder. Old rows are only replaced once the scan has
+    /// finished, so cancelling keeps the previous index and history.
+    pub fn reindex_current_folder_with_progress<F>(
+        &self,
+        job: &IndexingJob,
+        on_progress: F,
+    ) -> Result<(i64, String), LoaderError>
+    where
+        F: FnMut(String),
+    {
+        let (folder_id, folder_path) = match self.get_current_folder_id_and_path()? {
+            Some((id, path)) => (id, path),
+            None => return Err(LoaderError::NoCurrentFolder),
+        };
+
+        // Reindexing a missing folder would wipe it; keep it for relocation
+        if !self.refresh_folder_availability(folder_id, &folder_path)? {
+            return Err(LoaderError::FolderMissing { path: folder_path });
+        }
+
+        self.ensure_images_indexed_with_progress(job, on_progress, true)?;
+        Ok((folder_id, folder_path))
+    }
+
+    /// Snapshots the database. Skipped while no folder has been added: such a
+    /// snapshot has nothing worth restoring and would only rotate out an
+    /// older, useful one.
+    pub fn backup_database(
+        &self,
+        reason: BackupReason,
+    ) -> Result<Option<BackupEntry>, LoaderError> {
+        let folder_count: i64 = self
+            .db
+            .conn()
+            .query_row("SELECT COUNT(*) FROM folders", [], |row| row.get(0))?;
+        if folder_count == 0 {
+            return Ok(None);
+        }
+        self.db.backup(reason).map(Some)
+    }
+
+    pub fn list_backups(&self) -> Result<Vec<BackupEntry>, LoaderError> {
+        backup::list_backups(self.db.path())
+    }
+
+    /// Copies a listed snapshot over the live database after checking it and
+    /// snapshotting the current state, so a restore can itself be undone.
+    /// Returns the database path; the caller has to open a fresh `Db` on it
+    /// and stop using this loader.
+    pub fn restore_backup(&self, name: &str) -> Result<PathBuf, LoaderError> {
+        if self.indexing_jobs.is_running() {
+            return Err(LoaderError::Invalid(
+                "indexing is running - cancel it before restoring a backup".to_string(),
+            ));
+        }
+        let backup_path = backup::resolve_backup(self.db.path(), name)?;
+        backup::verify_backup(&backup_path, SCHEMA_VERSION)?;
+        self.backup_database(BackupReason::PreRestore)?;
+        self.db.restore_backup(&backup_path)?;
+        eprintln!("[RUST] backup: restored {}", backup_path.display());
+        Ok(self.db.path().to_path_buf())
+    }
+
+    /// Runs SQLite's own checks and the app invariants in `integrity`. With
+    /// `repair`, snapshots the database, fixes each failed invariant in order
+    /// and reports what is left; storage-level damage is only reported.
+    pub fn check_integrity(&self, repair: bool) -> Result<IntegrityReport, LoaderError> {
+        if repair && self.indexing_jobs.is_running() {
+            return Err(LoaderError::Invalid(
+                "indexing is running - cancel it before repairing the database".to_string(),
+            ));
+        }
+
+        let count_all = || -> Result<Vec<i64>, LoaderError> {
+            let conn = self.db.conn();
+            INVARIANT_CHECKS
+                .iter()
+                .map(|check| integrity::count_violations(&conn, check))
+                .collect()
+        };
+        let found = count_all()?;
+        let needs_repair = repair && found.iter().any(|count| *count > 0);
+
+        let mut backup = None;
+        let remaining = if needs_repair {
+            backup = self
+                .backup_database(BackupReason::Repair)?
+                .map(|entry| entry.name);
+            for check in INVARIANT_CHECKS {
+                // Earlier repairs can fix or uncover later violations, so
+                // count again right before deciding.
+                let count = integrity::count_violations(&self.db.conn(), check)?;
+                if count > 0 {
+                    eprintln!("[RUST] integrity: repairing {} ({})", check.name, count);
+                    self.repair_invariant(check.repair)?;
+                }
+            }
+            count_all()?
+        } else {
+            found.clone()
+        };
+
+        let (sqlite_errors, foreign_key_violations) = {
+            let conn = self.db.conn();
+            (
+                integrity::sqlite_integrity_errors(&conn)?,
+                integrity::foreign_key_violations(&conn)?,
+            )
+        };
+        let issues = INVARIANT_CHECKS
+            .iter()
+            .zip(found.iter().zip(remaining.iter()))
+            .filter(|(_, (found, remaining))| **found > 0 || **remaining > 0)
+            .map(|(check, (found, remaining))| IntegrityIssue {
+                check: check.name.to_string(),
+                description: check.description.to_string(),
+                found: *found,
+                remaining: *remaining,
+            })
+            .collect::<Vec<_>>();
+
+        Ok(IntegrityReport {
+            ok: sqlite_errors.is_empty()
+                && foreign_key_violations.is_empty()
+                && issues.iter().all(|issue| issue.remaining == 0),
+            repaired: needs_repair,
+            backup,
+            sqlite_errors,
+            foreign_key_violations,
+            issues,
+        })
+    }
+
+    fn repair_invariant(&self, repair: Repair) -> Result<(), LoaderError> {
+        match repair {
+            Repair::Sql(statements) => {
+                let mut conn = self.db.conn();
+                let tx = conn.transaction()?;
+                for statement in statements {
+                    tx.execute(statement, [])?;
+                }
+                tx.commit()?;
+            }
+            Repair::RebuildFolderNodes => {
+                let folder_ids = self.db.with_conn(|conn| {
+                    let mut stmt = conn.prepare(
+                        "SELECT f.id FROM folders f
+                         WHERE EXISTS (SELECT 1 FROM images i WHERE i.folder_id = f.id)
+                           AND NOT EXISTS (SELECT 1 FROM folder_nodes n WHERE n.path = f.path)",
+                    )?;
+                    let ids = stmt
+                        .query_map([], |row| row.get(0))?
+                        .collect::<Result<Vec<i64>, _>>()?;
+                    Ok(ids)
+                })?;
+                for folder_id in folder_ids {
+                    self.rebuild_folder_nodes_for_root(folder_id)?;
+                }
+            }
+            Repair::RebuildActiveImages => self.rebuild_active_images()?,
+        }
+        Ok(())
+    }
+
+    pub fn full_wipe(&self) -> Result<(), LoaderError> {
+        let mut conn = self.db.conn();
+        let tx = conn.transaction()?;
+        Self::wipe_library_in(&tx)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Clears every folder, image and history table, inside the caller's
+    /// transaction. Settings in `state` and the ignore list are kept.
+    fn wipe_library_in(tx: &rusqlite::Transaction<'_>) -> Result<(), LoaderError> {
+        tx.execute("DELETE FROM random_history", [])?;
+        tx.execute("DELETE FROM random_history_global", [])?;
+        tx.execute("DELETE FROM current_lap", [])?;
+        tx.execute("DELETE FROM current_lap_global", [])?;
+        tx.execute("DELETE FROM active_images", [])?;
+        tx.execute("DELETE FROM active_image_refcounts", [])?;
+        tx.execute("DELETE FROM hidden_normal_images", [])?;
+        tx.execute("DELETE FROM hidden_random_images", [])?;
+        tx.execute("DELETE FROM folder_images_direct", [])?;
+        tx.execute("DELETE FROM folder_closure", [])?;
+        tx.execute("DELETE FROM images", [])?;
+        tx.execute("DELETE FROM checked_folders", [])?;
+        tx.execute("DELETE FROM folder_nodes", [])?;
+        tx.execute("DELETE FROM folders", [])?;
+        tx.execute("DELETE FROM image_views", [])?;
+        tx.execute("DELETE FROM image_tags", [])?;
+        tx.execute(
+            "UPDATE state
+             SET current_folder_id = NULL,
+                 current_index = -1,
+                 current_random_index = -1,
+                 last_image_id = NULL
+             WHERE id = 1",
+            [],
+        )?;
+        Ok(())
+    }
+
+    /// Serializes the library into a portable document; see `library_io`.
+    pub fn export_library(&self) -> Result<LibraryDocument, LoaderError> {
+        let image_state = self.get_image_state()?;
+        let random_selection = self.get_random_selection_settings()?;
+        let indexing = self.get_indexing_settings()?;
+
+        let conn = self.db.conn();
+        let mut folders: Vec<LibraryFolder> = Vec::new();
+        let mut folder_slots: HashMap<i64, usize> = HashMap::new();
+        {
+            let mut stmt = conn.prepare(
+                "SELECT id, path, added_at, current_index, current_random_index,
+                        normal_sort_order, normal_shuffle_seed, follow_symlinks
+                 FROM folders
+                 ORDER BY id",
+            )?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                folder_slots.insert(row.get(0)?, folders.len());
+                folders.push(LibraryFolder {
+                    path: row.get(1)?,
+                    added_at: row.get(2)?,
+                    current_index: row.get(3)?,
+                    current_random_index: row.get(4)?,
+                    sort_order: row.get(5)?,
+                    shuffle_seed: row.get(6)?,
+                    follow_symlinks: row.get::<_, i64>(7)? != 0,
+                    images: Vec::new(),
+                    hidden_normal: Vec::new(),
+                    hidden_random: Vec::new(),
+                    random_history: Vec::new(),
+                    current_lap: Vec::new(),
+                });
+            }
+        }
+
+        let mut image_paths: HashMap<i64, String> = HashMap::new();
+        {
+            let mut stmt = conn.prepare(
+                "SELECT id, folder_id, path, mtime, file_size, width, height
+                 FROM images
+                 ORDER BY id",
+            )?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let image = LibraryImage {
+                    path: row.get(2)?,
+                    mtime: row.get(3)?,
+                    file_size: row.get(4)?,
+                    width: row.get(5)?,
+                    height: row.get(6)?,
+                };
+                image_paths.insert(row.get(0)?, image.path.clone());
+                let folder_id: Option<i64> = row.get(1)?;
+                if let Some(slot) = folder_id.and_then(|id| folder_slots.get(&id)) {
+                    folders[*slot].images.push(image);
+                }
+            }
+        }
+
+        // (folder_id, order_index, image_id) rows from the per-root tables;
+        // order_index is 0 for the unordered ones.
+        let per_folder_rows = |sql: &str| -> Result<Vec<(i64, i64, String)>, LoaderError> {
+            let mut stmt = conn.prepare(sql)?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows
+                .into_iter()
+                .filter_map(|(folder_id, order_index, image_id)| {
+                    let slot = *folder_slots.get(&folder_id)?;
+                    let path = image_paths.get(&image_id)?.clone();
+                    Some((slot as i64, order_index, path))
+                })
+                .collect())
+        };
+        for (slot, _, path) in per_folder_rows(
+            "SELECT folder_id, 0, image_id FROM hidden_normal_images ORDER BY folder_id, image_id",
+        )? {
+            folders[slot as usize].hidden_normal.push(path);
+        }
+        for (slot, _, path) in per_folder_rows(
+            "SELECT folder_id, 0, image_id FROM hidden_random_images ORDER BY folder_id, image_id",
+        )? {
+            folders[slot as usize].hidden_random.push(path);
+        }
+        for (slot, order_index, image) in per_folder_rows(
+            "SELECT folder_id, order_index, image_id FROM random_history ORDER BY folder_id, order_index",
+        )? {
+            folders[slot as usize]
+                .random_history
+                .push(LibraryHistoryEntry { order_index, image });
+        }
+        for (slot, _, path) in per_folder_rows(
+            "SELECT folder_id, 0, image_id FROM current_lap ORDER BY folder_id, image_id",
+        )? {
+            folders[slot as usize].current_lap.push(path);
+        }
+
+        let random_history = conn
+            .prepare("SELECT order_index, image_id FROM random_history_global ORDER BY order_index")?
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter_map(|(order_index, image_id)| {
+                Some(LibraryHistoryEntry {
+                    order_index,
+                    image: image_paths.get(&image_id)?.clone(),
+                })
+            })
+            .collect();
+        let current_lap = conn
+            .prepare("SELECT image_id FROM current_lap_global ORDER BY image_id")?
+            .query_map([], |row| row.get::<_, i64>(0))?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter_map(|image_id| image_paths.get(&image_id).cloned())
+            .collect();
+        let checked_folders = conn
+            .prepare("SELECT path FROM checked_folders ORDER BY path")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let image_views = conn
+            .prepare(
+                "SELECT path, last_shown_at, show_count FROM image_views ORDER BY path",
+            )?
+            .query_map([], |row| {
+                Ok(LibraryImageView {
+                    path: row.get(0)?,
+                    last_shown_at: row.get(1)?,
+                    show_count: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let image_tags = conn
+            .prepare("SELECT path, tag FROM image_tags ORDER BY path, tag")?
+            .query_map([], |row| {
+                Ok(LibraryImageTag {
+                    path: row.get(0)?,
+                    tag: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (current_folder_id, last_image_id, current_index, current_random_index) = conn
+            .query_row(
+                "SELECT current_folder_id, last_image_id, current_index, current_random_index
+                 FROM state WHERE id = 1",
+                [],
+                |row| {
+                    Ok((
+                        row.get::<_, Option<i64>>(0)?,
+                        row.get::<_, Option<i64>>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, i64>(3)?,
+                    ))
+                },
+            )?;
+
+        Ok(LibraryDocument {
+            format: LIBRARY_FORMAT.to_string(),
+            version: LIBRARY_VERSION,
+            exported_at: chrono::Utc::now().to_rfc3339(),
+            image_state,
+            random_selection,
+            indexing,
+            current_folder: current_folder_id
+                .and_then(|id| folder_slots.get(&id))
+                .map(|slot| folders[*slot].path.clone()),
+            last_image: last_image_id.and_then(|id| image_paths.get(&id).cloned()),
+            current_index,
+            current_random_index,
+            folders,
+            checked_folders,
+            random_history,
+            current_lap,
+            image_views,
+            image_tags,
+        })
+    }
+
+    /// Replaces the whole library with `document` after snapshotting the
+    /// current database. Everything except the checked scope is written in
+    /// one transaction; the folder tree is then rebuilt from the imported
+    /// images and the checked scope reapplied on top of it. References to
+    /// images the document does not list are dropped and counted.
+    pub fn import_library(
+        &self,
+        mut document: LibraryDocument,
+        mappings: &[PathMapping],
+    ) -> Result<crate::types::LibraryTransferSummary, LoaderError> {
+        document.validate()?;
+        document.remap_paths(mappings);
+        document.validate()?;
+        for folder in &document.folders {
+            if NormalSortOrder::parse(&folder.sort_order).is_none() {
+                return Err(LoaderError::Invalid(format!(
+                    "unknown sort order {} for {}",
+                    folder.sort_order, folder.path
+                )));
+            }
+        }
+        validate_ignore_patterns(&document.indexing.ignore_patterns)?;
+        for image_tag in &document.image_tags {
+            normalize_tag(&image_tag.tag)?;
+        }
+
+        self.indexing_jobs.cancel(None);
+        self.backup_database(BackupReason::Import)?;
+
+        let mut summary = crate::types::LibraryTransferSummary {
+            folders: document.folders.len() as i64,
+            images: 0,
+            skipped_references: 0,
+        };
+        {
+            let mut conn = self.db.conn();
+            let tx = conn.transaction()?;
+            Self::wipe_library_in(&tx)?;
+
+            // Global lists refer to an image by path alone; the first root
+            // that has the path wins.
+            let mut global_ids: HashMap<String, i64> = HashMap::new();
+            let mut folder_ids: HashMap<String, i64> = HashMap::new();
+            for folder in &document.folders {
+                tx.execute(
+                    "INSERT INTO folders (path, added_at, current_index, current_random_index,
+                                          normal_sort_order, normal_shuffle_seed, follow_symlinks,
+                                          indexed_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        folder.path,
+                        folder.added_at,
+                        folder.current_index,
+                        folder.current_random_index,
+                        folder.sort_order,
+                        folder.shuffle_seed,
+                        folder.follow_symlinks as i64,
+                        chrono::Utc::now().to_rfc3339(),
+                    ],
+                )?;
+                let folder_id = tx.last_insert_rowid();
+                folder_ids.insert(folder.path.clone(), folder_id);
+
+                let mut image_ids: HashMap<&str, i64> = HashMap::new();
+                {
+                    let mut stmt = tx.prepare(
+                        "INSERT OR IGNORE INTO images (path, folder_id, mtime, file_size, width, height)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    )?;
+                    for image in &folder.images {
+                        if stmt.execute(params![
+                            image.path,
+                            folder_id,
+                            image.mtime,
+                            image.file_size,
+                            image.width,
+                            image.height,
+                        ])? == 0
+                        {
+                            continue;
+                        }
+                        let image_id = tx.last_insert_rowid();
+                        image_ids.insert(image.path.as_str(), image_id);
+                        global_ids.entry(image.path.clone()).or_insert(image_id);
+                        summary.images += 1;
+                    }
+                }
+
+                let mut resolve = |path: &str| {
+                    let image_id = image_ids.get(path).copied();
+                    if image_id.is_none() {
+                        summary.skipped_references += 1;
+                    }
+                    image_id
+                };
+                for (table, paths) in [
+                    ("hidden_normal_images", &folder.hidden_normal),
+                    ("hidden_random_images", &folder.hidden_random),
+                    ("current_lap", &folder.current_lap),
+                ] {
+                    let mut stmt = tx.prepare(&format!(
+                        "INSERT OR IGNORE INTO {} (folder_id, image_id) VALUES (?1, ?2)",
+                        table
+                    ))?;
+                    for path in paths {
+                        if let Some(image_id) = resolve(path) {
+                            stmt.execute(params![folder_id, image_id])?;
+                        }
+                    }
+                }
+                let mut stmt = tx.prepare(
+                    "INSERT OR IGNORE INTO random_history (folder_id, order_index, image_id)
+                     VALUES (?1, ?2, ?3)",
+                )?;
+                for entry in &folder.random_history {
+                    if let Some(image_id) = resolve(&entry.image) {
+                        stmt.execute(params![folder_id, entry.order_index, image_id])?;
+                    }
+                }
+            }
+
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT OR IGNORE INTO random_history_global (order_index, image_id)
+                     VALUES (?1, ?2)",
+                )?;
+                for entry in &document.random_history {
+                    match global_ids.get(&entry.image) {
+                        Some(image_id) => {
+                            stmt.execute(params![entry.order_index, image_id])?;
+                        }
+                        None => summary.skipped_references += 1,
+                    }
+                }
+                let mut stmt =
+                    tx.prepare("INSERT OR IGNORE INTO current_lap_global (image_id) VALUES (?1)")?;
+                for path in &document.current_lap {
+                    match global_ids.get(path) {
+                        Some(image_id) => {
+                            stmt.execute(params![image_id])?;
+                        }
+                        None => summary.skipped_references += 1,
+                    }
+                }
+                let mut stmt = tx.prepare(
+                    "INSERT OR REPLACE INTO image_views (path, last_shown_at, show_count)
+                     VALUES (?1, ?2, ?3)",
+                )?;
+                for view in &document.image_views {
+                    stmt.execute(params![view.path, view.last_shown_at, view.show_count])?;
+                }
+                let mut stmt =
+                    tx.prepare("INSERT OR IGNORE INTO image_tags (path, tag) VALUES (?1, ?2)")?;
+                for image_tag in &document.image_tags {
+                    stmt.execute(params![image_tag.path, normalize_tag(&image_tag.tag)?])?;
+                }
+                tx.execute("DELETE FROM ignore_patterns", [])?;
+                let mut stmt =
+                    tx.prepare("INSERT INTO ignore_patterns (position, pattern) VALUES (?1, ?2)")?;
+                for (position, pattern) in document
+                    .indexing
+                    .ignore_patterns
+                    .iter()
+                    .filter(|pattern| !pattern.trim().is_empty())
+                    .enumerate()
+                {
+                    stmt.execute(params![position as i64, pattern])?;
+                }
+            }
+
+            let image_state = &document.image_state;
+            let random_selection = &document.random_selection;
+            tx.execute(
+                "UPDATE state
+                 SET current_folder_id = ?1, last_image_id = ?2,
+                     current_index = ?3, current_random_index = ?4,
+                     vertical_mirror = ?5, horizontal_mirror = ?6, greyscale = ?7,
+                     timer_flow_mode = ?8, show_folder_history_panel = ?9,
+                     show_top_controls = ?10, show_image_history_panel = ?11,
+                     show_bottom_controls = ?12, is_fullscreen_image = ?13,
+                     shortcut_hints_visible = ?14, shortcut_hint_side = ?15,
+                     random_selection_mode = ?16, spaced_randomness = ?17,
+                     skip_hidden_dirs = ?18
+                 WHERE id = 1",
+                params![
+                    document
+                        .current_folder
+                        .as_ref()
+                        .and_then(|path| folder_ids.get(path)),
+                    document
+                        .last_image
+                        .as_ref()
+                        .and_then(|path| global_ids.get(path)),
+                    document.current_index,
+                    document.current_random_index,
+                    image_state.vertical_mirror as i64,
+                    image_state.horizontal_mirror as i64,
+                    image_state.greyscale as i64,
+                    &image_state.timer_flow_mode,
+                    image_state.show_folder_history_panel as i64,
+                    image_state.show_top_controls as i64,
+                    image_state.show_image_history_panel as i64,
+                    image_state.show_bottom_controls as i64,
+                    image_state.is_fullscreen_image as i64,
+                    image_state.shortcut_hints_visible as i64,
+                    &image_state.shortcut_hint_side,
+                    &random_selection.mode,
+                    random_selection.spaced_randomness.clamp(0.0, 1.0),
+                    document.indexing.skip_hidden_dirs as i64,
+                ],
+            )?;
+            tx.commit()?;
+        }
+
+        self.bootstrap_checked_scope()?;
+        {
+            let conn = self.db.conn();
+            let mut stmt = conn.prepare(
+                "INSERT OR IGNORE INTO checked_folders (path)
+                 SELECT path FROM folder_nodes WHERE path = ?1",
+            )?;
+            for path in &document.checked_folders {
+                if stmt.execute(params![path])? == 0 {
+                    summary.skipped_references += 1;
+                }
+            }
+        }
+        self.bootstrap_checked_scope()?;
+        self.rebuild_active_images()?;
+        Ok(summary)
+    }
+
+    pub fn get_image_state(
+        &self,
+    ) -> Result<crate::types::ImageState, LoaderError> {
+        let row = self.db.read().query_row(
+            "SELECT vertical_mirror, horizontal_mirror, greyscale, timer_flow_mode, show_folder_history_panel, show_top_controls, show_image_history_panel, show_bottom_controls, is_fullscreen_image, shortcut_hints_visible, shortcut_hint_side FROM state WHERE id = 1",
+            [],
+            |row| {
+                let vertical_mirror: i64 = row.get(0)?;
+                let horizontal_mirror: i64 = row.get(1)?;
+                let greyscale: i64 = row.get(2)?;
+                let timer_flow_mode: String = row.get(3)?;
+                let show_folder_history_panel: i64 = row.get(4)?;
+                let show_top_controls: i64 = row.get(5)?;
+                let show_image_history_panel: i64 = row.get(6)?;
+                let show_bottom_controls: i64 = row.get(7)?;
+                let is_fullscreen_image: i64 = row.get(8)?;
+                let shortcut_hints_visible: i64 = row.get(9)?;
+                let shortcut_hint_side: String = row.get(10)?;
+                Ok((vertical_mirror, horizontal_mirror, greyscale, timer_flow_mode, show_folder_history_panel, show_top_controls, show_image_history_panel, show_bottom_controls, is_fullscreen_image, shortcut_hints_visible, shortcut_hint_side))
+            },
+        )?;
+
+        Ok(crate::types::ImageState {
+            vertical_mirror: row.0 != 0,
+            horizontal_mirror: row.1 != 0,
+            greyscale: row.2 != 0,
+            timer_flow_mode: if row.3 == "normal" {
+                "normal".to_string()
+            } else {
+                "random".to_string()
+            },
+            show_folder_history_panel: row.4 != 0,
+            show_top_controls: row.5 != 0,
+            show_image_history_panel: row.6 != 0,
+            show_bottom_controls: row.7 != 0,
+            is_fullscreen_image: row.8 != 0,
+            shortcut_hints_visible: row.9 != 0,
+            shortcut_hint_side: if row.10 == "right" {
+                "right".to_string()
+            } else {
+                "left".to_string()
+            },
+        })
+    }
+
+    /// The mode the slideshow timer steps in.
+    pub fn get_timer_flow_mode(&self) -> Result<ImageMode, LoaderError> {
+        if self.get_image_state()?.timer_flow_mode == "normal" {
+            Ok(ImageMode::Normal)
+        } else {
+            Ok(ImageMode::Random)
+        }
+    }
+
+    pub fn set_image_state(
+        &self,
+        state: &crate::types::ImageState,
+    ) -> Result<(), LoaderError> {
+        self.db.conn().execute(
+            "UPDATE state SET vertical_mirror = ?1, horizontal_mirror = ?2, greyscale = ?3, timer_flow_mode = ?4, show_folder_history_panel = ?5, show_top_controls = ?6, show_image_history_panel = ?7, show_bottom_controls = ?8, is_fullscreen_image = ?9, shortcut_hints_visible = ?10, shortcut_hint_side = ?11 WHERE id = 1",
+            params![
+                state.vertical_mirror as i64,
+                state.horizontal_mirror as i64,
+                state.greyscale as i64,
+                &state.timer_flow_mode,
+                state.show_folder_history_panel as i64,
+                state.show_top_controls as i64,
+                state.show_image_history_panel as i64,
+                state.show_bottom_controls as i64,
+                state.is_fullscreen_image as i64,
+                state.shortcut_hints_visible as i64,
+                &state.shortcut_hint_side,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_folder_by_index(
+        &self,
+        index: i64,
+    ) -> Result<(i64, String), LoaderError> {
+        let history = self.get_folder_history()?;
+        if history.is_empty() {
+            return Err(LoaderError::NoFoldersAvailable);
+        }
+
+        let idx = if index < 0 {
+            0
+        } else if index >= history.len() as i64 {
+            history.len() as i64 - 1
+        } else {
+            index
+        };
+
+        let (folder_id, path, _, _) = &history[idx as usize];
+
+        // Check if folder is still reachable
+        if !self.refresh_folder_availability(*folder_id, path)? {
+            return Err(LoaderError::FolderMissing { path: path.clone() });
+        }
+
+        self.set_current_folder_id(Some(*folder_id))?;
+        Ok((*folder_id, path.clone()))
+    }
+
+    pub fn set_normal_image_by_index(
+        &self,
+        index: i64,
+    ) -> Result<(Vec<u8>, bool), LoaderError> {
+        let (_folder_id, auto_switched) = self.ensure_images_indexed()?;
+        self.bootstrap_checked_scope()?;
+        self.require_checked_folders()?;
+        let visible = self.get_visible_checked_normal_entries()?;
+
+        if visible.is_empty() {
+            return Err(LoaderError::AllHidden {
+                mode: ImageMode::Normal,
+            });
+        }
+
+        let idx = if index < 0 {
+            0
+        } else if index >= visible.len() as i64 {
+            visible.len() as i64 - 1
+        } else {
+            index
+        };
+
+        let (order_index, image_id, _, selected_folder_id) = visible[idx as usize].clone();
+        self.set_state_normal_index(order_index)?;
+        if selected_folder_id > 0 {
+            self.set_current_folder_id(Some(selected_folder_id))?;
+        }
+        let data = self.load_by_image_id(image_id)?;
+        Ok((data, auto_switched))
+    }
+
+    /// Searches indexed image paths. Returns (page, total_matches); each item's
+    /// `order_index` is its position in the normal list, or -1 when the image is
+    /// not in the visible checked scope.
+    pub fn search_images(
+        &self,
+        query: &str,
+        mode: SearchMode,
+        checked_only: bool,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(Vec<crate::types::ImageHistoryItem>, i64), LoaderError> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Ok((Vec::new(), 0));
+        }
+        let offset = offset.max(0);
+        let limit = limit.clamp(1, 500);
+
+        // The trigram index answers MATCH for three or more characters, and
+        // GLOB and LIKE patterns with a three-character literal run.
+        let (match_sql, order_sql, pattern) = match mode {
+            SearchMode::Substring if query.chars().count() >= 3 => (
+                "images_fts MATCH ?1",
+                "i.path COLLATE NOCASE, i.id",
+                format!("\"{}\"", query.replace('"', "\"\"")),
+            ),
+            // Too short for trigrams: every path has to be looked at anyway.
+            SearchMode::Substring => (
+                "instr(lower(i.path), lower(?1)) > 0",
+                "i.path COLLATE NOCASE, i.id",
+                query.to_string(),
+            ),
+            SearchMode::Glob => (
+                "images_fts.path GLOB ?1",
+                "i.path COLLATE NOCASE, i.id",
+                query.to_string(),
+            ),
+            // Scored inside SQLite, so only the page is loaded.
+            SearchMode::Fuzzy => (
+                "fuzzy_path_score(?1, i.path) IS NOT NULL",
+                "fuzzy_path_score(?1, i.path) DESC, i.path COLLATE natural_path, i.id",
+                query.to_string(),
+            ),
+        };
+        let scope_sql = if checked_only {
+            "AND i.id IN (
+                 SELECT fi.image_id
+                 FROM checked_folders cf
+                 JOIN folder_closure c ON c.ancestor_path = cf.path
+                 JOIN folder_images_direct fi ON fi.folder_path = c.descendant_path
+             )"
+        } else {
+            ""
+        };
+
+        let (page, total) = self.db.with_read(|conn| {
+            let total: i64 = conn.query_row(
+                &format!(
+                    "SELECT COUNT(*)
+                     FROM images_fts
+                     JOIN images i ON i.id = images_fts.rowid
+                     WHERE {match_sql} {scope_sql}"
+                ),
+                params![pattern],
+                |row| row.get(0),
+            )?;
+            let mut stmt = conn.prepare(&format!(
+                "SELECT i.id, i.path
+                 FROM images_fts
+                 JOIN images i ON i.id = images_fts.rowid
+                 WHERE {match_sql} {scope_sql}
+                 ORDER BY {order_sql}
+                 LIMIT ?2 OFFSET ?3"
+            ))?;
+            let rows = stmt
+                .query_map(params![pattern, limit, offset], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok((rows, total))
+        })?;
+
+        let page_ids = page.iter().map(|(image_id, _)| *image_id).collect::<Vec<_>>();
+        let normal_positions = self.get_normal_positions(&page_ids)?;
+
+        let items = page
+            .into_iter()
+            .map(|(image_id, path)| crate::types::ImageHistoryItem {
+                image_id,
+                order_index: *normal_positions.get(&image_id).unwrap_or(&-1),
+                path,
+            })
+            .collect();
+        Ok((items, total))
+    }
+
+    /// Jumps the normal pointer to `image_id`, which must be visible in the
+    /// checked normal list. Returns (image_data, auto_switched_folder).
+    pub fn set_normal_image_by_id(
+        &self,
+        image_id: i64,
+    ) -> Result<(Vec<u8>, bool), LoaderError> {
+        let (_folder_id, auto_switched) = self.ensure_images_indexed()?;
+        self.bootstrap_checked_scope()?;
+        self.require_checked_folders()?;
+        let (order_index, _, _, selected_folder_id) = self
+            .get_visible_checked_normal_entries()?
+            .into_iter()
+            .find(|(_, id, _, _)| *id == image_id)
+            .ok_or_else(|| {
+                LoaderError::NotFound(
+                    "image is not in the checked folders or is hidden in normal mode".to_string(),
+                )
+            })?;
+
+        self.set_state_normal_index(order_index)?;
+        if selected_folder_id > 0 {
+            self.set_current_folder_id(Some(selected_folder_id))?;
+        }
+        let data = self.load_by_image_id(image_id)?;
+        Ok((data, auto_switched))
+    }
+
+    /// Finds the indexed image for a file path, indexing on demand: a file under a
+    /// known root is added to that root, anything else indexes its parent folder
+    /// as a new root.
+    fn resolve_image_id_for_path(
+        &self,
+        path: &str,
+    ) -> Result<i64, LoaderError> {
+        // Images reached through a followed symlink are stored under the link
+        // path, which canonicalizing would resolve away.
+        let indexed: Option<i64> = self
+            .db
+            .conn()
+            .query_row(
+                "SELECT id FROM images WHERE path = ?1 ORDER BY id LIMIT 1",
+                params![path.trim()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(image_id) = indexed {
+            return Ok(image_id);
+        }
+
+        let canonical = std::fs::canonicalize(Path::new(path.trim()))
+            .map_err(|_| LoaderError::ImageMissing {
+                path: path.trim().to_string(),
+            })?;
+        let invalid = || LoaderError::InvalidPath {
+            path: path.trim().to_string(),
+        };
+        if !canonical.is_file() {
+            return Err(LoaderError::NotAFile {
+                path: path.trim().to_string(),
+            });
+        }
+        let supported = canonical
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(Self::is_supported_image_ext)
+            .unwrap_or(false);
+        if !supported {
+            return Err(LoaderError::UnsupportedType {
+                path: path.trim().to_string(),
+            });
+        }
+        let image_path = canonical.to_str().ok_or_else(invalid)?.to_string();
+
+        let folder_id = match self.find_owning_root(&canonical)? {
+            Some((folder_id, _)) => {
+                let existing: Option<i64> = self
+                    .db
+                    .conn()
+                    .query_row(
+                        "SELECT id FROM images WHERE folder_id = ?1 AND path = ?2",
+                        params![folder_id, image_path],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                if let Some(image_id) = existing {
+                    return Ok(image_id);
+                }
+                self.insert_image(&image_path, folder_id)?;
+                self.rebuild_folder_nodes_for_root(folder_id)?;
+                folder_id
+            }
+            None => {
+                let parent = canonical
+                    .parent()
+                    .and_then(|p| p.to_str())
+                    .ok_or_else(invalid)?;
+                let (folder_id, _, _) = self.set_current_folder_and_index(parent)?;
+                folder_id
+            }
+        };
+
+        let image_id: i64 = self.db.conn().query_row(
+            "SELECT id FROM images WHERE folder_id = ?1 AND path = ?2",
+            params![folder_id, image_path],
+            |row| row.get(0),
+        )?;
+        Ok(image_id)
+    }
+
+    /// Explains why an image is missing from the normal list. Opening an image
+    /// never unhides it or checks its folder; the caller decides whether to.
+    fn normal_list_exclusion(
+        &self,
+        image_id: i64,
+        image_path: &str,
+    ) -> Result<LoaderError, LoaderError> {
+        let conn = self.db.read();
+        let hidden: Option<i64> = conn
+            .query_row(
+                "SELECT 1 FROM hidden_normal_images WHERE image_id = ?1",
+                params![image_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if hidden.is_some() {
+            return Ok(LoaderError::ImageHidden {
+                path: image_path.to_string(),
+                mode: ImageMode::Normal,
+            });
+        }
+        let indexed: Option<i64> = conn
+            .query_row(
+                "SELECT 1 FROM folder_images_direct WHERE image_id = ?1 LIMIT 1",
+                params![image_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if indexed.is_none() {
+            return Ok(LoaderError::ReindexRequired(
+                "image is not part of an indexed folder".to_string(),
+            ));
+        }
+        Ok(LoaderError::ImageNotChecked {
+            path: image_path.to_string(),
+        })
+    }
+
+    /// Opens a specific image in both modes: positions the normal pointer on it
+    /// and, if requested, appends it to the global random history. Images hidden
+    /// in either mode or outside the checked folders are refused before any state
+    /// changes. Returns (image_data, auto_switched_folder).
+    pub fn open_image_by_id(
+        &self,
+        image_id: i64,
+        append_to_random_history: bool,
+    ) -> Result<(Vec<u8>, bool), LoaderError> {
+        let previous_folder_id = self.get_current_folder_id()?;
+        self.open_image_by_id_from(image_id, append_to_random_history, previous_folder_id)
+    }
+
+    fn open_image_by_id_from(
+        &self,
+        image_id: i64,
+        append_to_random_history: bool,
+        previous_folder_id: Option<i64>,
+    ) -> Result<(Vec<u8>, bool), LoaderError> {
+        // Fails with "no rows" for unknown ids before any state is touched.
+        let image_path = self.get_image_path(image_id)?;
+        self.bootstrap_checked_scope()?;
+
+        let Some((order_index, _, _, selected_folder_id)) = self
+            .get_visible_checked_normal_entries()?
+            .into_iter()
+            .find(|(_, id, _, _)| *id == image_id)
+        else {
+            return Err(self.normal_list_exclusion(image_id, &image_path)?);
+        };
+        if append_to_random_history {
+            let hidden: Option<i64> = self
+                .db
+                .read()
+                .query_row(
+                    "SELECT 1 FROM hidden_random_images WHERE image_id = ?1",
+                    params![image_id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if hidden.is_some() {
+                return Err(LoaderError::ImageHidden {
+                    path: image_path,
+                    mode: ImageMode::Random,
+                });
+            }
+        }
+
+        self.set_state_normal_index(order_index)?;
+        if selected_folder_id > 0 {
+            self.set_current_folder_id(Some(selected_folder_id))?;
+        }
+        if append_to_random_history {
+            let random_index = self.append_random_history_global(image_id)?;
+            self.set_state_random_index(random_index)?;
+            self.lap_global_insert(image_id)?;
+        }
+
+        let data = self.load_by_image_id(image_id)?;
+        let auto_switched = self.get_current_folder_id()? != previous_folder_id;
+        Ok((data, auto_switched))
+    }
+
+    /// Like `open_image_by_id`; a path outside every root indexes its folder as
+    /// a new root first.
+    pub fn open_image_by_path(
+        &self,
+        path: &str,
+        append_to_random_history: bool,
+    ) -> Result<(Vec<u8>, bool), LoaderError> {
+        let previous_folder_id = self.get_current_folder_id()?;
+        let image_id = self.resolve_image_id_for_path(path)?;
+        self.open_image_by_id_from(image_id, append_to_random_history, previous_folder_id)
+    }
+
+    pub fn set_random_image_by_index(
+        &self,
+        index: i64,
+    ) -> Result<(Vec<u8>, bool), LoaderError> {
+        let (_folder_id, auto_switched) = self.ensure_images_indexed()?;
+        let visible = self.get_visible_random_entries_global()?;
+
+        if visible.is_empty() {
+            return self.get_force_random_image(true);
+        }
+
+        let idx = if index < 0 {
+            0
+        } else if index >= visible.len() as i64 {
+            visible.len() as i64 - 1
+        } else {
+            index
+        };
+
+        let (order_index, image_id, _) = visible[idx as usize].clone();
+        self.set_state_random_index(order_index)?;
+        let data = self.load_by_image_id(image_id)?;
+        Ok((data, auto_switched))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fuzzy_subsequence_score, natural_cmp, ImageLoader, NormalSortOrder, SearchMode,
+        INDEX_INSERT_CHUNK_SIZE,
+    };
+    use crate::error::{ImageMode, LoaderError};
+    use crate::indexing::{IndexingJob, IndexingStatus};
+    use rusqlite::params;
+    use crate::test_support::{open_temp_db, unique_temp_root};
     use std::path::{Path, PathBuf};
-    use std::sync::atomic::{AtomicU64, Ordering};
-    use std::time::{SystemTime, UNIX_EPOCH};
-    use tauri::async_runtime::block_on;
-
-    static TEST_COUNTER: AtomicU64 = AtomicU64::new(0);
-
-    fn unique_temp_root(name: &str) -> PathBuf {
-        let counter = TEST_COUNTER.fetch_add(1, Ordering::Relaxed);
-        let nanos = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_nanos();
-        let root = std::env::temp_dir().join(format!(
-            "random_pics_img_loader_{}_{}_{}_{}",
-            name,
-            std::process::id(),
-            nanos,
-            counter
-        ));
-        std::fs::create_dir_all(&root).expect("test temp root should be created");
-        root
+
+    fn write_test_image(path: &Path, seed: u8) {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).expect("image parent should exist");
+        }
+        std::fs::write(path, [seed, seed.wrapping_add(1), seed.wrapping_add(2)])
+            .expect("test image should be writable");
+    }
+
+    fn setup_loader_with_images(name: &str, image_count: usize) -> (ImageLoader, PathBuf) {
+        let root = unique_temp_root("img_loader", name);
+        let folder = root.join("images");
+        std::fs::create_dir_all(&folder).expect("image folder should be created");
+
+        for idx in 0..image_count {
+            let path = if idx % 2 == 0 {
+                folder.join(format!("img_{idx}.jpg"))
+            } else {
+                folder.join("nested").join(format!("img_{idx}.png"))
+            };
+            write_test_image(&path, idx as u8);
+        }
+
+        let loader = ImageLoader::new(open_temp_db(&root));
+        let folder_str = folder.to_string_lossy().to_string();
+        loader.set_current_folder_and_index(&folder_str)
+            .expect("folder should index successfully");
+
+        (loader, root)
+    }
+
+    #[test]
+    fn force_random_errors_when_all_images_are_hidden_in_random_mode() {
+        let (loader, root) = setup_loader_with_images("all_hidden_random", 3);
+        let folder_id = loader
+            .get_current_folder_id()
+            .expect("folder lookup should succeed")
+            .expect("current folder should be set");
+
+        {
+            let conn = loader.db.conn();
+            let mut stmt = conn
+                .prepare("SELECT id FROM images WHERE folder_id = ?1")
+                .expect("image id query should prepare");
+            let image_ids = stmt
+                .query_map(params![folder_id], |row| row.get::<_, i64>(0))
+                .expect("image id query should run")
+                .collect::<Result<Vec<_>, _>>()
+                .expect("image ids should collect");
+
+            for image_id in image_ids {
+                conn.execute(
+                    "INSERT OR IGNORE INTO hidden_random_images (folder_id, image_id) VALUES (?1, ?2)",
+                    params![folder_id, image_id],
+                )
+                .expect("hidden_random insert should succeed");
+            }
+        }
+
+        let err = loader.get_force_random_image(true)
+            .expect_err("force random should fail when all images are hidden")
+            .to_string();
+        assert!(err.contains("all images for this folder are hidden in random mode"));
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn reset_random_history_clears_history_but_preserves_hidden_random_blacklist() {
+        let (loader, root) = setup_loader_with_images("reset_preserves_hidden", 4);
+        loader.get_force_random_image(true).expect("initial random image should load");
+        loader.get_force_random_image(true).expect("second random image should load");
+
+        let (history, _) = loader
+            .get_random_history()
+            .expect("random history should be readable");
+        assert!(!history.is_empty());
+        let hidden_image_id = history[0].image_id;
+
+        loader
+            .hide_random_history_image(hidden_image_id)
+            .expect("hiding random image should succeed");
+        loader
+            .reset_random_history()
+            .expect("reset random history should succeed");
+
+        let (history_after, current_after) = loader
+            .get_random_history()
+            .expect("random history should be readable after reset");
+        assert!(history_after.is_empty());
+        assert_eq!(current_after, -1);
+
+        let folder_id = loader
+            .get_current_folder_id()
+            .expect("folder lookup should succeed")
+            .expect("current folder should exist");
+        let hidden_count: i64 = loader
+            .db
+            .conn()
+            .query_row(
+                "SELECT COUNT(*) FROM hidden_random_images WHERE folder_id = ?1",
+                params![folder_id],
+                |row| row.get(0),
+            )
+            .expect("hidden random count query should work");
+        assert_eq!(hidden_count, 1);
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn hide_random_history_image_keeps_pointer_on_a_visible_entry() {
+        let (loader, root) = setup_loader_with_images("hide_pointer", 5);
+        loader.get_force_random_image(true).expect("random image load should work");
+        loader.get_force_random_image(true).expect("random image load should work");
+        loader.get_force_random_image(true).expect("random image load should work");
+
+        let (before, before_index) = loader
+            .get_random_history()
+            .expect("random history should be readable");
+        assert!(before.len() >= 2);
+        assert!(before_index >= 0);
+        let current_image_id = before[before_index as usize].image_id;
+
+        loader
+            .hide_random_history_image(current_image_id)
+            .expect("hiding current random image should succeed");
+
+        let (after, after_index) = loader
+            .get_random_history()
+            .expect("random history should be readable after hide");
+
+        assert!(after.iter().all(|item| item.image_id != current_image_id));
+        assert!(!after.is_empty());
+        assert!(after_index >= 0);
+        assert!((after_index as usize) < after.len());
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn delete_folder_cleans_related_rows_and_clears_current_folder_state() {
+        let (loader, root) = setup_loader_with_images("delete_folder_cleanup", 4);
+        loader.get_force_random_image(true).expect("random image load should work");
+        loader.get_force_random_image(true)
+            .expect("second random image load should work");
+
+        let (random_history, _) = loader
+            .get_random_history()
+            .expect("random history should be readable");
+        assert!(!random_history.is_empty());
+        let image_id = random_history[0].image_id;
+
+        loader
+            .hide_normal_history_image(image_id)
+            .expect("hiding normal history image should work");
+        loader
+            .hide_random_history_image(image_id)
+            .expect("hiding random history image should work");
+
+        let folder_id = loader
+            .get_current_folder_id()
+            .expect("folder lookup should succeed")
+            .expect("current folder should be set");
+        loader
+            .delete_folder_by_id(folder_id)
+            .expect("deleting folder should succeed");
+
+        let conn = loader.db.conn();
+        let folders_count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM folders", [], |row| row.get(0))
+            .expect("folders count query should work");
+        let images_count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM images", [], |row| row.get(0))
+            .expect("images count query should work");
+        let random_history_count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM random_history", [], |row| row.get(0))
+            .expect("random history count query should work");
+        let current_lap_count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM current_lap", [], |row| row.get(0))
+            .expect("current lap count query should work");
+        let hidden_normal_count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM hidden_normal_images", [], |row| {
+                row.get(0)
+            })
+            .expect("hidden normal count query should work");
+        let hidden_random_count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM hidden_random_images", [], |row| {
+                row.get(0)
+            })
+            .expect("hidden random count query should work");
+        let current_folder_id: Option<i64> = conn
+            .query_row(
+                "SELECT current_folder_id FROM state WHERE id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .expect("current folder state query should work");
+
+        assert_eq!(folders_count, 0);
+        assert_eq!(images_count, 0);
+        assert_eq!(random_history_count, 0);
+        assert_eq!(current_lap_count, 0);
+        assert_eq!(hidden_normal_count, 0);
+        assert_eq!(hidden_random_count, 0);
+        assert_eq!(current_folder_id, None);
+
+        drop(conn);
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn pick_spaced_candidate_prefers_least_recently_shown_images() {
+        let (loader, root) = setup_loader_with_images("spaced_pick", 4);
+        loader.bootstrap_checked_scope().expect("scope should bootstrap");
+        let ids: Vec<i64> = {
+            let conn = loader.db.conn();
+            let mut stmt = conn.prepare("SELECT id FROM images ORDER BY id").unwrap();
+            let ids = stmt
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            ids
+        };
+
+        // Zero randomness only draws among the never-shown tie.
+        for &shown in &ids[..2] {
+            loader.record_image_shown(&loader.get_image_path(shown).unwrap()).unwrap();
+        }
+        for draw in 0..8 {
+            let picked = loader.pick_spaced_candidate(&[], 0.0, draw).unwrap().unwrap();
+            assert!(picked == ids[2] || picked == ids[3]);
+        }
+        for &shown in &ids[2..] {
+            loader.record_image_shown(&loader.get_image_path(shown).unwrap()).unwrap();
+        }
+
+        // Everything is shown now, ids[0] longest ago.
+        assert_eq!(loader.pick_spaced_candidate(&[], 0.0, 99).unwrap(), Some(ids[0]));
+        assert_eq!(loader.pick_spaced_candidate(&[ids[0]], 0.0, 99).unwrap(), Some(ids[1]));
+        assert_eq!(loader.pick_spaced_candidate(&[], 1.0, 3).unwrap(), Some(ids[3]));
+        assert_eq!(loader.pick_spaced_candidate(&ids, 0.5, 0).unwrap(), None);
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn spaced_random_mode_covers_every_image_before_repeating() {
+        let (loader, root) = setup_loader_with_images("spaced_coverage", 4);
+        loader
+            .set_random_selection_settings(&crate::types::RandomSelectionSettings {
+                mode: "spaced".to_string(),
+                spaced_randomness: 0.0,
+            })
+            .expect("spaced mode should be settable");
+
+        for _ in 0..4 {
+            loader.get_force_random_image(true).expect("spaced pick should load");
+        }
+        let (history, _) = loader
+            .get_random_history()
+            .expect("random history should be readable");
+        let shown: std::collections::HashSet<i64> =
+            history.iter().map(|item| item.image_id).collect();
+        assert_eq!(shown.len(), 4);
+
+        // Clearing the lap and history must not make recently seen images eligible again.
+        loader
+            .reset_random_history()
+            .expect("reset random history should succeed");
+        loader.get_force_random_image(true).expect("spaced pick should load");
+        let (after_reset, _) = loader
+            .get_random_history()
+            .expect("random history should be readable");
+        assert_eq!(after_reset.len(), 1);
+        assert_eq!(after_reset[0].image_id, history[0].image_id);
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn natural_cmp_orders_digit_runs_numerically() {
+        use std::cmp::Ordering;
+
+        assert_eq!(natural_cmp("img2.jpg", "img10.jpg"), Ordering::Less);
+        assert_eq!(natural_cmp("IMG_10.jpg", "img_9.jpg"), Ordering::Greater);
+        assert_eq!(natural_cmp("a/b2/x.png", "a/b10/x.png"), Ordering::Less);
+        assert_eq!(natural_cmp("img02.jpg", "img2.jpg"), Ordering::Greater);
+        assert_eq!(natural_cmp("same.jpg", "same.jpg"), Ordering::Equal);
+    }
+
+    #[test]
+    fn folder_sort_order_is_persisted_and_keeps_pointer_on_current_image() {
+        let (loader, root) = setup_loader_with_images("natural_sort", 12);
+        let folder_id = loader
+            .get_current_folder_id()
+            .expect("folder lookup should succeed")
+            .expect("current folder should be set");
+
+        loader.get_next_image().expect("first normal image should load");
+        let (before, before_index) = loader
+            .get_normal_history()
+            .expect("normal history should be readable");
+        let current_image_id = before[before_index as usize].image_id;
+
+        loader
+            .set_folder_sort_order(folder_id, NormalSortOrder::Natural)
+            .expect("sort order should be settable");
+        assert_eq!(
+            loader
+                .get_folder_sort_order(folder_id)
+                .expect("sort order should be readable"),
+            NormalSortOrder::Natural
+        );
+
+        let (after, after_index) = loader
+            .get_normal_history()
+            .expect("normal history should be readable");
+        assert_eq!(after[after_index as usize].image_id, current_image_id);
+
+        let top_level = after
+            .iter()
+            .filter(|item| !item.path.contains("nested"))
+            .map(|item| {
+                Path::new(&item.path)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            top_level,
+            vec!["img_0.jpg", "img_2.jpg", "img_4.jpg", "img_6.jpg", "img_8.jpg", "img_10.jpg"]
+        );
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn roots_group_case_insensitively_and_indexing_fills_sort_metadata() {
+        let root = unique_temp_root("img_loader", "root_order");
+        for name in ["Beta", "alpha"] {
+            write_test_image(&root.join(name).join("img_0.jpg"), 0);
+        }
+        let loader = ImageLoader::new(open_temp_db(&root));
+        let mut beta_id = 0;
+        for name in ["Beta", "alpha"] {
+            let folder = root.join(name).to_string_lossy().to_string();
+            let (folder_id, folder_path, _) = loader
+                .set_current_folder_and_index(&folder)
+                .expect("folder should index");
+            loader.set_folder_checked(&folder_path, true).unwrap();
+            if name == "Beta" {
+                beta_id = folder_id;
+            }
+        }
+
+        let order = loader
+            .get_visible_checked_normal_entries()
+            .expect("entries should be listed")
+            .into_iter()
+            .map(|(_, _, path, _)| path)
+            .collect::<Vec<_>>();
+        assert_eq!(order.len(), 2);
+        assert!(order[0].contains("alpha") && order[1].contains("Beta"));
+
+        loader.set_folder_sort_order(beta_id, NormalSortOrder::Dimensions).unwrap();
+        loader.set_current_folder_id(Some(beta_id)).unwrap();
+        loader.reindex_current_folder().expect("reindex should work");
+        let missing: i64 = loader
+            .db
+            .conn()
+            .query_row(
+                "SELECT COUNT(*) FROM images WHERE folder_id = ?1 AND width IS NULL",
+                params![beta_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(missing, 0);
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn fuzzy_subsequence_score_prefers_consecutive_matches() {
+        assert!(fuzzy_subsequence_score("hnd", "hands_01.png").is_some());
+        assert!(fuzzy_subsequence_score("xyz", "hands_01.png").is_none());
+        let tight = fuzzy_subsequence_score("hand", "hands.png").unwrap();
+        let spread = fuzzy_subsequence_score("hand", "h_a_n_d.png").unwrap();
+        assert!(tight > spread);
+    }
+
+    #[test]
+    fn search_images_matches_paths_by_mode_and_reports_normal_index() {
+        let (loader, root) = setup_loader_with_images("search", 6);
+
+        let (items, total) = loader
+            .search_images("nested", SearchMode::Substring, false, 0, 50)
+            .expect("substring search should work");
+        assert_eq!(total, 3);
+        assert!(items.iter().all(|item| item.path.contains("nested")));
+        assert!(items.iter().all(|item| item.order_index >= 0));
+
+        let (page, total) = loader
+            .search_images("img", SearchMode::Substring, true, 2, 2)
+            .expect("paginated search should work");
+        assert_eq!(total, 6);
+        assert_eq!(page.len(), 2);
+        // Case-insensitive through the trigram index, and below its three
+        // characters; LIKE wildcards are literal. Queries avoid digits and
+        // underscores alone, which the temp directory name contains.
+        assert_eq!(loader.search_images("MG_", SearchMode::Substring, false, 0, 50).unwrap().1, 6);
+        assert_eq!(loader.search_images("5.", SearchMode::Substring, false, 0, 50).unwrap().1, 1);
+        assert_eq!(loader.search_images("g%", SearchMode::Substring, false, 0, 50).unwrap().1, 0);
+
+        let (items, total) = loader
+            .search_images("*/img_?.jpg", SearchMode::Glob, false, 0, 50)
+            .expect("glob search should work");
+        assert_eq!(total, 3);
+        assert!(items.iter().all(|item| item.path.ends_with(".jpg")));
+
+        let (items, _) = loader
+            .search_images("im5png", SearchMode::Fuzzy, false, 0, 50)
+            .expect("fuzzy search should work");
+        assert!(items[0].path.ends_with("img_5.png"));
+
+        let target = items[0].image_id;
+        loader.set_normal_image_by_id(target).expect("jump to result should work");
+        let (history, current) = loader
+            .get_normal_history()
+            .expect("normal history should be readable");
+        assert_eq!(history[current as usize].image_id, target);
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn open_image_by_path_indexes_on_demand_and_positions_both_modes() {
+        let (loader, root) = setup_loader_with_images("open_by_path", 4);
+        let folder = std::fs::canonicalize(root.join("images")).expect("folder should exist");
+        let nested = folder.join("nested");
+        loader
+            .set_folder_checked(nested.to_str().unwrap(), false)
+            .expect("nested folder should be uncheckable");
+
+        let late = folder.join("late.jpg");
+        write_test_image(&late, 42);
+        let (_, auto_switched) = loader
+            .open_image_by_path(late.to_str().unwrap(), true)
+            .expect("file added after indexing should open");
+        assert!(!auto_switched);
+        let (history, current) = loader
+            .get_normal_history()
+            .expect("normal history should be readable");
+        assert_eq!(history[current as usize].path, late.to_string_lossy());
+        let (random_history, random_current) = loader
+            .get_random_history()
+            .expect("random history should be readable");
+        assert_eq!(
+            random_history[random_current as usize].path,
+            late.to_string_lossy()
+        );
+
+        // Refused images leave the checked scope and both hide lists alone.
+        loader
+            .set_folder_exclusive(nested.to_str().unwrap())
+            .expect("nested folder should be checkable on its own");
+        let unchecked_image = folder.join("img_0.jpg");
+        let err = loader
+            .open_image_by_path(unchecked_image.to_str().unwrap(), false)
+            .expect_err("image outside the checked scope should be refused");
+        assert_eq!(err.code(), "image_not_checked");
+        let checked: Vec<String> = loader
+            .db
+            .conn()
+            .prepare("SELECT path FROM checked_folders")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(checked, vec![nested.to_string_lossy().into_owned()]);
+
+        let nested_image = nested.join("img_1.png");
+        loader
+            .hide_image_by_path(nested_image.to_str().unwrap(), ImageMode::Random)
+            .expect("image should be hideable in random mode");
+        let err = loader
+            .open_image_by_path(nested_image.to_str().unwrap(), true)
+            .expect_err("image hidden in random mode should not join random history");
+        assert!(matches!(err, LoaderError::ImageHidden { mode: ImageMode::Random, .. }));
+        loader
+            .open_image_by_path(nested_image.to_str().unwrap(), false)
+            .expect("image hidden only in random mode should open in normal mode");
+        loader
+            .hide_image_by_path(nested_image.to_str().unwrap(), ImageMode::Normal)
+            .expect("image should be hideable in normal mode");
+        let err = loader
+            .open_image_by_path(nested_image.to_str().unwrap(), false)
+            .expect_err("image hidden in normal mode should be refused");
+        assert!(matches!(err, LoaderError::ImageHidden { mode: ImageMode::Normal, .. }));
+        let hidden: i64 = loader
+            .db
+            .conn()
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM hidden_normal_images)
+                      + (SELECT COUNT(*) FROM hidden_random_images)",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(hidden, 2);
+
+        let outside = std::fs::canonicalize(&root)
+            .expect("root should exist")
+            .join("elsewhere")
+            .join("loose.png");
+        write_test_image(&outside, 7);
+        let err = loader
+            .open_image_by_path(outside.to_str().unwrap(), false)
+            .expect_err("a new root is indexed but not checked");
+        assert_eq!(err.code(), "image_not_checked");
+        assert_eq!(
+            loader
+                .get_folder_history()
+                .expect("folder history should be readable")
+                .len(),
+            2
+        );
+        let elsewhere = outside.parent().unwrap().to_str().unwrap();
+        loader
+            .set_folder_checked(elsewhere, true)
+            .expect("new root should be checkable");
+        let (_, auto_switched) = loader
+            .open_image_by_path(outside.to_str().unwrap(), false)
+            .expect("image in a checked new root should open");
+        assert!(!auto_switched);
+        let (history, current) = loader
+            .get_normal_history()
+            .expect("normal history should be readable");
+        assert_eq!(history[current as usize].path, outside.to_string_lossy());
+
+        let other_root_image = nested.join("img_3.png");
+        let (_, auto_switched) = loader
+            .open_image_by_path(other_root_image.to_str().unwrap(), false)
+            .expect("image in the first root should open");
+        assert!(auto_switched);
+
+        let missing = root.join("missing.jpg");
+        assert!(loader.open_image_by_path(missing.to_str().unwrap(), false).is_err());
+        assert!(loader.open_image_by_id(-1, false).is_err());
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn rebuilding_folder_nodes_keeps_checked_folders() {
+        let (loader, root) = setup_loader_with_images("rebuild_keeps_checked", 4);
+        let folder = std::fs::canonicalize(root.join("images")).expect("folder should exist");
+        let nested = folder.join("nested").to_string_lossy().into_owned();
+        loader
+            .set_folder_exclusive(&nested)
+            .expect("nested folder should be checkable on its own");
+        let checked_paths = |loader: &ImageLoader| -> Vec<String> {
+            loader
+                .db
+                .conn()
+                .prepare("SELECT path FROM checked_folders ORDER BY path")
+                .expect("query should prepare")
+                .query_map([], |row| row.get::<_, String>(0))
+                .expect("query should run")
+                .collect::<Result<_, _>>()
+                .expect("rows should read")
+        };
+
+        // Indexing one new file rebuilds the nodes of its root.
+        let late = folder.join("nested").join("deep").join("late.jpg");
+        write_test_image(&late, 9);
+        loader
+            .open_image_by_path(late.to_str().unwrap(), false)
+            .expect("file under a checked folder should open");
+        assert_eq!(checked_paths(&loader), vec![nested.clone()]);
+
+        // Merging the root into a new parent root rebuilds the parent's nodes.
+        loader
+            .set_current_folder_and_index(root.to_str().unwrap())
+            .expect("parent should index and absorb the root");
+        assert_eq!(checked_paths(&loader), vec![nested.clone()]);
+        let (history, current) = loader
+            .get_normal_history()
+            .expect("normal history should be readable");
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[current as usize].path, late.to_string_lossy());
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn history_windows_match_the_full_history() {
+        let (loader, root) = setup_loader_with_images("history_window", 9);
+        for _ in 0..6 {
+            loader.get_next_image().expect("normal next should work");
+            loader.get_force_random_image(true).expect("random next should work");
+        }
+
+        let (full, current) = loader
+            .get_normal_history()
+            .expect("normal history should be readable");
+        let window = loader
+            .get_normal_history_window(None, 4)
+            .expect("normal window should be readable");
+        assert_eq!(window.total, full.len() as i64);
+        assert_eq!(window.current_index, current);
+        assert_eq!(window.items.len(), 4);
+        assert!(window.offset <= current && current < window.offset + 4);
+        assert_eq!(
+            window.items[0].image_id,
+            full[window.offset as usize].image_id
+        );
+
+        let (full, current) = loader
+            .get_random_history()
+            .expect("random history should be readable");
+        let window = loader
+            .get_random_history_window(Some(1), 3)
+            .expect("random window should be readable");
+        assert_eq!(window.total, full.len() as i64);
+        assert_eq!(window.current_index, current);
+        assert_eq!(window.offset, 1);
+        let ids = window.items.iter().map(|item| item.image_id).collect::<Vec<_>>();
+        let expected = full[1..4].iter().map(|item| item.image_id).collect::<Vec<_>>();
+        assert_eq!(ids, expected);
+
+        let tail = loader
+            .get_random_history_window(Some(100), 3)
+            .expect("out-of-range offset should clamp");
+        assert_eq!(tail.offset, full.len() as i64 - 3);
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn cancelled_indexing_jobs_leave_the_previous_state_intact() {
+        let (loader, root) = setup_loader_with_images("cancel_indexing", 4);
+        let folder_id = loader
+            .get_current_folder_id()
+            .expect("folder lookup should succeed")
+            .expect("current folder should be set");
+        loader.get_next_image().expect("normal next should work");
+        let (history_before, current_before) = loader
+            .get_normal_history()
+            .expect("normal history should be readable");
+
+        let job = loader.indexing_jobs().start(|_| {});
+        assert_eq!(loader.indexing_jobs().cancel(Some(job.id())), 1);
+        let err = loader.reindex_current_folder_with_progress(&job, |_| {})
+            .expect_err("cancelled reindex should fail");
+        assert!(matches!(err, LoaderError::Cancelled));
+        loader.indexing_jobs().finish(job.id());
+
+        let (history_after, current_after) = loader
+            .get_normal_history()
+            .expect("normal history should be readable");
+        assert_eq!(history_after.len(), history_before.len());
+        assert_eq!(current_after, current_before);
+
+        let other = root.join("other");
+        write_test_image(&other.join("a.jpg"), 1);
+        let job = loader.indexing_jobs().start(|_| {});
+        loader.indexing_jobs().cancel(None);
+        let err = loader.set_current_folder_and_index_with_progress(
+            other.to_str().unwrap(),
+            &job,
+            |_| {},
+        )
+        .expect_err("cancelled pick should fail");
+        assert!(matches!(err, LoaderError::Cancelled));
+        assert_eq!(
+            loader.get_current_folder_id().expect("folder lookup should succeed"),
+            Some(folder_id)
+        );
+        assert_eq!(
+            loader
+                .get_folder_history()
+                .expect("folder history should be readable")
+                .len(),
+            1
+        );
+
+        let progress = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = std::sync::Arc::clone(&progress);
+        let job = loader.indexing_jobs().start(move |p| {
+            sink.lock().unwrap().push(p.clone());
+        });
+        loader.reindex_current_folder_with_progress(&job, |_| {})
+            .expect("reindex should work");
+        let progress = progress.lock().unwrap();
+        let last = progress.last().expect("progress should be reported");
+        assert_eq!(last.job_id, job.id());
+        assert_eq!(last.found, 4);
+        assert_eq!(last.inserted, 4);
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn cancelled_or_empty_rescans_keep_the_previous_index() {
+        let (loader, root) = setup_loader_with_images("cancel_rescan", 4);
+        loader.get_next_image().expect("normal next should work");
+        loader.get_next_random_image().expect("random next should work");
+        let hidden = std::fs::canonicalize(root.join("images").join("img_0.jpg"))
+            .expect("image should exist");
+        loader
+            .hide_image_by_path(hidden.to_str().unwrap(), ImageMode::Normal)
+            .expect("image should be hideable");
+        let snapshot = |loader: &ImageLoader| {
+            let conn = loader.db.conn();
+            let ids: Vec<i64> = conn
+                .prepare("SELECT id FROM images ORDER BY id")
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            let counts: (i64, i64, i64) = conn
+                .query_row(
+                    "SELECT (SELECT COUNT(*) FROM random_history_global),
+                            (SELECT COUNT(*) FROM hidden_normal_images),
+                            (SELECT COUNT(*) FROM active_images)",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .unwrap();
+            (ids, counts)
+        };
+        let normal_history = |loader: &ImageLoader| {
+            let (items, current) = loader.get_normal_history().expect("history should load");
+            let ids: Vec<i64> = items.iter().map(|item| item.image_id).collect();
+            (ids, current)
+        };
+        let before = snapshot(&loader);
+        let normal_before = normal_history(&loader);
+
+        // Cancelled once every path is staged, right before the swap.
+        write_test_image(&root.join("images").join("late.jpg"), 9);
+        let job = loader.indexing_jobs().start(|_| {});
+        let err = loader
+            .reindex_current_folder_with_progress(&job, |line| {
+                if line.starts_with("index:") {
+                    loader.indexing_jobs().cancel(Some(job.id()));
+                }
+            })
+            .expect_err("cancelled rescan should fail");
+        assert!(matches!(err, LoaderError::Cancelled));
+        loader.indexing_jobs().finish(job.id());
+        assert_eq!(snapshot(&loader), before);
+        assert_eq!(normal_history(&loader), normal_before);
+
+        // A rescan that finds nothing fails without wiping the index.
+        for entry in std::fs::read_dir(root.join("images")).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                std::fs::remove_dir_all(path).unwrap();
+            } else {
+                std::fs::remove_file(path).unwrap();
+            }
+        }
+        let err = loader
+            .reindex_current_folder_with_progress(&IndexingJob::detached(), |_| {})
+            .expect_err("empty rescan should fail");
+        assert!(matches!(err, LoaderError::NoImages));
+        assert_eq!(snapshot(&loader), before);
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn an_index_that_never_finished_is_redone_instead_of_skipped() {
+        let (loader, root) = setup_loader_with_images("interrupted_index", 4);
+        let folder = root.join("images");
+        let (folder_id, _) = loader
+            .get_current_folder_id_and_path()
+            .expect("current folder should load")
+            .expect("a folder should be current");
+        let pick = |loader: &ImageLoader| {
+            let mut lines = Vec::new();
+            loader
+                .set_current_folder_and_index_with_progress(
+                    folder.to_str().unwrap(),
+                    &IndexingJob::detached(),
+                    |line| lines.push(line),
+                )
+                .expect("folder should index");
+            lines
+        };
+        assert!(pick(&loader)
+            .iter()
+            .any(|line| line.starts_with("scan:skip already indexed")));
+
+        // The app quit after some chunks committed: rows are there, but the
+        // final transaction that marks the folder indexed never ran.
+        write_test_image(&folder.join("late_0.jpg"), 7);
+        write_test_image(&folder.join("late_1.jpg"), 8);
+        loader
+            .db
+            .conn()
+            .execute(
+                "UPDATE folders SET indexed_at = NULL WHERE id = ?1",
+                params![folder_id],
+            )
+            .unwrap();
+        assert!(pick(&loader)
+            .iter()
+            .any(|line| line.starts_with("scan:resume incomplete index count=4")));
+        assert_eq!(loader.count_images(folder_id).expect("count should work"), 6);
+        assert!(loader.is_folder_indexed(folder_id).expect("flag should load"));
+        assert!(pick(&loader)
+            .iter()
+            .any(|line| line.starts_with("scan:skip already indexed count=6")));
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
     }
 
-    fn write_test_image(path: &Path, seed: u8) {
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent).expect("image parent should exist");
-        }
-        std::fs::write(path, [seed, seed.wrapping_add(1), seed.wrapping_add(2)])
-            .expect("test image should be writable");
+    #[test]
+    fn image_loader_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ImageLoader>();
     }
 
-    fn setup_loader_with_images(name: &str, image_count: usize) -> (ImageLoader, PathBuf) {
-        let root = unique_temp_root(name);
-        let folder = root.join("images");
-        std::fs::create_dir_all(&folder).expect("image folder should be created");
-
-        for idx in 0..image_count {
-            let path = if idx % 2 == 0 {
-                folder.join(format!("img_{idx}.jpg"))
-            } else {
-                folder.join("nested").join(format!("img_{idx}.png"))
-            };
-            write_test_image(&path, idx as u8);
+    #[test]
+    fn indexing_inserts_in_chunks_and_only_keeps_image_files() {
+        let (loader, root) = setup_loader_with_images("chunked_index", 1);
+        let big = root.join("big");
+        for idx in 0..INDEX_INSERT_CHUNK_SIZE + 5 {
+            write_test_image(&big.join(format!("d{}", idx % 7)).join(format!("{idx}.jpg")), 0);
         }
+        std::fs::write(big.join("notes.txt"), b"not an image").expect("text file should be written");
+        std::fs::create_dir_all(big.join("folder.png")).expect("dir with image ext should exist");
+
+        let (folder_id, _, _) = loader
+            .set_current_folder_and_index(big.to_str().unwrap())
+            .expect("large folder should index");
+        assert_eq!(
+            loader.count_images(folder_id).expect("count should work"),
+            (INDEX_INSERT_CHUNK_SIZE + 5) as i64
+        );
 
-        let db_path = root.join("imgstate.sqlite");
-        let db = Db::open(db_path).expect("db open should succeed");
-        let loader = ImageLoader::new(db);
-        let folder_str = folder.to_string_lossy().to_string();
-        block_on(loader.set_current_folder_and_index(&folder_str))
-            .expect("folder should index successfully");
-
-        (loader, root)
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
     }
 
     #[test]
-    fn force_random_errors_when_all_images_are_hidden_in_random_mode() {
-        let (loader, root) = setup_loader_with_images("all_hidden_random", 3);
-        let folder_id = loader
-            .get_current_folder_id()
-            .expect("folder lookup should succeed")
-            .expect("current folder should be set");
-
-        {
-            let conn = loader.db.conn();
-            let mut stmt = conn
-                .prepare("SELECT id FROM images WHERE folder_id = ?1")
-                .expect("image id query should prepare");
-            let image_ids = stmt
-                .query_map(params![folder_id], |row| row.get::<_, i64>(0))
-                .expect("image id query should run")
-                .collect::<Result<Vec<_>, _>>()
-                .expect("image ids should collect");
-
-            for image_id in image_ids {
-                conn.execute(
-                    "INSERT OR IGNORE INTO hidden_random_images (folder_id, image_id) VALUES (?1, ?2)",
-                    params![folder_id, image_id],
-                )
-                .expect("hidden_random insert should succeed");
-            }
-        }
+    fn indexing_honours_ignore_files_global_patterns_and_hidden_dirs() {
+        let (loader, root) = setup_loader_with_images("ignore_rules", 1);
+        let lib = root.join("lib");
+        write_test_image(&lib.join("a.jpg"), 0);
+        write_test_image(&lib.join(".thumbs").join("t.jpg"), 1);
+        write_test_image(&lib.join(".keep").join("k.jpg"), 2);
+        write_test_image(&lib.join("raw").join("r.jpg"), 3);
+        write_test_image(&lib.join("sub").join("x.png"), 4);
+        write_test_image(&lib.join("sub").join("keep.png"), 5);
+        write_test_image(&lib.join("sub").join("y.jpg"), 6);
+        std::fs::write(lib.join(".randompicsignore"), "!.keep/\n")
+            .expect("root ignore file should be written");
+        std::fs::write(lib.join("sub").join(".randompicsignore"), "*.png\n!keep.png\n")
+            .expect("nested ignore file should be written");
+
+        let err = loader
+            .set_indexing_settings(&crate::types::IndexingSettings {
+                ignore_patterns: vec!["{a,b".to_string()],
+                skip_hidden_dirs: true,
+            })
+            .expect_err("invalid glob should be rejected");
+        assert!(matches!(err, LoaderError::Invalid(_)));
+        loader
+            .set_indexing_settings(&crate::types::IndexingSettings {
+                ignore_patterns: vec!["raw/".to_string(), "".to_string()],
+                skip_hidden_dirs: true,
+            })
+            .expect("settings should save");
+        let settings = loader
+            .get_indexing_settings()
+            .expect("settings should load");
+        assert_eq!(settings.ignore_patterns, vec!["raw/".to_string()]);
+        assert!(settings.skip_hidden_dirs);
+
+        let job = IndexingJob::detached();
+        let mut lines = Vec::new();
+        let (folder_id, _, _) = loader
+            .set_current_folder_and_index_with_progress(lib.to_str().unwrap(), &job, |line| {
+                lines.push(line)
+            })
+            .expect("folder should index");
+        assert!(lines.iter().any(|line| line == "scan:done total=4 ignored=3"));
+        assert_eq!(job.summary(IndexingStatus::Completed, None).ignored, 3);
 
-        let err = block_on(loader.get_force_random_image(true))
-            .expect_err("force random should fail when all images are hidden")
-            .to_string();
-        assert!(err.contains("all images for this folder are hidden in random mode"));
+        let canonical_lib = std::fs::canonicalize(&lib).expect("lib should canonicalize");
+        let mut indexed: Vec<String> = loader
+            .db
+            .conn()
+            .prepare("SELECT path FROM images WHERE folder_id = ?1")
+            .expect("query should prepare")
+            .query_map(params![folder_id], |row| row.get::<_, String>(0))
+            .expect("query should run")
+            .map(|path| {
+                let path = PathBuf::from(path.expect("row should read"));
+                path.strip_prefix(&canonical_lib)
+                    .expect("image should live under the root")
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect();
+        indexed.sort();
+        assert_eq!(indexed, vec![".keep/k.jpg", "a.jpg", "sub/keep.png", "sub/y.jpg"]);
 
         drop(loader);
         std::fs::remove_dir_all(root).expect("temp root should be removable");
     }
 
+    #[cfg(unix)]
     #[test]
-    fn reset_random_history_clears_history_but_preserves_hidden_random_blacklist() {
-        let (loader, root) = setup_loader_with_images("reset_preserves_hidden", 4);
-        block_on(loader.get_force_random_image(true)).expect("initial random image should load");
-        block_on(loader.get_force_random_image(true)).expect("second random image should load");
-
-        let (history, _) = loader
-            .get_random_history()
-            .expect("random history should be readable");
-        assert!(!history.is_empty());
-        let hidden_image_id = history[0].image_id;
+    fn following_symlinks_is_opt_in_loop_safe_and_deduplicated() {
+        use std::os::unix::fs::symlink;
+
+        let (loader, root) = setup_loader_with_images("follow_symlinks", 1);
+        let lib = root.join("lib");
+        let outside = root.join("outside");
+        write_test_image(&lib.join("photos").join("a.jpg"), 0);
+        write_test_image(&outside.join("nas").join("b.jpg"), 1);
+        symlink("photos", lib.join("alias")).expect("alias link should be created");
+        symlink("..", lib.join("photos").join("up")).expect("loop link should be created");
+        symlink("../outside/nas", lib.join("nas")).expect("nas link should be created");
+        symlink("../../lib", outside.join("nas").join("back"))
+            .expect("back link should be created");
+
+        let (folder_id, canonical_lib, _) = loader
+            .set_current_folder_and_index(lib.to_str().unwrap())
+            .expect("folder should index");
+        let indexed_paths = |loader: &ImageLoader| {
+            let mut paths: Vec<String> = loader
+                .db
+                .conn()
+                .prepare("SELECT path FROM images WHERE folder_id = ?1")
+                .expect("query should prepare")
+                .query_map(params![folder_id], |row| row.get::<_, String>(0))
+                .expect("query should run")
+                .map(|path| {
+                    let path = PathBuf::from(path.expect("row should read"));
+                    path.strip_prefix(&canonical_lib)
+                        .expect("image should live under the root")
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect();
+            paths.sort();
+            paths
+        };
+        assert_eq!(indexed_paths(&loader), vec!["photos/a.jpg"]);
+        assert!(!loader
+            .get_folder_follow_symlinks(folder_id)
+            .expect("option should read"));
 
         loader
-            .hide_random_history_image(hidden_image_id)
-            .expect("hiding random image should succeed");
+            .set_folder_follow_symlinks(folder_id, true)
+            .expect("option should save");
+        let mut lines = Vec::new();
         loader
-            .reset_random_history()
-            .expect("reset random history should succeed");
+            .reindex_current_folder_with_progress(&IndexingJob::detached(), |line| {
+                lines.push(line)
+            })
+            .expect("reindex should follow links");
+        assert_eq!(indexed_paths(&loader), vec!["nas/b.jpg", "photos/a.jpg"]);
+        assert!(lines
+            .iter()
+            .any(|line| line == "scan:symlinks duplicates=1 loops=3"));
 
-        let (history_after, current_after) = loader
-            .get_random_history()
-            .expect("random history should be readable after reset");
-        assert!(history_after.is_empty());
-        assert_eq!(current_after, -1);
+        let nas_node = format!("{}/nas", canonical_lib);
+        let tree = loader.get_folder_tree().expect("tree should load");
+        assert!(tree
+            .iter()
+            .any(|node| node.path == nas_node
+                && node.parent_path.as_deref() == Some(canonical_lib.as_str())
+                && node.image_count == 1));
 
-        let folder_id = loader
-            .get_current_folder_id()
-            .expect("folder lookup should succeed")
-            .expect("current folder should exist");
-        let hidden_count: i64 = loader
+        let folders_before = loader.get_folder_history().expect("history should load").len();
+        let linked = format!("{}/b.jpg", nas_node);
+        loader
+            .set_folder_checked(&canonical_lib, true)
+            .expect("library root should be checkable");
+        let (data, _) = loader
+            .open_image_by_path(&linked, false)
+            .expect("image under a followed link should open");
+        assert_eq!(data, vec![1, 2, 3]);
+        assert_eq!(
+            loader.get_folder_history().expect("history should load").len(),
+            folders_before
+        );
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn nested_roots_merge_into_one_tree_and_keep_checked_folders() {
+        let (loader, root) = setup_loader_with_images("nested_roots", 2);
+        let images_root = std::fs::canonicalize(root.join("images"))
+            .expect("images root should canonicalize")
+            .to_string_lossy()
+            .into_owned();
+        let refs = root.join("refs");
+        write_test_image(&refs.join("a.jpg"), 0);
+        write_test_image(&refs.join("hands").join("h1.jpg"), 1);
+        write_test_image(&refs.join("hands").join("left").join("h2.jpg"), 2);
+        write_test_image(&refs.join("feet").join("f.jpg"), 3);
+
+        let (hands_id, hands_path, _) = loader
+            .set_current_folder_and_index(refs.join("hands").to_str().unwrap())
+            .expect("hands should index");
+        let left_path = format!("{}/left", hands_path);
+        loader
+            .set_folder_exclusive(&left_path)
+            .expect("left should be checkable");
+        loader
+            .set_folder_checked(&images_root, true)
+            .expect("images root should be checkable");
+        let image_id_of = |loader: &ImageLoader, path: &str| -> (i64, i64) {
+            loader
+                .db
+                .conn()
+                .query_row(
+                    "SELECT id, folder_id FROM images WHERE path = ?1",
+                    params![path],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .expect("image should be indexed exactly once")
+        };
+        let h2 = format!("{}/h2.jpg", left_path);
+        let (h2_id, h2_folder) = image_id_of(&loader, &h2);
+        assert_eq!(h2_folder, hands_id);
+
+        let (refs_id, refs_path, _) = loader
+            .set_current_folder_and_index(refs.to_str().unwrap())
+            .expect("refs should index and absorb hands");
+        assert_ne!(refs_id, hands_id);
+        assert_eq!(image_id_of(&loader, &h2), (h2_id, refs_id));
+        let folder_paths: Vec<String> = loader
+            .get_folder_history()
+            .expect("history should load")
+            .into_iter()
+            .map(|(_, path, _, _)| path)
+            .collect();
+        assert!(folder_paths.contains(&refs_path));
+        assert!(!folder_paths.contains(&hands_path));
+        let duplicates: i64 = loader
             .db
             .conn()
             .query_row(
-                "SELECT COUNT(*) FROM hidden_random_images WHERE folder_id = ?1",
-                params![folder_id],
+                "SELECT COUNT(*) FROM (SELECT path FROM images GROUP BY path HAVING COUNT(*) > 1)",
+                [],
                 |row| row.get(0),
             )
-            .expect("hidden random count query should work");
-        assert_eq!(hidden_count, 1);
+            .expect("duplicate count should work");
+        assert_eq!(duplicates, 0);
+        let tree = loader.get_folder_tree().expect("tree should load");
+        let node = |path: &str| tree.iter().find(|node| node.path == path).cloned();
+        let hands = node(&hands_path).expect("hands node should exist");
+        let (hands_parent, hands_count) = (hands.parent_path, hands.image_count);
+        assert_eq!(hands_parent.as_deref(), Some(refs_path.as_str()));
+        assert_eq!(hands_count, 2);
+        assert!(node(&left_path).expect("left node should exist").checked);
+
+        let feet_path = format!("{}/feet", refs_path);
+        let folders_before = folder_paths.len();
+        let (picked_id, picked_path, _) = loader
+            .set_current_folder_and_index(&feet_path)
+            .expect("picking a subfolder should reuse its root");
+        assert_eq!((picked_id, picked_path.as_str()), (refs_id, refs_path.as_str()));
+        assert_eq!(
+            loader.get_folder_history().expect("history should load").len(),
+            folders_before
+        );
+        let tree = loader.get_folder_tree().expect("tree should load");
+        assert!(tree.iter().any(|node| node.path == feet_path && node.checked));
+
+        loader
+            .delete_folder_by_id(refs_id)
+            .expect("refs should be deletable");
+        let checked: Vec<String> = loader
+            .db
+            .conn()
+            .prepare("SELECT path FROM checked_folders")
+            .expect("query should prepare")
+            .query_map([], |row| row.get::<_, String>(0))
+            .expect("query should run")
+            .collect::<Result<_, _>>()
+            .expect("rows should read");
+        assert_eq!(checked, vec![images_root]);
 
         drop(loader);
         std::fs::remove_dir_all(root).expect("temp root should be removable");
     }
 
     #[test]
-    fn hide_random_history_image_keeps_pointer_on_a_visible_entry() {
-        let (loader, root) = setup_loader_with_images("hide_pointer", 5);
-        block_on(loader.get_force_random_image(true)).expect("random image load should work");
-        block_on(loader.get_force_random_image(true)).expect("random image load should work");
-        block_on(loader.get_force_random_image(true)).expect("random image load should work");
+    fn missing_roots_go_offline_and_can_be_relocated_with_their_history() {
+        let (loader, root) = setup_loader_with_images("relocate", 4);
+        let old_root = std::fs::canonicalize(root.join("images"))
+            .expect("images root should canonicalize")
+            .to_string_lossy()
+            .into_owned();
+        let folder_id = loader
+            .get_current_folder_id()
+            .expect("folder lookup should succeed")
+            .expect("a folder should be current");
+        loader.get_next_random_image().expect("random pick should work");
+        loader.get_next_random_image().expect("random pick should work");
+        let (random_before, _) = loader.get_random_history().expect("history should load");
+        loader
+            .hide_normal_history_image(random_before[0].image_id)
+            .expect("hide should work");
+        let offline = |loader: &ImageLoader| -> bool {
+            loader
+                .db
+                .conn()
+                .query_row(
+                    "SELECT offline FROM folders WHERE id = ?1",
+                    params![folder_id],
+                    |row| row.get::<_, i64>(0),
+                )
+                .expect("folder should still exist")
+                != 0
+        };
 
-        let (before, before_index) = loader
-            .get_random_history()
-            .expect("random history should be readable");
-        assert!(before.len() >= 2);
-        assert!(before_index >= 0);
-        let current_image_id = before[before_index as usize].image_id;
+        std::fs::rename(root.join("images"), root.join("moved")).expect("folder should move");
+        let err = loader
+            .get_next_image()
+            .expect_err("a missing root cannot be browsed");
+        assert!(matches!(err, LoaderError::AllFoldersMissing));
+        assert!(offline(&loader));
+        assert_eq!(
+            loader.get_folder_history().expect("history should load").len(),
+            1
+        );
 
+        std::fs::create_dir_all(root.join("empty")).expect("empty dir should be created");
+        let err = loader
+            .relocate_folder(&old_root, root.join("empty").to_str().unwrap())
+            .expect_err("a location without the images should be rejected");
+        assert!(matches!(err, LoaderError::Invalid(_)));
+
+        let (relocated_id, new_root) = loader
+            .relocate_folder(&old_root, root.join("moved").to_str().unwrap())
+            .expect("relocation should succeed");
+        assert_eq!(relocated_id, folder_id);
+        assert!(!offline(&loader));
+        let (random_after, _) = loader.get_random_history().expect("history should load");
+        assert_eq!(random_after.len(), random_before.len());
+        for (before, after) in random_before.iter().zip(&random_after) {
+            assert_eq!(after.image_id, before.image_id);
+            assert_eq!(after.path, format!("{}{}", new_root, &before.path[old_root.len()..]));
+        }
+        let hidden: i64 = loader
+            .db
+            .conn()
+            .query_row("SELECT COUNT(*) FROM hidden_normal_images", [], |row| row.get(0))
+            .expect("hidden count should work");
+        assert_eq!(hidden, 1);
+        let tree = loader.get_folder_tree().expect("tree should load");
+        assert!(tree.iter().any(|node| node.path == new_root
+            && node.parent_path.is_none()
+            && node.checked));
+        assert!(tree.iter().all(|node| node.path.starts_with(&new_root)));
         loader
-            .hide_random_history_image(current_image_id)
-            .expect("hiding current random image should succeed");
+            .get_next_image()
+            .expect("the relocated folder should be browsable");
 
-        let (after, after_index) = loader
-            .get_random_history()
-            .expect("random history should be readable after hide");
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
 
-        assert!(after.iter().all(|item| item.image_id != current_image_id));
-        assert!(!after.is_empty());
-        assert!(after_index >= 0);
-        assert!((after_index as usize) < after.len());
+    #[test]
+    fn offline_roots_leave_random_selection_and_come_back_intact() {
+        let (loader, root) = setup_loader_with_images("offline_roots", 2);
+        let images_root = std::fs::canonicalize(root.join("images"))
+            .expect("images root should canonicalize")
+            .to_string_lossy()
+            .into_owned();
+        for idx in 0..3 {
+            write_test_image(&root.join("drive").join("lib").join(format!("{idx}.jpg")), idx);
+        }
+        let (lib_id, lib_root, _) = loader
+            .set_current_folder_and_index(root.join("drive").join("lib").to_str().unwrap())
+            .expect("lib should index");
+        loader
+            .set_folder_checked(&images_root, true)
+            .expect("images root should be checkable");
+        loader
+            .set_folder_checked(&lib_root, true)
+            .expect("lib root should be checkable");
+        let active_count = |loader: &ImageLoader| -> i64 {
+            loader
+                .db
+                .conn()
+                .query_row("SELECT COUNT(*) FROM active_images", [], |row| row.get(0))
+                .expect("active count should work")
+        };
+
+        // Unplugging the drive takes the parent directory away too.
+        std::fs::rename(root.join("drive"), root.join("unplugged")).expect("drive should move");
+        for _ in 0..6 {
+            loader
+                .get_force_random_image(true)
+                .expect("online images should still be picked");
+        }
+        assert_eq!(active_count(&loader), 2);
+        // Spaced mode leaves them out too, rather than proposing the
+        // never-shown offline images first and rejecting them one by one.
+        loader
+            .set_random_selection_settings(&crate::types::RandomSelectionSettings {
+                mode: "spaced".to_string(),
+                spaced_randomness: 0.0,
+            })
+            .expect("spaced mode should be settable");
+        let oldest = loader
+            .pick_spaced_candidate(&[], 0.0, 0)
+            .expect("spaced candidate should load")
+            .expect("an online image should be a candidate");
+        assert!(loader
+            .get_image_path(oldest)
+            .expect("path should load")
+            .starts_with(&images_root));
+        for _ in 0..4 {
+            loader
+                .get_force_random_image(true)
+                .expect("online images should still be picked in spaced mode");
+        }
+        let (history, _) = loader.get_random_history().expect("history should load");
+        assert!(history.iter().all(|item| item.path.starts_with(&images_root)));
+        assert_eq!(loader.count_images(lib_id).expect("count should work"), 3);
+        let (online, last_seen_at) = loader
+            .get_folder_availability()
+            .expect("availability should load")
+            .remove(&lib_id)
+            .expect("lib should still be known");
+        assert!(!online);
+        assert!(last_seen_at.is_some());
+        assert!(loader
+            .cleanup_stale_folders()
+            .expect("cleanup should work")
+            .is_empty());
+
+        std::fs::rename(root.join("unplugged"), root.join("drive")).expect("drive should return");
+        loader.get_next_random_image().expect("random pick should work");
+        assert_eq!(active_count(&loader), 5);
+        assert!(loader.get_folder_availability().expect("availability should load")[&lib_id].0);
+
+        std::fs::remove_dir_all(root.join("drive").join("lib")).expect("lib should be deleted");
+        assert_eq!(
+            loader.cleanup_stale_folders().expect("cleanup should work"),
+            vec![lib_root]
+        );
 
         drop(loader);
         std::fs::remove_dir_all(root).expect("temp root should be removable");
     }
 
     #[test]
-    fn delete_folder_cleans_related_rows_and_clears_current_folder_state() {
-        let (loader, root) = setup_loader_with_images("delete_folder_cleanup", 4);
-        block_on(loader.get_force_random_image(true)).expect("random image load should work");
-        block_on(loader.get_force_random_image(true))
-            .expect("second random image load should work");
+    fn image_tags_are_case_insensitive_and_survive_reindexing() {
+        let (loader, root) = setup_loader_with_images("image_tags", 2);
+        let image = std::fs::canonicalize(root.join("images").join("img_0.jpg"))
+            .expect("image should exist")
+            .to_string_lossy()
+            .into_owned();
+        let tags = |raw: &[&str]| raw.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            loader
+                .tag_image_by_path(&image, &tags(&[" Figure ", "gesture"]))
+                .expect("tag should work"),
+            tags(&["Figure", "gesture"])
+        );
+        assert_eq!(
+            loader
+                .tag_image_by_path(&image, &tags(&["figure"]))
+                .expect("tag should work"),
+            tags(&["Figure", "gesture"])
+        );
+        assert!(loader.tag_image_by_path(&image, &tags(&["  "])).is_err());
+        assert_eq!(
+            loader.get_tag_counts().expect("tags should load"),
+            vec![("Figure".to_string(), 1), ("gesture".to_string(), 1)]
+        );
 
-        let (random_history, _) = loader
+        loader
+            .reindex_current_folder_with_progress(&IndexingJob::detached(), |_| {})
+            .expect("reindex should work");
+        assert_eq!(
+            loader.get_tagged_image_paths("FIGURE").expect("tagged images should load"),
+            vec![image.clone()]
+        );
+        assert_eq!(
+            loader
+                .untag_image_by_path(&image, &tags(&["FIGURE"]))
+                .expect("untag should work"),
+            tags(&["gesture"])
+        );
+        assert_eq!(
+            loader.get_image_tags(&image).expect("tags should load"),
+            tags(&["gesture"])
+        );
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn library_export_imports_into_a_fresh_database_under_a_new_prefix() {
+        let (source, studio) = setup_loader_with_images("library_export", 4);
+        let studio_images = std::fs::canonicalize(studio.join("images"))
+            .expect("images root should canonicalize")
+            .to_string_lossy()
+            .into_owned();
+        source
+            .set_folder_checked(&studio_images, true)
+            .expect("root should be checkable");
+        for _ in 0..3 {
+            source
+                .get_force_random_image(true)
+                .expect("random pick should work");
+        }
+        let (history, _) = source.get_random_history().expect("history should load");
+        source
+            .hide_random_history_image(history[0].image_id)
+            .expect("hide should work");
+        let tagged = history[1].path.clone();
+        source
+            .tag_image_by_path(&tagged, &["figure".to_string()])
+            .expect("tag should work");
+        let mut image_state = source.get_image_state().expect("state should load");
+        image_state.greyscale = true;
+        source
+            .set_image_state(&image_state)
+            .expect("state should save");
+        let document = source.export_library().expect("export should work");
+        let export_path = studio.join("library.json");
+        crate::library_io::write_document(&export_path, &document)
+            .expect("document should be written");
+        let expected_history: Vec<String> = source
             .get_random_history()
-            .expect("random history should be readable");
-        assert!(!random_history.is_empty());
-        let image_id = random_history[0].image_id;
+            .expect("history should load")
+            .0
+            .into_iter()
+            .map(|item| item.path)
+            .collect();
+        drop(source);
+
+        // The laptop holds the same files under a different root.
+        let laptop = unique_temp_root("img_loader", "library_import");
+        for idx in 0..4u8 {
+            let relative = if idx % 2 == 0 {
+                format!("img_{idx}.jpg")
+            } else {
+                format!("nested/img_{idx}.png")
+            };
+            write_test_image(&laptop.join("art").join(relative), idx);
+        }
+        let laptop_art = std::fs::canonicalize(laptop.join("art"))
+            .expect("laptop root should canonicalize")
+            .to_string_lossy()
+            .into_owned();
+        let target = ImageLoader::new(
+            open_temp_db(&laptop),
+        );
+        let document = crate::library_io::read_document(&export_path)
+            .expect("document should be read back");
+        let mappings = [crate::library_io::PathMapping {
+            from: studio_images.clone(),
+            to: laptop_art.clone(),
+        }];
+        let summary = target
+            .import_library(document, &mappings)
+            .expect("import should work");
+        assert_eq!(summary.folders, 1);
+        assert_eq!(summary.images, 4);
+        assert_eq!(summary.skipped_references, 0);
+
+        let history = target.get_folder_history().expect("folders should load");
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].1, laptop_art);
+        assert!(target.get_image_state().expect("state should load").greyscale);
+        let imported_history: Vec<String> = target
+            .get_random_history()
+            .expect("history should load")
+            .0
+            .into_iter()
+            .map(|item| item.path)
+            .collect();
+        assert_eq!(
+            imported_history,
+            expected_history
+                .iter()
+                .map(|path| crate::library_io::remap_path(&mappings, path))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            target
+                .get_tagged_image_paths("figure")
+                .expect("tagged images should load"),
+            vec![crate::library_io::remap_path(&mappings, &tagged)]
+        );
+        let tree = target.get_folder_tree().expect("tree should load");
+        assert!(tree
+            .iter()
+            .any(|node| node.path == laptop_art && node.checked));
+        target
+            .get_force_random_image(true)
+            .expect("imported library should serve random picks");
+
+        drop(target);
+        std::fs::remove_dir_all(studio).expect("temp root should be removable");
+        std::fs::remove_dir_all(laptop).expect("temp root should be removable");
+    }
 
+    #[test]
+    fn check_integrity_reports_orphans_and_repair_clears_them() {
+        let (loader, root) = setup_loader_with_images("integrity", 4);
+        let images_root = std::fs::canonicalize(root.join("images"))
+            .expect("images root should canonicalize")
+            .to_string_lossy()
+            .into_owned();
         loader
-            .hide_normal_history_image(image_id)
-            .expect("hiding normal history image should work");
+            .set_folder_checked(&images_root, true)
+            .expect("root should be checkable");
         loader
-            .hide_random_history_image(image_id)
-            .expect("hiding random history image should work");
+            .get_force_random_image(true)
+            .expect("random pick should work");
 
-        let folder_id = loader
-            .get_current_folder_id()
-            .expect("folder lookup should succeed")
-            .expect("current folder should be set");
-        loader
-            .delete_folder_by_id(folder_id)
-            .expect("deleting folder should succeed");
+        let clean = loader.check_integrity(false).expect("check should run");
+        assert!(clean.ok, "fresh library should be clean: {:?}", clean);
+        assert!(clean.issues.is_empty());
 
-        let conn = loader.db.conn();
-        let folders_count: i64 = conn
-            .query_row("SELECT COUNT(*) FROM folders", [], |row| row.get(0))
-            .expect("folders count query should work");
-        let images_count: i64 = conn
-            .query_row("SELECT COUNT(*) FROM images", [], |row| row.get(0))
-            .expect("images count query should work");
-        let random_history_count: i64 = conn
-            .query_row("SELECT COUNT(*) FROM random_history", [], |row| row.get(0))
-            .expect("random history count query should work");
-        let current_lap_count: i64 = conn
-            .query_row("SELECT COUNT(*) FROM current_lap", [], |row| row.get(0))
-            .expect("current lap count query should work");
-        let hidden_normal_count: i64 = conn
-            .query_row("SELECT COUNT(*) FROM hidden_normal_images", [], |row| {
-                row.get(0)
-            })
-            .expect("hidden normal count query should work");
-        let hidden_random_count: i64 = conn
-            .query_row("SELECT COUNT(*) FROM hidden_random_images", [], |row| {
-                row.get(0)
-            })
-            .expect("hidden random count query should work");
-        let current_folder_id: Option<i64> = conn
-            .query_row(
-                "SELECT current_folder_id FROM state WHERE id = 1",
-                [],
-                |row| row.get(0),
+        loader
+            .db
+            .conn()
+            .execute_batch(
+                "PRAGMA foreign_keys = OFF;
+                 INSERT INTO random_history_global (order_index, image_id) VALUES (900, 9999);
+                 INSERT INTO current_lap_global (image_id) VALUES (9999);
+                 INSERT INTO checked_folders (path) VALUES ('/gone/folder');
+                 UPDATE active_image_refcounts SET refcount = 7;
+                 UPDATE state SET last_image_id = 9999 WHERE id = 1;
+                 PRAGMA foreign_keys = ON;",
             )
-            .expect("current folder state query should work");
+            .expect("orphans should be planted");
+
+        let report = loader.check_integrity(false).expect("check should run");
+        assert!(!report.ok);
+        assert!(!report.repaired);
+        assert!(report.backup.is_none());
+        assert!(report.sqlite_errors.is_empty());
+        assert!(!report.foreign_key_violations.is_empty());
+        let found = |name: &str| {
+            report
+                .issues
+                .iter()
+                .find(|issue| issue.check == name)
+                .map(|issue| (issue.found, issue.remaining))
+        };
+        assert_eq!(found("random_history_global_orphans"), Some((1, 1)));
+        assert_eq!(found("current_lap_global_orphans"), Some((1, 1)));
+        assert_eq!(found("checked_folders_without_node"), Some((1, 1)));
+        assert_eq!(found("state_dangling_references"), Some((1, 1)));
+        assert_eq!(found("stale_active_images"), Some((4, 4)));
+
+        let repaired = loader.check_integrity(true).expect("repair should run");
+        assert!(repaired.ok, "repair should leave nothing behind: {:?}", repaired);
+        assert!(repaired.repaired);
+        assert!(repaired.backup.is_some());
+        assert!(repaired.foreign_key_violations.is_empty());
+        assert!(repaired
+            .issues
+            .iter()
+            .all(|issue| issue.found > 0 && issue.remaining == 0));
 
-        assert_eq!(folders_count, 0);
-        assert_eq!(images_count, 0);
-        assert_eq!(random_history_count, 0);
-        assert_eq!(current_lap_count, 0);
-        assert_eq!(hidden_normal_count, 0);
-        assert_eq!(hidden_random_count, 0);
-        assert_eq!(current_folder_id, None);
+        let tree = loader.get_folder_tree().expect("tree should load");
+        assert!(tree
+            .iter()
+            .any(|node| node.path == images_root && node.checked));
+        let (history, _) = loader.get_random_history().expect("history should load");
+        assert_eq!(history.len(), 1);
+        loader
+            .get_force_random_image(true)
+            .expect("repaired library should serve random picks");
+        assert!(loader.check_integrity(false).expect("check should run").ok);
+
+        drop(loader);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+
+    #[test]
+    fn folder_history_with_status_reports_current_folder_and_availability() {
+        let (loader, root) = setup_loader_with_images("folder_history_status", 2);
+        let history = loader
+            .get_folder_history_with_status()
+            .expect("folder history should load");
+        assert_eq!(history.current_index, 0);
+        assert_eq!(history.history.len(), 1);
+        assert_eq!(history.history[0].image_count, 2);
+        assert!(history.history[0].online);
+
+        std::fs::rename(root.join("images"), root.join("moved")).expect("folder should move");
+        let history = loader
+            .get_folder_history_with_status()
+            .expect("folder history should load");
+        assert!(!history.history[0].online);
+        assert!(history.history[0].last_seen_at.is_some());
 
-        drop(conn);
         drop(loader);
         std::fs::remove_dir_all(root).expect("temp root should be removable");
     }
@@ -0,0 +1,418 @@
+use crate::error::LoaderError;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Per-directory ignore file, gitignore syntax, honoured during the walk.
+pub const IGNORE_FILE_NAME: &str = ".randompicsignore";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexingPhase {
+    Scan,
+    Insert,
+    Finalize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexingStatus {
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+/// Payload of the `indexing-progress` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexingProgress {
+    #[serde(rename = "jobId")]
+    pub job_id: u64,
+    pub phase: IndexingPhase,
+    pub found: u64,
+    pub inserted: u64,
+    pub ignored: u64,
+    pub total: u64,
+    #[serde(rename = "currentPath")]
+    pub current_path: Option<String>,
+    #[serde(rename = "elapsedMs")]
+    pub elapsed_ms: u64,
+}
+
+/// Payload of the `indexing-summary` event, emitted once per job.
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexingSummary {
+    #[serde(rename = "jobId")]
+    pub job_id: u64,
+    pub status: IndexingStatus,
+    pub found: u64,
+    pub inserted: u64,
+    pub ignored: u64,
+    #[serde(rename = "elapsedMs")]
+    pub elapsed_ms: u64,
+    pub error: Option<String>,
+}
+
+type ProgressSink = Box<dyn Fn(&IndexingProgress) + Send + Sync>;
+
+/// One indexing run. The loader polls `is_cancelled` between directory entries
+/// and insert chunks, and reports typed progress through the optional sink.
+pub struct IndexingJob {
+    id: u64,
+    started: Instant,
+    cancelled: AtomicBool,
+    found: AtomicU64,
+    inserted: AtomicU64,
+    ignored: AtomicU64,
+    total: AtomicU64,
+    sink: Option<ProgressSink>,
+}
+
+impl IndexingJob {
+    fn new(id: u64, sink: Option<ProgressSink>) -> Self {
+        Self {
+            id,
+            started: Instant::now(),
+            cancelled: AtomicBool::new(false),
+            found: AtomicU64::new(0),
+            inserted: AtomicU64::new(0),
+            ignored: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+            sink,
+        }
+    }
+
+    /// A job that is not registered anywhere: it cannot be cancelled and
+    /// reports nothing. Used for indexing triggered implicitly by navigation.
+    pub fn detached() -> Self {
+        Self::new(0, None)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn set_found(&self, found: u64) {
+        self.found.store(found, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_inserted(&self, inserted: u64) {
+        self.inserted.store(inserted, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_ignored(&self, ignored: u64) {
+        self.ignored.store(ignored, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_total(&self, total: u64) {
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    pub(crate) fn report(&self, phase: IndexingPhase, current_path: Option<&str>) {
+        let Some(sink) = &self.sink else {
+            return;
+        };
+        sink(&IndexingProgress {
+            job_id: self.id,
+            phase,
+            found: self.found.load(Ordering::Relaxed),
+            inserted: self.inserted.load(Ordering::Relaxed),
+            ignored: self.ignored.load(Ordering::Relaxed),
+            total: self.total.load(Ordering::Relaxed),
+            current_path: current_path.map(|p| p.to_string()),
+            elapsed_ms: self.elapsed_ms(),
+        });
+    }
+
+    pub fn summary(&self, status: IndexingStatus, error: Option<String>) -> IndexingSummary {
+        IndexingSummary {
+            job_id: self.id,
+            status,
+            found: self.found.load(Ordering::Relaxed),
+            inserted: self.inserted.load(Ordering::Relaxed),
+            ignored: self.ignored.load(Ordering::Relaxed),
+            elapsed_ms: self.elapsed_ms(),
+            error,
+        }
+    }
+}
+
+/// Registry of running jobs so `cancel_indexing` can reach them.
+pub struct IndexingJobs {
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, Arc<IndexingJob>>>,
+}
+
+impl Default for IndexingJobs {
+    fn default() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            active: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl IndexingJobs {
+    pub fn start<S>(&self, sink: S) -> Arc<IndexingJob>
+    where
+        S: Fn(&IndexingProgress) + Send + Sync + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let job = Arc::new(IndexingJob::new(id, Some(Box::new(sink))));
+        if let Ok(mut active) = self.active.lock() {
+            active.insert(id, Arc::clone(&job));
+        }
+        job
+    }
+
+    pub fn finish(&self, job_id: u64) {
+        if let Ok(mut active) = self.active.lock() {
+            active.remove(&job_id);
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.active
+            .lock()
+            .map(|active| !active.is_empty())
+            .unwrap_or(false)
+    }
+
+    /// Cancels one job, or every running job when `job_id` is None.
+    /// Returns how many jobs were signalled.
+    pub fn cancel(&self, job_id: Option<u64>) -> usize {
+        let Ok(active) = self.active.lock() else {
+            return 0;
+        };
+        active
+            .values()
+            .filter(|job| job_id.map_or(true, |id| job.id() == id))
+            .map(|job| job.cancel())
+            .count()
+    }
+}
+
+/// Rejects global patterns that are not valid gitignore globs.
+pub fn validate_ignore_patterns(patterns: &[String]) -> Result<(), LoaderError> {
+    ScanRules::new(Path::new(""), patterns, true, false).map(|_| ())
+}
+
+/// Walk state inherited from the scan root down to one directory; jwalk
+/// clones it into every child directory it reads.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ScanDirState {
+    /// Ignore files found on the way down, innermost last.
+    ignores: Vec<Arc<Gitignore>>,
+    /// Canonical paths of the directories on the way down. Only tracked when
+    /// following symlinks, to spot links that lead back into their own chain.
+    canonical_ancestors: Vec<Arc<Path>>,
+}
+
+pub(crate) type ScanWalkDir = jwalk::WalkDirGeneric<(ScanDirState, ())>;
+type ScanWalkEntry = jwalk::Result<jwalk::DirEntry<(ScanDirState, ())>>;
+
+/// Rules for one scan: the global patterns (rooted at the scanned folder),
+/// every `.randompicsignore` on the path, the dot-directory default, and
+/// whether symlinked directories are descended into. Deeper ignore files win
+/// over shallower ones and over the global list, so a `!pattern` can
+/// re-include something ignored further up, including a hidden directory.
+pub(crate) struct ScanRules {
+    global: Gitignore,
+    skip_hidden_dirs: bool,
+    follow_symlinks: bool,
+    ignored: AtomicU64,
+    symlink_loops: AtomicU64,
+}
+
+impl ScanRules {
+    pub(crate) fn new(
+        root: &Path,
+        patterns: &[String],
+        skip_hidden_dirs: bool,
+        follow_symlinks: bool,
+    ) -> Result<Self, LoaderError> {
+        let mut builder = GitignoreBuilder::new(root);
+        for pattern in patterns {
+            builder.add_line(None, pattern).map_err(|err| {
+                LoaderError::Invalid(format!("invalid ignore pattern '{}': {}", pattern, err))
+            })?;
+        }
+        let global = builder
+            .build()
+            .map_err(|err| LoaderError::Invalid(format!("invalid ignore patterns: {}", err)))?;
+        Ok(Self {
+            global,
+            skip_hidden_dirs,
+            follow_symlinks,
+            ignored: AtomicU64::new(0),
+            symlink_loops: AtomicU64::new(0),
+        })
+    }
+
+    pub(crate) fn follow_symlinks(&self) -> bool {
+        self.follow_symlinks
+    }
+
+    /// Entries dropped so far; ignored directories count once and are not
+    /// descended into.
+    pub(crate) fn ignored_count(&self) -> u64 {
+        self.ignored.load(Ordering::Relaxed)
+    }
+
+    /// Symlinked directories skipped because they point back at one of their
+    /// own ancestors.
+    pub(crate) fn symlink_loop_count(&self) -> u64 {
+        self.symlink_loops.load(Ordering::Relaxed)
+    }
+
+    /// `process_read_dir` callback: picks up the directory's own ignore file,
+    /// marks symlinked directories for descent when following links, then
+    /// drops ignored children before jwalk yields or descends into them.
+    pub(crate) fn filter_children(
+        &self,
+        dir: &Path,
+        state: &mut ScanDirState,
+        children: &mut Vec<ScanWalkEntry>,
+    ) {
+        let has_ignore_file = children
+            .iter()
+            .flatten()
+            .any(|entry| entry.file_name == IGNORE_FILE_NAME && !entry.file_type.is_dir());
+        if has_ignore_file {
+            let mut builder = GitignoreBuilder::new(dir);
+            // Bad lines are skipped; the rest of the file still applies.
+            let _ = builder.add(dir.join(IGNORE_FILE_NAME));
+            if let Ok(matcher) = builder.build() {
+                if !matcher.is_empty() {
+                    state.ignores.push(Arc::new(matcher));
+                }
+            }
+        }
+
+        if self.follow_symlinks {
+            self.follow_symlinked_dirs(dir, state, children);
+        }
+
+        let before = children.len();
+        children.retain(|child| match child {
+            // Followed symlinks have a children path, so they match as directories.
+            Ok(entry) => !self.is_ignored(
+                state,
+                &dir.join(&entry.file_name),
+                entry.read_children_path.is_some(),
+            ),
+            Err(_) => true,
+        });
+        let dropped = (before - children.len()) as u64;
+        if dropped > 0 {
+            self.ignored.fetch_add(dropped, Ordering::Relaxed);
+        }
+    }
+
+    fn follow_symlinked_dirs(
+        &self,
+        dir: &Path,
+        state: &mut ScanDirState,
+        children: &mut [ScanWalkEntry],
+    ) {
+        let Ok(canonical_dir) = std::fs::canonicalize(dir) else {
+            return;
+        };
+        state.canonical_ancestors.push(Arc::from(canonical_dir));
+
+        for entry in children.iter_mut().flatten() {
+            if !entry.file_type.is_symlink() {
+                continue;
+            }
+            let link = dir.join(&entry.file_name);
+            let Ok(target) = std::fs::canonicalize(&link) else {
+                continue;
+            };
+            if !target.is_dir() {
+                continue;
+            }
+            if state
+                .canonical_ancestors
+                .iter()
+                .any(|ancestor| target.as_path() == ancestor.as_ref())
+            {
+                self.symlink_loops.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            // jwalk reads the link path itself, so children keep paths under
+            // the root and the folder tree nests them below the link.
+            entry.read_children_path = Some(Arc::from(link.as_path()));
+        }
+    }
+
+    fn is_ignored(&self, state: &ScanDirState, path: &Path, is_dir: bool) -> bool {
+        let matchers = state
+            .ignores
+            .iter()
+            .rev()
+            .map(|matcher| matcher.as_ref())
+            .chain(std::iter::once(&self.global));
+        for matcher in matchers {
+            match matcher.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        is_dir
+            && self.skip_hidden_dirs
+            && path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with('.'))
+    }
+}
+
+/// Keeps one path per canonical file. A path that is already canonical wins,
+/// then the lexicographically smallest one, so repeated scans pick the same
+/// path. Paths that cannot be resolved are kept as they are.
+/// Returns the kept paths, sorted, and how many duplicates were dropped.
+pub(crate) fn dedup_by_canonical_path(paths: Vec<String>) -> (Vec<String>, u64) {
+    let total = paths.len();
+    let mut best: HashMap<PathBuf, String> = HashMap::with_capacity(total);
+    let mut unresolved = Vec::new();
+    for path in paths {
+        let Ok(canonical) = std::fs::canonicalize(&path) else {
+            unresolved.push(path);
+            continue;
+        };
+        let is_canonical = canonical.as_path() == Path::new(&path);
+        match best.get(&canonical) {
+            Some(current) => {
+                let current_is_canonical = canonical.as_path() == Path::new(current);
+                let better = (!is_canonical, &path) < (!current_is_canonical, current);
+                if better {
+                    best.insert(canonical, path);
+                }
+            }
+            None => {
+                best.insert(canonical, path);
+            }
+        }
+    }
+    let mut kept: Vec<String> = best.into_values().chain(unresolved).collect();
+    kept.sort();
+    let dropped = (total - kept.len()) as u64;
+    (kept, dropped)
+}
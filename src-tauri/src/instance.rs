@@ -0,0 +1,271 @@
+use crate::error::LoaderError;
+use crate::profiles::write_json_atomically;
+use crate::remote::generate_token;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const INSTANCE_FILE_NAME: &str = "instance.json";
+/// How long a second launch waits on the running instance before deciding
+/// there is none.
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(2);
+const ACK: &str = "ok";
+
+/// What a launch asked for on the command line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", content = "path", rename_all = "camelCase")]
+pub enum LaunchAction {
+    OpenFolder(String),
+    OpenImage(String),
+    Next,
+    Prev,
+    Pause,
+    Resume,
+}
+
+/// Parses `random-pics [PATH] [--next | --prev | --pause | --resume]`.
+/// Paths are made absolute here, because the running instance has its own
+/// working directory.
+pub fn parse_launch_args<I>(args: I) -> Result<Vec<LaunchAction>, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut actions = Vec::new();
+    for arg in args {
+        let action = match arg.as_str() {
+            "--next" => LaunchAction::Next,
+            "--prev" => LaunchAction::Prev,
+            "--pause" => LaunchAction::Pause,
+            "--resume" => LaunchAction::Resume,
+            flag if flag.starts_with("--") => return Err(format!("unknown option: {}", flag)),
+            path => {
+                let canonical = std::fs::canonicalize(path)
+                    .map_err(|err| format!("cannot open {}: {}", path, err))?;
+                let path = canonical.to_string_lossy().into_owned();
+                if canonical.is_dir() {
+                    LaunchAction::OpenFolder(path)
+                } else {
+                    LaunchAction::OpenImage(path)
+                }
+            }
+        };
+        actions.push(action);
+    }
+    Ok(actions)
+}
+
+/// Where the running instance can be reached.
+#[derive(Debug, Serialize, Deserialize)]
+struct InstanceFile {
+    port: u16,
+    token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ForwardRequest {
+    token: String,
+    actions: Vec<LaunchAction>,
+}
+
+pub enum Launch {
+    /// Another instance is running and took the actions.
+    Forwarded,
+    /// This process is the instance; actions forwarded by later launches
+    /// arrive on the receiver.
+    Primary(PrimaryInstance, Receiver<Vec<LaunchAction>>),
+}
+
+/// Hands `actions` to the running instance when there is one, and otherwise
+/// starts listening for later launches. Instances are per data directory.
+pub fn claim(data_dir: &Path, actions: &[LaunchAction]) -> Result<Launch, LoaderError> {
+    let file = data_dir.join(INSTANCE_FILE_NAME);
+    if let Some(running) = read_instance_file(&file) {
+        match forward(&running, actions) {
+            Ok(()) => return Ok(Launch::Forwarded),
+            // Left behind by an instance that did not exit cleanly.
+            Err(err) => eprintln!("[RUST] instance: not forwarding: {}", err),
+        }
+    }
+    let (instance, receiver) = PrimaryInstance::listen(file)?;
+    Ok(Launch::Primary(instance, receiver))
+}
+
+fn read_instance_file(file: &Path) -> Option<InstanceFile> {
+    let bytes = std::fs::read(file).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+fn forward(running: &InstanceFile, actions: &[LaunchAction]) -> std::io::Result<()> {
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, running.port));
+    let mut stream = TcpStream::connect_timeout(&address, FORWARD_TIMEOUT)?;
+    stream.set_read_timeout(Some(FORWARD_TIMEOUT))?;
+    let request = ForwardRequest {
+        token: running.token.clone(),
+        actions: actions.to_vec(),
+    };
+    let mut line = serde_json::to_string(&request).map_err(std::io::Error::other)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+
+    // Whatever else listens on a stale port will not acknowledge.
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    if reply.trim() == ACK {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(
+            "no acknowledgement from the instance",
+        ))
+    }
+}
+
+/// The running instance's end of the socket. Dropping it stops listening
+/// and removes `instance.json`.
+pub struct PrimaryInstance {
+    file: PathBuf,
+    token: String,
+    address: SocketAddr,
+    stopping: Arc<AtomicBool>,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+impl PrimaryInstance {
+    fn listen(file: PathBuf) -> Result<(Self, Receiver<Vec<LaunchAction>>), LoaderError> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let address = listener.local_addr()?;
+        let token = generate_token();
+        write_json_atomically(
+            &file,
+            &InstanceFile {
+                port: address.port(),
+                token: token.clone(),
+            },
+        )?;
+
+        let (sender, receiver) = mpsc::channel();
+        let stopping = Arc::new(AtomicBool::new(false));
+        let accept_thread = {
+            let token = token.clone();
+            let stopping = Arc::clone(&stopping);
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stopping.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        if let Err(err) = receive(stream, &token, &sender) {
+                            eprintln!("[RUST] instance: ignoring a forwarded launch: {}", err);
+                        }
+                    }
+                }
+            })
+        };
+
+        Ok((
+            Self {
+                file,
+                token,
+                address,
+                stopping,
+                accept_thread: Some(accept_thread),
+            },
+            receiver,
+        ))
+    }
+}
+
+impl Drop for PrimaryInstance {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        // Wake the accept loop so it sees the flag.
+        let _ = TcpStream::connect_timeout(&self.address, FORWARD_TIMEOUT);
+        if let Some(thread) = self.accept_thread.take() {
+            let _ = thread.join();
+        }
+        // A newer instance may have taken over the file.
+        if read_instance_file(&self.file).is_some_and(|file| file.token == self.token) {
+            let _ = std::fs::remove_file(&self.file);
+        }
+    }
+}
+
+fn receive(
+    stream: TcpStream,
+    token: &str,
+    sender: &Sender<Vec<LaunchAction>>,
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(FORWARD_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let request: ForwardRequest = serde_json::from_str(&line).map_err(std::io::Error::other)?;
+    if request.token != token {
+        return Err(std::io::Error::other("wrong token"));
+    }
+    let _ = sender.send(request.actions);
+    let mut stream = reader.into_inner();
+    stream.write_all(format!("{}\n", ACK).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{claim, parse_launch_args, Launch, LaunchAction, INSTANCE_FILE_NAME};
+    use crate::test_support::unique_temp_root;
+    use std::time::Duration;
+
+    #[test]
+    fn second_launch_forwards_its_actions_to_the_first() {
+        let root = unique_temp_root("instance", "forward");
+        let folder = root.join("pictures");
+        std::fs::create_dir_all(&folder).unwrap();
+        let actions = parse_launch_args(vec![
+            folder.to_string_lossy().into_owned(),
+            "--next".to_string(),
+        ])
+        .expect("arguments should parse");
+        assert_eq!(
+            actions,
+            vec![
+                LaunchAction::OpenFolder(
+                    std::fs::canonicalize(&folder)
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned()
+                ),
+                LaunchAction::Next,
+            ]
+        );
+        assert!(parse_launch_args(vec!["--rewind".to_string()]).is_err());
+        assert!(
+            parse_launch_args(vec![root.join("missing").to_string_lossy().into_owned()]).is_err()
+        );
+
+        let Launch::Primary(primary, forwarded) = claim(&root, &[]).unwrap() else {
+            panic!("the first launch should become the instance");
+        };
+        assert!(matches!(claim(&root, &actions).unwrap(), Launch::Forwarded));
+        assert_eq!(
+            forwarded
+                .recv_timeout(Duration::from_secs(5))
+                .expect("the actions should arrive"),
+            actions
+        );
+
+        drop(primary);
+        assert!(!root.join(INSTANCE_FILE_NAME).exists());
+        // A stale file does not stop the next launch from taking over.
+        std::fs::write(root.join(INSTANCE_FILE_NAME), r#"{"port":9,"token":"x"}"#).unwrap();
+        let Launch::Primary(primary, _) = claim(&root, &[]).unwrap() else {
+            panic!("a stale instance file should be ignored");
+        };
+        drop(primary);
+
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+}
@@ -0,0 +1,247 @@
+use crate::types::ForeignKeyViolation;
+use crate::error::LoaderError;
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+
+/// How a failed invariant is put right.
+#[derive(Debug, Clone, Copy)]
+pub enum Repair {
+    /// Statements run in order inside one transaction.
+    Sql(&'static [&'static str]),
+    /// Rebuild the folder tree of every root that has images but no root node.
+    RebuildFolderNodes,
+    /// Recompute `active_images` and `active_image_refcounts` from the
+    /// checked scope.
+    RebuildActiveImages,
+}
+
+/// An app-level invariant that SQLite itself does not enforce, or that older
+/// builds could leave broken. `count_sql` returns the number of offending
+/// rows; zero means the invariant holds.
+#[derive(Debug, Clone, Copy)]
+pub struct InvariantCheck {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub count_sql: &'static str,
+    pub repair: Repair,
+}
+
+/// Checks run in this order and so do their repairs: removing orphaned
+/// images first turns their history and hides into orphans that the later
+/// checks clean up, and the active set is rebuilt last from whatever is left.
+pub const INVARIANT_CHECKS: &[InvariantCheck] = &[
+    InvariantCheck {
+        name: "images_without_folder",
+        description: "images whose root folder no longer exists",
+        count_sql: "SELECT COUNT(*) FROM images
+                    WHERE folder_id IS NULL OR folder_id NOT IN (SELECT id FROM folders)",
+        repair: Repair::Sql(&[
+            "DELETE FROM random_history WHERE image_id IN (SELECT id FROM images WHERE folder_id IS NULL OR folder_id NOT IN (SELECT id FROM folders))",
+            "DELETE FROM random_history_global WHERE image_id IN (SELECT id FROM images WHERE folder_id IS NULL OR folder_id NOT IN (SELECT id FROM folders))",
+            "DELETE FROM current_lap WHERE image_id IN (SELECT id FROM images WHERE folder_id IS NULL OR folder_id NOT IN (SELECT id FROM folders))",
+            "DELETE FROM current_lap_global WHERE image_id IN (SELECT id FROM images WHERE folder_id IS NULL OR folder_id NOT IN (SELECT id FROM folders))",
+            "DELETE FROM hidden_normal_images WHERE image_id IN (SELECT id FROM images WHERE folder_id IS NULL OR folder_id NOT IN (SELECT id FROM folders))",
+            "DELETE FROM hidden_random_images WHERE image_id IN (SELECT id FROM images WHERE folder_id IS NULL OR folder_id NOT IN (SELECT id FROM folders))",
+            "DELETE FROM folder_images_direct WHERE image_id IN (SELECT id FROM images WHERE folder_id IS NULL OR folder_id NOT IN (SELECT id FROM folders))",
+            "DELETE FROM active_images WHERE image_id IN (SELECT id FROM images WHERE folder_id IS NULL OR folder_id NOT IN (SELECT id FROM folders))",
+            "DELETE FROM active_image_refcounts WHERE image_id IN (SELECT id FROM images WHERE folder_id IS NULL OR folder_id NOT IN (SELECT id FROM folders))",
+            "DELETE FROM images WHERE folder_id IS NULL OR folder_id NOT IN (SELECT id FROM folders)",
+        ]),
+    },
+    InvariantCheck {
+        name: "folder_nodes_without_root",
+        description: "folder tree nodes whose root folder no longer exists",
+        count_sql: "SELECT COUNT(*) FROM folder_nodes
+                    WHERE root_folder_id NOT IN (SELECT id FROM folders)",
+        repair: Repair::Sql(&[
+            "DELETE FROM folder_closure
+             WHERE ancestor_path IN (SELECT path FROM folder_nodes WHERE root_folder_id NOT IN (SELECT id FROM folders))
+                OR descendant_path IN (SELECT path FROM folder_nodes WHERE root_folder_id NOT IN (SELECT id FROM folders))",
+            "DELETE FROM folder_images_direct
+             WHERE folder_path IN (SELECT path FROM folder_nodes WHERE root_folder_id NOT IN (SELECT id FROM folders))",
+            "DELETE FROM checked_folders
+             WHERE path IN (SELECT path FROM folder_nodes WHERE root_folder_id NOT IN (SELECT id FROM folders))",
+            "DELETE FROM folder_nodes WHERE root_folder_id NOT IN (SELECT id FROM folders)",
+        ]),
+    },
+    InvariantCheck {
+        name: "folder_closure_orphans",
+        description: "folder tree links to nodes that no longer exist",
+        count_sql: "SELECT COUNT(*) FROM folder_closure
+                    WHERE ancestor_path NOT IN (SELECT path FROM folder_nodes)
+                       OR descendant_path NOT IN (SELECT path FROM folder_nodes)",
+        repair: Repair::Sql(&["DELETE FROM folder_closure
+             WHERE ancestor_path NOT IN (SELECT path FROM folder_nodes)
+                OR descendant_path NOT IN (SELECT path FROM folder_nodes)"]),
+    },
+    InvariantCheck {
+        name: "folder_images_direct_orphans",
+        description: "folder tree image links to missing folders or images",
+        count_sql: "SELECT COUNT(*) FROM folder_images_direct
+                    WHERE folder_path NOT IN (SELECT path FROM folder_nodes)
+                       OR image_id NOT IN (SELECT id FROM images)",
+        repair: Repair::Sql(&["DELETE FROM folder_images_direct
+             WHERE folder_path NOT IN (SELECT path FROM folder_nodes)
+                OR image_id NOT IN (SELECT id FROM images)"]),
+    },
+    InvariantCheck {
+        name: "checked_folders_without_node",
+        description: "checked folders missing from the folder tree",
+        count_sql: "SELECT COUNT(*) FROM checked_folders
+                    WHERE path NOT IN (SELECT path FROM folder_nodes)",
+        repair: Repair::Sql(&[
+            "DELETE FROM checked_folders WHERE path NOT IN (SELECT path FROM folder_nodes)",
+        ]),
+    },
+    InvariantCheck {
+        name: "random_history_orphans",
+        description: "folder random history entries pointing at deleted images or folders",
+        count_sql: "SELECT COUNT(*) FROM random_history
+                    WHERE image_id NOT IN (SELECT id FROM images)
+                       OR folder_id NOT IN (SELECT id FROM folders)",
+        repair: Repair::Sql(&["DELETE FROM random_history
+             WHERE image_id NOT IN (SELECT id FROM images)
+                OR folder_id NOT IN (SELECT id FROM folders)"]),
+    },
+    InvariantCheck {
+        name: "random_history_global_orphans",
+        description: "random history entries pointing at deleted images",
+        count_sql: "SELECT COUNT(*) FROM random_history_global
+                    WHERE image_id NOT IN (SELECT id FROM images)",
+        repair: Repair::Sql(&[
+            "DELETE FROM random_history_global WHERE image_id NOT IN (SELECT id FROM images)",
+        ]),
+    },
+    InvariantCheck {
+        name: "current_lap_orphans",
+        description: "folder lap entries pointing at deleted images or folders",
+        count_sql: "SELECT COUNT(*) FROM current_lap
+                    WHERE image_id NOT IN (SELECT id FROM images)
+                       OR folder_id NOT IN (SELECT id FROM folders)",
+        repair: Repair::Sql(&["DELETE FROM current_lap
+             WHERE image_id NOT IN (SELECT id FROM images)
+                OR folder_id NOT IN (SELECT id FROM folders)"]),
+    },
+    InvariantCheck {
+        name: "current_lap_global_orphans",
+        description: "lap entries pointing at deleted images",
+        count_sql: "SELECT COUNT(*) FROM current_lap_global
+                    WHERE image_id NOT IN (SELECT id FROM images)",
+        repair: Repair::Sql(&[
+            "DELETE FROM current_lap_global WHERE image_id NOT IN (SELECT id FROM images)",
+        ]),
+    },
+    InvariantCheck {
+        name: "hidden_images_orphans",
+        description: "hidden image entries pointing at deleted images or folders",
+        count_sql: "SELECT
+                      (SELECT COUNT(*) FROM hidden_normal_images
+                       WHERE image_id NOT IN (SELECT id FROM images)
+                          OR folder_id NOT IN (SELECT id FROM folders))
+                    + (SELECT COUNT(*) FROM hidden_random_images
+                       WHERE image_id NOT IN (SELECT id FROM images)
+                          OR folder_id NOT IN (SELECT id FROM folders))",
+        repair: Repair::Sql(&[
+            "DELETE FROM hidden_normal_images
+             WHERE image_id NOT IN (SELECT id FROM images)
+                OR folder_id NOT IN (SELECT id FROM folders)",
+            "DELETE FROM hidden_random_images
+             WHERE image_id NOT IN (SELECT id FROM images)
+                OR folder_id NOT IN (SELECT id FROM folders)",
+        ]),
+    },
+    InvariantCheck {
+        name: "state_dangling_references",
+        description: "current folder or last image pointing at deleted rows",
+        count_sql: "SELECT
+                      (SELECT COUNT(*) FROM state
+                       WHERE current_folder_id IS NOT NULL
+                         AND current_folder_id NOT IN (SELECT id FROM folders))
+                    + (SELECT COUNT(*) FROM state
+                       WHERE last_image_id IS NOT NULL
+                         AND last_image_id NOT IN (SELECT id FROM images))",
+        repair: Repair::Sql(&[
+            "UPDATE state SET current_folder_id = NULL, current_index = -1, current_random_index = -1
+             WHERE current_folder_id IS NOT NULL
+               AND current_folder_id NOT IN (SELECT id FROM folders)",
+            "UPDATE state SET last_image_id = NULL
+             WHERE last_image_id IS NOT NULL
+               AND last_image_id NOT IN (SELECT id FROM images)",
+        ]),
+    },
+    InvariantCheck {
+        name: "missing_folder_nodes",
+        description: "indexed folders missing from the folder tree",
+        count_sql: "SELECT COUNT(*) FROM folders f
+                    WHERE EXISTS (SELECT 1 FROM images i WHERE i.folder_id = f.id)
+                      AND NOT EXISTS (SELECT 1 FROM folder_nodes n WHERE n.path = f.path)",
+        repair: Repair::RebuildFolderNodes,
+    },
+    InvariantCheck {
+        name: "stale_active_images",
+        description: "selection pool out of sync with the checked folders",
+        // An empty refcount table is not stale: it is rebuilt lazily on the
+        // next selection.
+        count_sql: "WITH expected AS (
+                        SELECT fi.image_id, COUNT(*) AS refcount
+                        FROM checked_folders cf
+                        JOIN folder_nodes n ON n.path = cf.path
+                        JOIN folders f ON f.id = n.root_folder_id AND f.offline = 0
+                        JOIN folder_closure c ON c.ancestor_path = cf.path
+                        JOIN folder_images_direct fi ON fi.folder_path = c.descendant_path
+                        GROUP BY fi.image_id
+                    )
+                    SELECT CASE WHEN NOT EXISTS (SELECT 1 FROM active_image_refcounts) THEN 0 ELSE
+                      (SELECT COUNT(*) FROM expected e
+                       LEFT JOIN active_image_refcounts r ON r.image_id = e.image_id
+                       WHERE r.refcount IS NOT e.refcount)
+                    + (SELECT COUNT(*) FROM active_image_refcounts
+                       WHERE image_id NOT IN (SELECT image_id FROM expected))
+                    + (SELECT COUNT(*) FROM active_images
+                       WHERE image_id NOT IN (SELECT image_id FROM expected))
+                    + (SELECT COUNT(*) FROM expected
+                       WHERE image_id NOT IN (SELECT image_id FROM active_images))
+                    END",
+        repair: Repair::RebuildActiveImages,
+    },
+];
+
+pub fn count_violations(conn: &Connection, check: &InvariantCheck) -> Result<i64, LoaderError> {
+    Ok(conn.query_row(check.count_sql, [], |row| row.get(0))?)
+}
+
+/// Problems reported by `PRAGMA integrity_check`; empty when the file is
+/// sound. These are storage-level and cannot be repaired in place - restoring
+/// a backup is the way out.
+pub fn sqlite_integrity_errors(conn: &Connection) -> Result<Vec<String>, LoaderError> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let rows = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows.into_iter().filter(|row| row != "ok").collect())
+}
+
+/// Rows from `PRAGMA foreign_key_check`, grouped by child and parent table.
+pub fn foreign_key_violations(conn: &Connection) -> Result<Vec<ForeignKeyViolation>, LoaderError> {
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+    let rows = stmt
+        .query_map([], |row| {
+            let table: String = row.get(0)?;
+            let parent: String = row.get(2)?;
+            Ok((table, parent))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut grouped: BTreeMap<(String, String), i64> = BTreeMap::new();
+    for key in rows {
+        *grouped.entry(key).or_insert(0) += 1;
+    }
+    Ok(grouped
+        .into_iter()
+        .map(|((table, parent), count)| ForeignKeyViolation {
+            table,
+            parent,
+            count,
+        })
+        .collect())
+}
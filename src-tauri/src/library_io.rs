@@ -0,0 +1,273 @@
+use crate::types::{ImageState, IndexingSettings, RandomSelectionSettings};
+use crate::error::LoaderError;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Marks a file as a library export so unrelated JSON is rejected early.
+pub const LIBRARY_FORMAT: &str = "random-pics-library";
+/// Bump when the document layout changes; older documents must stay readable.
+pub const LIBRARY_VERSION: u32 = 2;
+
+/// Portable snapshot of everything the user built up: roots and their
+/// images, the checked scope, hides, histories, laps, view times, tags and
+/// settings. Images are referenced by path rather than database id so the
+/// document can be replayed into any database. Derived tables (folder tree,
+/// closure, active set) and machine-specific availability are left out and
+/// rebuilt on import.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LibraryDocument {
+    pub format: String,
+    pub version: u32,
+    #[serde(rename = "exportedAt")]
+    pub exported_at: String,
+    #[serde(rename = "imageState")]
+    pub image_state: ImageState,
+    #[serde(rename = "randomSelection")]
+    pub random_selection: RandomSelectionSettings,
+    pub indexing: IndexingSettings,
+    #[serde(rename = "currentFolder")]
+    pub current_folder: Option<String>,
+    #[serde(rename = "lastImage")]
+    pub last_image: Option<String>,
+    #[serde(rename = "currentIndex")]
+    pub current_index: i64,
+    #[serde(rename = "currentRandomIndex")]
+    pub current_random_index: i64,
+    pub folders: Vec<LibraryFolder>,
+    #[serde(rename = "checkedFolders")]
+    pub checked_folders: Vec<String>,
+    #[serde(rename = "randomHistory")]
+    pub random_history: Vec<LibraryHistoryEntry>,
+    #[serde(rename = "currentLap")]
+    pub current_lap: Vec<String>,
+    #[serde(rename = "imageViews")]
+    pub image_views: Vec<LibraryImageView>,
+    /// Added in version 2.
+    #[serde(rename = "imageTags", default)]
+    pub image_tags: Vec<LibraryImageTag>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryFolder {
+    pub path: String,
+    #[serde(rename = "addedAt")]
+    pub added_at: String,
+    #[serde(rename = "currentIndex")]
+    pub current_index: i64,
+    #[serde(rename = "currentRandomIndex")]
+    pub current_random_index: i64,
+    #[serde(rename = "sortOrder")]
+    pub sort_order: String,
+    #[serde(rename = "shuffleSeed")]
+    pub shuffle_seed: i64,
+    #[serde(rename = "followSymlinks")]
+    pub follow_symlinks: bool,
+    pub images: Vec<LibraryImage>,
+    #[serde(rename = "hiddenNormal")]
+    pub hidden_normal: Vec<String>,
+    #[serde(rename = "hiddenRandom")]
+    pub hidden_random: Vec<String>,
+    #[serde(rename = "randomHistory")]
+    pub random_history: Vec<LibraryHistoryEntry>,
+    #[serde(rename = "currentLap")]
+    pub current_lap: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryImage {
+    pub path: String,
+    pub mtime: Option<i64>,
+    #[serde(rename = "fileSize")]
+    pub file_size: Option<i64>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryHistoryEntry {
+    #[serde(rename = "orderIndex")]
+    pub order_index: i64,
+    pub image: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryImageView {
+    pub path: String,
+    #[serde(rename = "lastShownAt")]
+    pub last_shown_at: i64,
+    #[serde(rename = "showCount")]
+    pub show_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryImageTag {
+    pub path: String,
+    pub tag: String,
+}
+
+/// Rewrites paths under `from` to live under `to`, e.g. `D:\Art` to
+/// `/mnt/art`. Only whole path components match, so `/art` does not catch
+/// `/artwork`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathMapping {
+    pub from: String,
+    pub to: String,
+}
+
+fn is_separator(c: char) -> bool {
+    c == '/' || c == '\\'
+}
+
+/// Applies the first matching mapping. The remainder keeps its separators
+/// except that they are switched to the style of `to`, so a Windows export
+/// lands as ordinary paths on Linux and macOS and vice versa.
+pub fn remap_path(mappings: &[PathMapping], path: &str) -> String {
+    for mapping in mappings {
+        let from = mapping.from.trim_end_matches(is_separator);
+        if from.is_empty() {
+            continue;
+        }
+        let Some(rest) = path.strip_prefix(from) else {
+            continue;
+        };
+        if !rest.is_empty() && !rest.starts_with(is_separator) {
+            continue;
+        }
+        let to = mapping.to.trim_end_matches(is_separator);
+        let separator = if to.contains('\\') && !to.contains('/') {
+            '\\'
+        } else {
+            '/'
+        };
+        let rest: String = rest
+            .chars()
+            .map(|c| if is_separator(c) { separator } else { c })
+            .collect();
+        return format!("{}{}", to, rest);
+    }
+    path.to_string()
+}
+
+impl LibraryDocument {
+    pub fn remap_paths(&mut self, mappings: &[PathMapping]) {
+        if mappings.is_empty() {
+            return;
+        }
+        let remap = |path: &mut String| *path = remap_path(mappings, path);
+        let remap_history = |entries: &mut Vec<LibraryHistoryEntry>| {
+            entries.iter_mut().for_each(|entry| remap(&mut entry.image));
+        };
+
+        self.current_folder.iter_mut().for_each(remap);
+        self.last_image.iter_mut().for_each(remap);
+        self.checked_folders.iter_mut().for_each(remap);
+        remap_history(&mut self.random_history);
+        self.current_lap.iter_mut().for_each(remap);
+        self.image_views
+            .iter_mut()
+            .for_each(|view| remap(&mut view.path));
+        self.image_tags
+            .iter_mut()
+            .for_each(|tag| remap(&mut tag.path));
+        for folder in &mut self.folders {
+            remap(&mut folder.path);
+            folder
+                .images
+                .iter_mut()
+                .for_each(|image| remap(&mut image.path));
+            folder.hidden_normal.iter_mut().for_each(remap);
+            folder.hidden_random.iter_mut().for_each(remap);
+            remap_history(&mut folder.random_history);
+            folder.current_lap.iter_mut().for_each(remap);
+        }
+    }
+
+    /// Rejects documents this build cannot replay faithfully.
+    pub fn validate(&self) -> Result<(), LoaderError> {
+        if self.format != LIBRARY_FORMAT {
+            return Err(LoaderError::Invalid(
+                "not a library export file".to_string(),
+            ));
+        }
+        if self.version == 0 || self.version > LIBRARY_VERSION {
+            return Err(LoaderError::Invalid(format!(
+                "unsupported library export version {} (this build reads up to {})",
+                self.version, LIBRARY_VERSION
+            )));
+        }
+        let mut seen = std::collections::HashSet::new();
+        for folder in &self.folders {
+            if !seen.insert(folder.path.as_str()) {
+                return Err(LoaderError::Invalid(format!(
+                    "folder listed twice: {}",
+                    folder.path
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes through a temporary file so an interrupted export never leaves a
+/// truncated document where a good one used to be.
+pub fn write_document(path: &Path, document: &LibraryDocument) -> Result<(), LoaderError> {
+    let json = serde_json::to_vec_pretty(document)
+        .map_err(|err| LoaderError::Invalid(format!("failed to serialize library: {}", err)))?;
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    std::fs::write(&partial, json)?;
+    std::fs::rename(&partial, path)?;
+    Ok(())
+}
+
+pub fn read_document(path: &Path) -> Result<LibraryDocument, LoaderError> {
+    let bytes = std::fs::read(path)?;
+    let document: LibraryDocument = serde_json::from_slice(&bytes)
+        .map_err(|err| LoaderError::Invalid(format!("invalid library export file: {}", err)))?;
+    document.validate()?;
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{remap_path, PathMapping};
+
+    fn mapping(from: &str, to: &str) -> PathMapping {
+        PathMapping {
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    #[test]
+    fn remap_path_matches_whole_components_only() {
+        let mappings = [mapping("/studio/art/", "/home/me/art")];
+        assert_eq!(remap_path(&mappings, "/studio/art"), "/home/me/art");
+        assert_eq!(
+            remap_path(&mappings, "/studio/art/figures/a.jpg"),
+            "/home/me/art/figures/a.jpg"
+        );
+        assert_eq!(
+            remap_path(&mappings, "/studio/artwork/a.jpg"),
+            "/studio/artwork/a.jpg"
+        );
+    }
+
+    #[test]
+    fn remap_path_switches_separators_to_the_target_style() {
+        let to_unix = [mapping(r"D:\Art", "/mnt/art")];
+        assert_eq!(
+            remap_path(&to_unix, r"D:\Art\poses\b.png"),
+            "/mnt/art/poses/b.png"
+        );
+
+        let to_windows = [mapping("/mnt/art", r"E:\Refs")];
+        assert_eq!(
+            remap_path(&to_windows, "/mnt/art/poses/b.png"),
+            r"E:\Refs\poses\b.png"
+        );
+
+        let first_match_wins = [mapping("/a", "/x"), mapping("/a/b", "/y")];
+        assert_eq!(remap_path(&first_match_wins, "/a/b/c.jpg"), "/x/b/c.jpg");
+    }
+}
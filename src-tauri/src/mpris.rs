@@ -0,0 +1,552 @@
+use crate::error::{ImageMode, LoaderError};
+use crate::remote::{RemoteEvent, RemoteHost, RemoteHub, TimerCommand};
+use crate::types::TimerStatus;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use zbus::blocking::connection::Builder;
+use zbus::blocking::Connection;
+use zbus::fdo;
+use zbus::names::BusName;
+use zbus::zvariant::{ObjectPath, Value};
+
+pub const BUS_NAME: &str = "org.mpris.MediaPlayer2.random_pics";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+/// How often the updater checks whether the player is stopping.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The `org.mpris.MediaPlayer2` root interface.
+struct MediaPlayer {
+    host: Arc<dyn RemoteHost>,
+}
+
+#[zbus::interface(name = "org.mpris.MediaPlayer2")]
+impl MediaPlayer {
+    fn raise(&self) {
+        self.host.raise();
+    }
+
+    /// The window owns the app's lifetime.
+    fn quit(&self) {}
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> String {
+        "Random Pics".to_string()
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// The `org.mpris.MediaPlayer2.Player` interface. An image is a track and
+/// the slideshow timer is playback: its interval is the track length.
+struct Player {
+    hub: Arc<RemoteHub>,
+    host: Arc<dyn RemoteHost>,
+}
+
+impl Player {
+    /// Steps in whichever mode the timer flows in, like the window's
+    /// controls: next in random mode draws a new random image.
+    fn step(&self, forward: bool) -> fdo::Result<()> {
+        let loader = self
+            .host
+            .loader()
+            .ok_or_else(|| fdo::Error::Failed("ImageLoader not initialized".to_string()))?;
+        if loader.indexing_jobs().is_running() {
+            return Err(fdo::Error::Failed("indexing is running".to_string()));
+        }
+        let picked = loader.get_timer_flow_mode().and_then(|mode| {
+            let (data, auto_switched) = match (mode, forward) {
+                (ImageMode::Normal, true) => loader.get_next_image()?,
+                (ImageMode::Normal, false) => loader.get_prev_image()?,
+                (ImageMode::Random, true) => loader.get_force_random_image(true)?,
+                (ImageMode::Random, false) => loader.get_prev_random_image()?,
+            };
+            Ok((mode, data, auto_switched))
+        });
+        let (mode, data, auto_switched) =
+            picked.map_err(|err| fdo::Error::Failed(err.user_message()))?;
+        self.host.image_selected(mode, data, auto_switched);
+        Ok(())
+    }
+}
+
+#[zbus::interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) -> fdo::Result<()> {
+        self.step(true)
+    }
+
+    fn previous(&self) -> fdo::Result<()> {
+        self.step(false)
+    }
+
+    fn pause(&self) {
+        self.host.timer_command(TimerCommand::Pause);
+    }
+
+    fn play(&self) {
+        self.host.timer_command(TimerCommand::Resume);
+    }
+
+    fn play_pause(&self) {
+        let running = self.hub.timer().is_some_and(|timer| timer.running);
+        self.host.timer_command(if running {
+            TimerCommand::Pause
+        } else {
+            TimerCommand::Resume
+        });
+    }
+
+    fn stop(&self) {
+        self.host.timer_command(TimerCommand::Stop);
+    }
+
+    /// Images have no position to seek to; `CanSeek` is false.
+    fn seek(&self, _offset: i64) {}
+
+    fn set_position(&self, _track_id: ObjectPath<'_>, _position: i64) {}
+
+    fn open_uri(&self, _uri: String) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(
+            "open folders from the window".to_string(),
+        ))
+    }
+
+    #[zbus(property)]
+    fn playback_status(&self) -> String {
+        playback_status(self.hub.timer().as_ref()).to_string()
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, Value<'static>> {
+        metadata(&self.hub)
+    }
+
+    #[zbus(property)]
+    fn position(&self) -> i64 {
+        self.hub.timer().map_or(0, |timer| {
+            micros(timer.initial_seconds - timer.remaining_seconds)
+        })
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+/// A paused timer has time used up; a stopped one is back at its interval.
+fn playback_status(timer: Option<&TimerStatus>) -> &'static str {
+    match timer {
+        Some(timer) if timer.running => "Playing",
+        Some(timer) if timer.remaining_seconds != timer.initial_seconds => "Paused",
+        _ => "Stopped",
+    }
+}
+
+fn metadata(hub: &RemoteHub) -> HashMap<String, Value<'static>> {
+    let mut metadata = HashMap::new();
+    let Some(image) = hub.image() else {
+        metadata.insert(
+            "mpris:trackid".to_string(),
+            object_path(NO_TRACK.to_string()),
+        );
+        return metadata;
+    };
+    let path = Path::new(&image.path);
+    let url = file_url(&image.path);
+    metadata.insert(
+        "mpris:trackid".to_string(),
+        object_path(format!("/org/randompics/image/{}", image.image_id)),
+    );
+    metadata.insert("xesam:url".to_string(), Value::from(url.clone()));
+    metadata.insert("mpris:artUrl".to_string(), Value::from(url));
+    if let Some(name) = path.file_name() {
+        metadata.insert(
+            "xesam:title".to_string(),
+            Value::from(name.to_string_lossy().into_owned()),
+        );
+    }
+    if let Some(folder) = path.parent() {
+        metadata.insert(
+            "xesam:album".to_string(),
+            Value::from(folder.to_string_lossy().into_owned()),
+        );
+    }
+    if let Some(timer) = hub.timer() {
+        metadata.insert(
+            "mpris:length".to_string(),
+            Value::from(micros(timer.initial_seconds)),
+        );
+    }
+    metadata
+}
+
+fn object_path(path: String) -> Value<'static> {
+    Value::from(ObjectPath::try_from(path).expect("track ids should be valid object paths"))
+}
+
+fn micros(seconds: i64) -> i64 {
+    seconds.max(0) * 1_000_000
+}
+
+/// `file://` URL for an absolute path, percent-encoding everything but
+/// unreserved characters and separators.
+fn file_url(path: &str) -> String {
+    let mut url = String::from("file://");
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            url.push(byte as char);
+        } else {
+            url.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    url
+}
+
+/// The app on the session bus as an MPRIS media player, so media keys and
+/// desktop widgets drive the slideshow. Dropping it leaves the bus.
+pub struct MprisPlayer {
+    stopping: Arc<AtomicBool>,
+    updater: Option<JoinHandle<()>>,
+}
+
+impl MprisPlayer {
+    pub fn start(hub: Arc<RemoteHub>, host: Arc<dyn RemoteHost>) -> Result<Self, LoaderError> {
+        Self::serve(Builder::session().map_err(bus_error)?, hub, host)
+    }
+
+    /// Joins the bus at `address` instead of the session bus.
+    pub fn start_at(
+        address: &str,
+        hub: Arc<RemoteHub>,
+        host: Arc<dyn RemoteHost>,
+    ) -> Result<Self, LoaderError> {
+        Self::serve(Builder::address(address).map_err(bus_error)?, hub, host)
+    }
+
+    fn serve(
+        builder: Builder<'_>,
+        hub: Arc<RemoteHub>,
+        host: Arc<dyn RemoteHost>,
+    ) -> Result<Self, LoaderError> {
+        let connection = builder
+            .name(BUS_NAME)
+            .and_then(|builder| {
+                builder.serve_at(
+                    OBJECT_PATH,
+                    MediaPlayer {
+                        host: Arc::clone(&host),
+                    },
+                )
+            })
+            .and_then(|builder| {
+                builder.serve_at(
+                    OBJECT_PATH,
+                    Player {
+                        hub: Arc::clone(&hub),
+                        host,
+                    },
+                )
+            })
+            .and_then(|builder| builder.build())
+            .map_err(bus_error)?;
+
+        let stopping = Arc::new(AtomicBool::new(false));
+        let updater = {
+            let stopping = Arc::clone(&stopping);
+            std::thread::spawn(move || announce_changes(&connection, &hub, &stopping))
+        };
+        eprintln!("[RUST] mpris: serving {}", BUS_NAME);
+
+        Ok(Self {
+            stopping,
+            updater: Some(updater),
+        })
+    }
+}
+
+impl Drop for MprisPlayer {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.updater.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Emits `PropertiesChanged` for the player as the hub reports changes.
+/// Holds the connection, so the name is released when it returns.
+fn announce_changes(connection: &Connection, hub: &RemoteHub, stopping: &AtomicBool) {
+    let events = hub.subscribe();
+    while !stopping.load(Ordering::SeqCst) {
+        let event = match events.recv_timeout(STOP_POLL_INTERVAL) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let mut changed: HashMap<&str, Value> = HashMap::new();
+        // The track length follows the timer interval, so both events can
+        // change the metadata.
+        changed.insert("Metadata", Value::from(metadata(hub)));
+        if let RemoteEvent::Timer { timer } = &event {
+            changed.insert("PlaybackStatus", Value::from(playback_status(Some(timer))));
+        }
+        let result = connection.emit_signal(
+            None::<BusName>,
+            OBJECT_PATH,
+            "org.freedesktop.DBus.Properties",
+            "PropertiesChanged",
+            &(PLAYER_INTERFACE, changed, Vec::<&str>::new()),
+        );
+        if let Err(err) = result {
+            eprintln!("[RUST] mpris: failed to announce a change: {}", err);
+        }
+    }
+}
+
+fn bus_error(err: zbus::Error) -> LoaderError {
+    LoaderError::Io(std::io::Error::other(format!("D-Bus: {}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MprisPlayer, BUS_NAME, OBJECT_PATH, PLAYER_INTERFACE};
+    use crate::error::ImageMode;
+    use crate::img_loader::ImageLoader;
+    use crate::remote::{RemoteHost, RemoteHub, TimerCommand};
+    use crate::test_support::{open_temp_db, unique_temp_root};
+    use crate::types::TimerStatus;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+    use std::sync::{Arc, Mutex};
+    use zbus::blocking::connection::Builder;
+    use zbus::blocking::fdo::PropertiesProxy;
+    use zbus::blocking::proxy::Builder as ProxyBuilder;
+    use zbus::blocking::Proxy;
+    use zbus::proxy::CacheProperties;
+    use zbus::zvariant::OwnedValue;
+
+    struct TestHost {
+        loader: Arc<ImageLoader>,
+        selected: Mutex<Vec<ImageMode>>,
+        timer: Mutex<Vec<TimerCommand>>,
+        raised: Mutex<usize>,
+    }
+
+    impl RemoteHost for TestHost {
+        fn loader(&self) -> Option<Arc<ImageLoader>> {
+            Some(Arc::clone(&self.loader))
+        }
+
+        fn image_selected(&self, mode: ImageMode, _data: Vec<u8>, _auto_switched_folder: bool) {
+            self.selected.lock().unwrap().push(mode);
+        }
+
+        fn timer_command(&self, command: TimerCommand) {
+            self.timer.lock().unwrap().push(command);
+        }
+
+        fn raise(&self) {
+            *self.raised.lock().unwrap() += 1;
+        }
+    }
+
+    /// Starts its own `dbus-daemon`, so it only runs on request:
+    /// `cargo test -- --ignored`.
+    #[test]
+    #[ignore = "needs dbus-daemon; run with `cargo test -- --ignored`"]
+    fn media_keys_drive_the_slideshow_over_a_private_bus() {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address=1"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("dbus-daemon should be installed to run this test");
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .expect("the bus address should be printed");
+        let address = address.trim();
+
+        let root = unique_temp_root("mpris", "player");
+        let folder = root.join("images");
+        std::fs::create_dir_all(&folder).unwrap();
+        for idx in 0..3u8 {
+            std::fs::write(folder.join(format!("img {idx}.png")), [idx; 4]).unwrap();
+        }
+        let hub = Arc::new(RemoteHub::default());
+        let loader = ImageLoader::new(open_temp_db(&root));
+        let listener_hub = Arc::clone(&hub);
+        loader.set_image_listener(Some(Box::new(move |image_id, path| {
+            listener_hub.image_shown(image_id, path)
+        })));
+        loader
+            .set_current_folder_and_index(folder.to_str().unwrap())
+            .expect("folder should index");
+        let host = Arc::new(TestHost {
+            loader: Arc::new(loader),
+            selected: Mutex::new(Vec::new()),
+            timer: Mutex::new(Vec::new()),
+            raised: Mutex::new(0),
+        });
+        let player = MprisPlayer::start_at(address, Arc::clone(&hub), host.clone())
+            .expect("player should join the bus");
+
+        let client = Builder::address(address).unwrap().build().unwrap();
+        // Uncached, so reads see the player's state rather than the signals.
+        let player_proxy: Proxy = ProxyBuilder::new(&client)
+            .destination(BUS_NAME)
+            .unwrap()
+            .path(OBJECT_PATH)
+            .unwrap()
+            .interface(PLAYER_INTERFACE)
+            .unwrap()
+            .cache_properties(CacheProperties::No)
+            .build()
+            .unwrap();
+        let root_proxy =
+            Proxy::new(&client, BUS_NAME, OBJECT_PATH, "org.mpris.MediaPlayer2").unwrap();
+        let properties = PropertiesProxy::builder(&client)
+            .destination(BUS_NAME)
+            .unwrap()
+            .path(OBJECT_PATH)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut changes = properties.receive_properties_changed().unwrap();
+
+        let metadata: HashMap<String, OwnedValue> = player_proxy.get_property("Metadata").unwrap();
+        assert!(!metadata.contains_key("xesam:url"));
+
+        player_proxy
+            .call_method("Next", &())
+            .expect("Next should succeed");
+        assert_eq!(*host.selected.lock().unwrap(), vec![ImageMode::Random]);
+        let change = changes.next().expect("the change should be announced");
+        let args = change.args().unwrap();
+        assert_eq!(args.interface_name().as_str(), PLAYER_INTERFACE);
+        assert!(args.changed_properties().contains_key("Metadata"));
+        let shown = hub.image().expect("the hub should know the image");
+        let metadata: HashMap<String, OwnedValue> = player_proxy.get_property("Metadata").unwrap();
+        let url = String::try_from(metadata["xesam:url"].try_clone().unwrap()).unwrap();
+        assert!(url.starts_with("file://"));
+        assert!(url.contains("/img%20"));
+        let album = String::try_from(metadata["xesam:album"].try_clone().unwrap()).unwrap();
+        assert_eq!(album, folder.to_string_lossy());
+
+        let status: String = player_proxy.get_property("PlaybackStatus").unwrap();
+        assert_eq!(status, "Stopped");
+        player_proxy.call_method("PlayPause", &()).unwrap();
+        hub.timer_changed(TimerStatus {
+            running: true,
+            remaining_seconds: 30,
+            initial_seconds: 60,
+            flow_mode: "random".to_string(),
+        });
+        let status: String = player_proxy.get_property("PlaybackStatus").unwrap();
+        assert_eq!(status, "Playing");
+        let position: i64 = player_proxy.get_property("Position").unwrap();
+        assert_eq!(position, 30_000_000);
+        player_proxy.call_method("PlayPause", &()).unwrap();
+        player_proxy.call_method("Stop", &()).unwrap();
+        assert_eq!(
+            *host.timer.lock().unwrap(),
+            vec![
+                TimerCommand::Resume,
+                TimerCommand::Pause,
+                TimerCommand::Stop
+            ]
+        );
+
+        player_proxy.call_method("Next", &()).unwrap();
+        assert_ne!(hub.image().as_ref(), Some(&shown));
+        player_proxy.call_method("Previous", &()).unwrap();
+        assert_eq!(hub.image(), Some(shown));
+        assert_eq!(host.selected.lock().unwrap().len(), 3);
+        root_proxy.call_method("Raise", &()).unwrap();
+        assert_eq!(*host.raised.lock().unwrap(), 1);
+
+        drop(player);
+        drop(client);
+        let _ = daemon.kill();
+        let _ = daemon.wait();
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+}
@@ -0,0 +1,312 @@
+use crate::db::DB_FILE_NAME;
+use crate::error::LoaderError;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The database that predates profiles; it stays where it always was.
+pub const DEFAULT_PROFILE: &str = "default";
+const PROFILES_DIR_NAME: &str = "profiles";
+const ACTIVE_FILE_NAME: &str = "profiles.json";
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ActiveProfileFile {
+    active: Option<String>,
+}
+
+/// Named libraries, each with its own database and therefore its own
+/// folders, history, checked scope, settings and backups. The default
+/// profile lives directly in the data directory; the others in
+/// `profiles/<name>/`. Which one is active is kept in `profiles.json`.
+pub struct Profiles {
+    data_dir: PathBuf,
+}
+
+/// Trims the name and checks it is usable as a directory name on every
+/// platform. Names are compared case-insensitively elsewhere, because the
+/// file systems on Windows and macOS do.
+pub fn validate_profile_name(name: &str) -> Result<String, LoaderError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(LoaderError::Invalid(
+            "profile name must not be empty".to_string(),
+        ));
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(LoaderError::Invalid(format!(
+            "profile name must be at most {} characters",
+            MAX_NAME_LEN
+        )));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_')
+    {
+        return Err(LoaderError::Invalid(
+            "profile name may only contain letters, digits, spaces, '-' and '_'".to_string(),
+        ));
+    }
+    if name.eq_ignore_ascii_case(DEFAULT_PROFILE) {
+        return Err(LoaderError::Invalid(format!(
+            "\"{}\" is reserved",
+            DEFAULT_PROFILE
+        )));
+    }
+    Ok(name.to_string())
+}
+
+impl Profiles {
+    pub fn new(data_dir: PathBuf) -> Self {
+        Self { data_dir }
+    }
+
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    fn profiles_dir(&self) -> PathBuf {
+        self.data_dir.join(PROFILES_DIR_NAME)
+    }
+
+    fn active_file(&self) -> PathBuf {
+        self.data_dir.join(ACTIVE_FILE_NAME)
+    }
+
+    pub fn db_path(&self, name: &str) -> PathBuf {
+        if name == DEFAULT_PROFILE {
+            self.data_dir.join(DB_FILE_NAME)
+        } else {
+            self.profiles_dir().join(name).join(DB_FILE_NAME)
+        }
+    }
+
+    /// The default profile first, then the others by name.
+    pub fn list(&self) -> Result<Vec<String>, LoaderError> {
+        let mut names = Vec::new();
+        match std::fs::read_dir(self.profiles_dir()) {
+            Ok(read_dir) => {
+                for entry in read_dir {
+                    let entry = entry?;
+                    if !entry.file_type()?.is_dir() {
+                        continue;
+                    }
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    // Skip anything the app would not have created itself.
+                    if validate_profile_name(&name).ok().as_deref() == Some(name.as_str()) {
+                        names.push(name);
+                    }
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        names.sort_by_key(|name| name.to_lowercase());
+        names.insert(0, DEFAULT_PROFILE.to_string());
+        Ok(names)
+    }
+
+    /// Resolves a name as the user typed it to the stored spelling.
+    pub fn find(&self, name: &str) -> Result<String, LoaderError> {
+        let wanted = name.trim().to_lowercase();
+        self.list()?
+            .into_iter()
+            .find(|existing| existing.to_lowercase() == wanted)
+            .ok_or_else(|| LoaderError::NotFound(format!("profile not found: {}", name.trim())))
+    }
+
+    /// Falls back to the default profile when nothing was chosen yet or the
+    /// chosen profile has since disappeared.
+    pub fn active(&self) -> Result<String, LoaderError> {
+        let chosen = match std::fs::read(self.active_file()) {
+            Ok(bytes) => serde_json::from_slice::<ActiveProfileFile>(&bytes)
+                .map(|file| file.active)
+                .unwrap_or_else(|err| {
+                    eprintln!(
+                        "[RUST] profiles: ignoring unreadable {}: {}",
+                        ACTIVE_FILE_NAME, err
+                    );
+                    None
+                }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        let Some(chosen) = chosen else {
+            return Ok(DEFAULT_PROFILE.to_string());
+        };
+        match self.find(&chosen) {
+            Ok(name) => Ok(name),
+            Err(LoaderError::NotFound(_)) => {
+                eprintln!("[RUST] profiles: {} is gone, using the default", chosen);
+                Ok(DEFAULT_PROFILE.to_string())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn set_active(&self, name: &str) -> Result<(), LoaderError> {
+        let name = self.find(name)?;
+        write_json_atomically(
+            &self.active_file(),
+            &ActiveProfileFile { active: Some(name) },
+        )
+    }
+
+    pub fn create(&self, name: &str) -> Result<String, LoaderError> {
+        let name = validate_profile_name(name)?;
+        if self.find(&name).is_ok() {
+            return Err(LoaderError::Invalid(format!(
+                "profile already exists: {}",
+                name
+            )));
+        }
+        std::fs::create_dir_all(self.profiles_dir().join(&name))?;
+        Ok(name)
+    }
+
+    /// Moves the profile's directory, database and backups included. The
+    /// caller must close the profile's database first when it is open.
+    pub fn rename(&self, from: &str, to: &str) -> Result<String, LoaderError> {
+        let from = self.find(from)?;
+        if from == DEFAULT_PROFILE {
+            return Err(LoaderError::Invalid(
+                "the default profile cannot be renamed".to_string(),
+            ));
+        }
+        let to = validate_profile_name(to)?;
+        // A change of case alone is allowed; it matches `from` itself.
+        if let Ok(existing) = self.find(&to) {
+            if existing != from {
+                return Err(LoaderError::Invalid(format!(
+                    "profile already exists: {}",
+                    existing
+                )));
+            }
+        }
+        let was_active = self.active()? == from;
+        std::fs::rename(
+            self.profiles_dir().join(&from),
+            self.profiles_dir().join(&to),
+        )?;
+        if was_active {
+            self.set_active(&to)?;
+        }
+        Ok(to)
+    }
+
+    /// Removes the profile's directory, database and backups included. The
+    /// active profile has to be switched away from first.
+    pub fn delete(&self, name: &str) -> Result<(), LoaderError> {
+        let name = self.find(name)?;
+        if name == DEFAULT_PROFILE {
+            return Err(LoaderError::Invalid(
+                "the default profile cannot be deleted".to_string(),
+            ));
+        }
+        if self.active()? == name {
+            return Err(LoaderError::Invalid(
+                "switch to another profile before deleting this one".to_string(),
+            ));
+        }
+        std::fs::remove_dir_all(self.profiles_dir().join(&name))?;
+        Ok(())
+    }
+}
+
+/// Writes small JSON files kept in the data directory, so a crash never
+/// leaves a half-written one behind.
+pub(crate) fn write_json_atomically<T: Serialize>(
+    path: &Path,
+    value: &T,
+) -> Result<(), LoaderError> {
+    let json = serde_json::to_vec_pretty(value).map_err(|err| {
+        LoaderError::Invalid(format!("failed to serialize {}: {}", path.display(), err))
+    })?;
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    std::fs::write(&partial, json)?;
+    std::fs::rename(&partial, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_profile_name, Profiles, DEFAULT_PROFILE};
+    use crate::db::Db;
+    use crate::test_support::unique_temp_root;
+
+    #[test]
+    fn profile_names_are_checked() {
+        assert_eq!(validate_profile_name("  student-A ").unwrap(), "student-A");
+        assert!(validate_profile_name("").is_err());
+        assert!(validate_profile_name("Default").is_err());
+        assert!(validate_profile_name("../escape").is_err());
+        assert!(validate_profile_name("a/b").is_err());
+        assert!(validate_profile_name(&"x".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn profiles_keep_separate_databases_and_track_the_active_one() {
+        let root = unique_temp_root("profiles", "lifecycle");
+        let profiles = Profiles::new(root.clone());
+        assert_eq!(profiles.list().unwrap(), vec![DEFAULT_PROFILE]);
+        assert_eq!(profiles.active().unwrap(), DEFAULT_PROFILE);
+        assert_eq!(
+            profiles.db_path(DEFAULT_PROFILE),
+            root.join("imgstate.sqlite")
+        );
+
+        profiles
+            .create("figure")
+            .expect("profile should be created");
+        profiles
+            .create("environment")
+            .expect("profile should be created");
+        assert!(profiles.create("FIGURE").is_err());
+        assert_eq!(
+            profiles.list().unwrap(),
+            vec![DEFAULT_PROFILE, "environment", "figure"]
+        );
+
+        let db = Db::open(profiles.db_path("figure")).expect("profile db should open");
+        db.conn()
+            .execute(
+                "INSERT INTO folders (path, added_at) VALUES ('/figure', 'now')",
+                [],
+            )
+            .expect("folder insert should succeed");
+        drop(db);
+        profiles
+            .set_active("Figure")
+            .expect("switch should succeed");
+        assert_eq!(profiles.active().unwrap(), "figure");
+        assert!(profiles.delete("figure").is_err());
+        assert!(profiles.delete(DEFAULT_PROFILE).is_err());
+
+        assert_eq!(profiles.rename("figure", "gesture").unwrap(), "gesture");
+        assert_eq!(profiles.active().unwrap(), "gesture");
+        assert!(profiles.rename("gesture", "environment").is_err());
+        assert!(profiles.rename(DEFAULT_PROFILE, "main").is_err());
+        let db = Db::open(profiles.db_path("gesture")).expect("renamed db should open");
+        let folders: i64 = db
+            .conn()
+            .query_row("SELECT COUNT(*) FROM folders", [], |row| row.get(0))
+            .expect("folder count should work");
+        assert_eq!(folders, 1);
+        drop(db);
+
+        profiles
+            .set_active(DEFAULT_PROFILE)
+            .expect("switch should succeed");
+        profiles
+            .delete("gesture")
+            .expect("inactive profile should delete");
+        assert!(!root.join("profiles").join("gesture").exists());
+
+        // A vanished profile falls back to the default.
+        profiles.set_active("environment").unwrap();
+        std::fs::remove_dir_all(root.join("profiles").join("environment")).unwrap();
+        assert_eq!(profiles.active().unwrap(), DEFAULT_PROFILE);
+
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+}
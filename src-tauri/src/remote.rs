@@ -0,0 +1,666 @@
+use crate::error::{ImageMode, LoaderError};
+use crate::img_loader::ImageLoader;
+use crate::profiles::write_json_atomically;
+use crate::types::{ImageInfo, RemoteSettings, TimerStatus};
+use rand::Rng;
+use serde::Serialize;
+use std::io::Cursor;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+pub const DEFAULT_PORT: u16 = 47821;
+const SETTINGS_FILE_NAME: &str = "remote.json";
+/// How often event streams check whether the server is stopping.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+impl Default for RemoteSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_PORT,
+            allow_lan: false,
+            token: String::new(),
+        }
+    }
+}
+
+pub fn load_settings(data_dir: &Path) -> Result<RemoteSettings, LoaderError> {
+    match std::fs::read(data_dir.join(SETTINGS_FILE_NAME)) {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes).unwrap_or_else(|err| {
+            eprintln!(
+                "[RUST] remote: ignoring unreadable {}: {}",
+                SETTINGS_FILE_NAME, err
+            );
+            RemoteSettings::default()
+        })),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(RemoteSettings::default()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Validates and stores the settings, generating a token when none is set.
+/// Returns what was stored.
+pub fn save_settings(
+    data_dir: &Path,
+    mut settings: RemoteSettings,
+) -> Result<RemoteSettings, LoaderError> {
+    if settings.port == 0 {
+        return Err(LoaderError::Invalid(
+            "remote port must be between 1 and 65535".to_string(),
+        ));
+    }
+    settings.token = settings.token.trim().to_string();
+    if settings.token.is_empty() {
+        settings.token = generate_token();
+    }
+    write_json_atomically(&data_dir.join(SETTINGS_FILE_NAME), &settings)?;
+    Ok(settings)
+}
+
+/// 128 random bits as hex, for tokens that guard local sockets.
+pub(crate) fn generate_token() -> String {
+    let bytes: [u8; 16] = rand::thread_rng().gen();
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Timer actions; the timer itself runs in the frontend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerCommand {
+    Start,
+    Stop,
+    Pause,
+    Resume,
+}
+
+impl TimerCommand {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TimerCommand::Start => "start",
+            TimerCommand::Stop => "stop",
+            TimerCommand::Pause => "pause",
+            TimerCommand::Resume => "resume",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "start" => Some(TimerCommand::Start),
+            "stop" => Some(TimerCommand::Stop),
+            "pause" => Some(TimerCommand::Pause),
+            "resume" => Some(TimerCommand::Resume),
+            _ => None,
+        }
+    }
+}
+
+/// What the server needs from the app it runs in.
+pub trait RemoteHost: Send + Sync + 'static {
+    /// The loader of the active profile, if one is open.
+    fn loader(&self) -> Option<Arc<ImageLoader>>;
+    /// An image was picked through the API; the app should show it.
+    fn image_selected(&self, mode: ImageMode, data: Vec<u8>, auto_switched_folder: bool);
+    fn timer_command(&self, command: TimerCommand);
+    /// Brings the window to the front, where there is one.
+    fn raise(&self) {}
+}
+
+/// A change of what the app shows. WebSocket clients get it as JSON with a
+/// `type` tag.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum RemoteEvent {
+    Image { image: ImageInfo },
+    Timer { timer: TimerStatus },
+}
+
+/// Fans image and timer changes out to subscribers: WebSocket clients and,
+/// on Linux, the MPRIS player. It outlives the server, so it keeps the last
+/// state across restarts and is fed whether or not the server runs.
+#[derive(Default)]
+pub struct RemoteHub {
+    subscribers: Mutex<Vec<Sender<RemoteEvent>>>,
+    image: Mutex<Option<ImageInfo>>,
+    timer: Mutex<Option<TimerStatus>>,
+}
+
+impl RemoteHub {
+    /// Meant as the loader's image listener. Reloading the image already
+    /// shown is not a change.
+    pub fn image_shown(&self, image_id: i64, path: &str) {
+        let image = ImageInfo {
+            image_id,
+            path: path.to_string(),
+        };
+        let Ok(mut current) = self.image.lock() else {
+            return;
+        };
+        if current.as_ref() == Some(&image) {
+            return;
+        }
+        self.broadcast(RemoteEvent::Image {
+            image: image.clone(),
+        });
+        *current = Some(image);
+    }
+
+    pub fn timer_changed(&self, status: TimerStatus) {
+        let Ok(mut current) = self.timer.lock() else {
+            return;
+        };
+        if current.as_ref() == Some(&status) {
+            return;
+        }
+        self.broadcast(RemoteEvent::Timer {
+            timer: status.clone(),
+        });
+        *current = Some(status);
+    }
+
+    pub fn image(&self) -> Option<ImageInfo> {
+        self.image.lock().ok().and_then(|image| image.clone())
+    }
+
+    pub fn timer(&self) -> Option<TimerStatus> {
+        self.timer.lock().ok().and_then(|timer| timer.clone())
+    }
+
+    /// New subscribers get the current image and timer first. Dropping the
+    /// receiver unsubscribes.
+    pub fn subscribe(&self) -> Receiver<RemoteEvent> {
+        let (sender, receiver) = mpsc::channel();
+        if let Some(image) = self.image() {
+            let _ = sender.send(RemoteEvent::Image { image });
+        }
+        if let Some(timer) = self.timer() {
+            let _ = sender.send(RemoteEvent::Timer { timer });
+        }
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(sender);
+        }
+        receiver
+    }
+
+    fn broadcast(&self, event: RemoteEvent) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        }
+    }
+}
+
+/// The HTTP/WebSocket remote-control server. Every request needs the token,
+/// as `Authorization: Bearer <token>` or a `token` query parameter, even on
+/// loopback: any web page open in a browser can reach localhost too.
+/// Dropping the server stops it.
+pub struct RemoteServer {
+    server: Arc<Server>,
+    stopping: Arc<AtomicBool>,
+    address: SocketAddr,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+impl RemoteServer {
+    pub fn start(
+        settings: &RemoteSettings,
+        hub: Arc<RemoteHub>,
+        host: Arc<dyn RemoteHost>,
+    ) -> Result<Self, LoaderError> {
+        if settings.token.is_empty() {
+            return Err(LoaderError::Invalid(
+                "remote control needs a token".to_string(),
+            ));
+        }
+        let ip = if settings.allow_lan {
+            Ipv4Addr::UNSPECIFIED
+        } else {
+            Ipv4Addr::LOCALHOST
+        };
+        let requested = SocketAddr::from((ip, settings.port));
+        let server = Server::http(requested).map_err(|err| {
+            LoaderError::Io(std::io::Error::other(format!(
+                "failed to listen on {}: {}",
+                requested, err
+            )))
+        })?;
+        let address = server.server_addr().to_ip().unwrap_or(requested);
+        let server = Arc::new(server);
+        let stopping = Arc::new(AtomicBool::new(false));
+
+        let accept_thread = {
+            let server = Arc::clone(&server);
+            let stopping = Arc::clone(&stopping);
+            let token = settings.token.clone();
+            std::thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    let hub = Arc::clone(&hub);
+                    let host = Arc::clone(&host);
+                    let stopping = Arc::clone(&stopping);
+                    let token = token.clone();
+                    // Navigation can wait on the database; keep accepting.
+                    std::thread::spawn(move || {
+                        handle_request(request, &token, &hub, &*host, &stopping)
+                    });
+                }
+            })
+        };
+        eprintln!("[RUST] remote: listening on {}", address);
+
+        Ok(Self {
+            server,
+            stopping,
+            address,
+            accept_thread: Some(accept_thread),
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl Drop for RemoteServer {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        self.server.unblock();
+        if let Some(thread) = self.accept_thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ApiError {
+    #[serde(skip)]
+    status: u16,
+    code: String,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, code: &str, message: &str) -> Self {
+        Self {
+            status,
+            code: code.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<LoaderError> for ApiError {
+    fn from(err: LoaderError) -> Self {
+        let status = match &err {
+            LoaderError::Invalid(_) => 400,
+            LoaderError::NotFound(_) | LoaderError::Db(rusqlite::Error::QueryReturnedNoRows) => 404,
+            LoaderError::Db(_) | LoaderError::Io(_) => 500,
+            // Everything else is the library's state refusing the request,
+            // such as no folder being selected.
+            _ => 409,
+        };
+        Self::new(status, err.code(), &err.user_message())
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RemoteStatus {
+    image: Option<ImageInfo>,
+    timer: Option<TimerStatus>,
+}
+
+type ApiResponse = Response<Cursor<Vec<u8>>>;
+
+fn handle_request(
+    request: Request,
+    token: &str,
+    hub: &RemoteHub,
+    host: &dyn RemoteHost,
+    stopping: &AtomicBool,
+) {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((url.as_str(), ""));
+
+    if *request.method() == Method::Options {
+        let _ = request.respond(with_cors(
+            Response::from_data(Vec::new()).with_status_code(204),
+        ));
+        return;
+    }
+    if !is_authorized(&request, query, token) {
+        let _ = request.respond(with_cors(error_response(ApiError::new(
+            401,
+            "unauthorized",
+            "missing or wrong token",
+        ))));
+        return;
+    }
+    if *request.method() == Method::Get && path == "/api/events" {
+        open_event_stream(request, hub, stopping);
+        return;
+    }
+
+    let response = route(request.method(), path, hub, host).unwrap_or_else(error_response);
+    let _ = request.respond(with_cors(response));
+}
+
+fn route(
+    method: &Method,
+    path: &str,
+    hub: &RemoteHub,
+    host: &dyn RemoteHost,
+) -> Result<ApiResponse, ApiError> {
+    match (method, path) {
+        (Method::Get, "/api/status") => json_response(&RemoteStatus {
+            image: loader(host)?.get_current_image_info()?,
+            timer: hub.timer(),
+        }),
+        (Method::Get, "/api/image/info") => json_response(&current_image(host)?),
+        (Method::Get, "/api/image") => {
+            // Read straight from disk: fetching is not showing, so it must
+            // not count as a view for spaced random mode.
+            let image = current_image(host)?;
+            let data = std::fs::read(&image.path).map_err(LoaderError::from)?;
+            Ok(Response::from_data(data)
+                .with_header(header("Content-Type", content_type_for(&image.path))))
+        }
+        (Method::Post, "/api/next") => {
+            navigate(host, ImageMode::Normal, ImageLoader::get_next_image)
+        }
+        (Method::Post, "/api/prev") => {
+            navigate(host, ImageMode::Normal, ImageLoader::get_prev_image)
+        }
+        (Method::Post, "/api/random/next") => {
+            navigate(host, ImageMode::Random, ImageLoader::get_next_random_image)
+        }
+        (Method::Post, "/api/random/prev") => {
+            navigate(host, ImageMode::Random, ImageLoader::get_prev_random_image)
+        }
+        (Method::Post, "/api/random/force") => navigate(host, ImageMode::Random, |loader| {
+            loader.get_force_random_image(true)
+        }),
+        (Method::Post, _) if path.starts_with("/api/timer/") => {
+            let command = TimerCommand::parse(&path["/api/timer/".len()..])
+                .ok_or_else(|| ApiError::new(404, "not_found", "unknown timer action"))?;
+            host.timer_command(command);
+            Ok(Response::from_data(Vec::new()).with_status_code(202))
+        }
+        _ => Err(ApiError::new(404, "not_found", "no such endpoint")),
+    }
+}
+
+fn loader(host: &dyn RemoteHost) -> Result<Arc<ImageLoader>, ApiError> {
+    host.loader()
+        .ok_or_else(|| ApiError::new(503, "not_initialized", "ImageLoader not initialized"))
+}
+
+fn current_image(host: &dyn RemoteHost) -> Result<ImageInfo, ApiError> {
+    loader(host)?
+        .get_current_image_info()?
+        .ok_or_else(|| ApiError::new(404, "no_images", "no image shown yet"))
+}
+
+/// Runs a loader navigation method, hands the image to the app so the window
+/// follows, and answers with the new image.
+fn navigate(
+    host: &dyn RemoteHost,
+    mode: ImageMode,
+    pick: impl FnOnce(&ImageLoader) -> Result<(Vec<u8>, bool), LoaderError>,
+) -> Result<ApiResponse, ApiError> {
+    let loader = loader(host)?;
+    // The window ignores navigation while indexing; so does the API.
+    if loader.indexing_jobs().is_running() {
+        return Err(ApiError::new(409, "indexing", "indexing is running"));
+    }
+    let (data, auto_switched_folder) = pick(&loader)?;
+    let image = loader.get_current_image_info()?;
+    host.image_selected(mode, data, auto_switched_folder);
+    json_response(&image)
+}
+
+/// Upgrades to a WebSocket that carries hub events to the client. The
+/// stream is one-way; the client closing the connection or the server
+/// stopping ends it.
+fn open_event_stream(request: Request, hub: &RemoteHub, stopping: &AtomicBool) {
+    let key = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Sec-WebSocket-Key"))
+        .map(|header| header.value.as_str().to_string());
+    let Some(key) = key else {
+        let _ = request.respond(error_response(ApiError::new(
+            400,
+            "invalid_argument",
+            "expected a WebSocket upgrade",
+        )));
+        return;
+    };
+    let accept = tungstenite::handshake::derive_accept_key(key.as_bytes());
+    let response = Response::empty(101).with_header(header("Sec-WebSocket-Accept", &accept));
+    let stream = request.upgrade("websocket", response);
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+    let events = hub.subscribe();
+    while !stopping.load(Ordering::SeqCst) {
+        let event = match events.recv_timeout(STOP_POLL_INTERVAL) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let json = serde_json::to_string(&event).unwrap_or_default();
+        if socket.send(Message::Text(json)).is_err() {
+            return;
+        }
+    }
+    let _ = socket.close(None);
+    let _ = socket.flush();
+}
+
+fn is_authorized(request: &Request, query: &str, token: &str) -> bool {
+    let from_header = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+        .map(str::trim);
+    let from_query = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == "token")
+        .map(|(_, value)| value);
+    from_header
+        .into_iter()
+        .chain(from_query)
+        .any(|candidate| constant_time_eq(candidate.as_bytes(), token.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn content_type_for(path: &str) -> &'static str {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("header should be valid ASCII")
+}
+
+/// Browser sources such as OBS's load from another origin; the token is what
+/// guards the API, not the origin.
+fn with_cors(response: ApiResponse) -> ApiResponse {
+    response
+        .with_header(header("Access-Control-Allow-Origin", "*"))
+        .with_header(header("Access-Control-Allow-Headers", "Authorization"))
+        .with_header(header("Access-Control-Allow-Methods", "GET, POST, OPTIONS"))
+}
+
+fn json_response<T: Serialize>(value: &T) -> Result<ApiResponse, ApiError> {
+    let json = serde_json::to_vec(value)
+        .map_err(|err| ApiError::new(500, "internal", &err.to_string()))?;
+    Ok(Response::from_data(json).with_header(header("Content-Type", "application/json")))
+}
+
+fn error_response(err: ApiError) -> ApiResponse {
+    let status = err.status;
+    let json = serde_json::to_vec(&err).unwrap_or_default();
+    Response::from_data(json)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RemoteHost, RemoteHub, RemoteServer, TimerCommand};
+    use crate::error::ImageMode;
+    use crate::img_loader::ImageLoader;
+    use crate::test_support::{open_temp_db, unique_temp_root};
+    use crate::types::RemoteSettings;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    struct TestHost {
+        loader: Arc<ImageLoader>,
+        selected: Mutex<Vec<ImageMode>>,
+        timer: Mutex<Vec<TimerCommand>>,
+    }
+
+    impl RemoteHost for TestHost {
+        fn loader(&self) -> Option<Arc<ImageLoader>> {
+            Some(Arc::clone(&self.loader))
+        }
+
+        fn image_selected(&self, mode: ImageMode, _data: Vec<u8>, _auto_switched_folder: bool) {
+            self.selected.lock().unwrap().push(mode);
+        }
+
+        fn timer_command(&self, command: TimerCommand) {
+            self.timer.lock().unwrap().push(command);
+        }
+    }
+
+    /// Sends one request and returns the status code and body.
+    fn request(server: &RemoteServer, method: &str, target: &str) -> (u16, Vec<u8>) {
+        let mut stream = TcpStream::connect(server.address()).expect("server should accept");
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+            method, target
+        )
+        .unwrap();
+        let mut response = Vec::new();
+        stream
+            .read_to_end(&mut response)
+            .expect("response should arrive");
+        let split = response
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .expect("response should have a head");
+        let head = String::from_utf8_lossy(&response[..split]).to_string();
+        let status = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .expect("status line should parse");
+        (status, response[split + 4..].to_vec())
+    }
+
+    #[test]
+    fn remote_api_needs_the_token_and_drives_the_loader() {
+        let root = unique_temp_root("remote", "api");
+        let folder = root.join("images");
+        std::fs::create_dir_all(&folder).unwrap();
+        for idx in 0..3u8 {
+            std::fs::write(folder.join(format!("img_{idx}.png")), [idx; 4]).unwrap();
+        }
+        let hub = Arc::new(RemoteHub::default());
+        let loader = ImageLoader::new(open_temp_db(&root));
+        let listener_hub = Arc::clone(&hub);
+        loader.set_image_listener(Some(Box::new(move |image_id, path| {
+            listener_hub.image_shown(image_id, path)
+        })));
+        loader
+            .set_current_folder_and_index(folder.to_str().unwrap())
+            .expect("folder should index");
+        let host = Arc::new(TestHost {
+            loader: Arc::new(loader),
+            selected: Mutex::new(Vec::new()),
+            timer: Mutex::new(Vec::new()),
+        });
+        let settings = RemoteSettings {
+            enabled: true,
+            port: 0,
+            allow_lan: false,
+            token: "secret".to_string(),
+        };
+        let server = RemoteServer::start(&settings, Arc::clone(&hub), host.clone())
+            .expect("server should start");
+        assert!(server.address().ip().is_loopback());
+
+        assert_eq!(request(&server, "POST", "/api/random/force").0, 401);
+        assert_eq!(
+            request(&server, "POST", "/api/random/force?token=wrong").0,
+            401
+        );
+        assert_eq!(request(&server, "GET", "/api/image?token=secret").0, 404);
+
+        let url = format!("ws://{}/api/events?token=secret", server.address());
+        let stream = TcpStream::connect(server.address()).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let (mut socket, _) = tungstenite::client(url, stream).expect("WebSocket should open");
+
+        let (status, body) = request(&server, "POST", "/api/random/force?token=secret");
+        assert_eq!(status, 200);
+        let info: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let path = info["path"]
+            .as_str()
+            .expect("path should be reported")
+            .to_string();
+        assert_eq!(*host.selected.lock().unwrap(), vec![ImageMode::Random]);
+
+        let (status, bytes) = request(&server, "GET", "/api/image?token=secret");
+        assert_eq!(status, 200);
+        assert_eq!(bytes, std::fs::read(&path).unwrap());
+
+        let event = socket.read().expect("an image event should arrive");
+        let event: serde_json::Value = serde_json::from_str(event.to_text().unwrap()).unwrap();
+        assert_eq!(event["type"], "image");
+        assert_eq!(event["image"]["path"], path.as_str());
+
+        assert_eq!(
+            request(&server, "POST", "/api/timer/pause?token=secret").0,
+            202
+        );
+        assert_eq!(*host.timer.lock().unwrap(), vec![TimerCommand::Pause]);
+        assert_eq!(
+            request(&server, "POST", "/api/timer/rewind?token=secret").0,
+            404
+        );
+
+        drop(server);
+        drop(socket);
+        drop(host);
+        std::fs::remove_dir_all(root).expect("temp root should be removable");
+    }
+}
@@ -0,0 +1,36 @@
+use crate::db::{Db, DB_FILE_NAME};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static TEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A temp path no other test, thread or run uses:
+/// `random_pics_{scope}_{name}_{pid}_{nanos}_{counter}`. Nothing is created.
+pub(crate) fn unique_temp_path(scope: &str, name: &str) -> PathBuf {
+    let counter = TEST_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    std::env::temp_dir().join(format!(
+        "random_pics_{}_{}_{}_{}_{}",
+        scope,
+        name,
+        std::process::id(),
+        nanos,
+        counter
+    ))
+}
+
+/// A fresh, empty directory at `unique_temp_path`.
+pub(crate) fn unique_temp_root(scope: &str, name: &str) -> PathBuf {
+    let root = unique_temp_path(scope, name);
+    std::fs::create_dir_all(&root).expect("temp root should be created");
+    root
+}
+
+/// Opens (and migrates) the database a profile would keep in `root`.
+pub(crate) fn open_temp_db(root: &Path) -> Db {
+    Db::open(root.join(DB_FILE_NAME)).expect("temp db should open")
+}
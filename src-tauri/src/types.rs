@@ -0,0 +1,212 @@
+use crate::backup::BackupEntry;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FolderInfo {
+    pub id: i64,
+    pub path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImageHistory {
+    pub history: Vec<ImageHistoryItem>,
+    #[serde(rename = "currentIndex")]
+    pub current_index: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImageHistoryItem {
+    #[serde(rename = "imageId")]
+    pub image_id: i64,
+    #[serde(rename = "orderIndex")]
+    pub order_index: i64,
+    pub path: String,
+}
+
+/// A slice of a history list. `current_index` and `offset` are positions in the
+/// full visible list, so the frontend can place the window inside a virtual list.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImageHistoryWindow {
+    pub items: Vec<ImageHistoryItem>,
+    #[serde(rename = "currentIndex")]
+    pub current_index: i64,
+    pub total: i64,
+    pub offset: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImageSearchResults {
+    pub items: Vec<ImageHistoryItem>,
+    pub total: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FolderHistoryItem {
+    pub id: i64,
+    pub path: String,
+    #[serde(rename = "imageCount")]
+    pub image_count: i64,
+    pub online: bool,
+    #[serde(rename = "lastSeenAt")]
+    pub last_seen_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FolderHistory {
+    pub history: Vec<FolderHistoryItem>,
+    #[serde(rename = "currentIndex")]
+    pub current_index: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FolderTreeNode {
+    pub path: String,
+    #[serde(rename = "parentPath")]
+    pub parent_path: Option<String>,
+    #[serde(rename = "imageCount")]
+    pub image_count: i64,
+    pub checked: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImageState {
+    #[serde(rename = "verticalMirror")]
+    pub vertical_mirror: bool,
+    #[serde(rename = "horizontalMirror")]
+    pub horizontal_mirror: bool,
+    pub greyscale: bool,
+    #[serde(rename = "timerFlowMode")]
+    pub timer_flow_mode: String,
+    #[serde(rename = "showFolderHistoryPanel")]
+    pub show_folder_history_panel: bool,
+    #[serde(rename = "showTopControls")]
+    pub show_top_controls: bool,
+    #[serde(rename = "showImageHistoryPanel")]
+    pub show_image_history_panel: bool,
+    #[serde(rename = "showBottomControls")]
+    pub show_bottom_controls: bool,
+    #[serde(rename = "isFullscreenImage")]
+    pub is_fullscreen_image: bool,
+    #[serde(rename = "shortcutHintsVisible")]
+    pub shortcut_hints_visible: bool,
+    #[serde(rename = "shortcutHintSide")]
+    pub shortcut_hint_side: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RandomSelectionSettings {
+    pub mode: String,
+    #[serde(rename = "spacedRandomness")]
+    pub spaced_randomness: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IndexingSettings {
+    #[serde(rename = "ignorePatterns")]
+    pub ignore_patterns: Vec<String>,
+    #[serde(rename = "skipHiddenDirs")]
+    pub skip_hidden_dirs: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub name: String,
+    pub reason: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    #[serde(rename = "sizeBytes")]
+    pub size_bytes: u64,
+}
+
+impl From<BackupEntry> for BackupInfo {
+    fn from(entry: BackupEntry) -> Self {
+        Self {
+            name: entry.name,
+            reason: entry.reason,
+            created_at: entry.created_at,
+            size_bytes: entry.size_bytes,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProfileInfo {
+    pub name: String,
+    pub active: bool,
+    #[serde(rename = "isDefault")]
+    pub is_default: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LibraryTransferSummary {
+    pub folders: i64,
+    pub images: i64,
+    /// Hides, history entries and checked folders that pointed at an image or
+    /// folder missing from the document; only non-zero on import.
+    #[serde(rename = "skippedReferences")]
+    pub skipped_references: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IntegrityIssue {
+    pub check: String,
+    pub description: String,
+    /// Offending rows found before any repair.
+    pub found: i64,
+    /// Offending rows left afterwards; equals `found` when not repairing.
+    pub remaining: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForeignKeyViolation {
+    pub table: String,
+    pub parent: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    /// True when nothing is left to fix.
+    pub ok: bool,
+    pub repaired: bool,
+    /// Snapshot taken before repairing, if any.
+    pub backup: Option<String>,
+    #[serde(rename = "sqliteErrors")]
+    pub sqlite_errors: Vec<String>,
+    #[serde(rename = "foreignKeyViolations")]
+    pub foreign_key_violations: Vec<ForeignKeyViolation>,
+    /// Failed app-level invariants only; passing checks are left out.
+    pub issues: Vec<IntegrityIssue>,
+}
+
+/// Remote-control server settings. App-wide rather than per profile, so they
+/// live next to `profiles.json` instead of in a database.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteSettings {
+    pub enabled: bool,
+    pub port: u16,
+    /// Listen on every interface instead of loopback only.
+    #[serde(rename = "allowLan")]
+    pub allow_lan: bool,
+    /// Required on every request; generated when empty.
+    pub token: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageInfo {
+    #[serde(rename = "imageId")]
+    pub image_id: i64,
+    pub path: String,
+}
+
+/// The frontend's timer as last reported by it; the timer runs there.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimerStatus {
+    pub running: bool,
+    #[serde(rename = "remainingSeconds")]
+    pub remaining_seconds: i64,
+    #[serde(rename = "initialSeconds")]
+    pub initial_seconds: i64,
+    #[serde(rename = "flowMode")]
+    pub flow_mode: String,
+}
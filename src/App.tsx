@@ -35,6 +35,7 @@ import {
   hideRandomHistoryImage,
   cleanupStaleFolders,
   playNativeTimerTone,
+  reportTimerStatus,
   type FolderHistoryItem,
   type ImageHistoryItem,
   type ImageHistory,
@@ -42,6 +43,9 @@ import {
   type FolderInfo,
   type ImageResponse,
   type FolderTreeNode,
+  type RemoteImageSelected,
+  type RemoteTimerCommand,
+  type LaunchFolderFinished,
 } from './apiClient.ts';
 import { FolderControls } from './components/FolderControls.tsx';
 import { HistoryPanel } from './components/HistoryPanel.tsx';
@@ -232,6 +236,14 @@ export default function App() {
   const timerSoundErrorShownRef = useRef(false);
   const timerVolumeSliderTrackRef = useRef<HTMLDivElement | null>(null);
   const expandedFolderPathsRef = useRef<Set<string>>(readExpandedFolderPaths());
+  // Latest handlers for events from the remote-control server and from
+  // forwarded launches; reassigned every render.
+  const externalHandlersRef = useRef<{
+    imageSelected: (payload: RemoteImageSelected) => Promise<void>;
+    timer: (command: RemoteTimerCommand) => void;
+    folderStarted: (path: string) => void;
+    folderFinished: (payload: LaunchFolderFinished) => Promise<void>;
+  } | null>(null);
 
   const loadHistory = async (history: ImageHistory, mode: 'normal' | 'random') => {
     setHistory(history.history);
@@ -670,6 +682,38 @@ export default function App() {
     };
   }, []);
 
+  useEffect(() => {
+    void reportTimerStatus({
+      running: isTimerRunning,
+      remainingSeconds: remainingTimerSeconds,
+      initialSeconds: initialTimerSeconds,
+      flowMode: timerFlowMode,
+    }).catch(() => {});
+  }, [isTimerRunning, remainingTimerSeconds, initialTimerSeconds, timerFlowMode]);
+
+  useEffect(() => {
+    const unlisteners: Array<() => void> = [];
+    void listen<RemoteImageSelected>('remote-image-selected', (event) => {
+      void externalHandlersRef.current?.imageSelected(event.payload);
+    }).then((fn) => unlisteners.push(fn));
+    void listen<RemoteTimerCommand>('remote-timer', (event) => {
+      externalHandlersRef.current?.timer(event.payload);
+    }).then((fn) => unlisteners.push(fn));
+    void listen<string>('launch-folder-started', (event) => {
+      externalHandlersRef.current?.folderStarted(event.payload);
+    }).then((fn) => unlisteners.push(fn));
+    void listen<LaunchFolderFinished>('launch-folder-finished', (event) => {
+      void externalHandlersRef.current?.folderFinished(event.payload);
+    }).then((fn) => unlisteners.push(fn));
+    void listen<string>('launch-error', (event) => {
+      showToast(event.payload);
+    }).then((fn) => unlisteners.push(fn));
+
+    return () => {
+      unlisteners.forEach((unlisten) => unlisten());
+    };
+  }, []);
+
   useEffect(() => {
     const handleContextMenu = (event: MouseEvent) => {
       event.preventDefault();
@@ -1389,6 +1433,42 @@ export default function App() {
     void startTimerLoop(restartAt, false, false);
   };
 
+  externalHandlersRef.current = {
+    // The backend already moved to the image; show it like local navigation.
+    imageSelected: async ({ mode, image }) => {
+      await handleLoadImage(image);
+      const history = await runOp(() => (mode === 'normal' ? getNormalHistory() : getRandomHistory()));
+      if (history) await loadHistory(history, mode);
+      resetTimerAfterManualNavigation();
+    },
+    timer: (command) => {
+      if (command === 'start' && !isTimerRunning) void handleToggleStartStop();
+      if (command === 'stop' && isTimerRunning) void handleToggleStartStop();
+      if (command === 'pause' && isTimerRunning) handleTogglePausePlay();
+      if (command === 'resume' && !isTimerRunning) handleTogglePausePlay();
+    },
+    // The backend indexes the folder; mirror handlePickFolder around it.
+    folderStarted: (path) => startIndexingUi(path),
+    folderFinished: async ({ folder, error }) => {
+      if (!folder) {
+        appendIndexLog(`error:${error ?? 'failed to open folder'}`);
+        endIndexingUi(false);
+        showToast(error ?? 'failed to open folder');
+        return;
+      }
+      try {
+        await loadFolderHistory();
+        await loadFolderTree();
+        await handleLoadImage(await getCurrentImage());
+        await loadPreferredHistoryForFolder(folder.id);
+        endIndexingUi(true);
+      } catch (err) {
+        endIndexingUi(false);
+        await handleBackendError(err);
+      }
+    },
+  };
+
   const handleToggleTimerSound = () => {
     setIsTimerSoundEnabled((prev) => !prev);
   };
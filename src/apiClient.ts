@@ -7,6 +7,50 @@
 
 import { invoke } from '@tauri-apps/api/core';
 
+export type CommandErrorCode =
+  | 'no_folders_selected'
+  | 'no_current_folder'
+  | 'no_folders_available'
+  | 'all_folders_missing'
+  | 'folder_missing'
+  | 'folder_offline'
+  | 'folder_unreadable'
+  | 'invalid_path'
+  | 'not_a_directory'
+  | 'not_a_file'
+  | 'unsupported_type'
+  | 'no_images'
+  | 'all_hidden'
+  | 'image_missing'
+  | 'image_unreadable'
+  | 'image_hidden'
+  | 'image_not_checked'
+  | 'cancelled'
+  | 'reindex_required'
+  | 'not_found'
+  | 'invalid_argument'
+  | 'db_constraint'
+  | 'db'
+  | 'io'
+  | 'internal'
+  | 'not_initialized';
+
+// Rejection value of every command.
+export type CommandError = {
+  code: CommandErrorCode;
+  message: string;
+  details?: { mode?: 'normal' | 'random'; path?: string; reason?: string };
+};
+
+export function isCommandError(err: unknown): err is CommandError {
+  return (
+    typeof err === 'object' &&
+    err !== null &&
+    typeof (err as { code?: unknown }).code === 'string' &&
+    typeof (err as { message?: unknown }).message === 'string'
+  );
+}
+
 export type FolderInfo = {
   id: number;
   path: string;
@@ -23,6 +67,43 @@ export type ImageHistoryItem = {
   path: string;
 };
 
+export type SearchMode = 'substring' | 'glob' | 'fuzzy';
+
+export type SearchScope = 'all' | 'checked';
+
+export type ImageSearchResults = {
+  items: ImageHistoryItem[];
+  total: number;
+};
+
+export type ImageHistoryWindow = {
+  items: ImageHistoryItem[];
+  currentIndex: number;
+  total: number;
+  offset: number;
+};
+
+export type IndexingProgress = {
+  jobId: number;
+  phase: 'scan' | 'insert' | 'finalize';
+  found: number;
+  inserted: number;
+  ignored: number;
+  total: number;
+  currentPath: string | null;
+  elapsedMs: number;
+};
+
+export type IndexingSummary = {
+  jobId: number;
+  status: 'completed' | 'cancelled' | 'failed';
+  found: number;
+  inserted: number;
+  ignored: number;
+  elapsedMs: number;
+  error: string | null;
+};
+
 export type FolderHistory = {
   history: FolderHistoryItem[];
   currentIndex: number;
@@ -32,6 +113,8 @@ export type FolderHistoryItem = {
   id: number;
   path: string;
   imageCount: number;
+  online: boolean;
+  lastSeenAt: string | null;
 };
 
 export type FolderTreeNode = {
@@ -55,8 +138,48 @@ export type ImageState = {
   shortcutHintSide: 'left' | 'right';
 };
 
+export type RandomSelectionMode = 'lap' | 'spaced';
+
+export type RandomSelectionSettings = {
+  mode: RandomSelectionMode;
+  spacedRandomness: number;
+};
+
+// Global patterns use .gitignore syntax, relative to each indexed folder.
+export type IndexingSettings = {
+  ignorePatterns: string[];
+  skipHiddenDirs: boolean;
+};
+
+export type BackupInfo = {
+  name: string;
+  reason: 'startup' | 'migration' | 'full-wipe' | 'delete-folder' | 'pre-restore' | string;
+  createdAt: string;
+  sizeBytes: number;
+};
+
+export type PathMapping = {
+  from: string;
+  to: string;
+};
+
+export type LibraryTransferSummary = {
+  folders: number;
+  images: number;
+  skippedReferences: number;
+};
+
+export type NormalSortOrder = 'path' | 'natural' | 'mtime' | 'size' | 'dimensions' | 'shuffle';
+
 // Folder operations
-export async function pickFolder(path: string): Promise<FolderInfo> {
+// The folder a pick settled on; `auto_switched_folder` is set when the picked
+// root was offline and another folder was switched to.
+export type PickedFolder = FolderInfo & {
+  auto_switched_folder: boolean;
+};
+
+// Picking a subfolder of an existing root resolves to that root.
+export async function pickFolder(path: string): Promise<PickedFolder> {
   return await invoke('pick_folder', { path });
 }
 
@@ -84,10 +207,37 @@ export async function setFolderExclusive(path: string): Promise<void> {
   await invoke('set_folder_exclusive', { path });
 }
 
+// Points an existing root (e.g. an offline one) at its new location.
+export async function relocateFolder(oldRoot: string, newRoot: string): Promise<FolderInfo> {
+  return await invoke('relocate_folder', { oldRoot, newRoot });
+}
+
+export async function getFolderSortOrder(folderId: number): Promise<NormalSortOrder> {
+  return await invoke('get_folder_sort_order', { folderId });
+}
+
+export async function setFolderSortOrder(folderId: number, order: NormalSortOrder): Promise<void> {
+  await invoke('set_folder_sort_order', { folderId, order });
+}
+
+export async function getFolderFollowSymlinks(folderId: number): Promise<boolean> {
+  return await invoke('get_folder_follow_symlinks', { folderId });
+}
+
+// Takes effect on the next scan; call reindexCurrentFolder to apply it now.
+export async function setFolderFollowSymlinks(folderId: number, follow: boolean): Promise<void> {
+  await invoke('set_folder_follow_symlinks', { folderId, follow });
+}
+
 export async function reindexCurrentFolder(): Promise<FolderInfo> {
   return await invoke('reindex_current_folder');
 }
 
+// Omitting jobId cancels every running indexing job.
+export async function cancelIndexing(jobId?: number): Promise<number> {
+  return await invoke<number>('cancel_indexing', { jobId });
+}
+
 export type ImageResponse = {
   data: number[];
   folder: FolderInfo | null;
@@ -133,6 +283,19 @@ export async function getRandomHistory(): Promise<ImageHistory> {
   return await invoke('get_random_history');
 }
 
+// Omitting offset centers the window on the current image.
+export async function getNormalHistoryWindow(
+  options: { offset?: number; limit?: number } = {},
+): Promise<ImageHistoryWindow> {
+  return await invoke<ImageHistoryWindow>('get_normal_history_window', options);
+}
+
+export async function getRandomHistoryWindow(
+  options: { offset?: number; limit?: number } = {},
+): Promise<ImageHistoryWindow> {
+  return await invoke<ImageHistoryWindow>('get_random_history_window', options);
+}
+
 export async function resetNormalHistory(): Promise<void> {
   await invoke('reset_normal_history');
 }
@@ -150,11 +313,148 @@ export async function setImageState(state: ImageState): Promise<void> {
   await invoke('set_image_state', { state });
 }
 
+export async function getRandomSelectionSettings(): Promise<RandomSelectionSettings> {
+  return await invoke('get_random_selection_settings');
+}
+
+export async function setRandomSelectionSettings(settings: RandomSelectionSettings): Promise<void> {
+  await invoke('set_random_selection_settings', { settings });
+}
+
+export async function getIndexingSettings(): Promise<IndexingSettings> {
+  return await invoke('get_indexing_settings');
+}
+
+export async function setIndexingSettings(settings: IndexingSettings): Promise<void> {
+  await invoke('set_indexing_settings', { settings });
+}
+
 // Destructive operations
 export async function fullWipe(): Promise<void> {
   await invoke('full_wipe');
 }
 
+export async function exportLibrary(path: string): Promise<LibraryTransferSummary> {
+  return await invoke('export_library', { path });
+}
+
+// Replaces the whole library; the first mapping whose `from` prefix matches a
+// path rewrites it. Reload everything afterwards.
+export async function importLibrary(
+  path: string,
+  mappings: PathMapping[] = [],
+): Promise<LibraryTransferSummary> {
+  return await invoke('import_library', { path, mappings });
+}
+
+// Newest first.
+export async function listBackups(): Promise<BackupInfo[]> {
+  return await invoke('list_backups');
+}
+
+// Replaces all state with the snapshot; reload everything afterwards.
+export async function restoreBackup(name: string): Promise<void> {
+  await invoke('restore_backup', { name });
+}
+
+export interface ProfileInfo {
+  name: string;
+  active: boolean;
+  isDefault: boolean;
+}
+
+export async function listProfiles(): Promise<ProfileInfo[]> {
+  return await invoke('list_profiles');
+}
+
+export async function createProfile(name: string): Promise<ProfileInfo[]> {
+  return await invoke('create_profile', { name });
+}
+
+// Swaps the whole library; reload everything afterwards.
+export async function switchProfile(name: string): Promise<ProfileInfo[]> {
+  return await invoke('switch_profile', { name });
+}
+
+export async function renameProfile(from: string, to: string): Promise<ProfileInfo[]> {
+  return await invoke('rename_profile', { from, to });
+}
+
+export async function deleteProfile(name: string): Promise<ProfileInfo[]> {
+  return await invoke('delete_profile', { name });
+}
+
+export interface RemoteSettings {
+  enabled: boolean;
+  port: number;
+  allowLan: boolean;
+  token: string;
+}
+
+export interface TimerStatus {
+  running: boolean;
+  remainingSeconds: number;
+  initialSeconds: number;
+  flowMode: 'random' | 'normal';
+}
+
+export type RemoteTimerCommand = 'start' | 'stop' | 'pause' | 'resume';
+
+// Payload of the `remote-image-selected` event.
+export interface RemoteImageSelected {
+  mode: 'normal' | 'random';
+  image: ImageResponse;
+}
+
+// Payload of the `launch-folder-finished` event, sent after a later launch
+// asked the running instance to open a folder.
+export interface LaunchFolderFinished {
+  folder: PickedFolder | null;
+  error: string | null;
+}
+
+export async function getRemoteSettings(): Promise<RemoteSettings> {
+  return await invoke('get_remote_settings');
+}
+
+// Restarts the server to match. An empty token is replaced by a generated
+// one, returned here.
+export async function setRemoteSettings(settings: RemoteSettings): Promise<RemoteSettings> {
+  return await invoke('set_remote_settings', { settings });
+}
+
+export async function reportTimerStatus(status: TimerStatus): Promise<void> {
+  await invoke('report_timer_status', { status });
+}
+
+export interface IntegrityIssue {
+  check: string;
+  description: string;
+  found: number;
+  remaining: number;
+}
+
+export interface ForeignKeyViolation {
+  table: string;
+  parent: string;
+  count: number;
+}
+
+export interface IntegrityReport {
+  ok: boolean;
+  repaired: boolean;
+  backup: string | null;
+  sqliteErrors: string[];
+  foreignKeyViolations: ForeignKeyViolation[];
+  issues: IntegrityIssue[];
+}
+
+// With `repair`, snapshots the database and fixes what it can; reload
+// everything afterwards.
+export async function checkIntegrity(repair = false): Promise<IntegrityReport> {
+  return await invoke('check_integrity', { repair });
+}
+
 export async function isHealthy(): Promise<boolean> {
   try {
     return await invoke('is_healthy');
@@ -176,6 +476,32 @@ export async function setRandomImageByIndex(index: number): Promise<ImageRespons
   return await invoke<ImageResponse>('set_random_image_by_index', { index });
 }
 
+export async function setNormalImageById(imageId: number): Promise<ImageResponse> {
+  return await invoke<ImageResponse>('set_normal_image_by_id', { imageId });
+}
+
+export async function openImageById(
+  imageId: number,
+  appendToRandomHistory = false,
+): Promise<ImageResponse> {
+  return await invoke<ImageResponse>('open_image_by_id', { imageId, appendToRandomHistory });
+}
+
+export async function openImageByPath(
+  path: string,
+  appendToRandomHistory = false,
+): Promise<ImageResponse> {
+  return await invoke<ImageResponse>('open_image_by_path', { path, appendToRandomHistory });
+}
+
+// Search
+export async function searchImages(
+  query: string,
+  options: { mode?: SearchMode; scope?: SearchScope; offset?: number; limit?: number } = {},
+): Promise<ImageSearchResults> {
+  return await invoke('search_images', { query, ...options });
+}
+
 export async function getCurrentFolder(): Promise<FolderInfo | null> {
   return await invoke('get_current_folder');
 }